@@ -0,0 +1,20 @@
+#!/bin/sh
+# Downloads the blargg test ROMs used by rsboy-core/tests/blargg.rs into test_roms/blargg.
+set -e
+BASE=https://raw.githubusercontent.com/retrio/gb-test-roms/master
+DEST=$(dirname "$0")/test_roms/blargg
+mkdir -p "$DEST/mem_timing" "$DEST/oam_bug"
+
+fetch() {
+    curl -fsSL "$BASE/$1" -o "$DEST/$2"
+    echo "$2"
+}
+
+for rom in 01-read_timing 02-write_timing 03-modify_timing; do
+    fetch "mem_timing/individual/$rom.gb" "mem_timing/$rom.gb"
+done
+fetch halt_bug.gb halt_bug.gb
+for rom in 1-lcd_sync 2-causes 3-non_causes 4-scanline_timing 5-timing_bug 6-timing_no_bug \
+    7-timing_effect 8-instr_effect; do
+    fetch "oam_bug/rom_singles/$rom.gb" "oam_bug/$rom.gb"
+done
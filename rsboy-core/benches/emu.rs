@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rsboy_core::emu::Emu;
+use rsboy_core::instructions::{dispatch, Instr, INSTR_TABLE};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("Emu step", |b| {
+        b.iter(|| {
+            let mut emu = Emu::new(vec![], None);
+            emu.bus.in_bios = 1;
+            for _instr in INSTR_TABLE.iter() {
+                // emu.cpu.opcode = instr;
+                emu.cpu.step(&mut emu.bus);
+            }
+        })
+    });
+}
+
+// Register and (HL) opcodes, safe to run in any order while HL points into WRAM.
+fn register_opcodes() -> Vec<u8> {
+    (0x40..=0xBF).filter(|&op| op != 0x76).collect()
+}
+
+// Matching on the Instr from INSTR_TABLE against the fast-dispatch handler table.
+fn dispatch_benchmark(c: &mut Criterion) {
+    let opcodes = register_opcodes();
+    let mut emu = Emu::new(vec![], None);
+    c.bench_function("Dispatch by match", |b| {
+        b.iter(|| {
+            for &opcode in &opcodes {
+                emu.cpu.registers.h = 0xC0;
+                Instr::from(opcode).run(&mut emu.cpu, &mut emu.bus);
+            }
+        })
+    });
+    c.bench_function("Dispatch by table", |b| {
+        b.iter(|| {
+            for &opcode in &opcodes {
+                emu.cpu.registers.h = 0xC0;
+                dispatch::run(opcode, &mut emu.cpu, &mut emu.bus);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, dispatch_benchmark);
+criterion_main!(benches);
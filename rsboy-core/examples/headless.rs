@@ -0,0 +1,33 @@
+use rsboy_core::batch;
+use rsboy_core::constants::MaybeErr;
+use rsboy_core::emu::Emu;
+use std::fs;
+
+// Runs a ROM without a window, printing what it sends over serial and saving the last frame.
+// cargo run -p rsboy-core --example headless -- <rom> [frames] [screenshot.png]
+fn main() -> MaybeErr<()> {
+    let mut args = std::env::args().skip(1);
+    let rom = args
+        .next()
+        .ok_or("Usage: headless <rom> [frames] [screenshot.png]")?;
+    let frames: usize = match args.next() {
+        Some(frames) => frames.parse()?,
+        None => 600,
+    };
+    let out = args.next().unwrap_or_else(|| "screenshot.png".to_string());
+
+    let mut emu = Emu::from_path(rom.into(), None)?;
+    for _ in 0..frames {
+        let frame = emu.run_frame();
+        if let Some(text) = &frame.serial_out {
+            print!("{}", text);
+        }
+        if let Some(reason) = frame.stopped() {
+            eprintln!("Stopped: {}", reason);
+            break;
+        }
+    }
+    fs::write(&out, batch::png(&emu.bus.gpu.visible_frame()))?;
+    println!("\nWrote {} at frame {}", out, emu.bus.gpu._vblank_count);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use minifb::{Key, Scale, Window, WindowOptions};
+use rsboy_core::constants::MaybeErr;
+use rsboy_core::emu::Emu;
+use rsboy_core::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rsboy_core::input::Button;
+use std::time::Duration;
+
+// The smallest frontend: one run_frame per window update, keys straight to the joypad.
+// cargo run -p rsboy-core --example minifb -- <rom>
+const KEYS: [(Key, Button); 8] = [
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Backspace, Button::Select),
+    (Key::Enter, Button::Start),
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+];
+
+// A Game Boy frame, about 59.7 per second.
+const FRAME_TIME: Duration = Duration::from_micros(16_742);
+
+fn main() -> MaybeErr<()> {
+    let rom = std::env::args().nth(1).ok_or("Usage: minifb <rom>")?;
+    let mut emu = Emu::from_path(rom.into(), None)?;
+    let options = WindowOptions {
+        scale: Scale::X4,
+        ..WindowOptions::default()
+    };
+    let mut window = Window::new(".rsboy", SCREEN_WIDTH, SCREEN_HEIGHT, options)?;
+    window.limit_update_rate(Some(FRAME_TIME));
+
+    let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for &(key, button) in KEYS.iter() {
+            match (window.is_key_down(key), button.is_pressed(&emu.bus)) {
+                (true, false) => button.press(&mut emu.bus),
+                (false, true) => button.release(&mut emu.bus),
+                _ => {}
+            }
+        }
+        let frame = emu.run_frame();
+        if let Some(reason) = frame.stopped() {
+            return Err(reason.to_string().into());
+        }
+        // RGBA to the 0RGB minifb expects.
+        for (pixel, &rgba) in buffer.iter_mut().zip(frame.pixels.iter().flatten()) {
+            *pixel = rgba >> 8;
+        }
+        window.update_with_buffer(&buffer, SCREEN_WIDTH, SCREEN_HEIGHT)?;
+    }
+    Ok(())
+}
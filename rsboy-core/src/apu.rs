@@ -0,0 +1,348 @@
+use crate::io::{IoContext, IoDevice};
+
+pub const APU_START: usize = 0xFF10;
+pub const APU_END: usize = 0xFF3F;
+pub const NR52: usize = 0xFF26;
+pub const WAVE_START: usize = 0xFF30;
+const NR30: usize = 0xFF1A;
+const NR33: usize = 0xFF1D;
+const NR34: usize = 0xFF1E;
+// Channel 3 in NR52 and `status`.
+const WAVE_CHANNEL: u8 = 0x04;
+// NR11, NR21, NR31 and NR41, the length counters.
+const LENGTH_REGS: [usize; 4] = [0xFF11, 0xFF16, 0xFF1B, 0xFF20];
+
+// Bits that read back as 1 regardless of what was written, indexed from 0xFF10.
+// https://gbdev.io/pandocs/Audio_Registers.html
+#[rustfmt::skip]
+pub const READ_MASKS: [u8; 0x30] = [
+    // NR10  NR11  NR12  NR13  NR14
+    0x80, 0x3F, 0x00, 0xFF, 0xBF,
+    // ----  NR21  NR22  NR23  NR24
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF,
+    // NR30  NR31  NR32  NR33  NR34
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF,
+    // ----  NR41  NR42  NR43  NR44
+    0xFF, 0xFF, 0x00, 0x00, 0xBF,
+    // NR50  NR51  NR52
+    0x00, 0x00, 0x70,
+    // Unused 0xFF27-0xFF2F
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// Sound registers without any sound. Stores NR10-NR52 and wave RAM so reads
+// come back the way games expect, ahead of actual audio output.
+pub struct ApuRegs {
+    pub regs: [u8; 0x30],
+    // Channel on flags reported in the low nibble of NR52.
+    pub status: u8,
+    // Channel 3 playback: dots until the next sample, the sample (0-31) and whether it was
+    // fetched from wave RAM this very dot.
+    pub wave_timer: u16,
+    pub wave_position: u8,
+    pub wave_fetched: bool,
+}
+
+impl Default for ApuRegs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ApuRegs {
+    pub fn new() -> Self {
+        Self {
+            regs: [0; 0x30],
+            status: 0,
+            wave_timer: 0,
+            wave_position: 0,
+            wave_fetched: false,
+        }
+    }
+
+    pub fn powered(&self) -> bool {
+        self.regs[NR52 - APU_START] & 0x80 != 0
+    }
+
+    // Whether the DAC of `channel` (0-3) is on. Triggering a channel with its DAC off does nothing.
+    fn dac_enabled(&self, channel: usize) -> bool {
+        match channel {
+            2 => self.regs[NR30 - APU_START] & 0x80 != 0,
+            _ => self.regs[0xFF12 - APU_START + channel * 5] & 0xF8 != 0,
+        }
+    }
+
+    // Dots between channel 3 samples, from the frequency in NR33 and NR34.
+    fn wave_period(&self) -> u16 {
+        let frequency =
+            (self.regs[NR34 - APU_START] as u16 & 0x07) << 8 | self.regs[NR33 - APU_START] as u16;
+        (2048 - frequency) * 2
+    }
+
+    // One dot of channel 3.
+    pub fn tick(&mut self) {
+        self.skip(1);
+    }
+
+    // Moves channel 3 on by `n` dots.
+    pub fn skip(&mut self, n: usize) {
+        self.wave_fetched = false;
+        if self.status & WAVE_CHANNEL == 0 || n == 0 {
+            return;
+        }
+        let timer = self.wave_timer as usize;
+        if n < timer {
+            self.wave_timer -= n as u16;
+            return;
+        }
+        let period = self.wave_period() as usize;
+        let after = n - timer;
+        self.wave_position = ((self.wave_position as usize + 1 + after / period) % 32) as u8;
+        self.wave_timer = (period - after % period) as u16;
+        self.wave_fetched = after.is_multiple_of(period);
+    }
+
+    // Wave RAM as the CPU sees it. While channel 3 plays it gets the byte being played instead,
+    // on DMG only in the dot that byte is fetched and 0xFF otherwise.
+    fn wave_address(&self, address: usize, cgb: bool) -> Option<usize> {
+        if self.status & WAVE_CHANNEL == 0 {
+            Some(address)
+        } else if cgb || self.wave_fetched {
+            Some(WAVE_START + self.wave_position as usize / 2)
+        } else {
+            None
+        }
+    }
+
+    // A read from the CPU.
+    pub fn cpu_read(&self, address: usize, cgb: bool) -> u8 {
+        match address {
+            WAVE_START..=APU_END => self
+                .wave_address(address, cgb)
+                .map_or(0xFF, |a| self.read(a)),
+            _ => self.read(address),
+        }
+    }
+
+    pub fn read(&self, address: usize) -> u8 {
+        let i = address - APU_START;
+        if address == NR52 {
+            return (self.regs[i] & 0x80) | READ_MASKS[i] | self.status;
+        }
+        self.regs[i] | READ_MASKS[i]
+    }
+
+    // A write from the CPU. Powering off clears NR10-NR51 and until powered on again only NR52 and
+    // wave RAM take writes, plus the length counters on DMG, which keep theirs through it all.
+    pub fn cpu_write(&mut self, address: usize, value: u8, cgb: bool) {
+        if address == NR52 && value & 0x80 == 0 && self.powered() {
+            for (i, reg) in self.regs[..NR52 - APU_START].iter_mut().enumerate() {
+                *reg = if LENGTH_REGS.contains(&(APU_START + i)) && !cgb {
+                    *reg & length_mask(APU_START + i)
+                } else {
+                    0
+                };
+            }
+        }
+        if address >= WAVE_START {
+            if let Some(address) = self.wave_address(address, cgb) {
+                self.write(address, value);
+            }
+        } else if self.powered() || address == NR52 {
+            self.write(address, value);
+        } else if LENGTH_REGS.contains(&address) && !cgb {
+            self.regs[address - APU_START] = value & length_mask(address);
+        }
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) {
+        let i = address - APU_START;
+        match address {
+            NR52 => {
+                self.regs[i] = value & 0x80;
+                if value & 0x80 == 0 {
+                    self.status = 0;
+                }
+            }
+            // NRx4: bit 7 triggers the channel.
+            0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => {
+                self.regs[i] = value;
+                let channel = (address - 0xFF14) / 5;
+                if value & 0x80 != 0 && self.powered() && self.dac_enabled(channel) {
+                    self.status |= 1 << channel;
+                    // Starts over at sample 0, the first one fetched is sample 1.
+                    if address == NR34 {
+                        self.wave_position = 0;
+                        self.wave_timer = self.wave_period();
+                    }
+                }
+            }
+            // Turning a DAC off also turns its channel off.
+            0xFF12 | 0xFF17 | NR30 | 0xFF21 => {
+                self.regs[i] = value;
+                let channel = match address {
+                    0xFF12 => 0,
+                    0xFF17 => 1,
+                    NR30 => 2,
+                    _ => 3,
+                };
+                if !self.dac_enabled(channel) {
+                    self.status &= !(1 << channel);
+                }
+            }
+            _ => self.regs[i] = value,
+        }
+    }
+}
+
+// Length bits of an NRx1 register, the rest is the duty cycle.
+fn length_mask(address: usize) -> u8 {
+    match address {
+        0xFF1B => 0xFF,
+        _ => 0x3F,
+    }
+}
+
+impl IoDevice for ApuRegs {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        match address as usize {
+            APU_START..=APU_END => Some(self.read(address as usize)),
+            _ => None,
+        }
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, ctx: &mut IoContext) -> bool {
+        match address as usize {
+            APU_START..=APU_END => self.cpu_write(address as usize, value, ctx.cgb),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unused_bits_read_as_one() {
+        let mut apu = ApuRegs::new();
+        for address in APU_START..WAVE_START {
+            apu.write(address, 0);
+        }
+        assert_eq!(apu.read(0xFF10), 0x80);
+        assert_eq!(apu.read(0xFF11), 0x3F);
+        assert_eq!(apu.read(0xFF13), 0xFF);
+        assert_eq!(apu.read(0xFF15), 0xFF);
+        assert_eq!(apu.read(0xFF1A), 0x7F);
+        assert_eq!(apu.read(0xFF1C), 0x9F);
+        assert_eq!(apu.read(0xFF26), 0x70);
+        assert_eq!(apu.read(0xFF2F), 0xFF);
+    }
+
+    #[test]
+    fn wave_ram_reads_back() {
+        let mut apu = ApuRegs::new();
+        apu.write(0xFF30, 0x12);
+        apu.write(0xFF3F, 0xEF);
+        assert_eq!(apu.read(0xFF30), 0x12);
+        assert_eq!(apu.read(0xFF3F), 0xEF);
+    }
+
+    #[test]
+    fn nr52_status_bits() {
+        let mut apu = ApuRegs::new();
+        apu.write(NR52, 0xFF);
+        assert_eq!(apu.read(NR52), 0xF0);
+        // Trigger channel 1 with its DAC on.
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF14, 0x80);
+        assert_eq!(apu.read(NR52), 0xF1);
+        // Channel 2's DAC is off, triggering it does nothing.
+        apu.write(0xFF19, 0x80);
+        assert_eq!(apu.read(NR52), 0xF1);
+        // Channel 3.
+        apu.write(0xFF1A, 0x80);
+        apu.write(0xFF1E, 0x80);
+        assert_eq!(apu.read(NR52), 0xF5);
+        // DAC off turns the channel off.
+        apu.write(0xFF12, 0x00);
+        assert_eq!(apu.read(NR52), 0xF4);
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+    }
+
+    #[test]
+    fn playing_wave_ram_maps_the_current_sample() {
+        for &cgb in [false, true].iter() {
+            let mut apu = ApuRegs::new();
+            apu.cpu_write(NR52, 0x80, cgb);
+            for i in 0..16 {
+                apu.cpu_write(WAVE_START + i, i as u8, cgb);
+            }
+            // Frequency 2044, a sample every 8 dots.
+            apu.cpu_write(NR30, 0x80, cgb);
+            apu.cpu_write(NR33, 0xFC, cgb);
+            apu.cpu_write(NR34, 0x87, cgb);
+            assert_eq!(apu.read(NR52) & WAVE_CHANNEL, WAVE_CHANNEL);
+
+            // Samples 2 and 3 are in byte 1.
+            apu.skip(8 * 2);
+            assert!(apu.wave_fetched);
+            assert_eq!(apu.cpu_read(0xFF3F, cgb), 1);
+            apu.tick();
+            let between = if cgb { 1 } else { 0xFF };
+            assert_eq!(apu.cpu_read(0xFF3F, cgb), between);
+            apu.cpu_write(0xFF30, 0xAA, cgb);
+            let written = if cgb { 0xAA } else { 1 };
+            assert_eq!(apu.read(0xFF31), written);
+            apu.skip(7 + 8 * 29);
+            assert_eq!((apu.wave_position, apu.wave_fetched), (0, true));
+
+            // Stopped, wave RAM is plain memory again.
+            apu.cpu_write(NR30, 0x00, cgb);
+            apu.cpu_write(0xFF3F, 0x55, cgb);
+            assert_eq!(apu.cpu_read(0xFF3F, cgb), 0x55);
+        }
+    }
+
+    #[test]
+    fn powered_off_apu_ignores_writes() {
+        for &cgb in [false, true].iter() {
+            let mut apu = ApuRegs::new();
+            apu.cpu_write(NR52, 0x80, cgb);
+            apu.cpu_write(0xFF24, 0x77, cgb);
+            apu.cpu_write(0xFF11, 0xBF, cgb);
+            apu.cpu_write(0xFF12, 0xF3, cgb);
+            apu.cpu_write(0xFF14, 0x80, cgb);
+            assert_eq!(apu.read(NR52), 0xF1);
+
+            apu.cpu_write(NR52, 0x00, cgb);
+            assert_eq!(apu.read(NR52), 0x70);
+            assert_eq!(apu.read(0xFF24), 0x00);
+            assert_eq!(apu.read(0xFF12), 0x00);
+            assert_eq!(apu.read(0xFF11), 0x3F);
+            apu.cpu_write(0xFF24, 0x77, cgb);
+            apu.cpu_write(0xFF12, 0xF3, cgb);
+            assert_eq!(apu.read(0xFF24), 0x00);
+            assert_eq!(apu.read(0xFF12), 0x00);
+            apu.cpu_write(0xFF30, 0x12, cgb);
+            assert_eq!(apu.read(0xFF30), 0x12);
+
+            // Length counters survive and still take writes on DMG, not the duty cycle.
+            let length = if cgb { 0x00 } else { 0x3F };
+            assert_eq!(apu.regs[0xFF11 - APU_START], length);
+            apu.cpu_write(0xFF20, 0xFF, cgb);
+            assert_eq!(apu.regs[0xFF20 - APU_START], length);
+            apu.cpu_write(0xFF16, 0xC1, cgb);
+            assert_eq!(apu.regs[0xFF16 - APU_START], length & 0x01);
+
+            apu.cpu_write(NR52, 0x80, cgb);
+            apu.cpu_write(0xFF24, 0x77, cgb);
+            assert_eq!(apu.read(0xFF24), 0x77);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::clock::Cycles;
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+// Number of writes to the mapper registers kept in Banks::history.
+pub const BANK_LOG_LEN: usize = 32;
+
+// MBC register ranges, each 0x2000 bytes wide, in address order.
+pub const REGISTER_NAMES: [&str; 4] = ["RAM enable", "ROM bank", "RAM bank", "Mode"];
+
+// Write into cartridge ROM space, which a mapper would treat as a register write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BankWrite {
+    pub clock: Cycles,
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl Display for BankWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>10} PC:{:04x} [{:04x}] <- {:02x} ({})",
+            self.clock,
+            self.pc,
+            self.address,
+            self.value,
+            REGISTER_NAMES[register(self.address)]
+        )
+    }
+}
+
+fn register(address: u16) -> usize {
+    (address as usize >> 13) & 0b11
+}
+
+// Banks currently mapped in and the last value written to each mapper register.
+// Only camera and MBC1 cartridges have a mapper (see Bus::camera and Bus::mbc1), otherwise ROM
+// bank 1 stays switched in and there is no cartridge RAM, but the writes are still recorded to see
+// what a game expected.
+#[derive(Debug, Clone)]
+pub struct Banks {
+    pub rom: usize,
+    pub ram: Option<usize>,
+    pub registers: [u8; 4],
+    history: VecDeque<BankWrite>,
+}
+
+impl Default for Banks {
+    fn default() -> Self {
+        Self {
+            rom: 1,
+            ram: None,
+            registers: [0; 4],
+            history: VecDeque::with_capacity(BANK_LOG_LEN),
+        }
+    }
+}
+
+impl Banks {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn write(&mut self, clock: Cycles, pc: u16, address: u16, value: u8) {
+        self.registers[register(address)] = value;
+        if self.history.len() == BANK_LOG_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(BankWrite {
+            clock,
+            pc,
+            address,
+            value,
+        });
+    }
+
+    // Oldest first.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &BankWrite> {
+        self.history.iter()
+    }
+}
+
+impl Display for Banks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "ROM0: bank 0")?;
+        writeln!(f, "ROMX: bank {}", self.rom)?;
+        match self.ram {
+            Some(bank) => writeln!(f, "SRAM: bank {}", bank)?,
+            None => writeln!(f, "SRAM: none")?,
+        }
+        for (name, value) in REGISTER_NAMES.iter().zip(self.registers.iter()) {
+            writeln!(f, "{:<10} {:02x}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_register_writes() {
+        let mut banks = Banks::new();
+        banks.write(10, 0x0150, 0x2100, 0x05);
+        banks.write(20, 0x0160, 0x0000, 0x0A);
+        assert_eq!(banks.registers, [0x0A, 0x05, 0, 0]);
+        let history: Vec<_> = banks.history().map(|w| (w.pc, w.address)).collect();
+        assert_eq!(history, vec![(0x0150, 0x2100), (0x0160, 0x0000)]);
+        // No mapper, nothing is switched.
+        assert_eq!(banks.rom, 1);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut banks = Banks::new();
+        for i in 0..BANK_LOG_LEN as Cycles + 5 {
+            banks.write(i, 0, 0x6000, i as u8);
+        }
+        assert_eq!(banks.history().count(), BANK_LOG_LEN);
+        assert_eq!(banks.history().next().unwrap().clock, 5);
+    }
+}
@@ -0,0 +1,512 @@
+use crate::clock::{self, Cycles};
+use crate::constants::{MaybeErr, CYCLES_PER_FRAME, GB_CYCLE_SPEED};
+use crate::cpu::CPUState;
+use crate::decode::DecodeCache;
+use crate::emu::{Emu, StopReason};
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::instructions::{Instr, INSTR_TABLE};
+use crate::printer;
+use crate::stats::Stats;
+use crate::watchdog::{Watchdog, DEFAULT_HANG_CYCLES, DEFAULT_LOOP_WINDOW};
+use rayon::prelude::*;
+use std::{
+    fmt::Display,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// Headless compatibility runs over a directory of ROMs.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    // Ran every requested frame.
+    Completed,
+    IllegalOpcode { addr: u16, opcode: u8 },
+    Hang(StopReason),
+    // The emulator panicked, usually on something unimplemented.
+    Crashed(String),
+    LoadError(String),
+}
+
+impl Outcome {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Outcome::Completed => "completed",
+            Outcome::IllegalOpcode { .. } => "illegal-opcode",
+            Outcome::Hang(_) => "hang",
+            Outcome::Crashed(_) => "crashed",
+            Outcome::LoadError(_) => "load-error",
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Completed => write!(f, "Completed"),
+            Outcome::IllegalOpcode { addr, opcode } => {
+                write!(f, "Illegal opcode {:02x} at {:04x}", opcode, addr)
+            }
+            Outcome::Hang(reason) => write!(f, "{}", reason),
+            Outcome::Crashed(message) => write!(f, "Crashed: {}", message),
+            Outcome::LoadError(message) => write!(f, "Failed to load: {}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RomReport {
+    pub path: PathBuf,
+    pub title: String,
+    pub outcome: Outcome,
+    pub frames: usize,
+    // FNV-1a of the last visible frame, None if the ROM never got to run.
+    pub frame_hash: Option<u64>,
+    pub frame: Vec<u32>,
+    pub stats: Stats,
+}
+
+fn is_rom(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("gb") || ext.eq_ignore_ascii_case("gbc"),
+        None => false,
+    }
+}
+
+pub fn find_roms(dir: &Path) -> MaybeErr<Vec<PathBuf>> {
+    let mut roms = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_rom(&path) {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+pub fn frame_hash(frame: &[u32]) -> u64 {
+    frame
+        .iter()
+        .flat_map(|pixel| pixel.to_be_bytes().to_vec())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+// Runs `emu` for up to `frames` frames, stopping early on an illegal opcode or a hang.
+pub fn run(emu: &mut Emu, frames: usize) -> (Outcome, usize) {
+    if emu.watchdog.is_none() {
+        emu.watchdog = Some(Watchdog::new(DEFAULT_HANG_CYCLES, DEFAULT_LOOP_WINDOW));
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        for frame in 0..frames {
+            let end = clock::deadline(emu.bus.clock, CYCLES_PER_FRAME);
+            while emu.bus.clock < end {
+                if let CPUState::Running = emu.cpu.state {
+                    if let Instr::UNIMPLEMENTED = INSTR_TABLE[emu.cpu.opcode as usize] {
+                        let (addr, opcode) = (emu.cpu.op_addr, emu.cpu.opcode);
+                        return (Outcome::IllegalOpcode { addr, opcode }, frame);
+                    }
+                }
+                match emu.emulate_step() {
+                    Some(reason @ StopReason::SuspectedHang { .. }) => {
+                        return (Outcome::Hang(reason), frame)
+                    }
+                    Some(StopReason::Breakpoint(_)) | None => {}
+                }
+            }
+        }
+        (Outcome::Completed, frames)
+    }));
+    result.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic".to_string());
+        (Outcome::Crashed(message), 0)
+    })
+}
+
+pub fn run_rom(path: &Path, frames: usize) -> RomReport {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut emu = match Emu::from_path(path.to_path_buf(), None) {
+        Ok(emu) => emu,
+        Err(e) => {
+            return RomReport {
+                path: path.to_path_buf(),
+                title: name,
+                outcome: Outcome::LoadError(e.to_string()),
+                frames: 0,
+                frame_hash: None,
+                frame: vec![],
+                stats: Stats::default(),
+            }
+        }
+    };
+    let title = emu
+        .header
+        .as_ref()
+        .map(|h| h.title.clone())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(name);
+    // Nothing is traced in a batch run, so skipping idle cycles only makes it faster.
+    emu.idle_skip = true;
+    emu.bus.decode_cache = Some(DecodeCache::new());
+    let (outcome, frames) = run(&mut emu, frames);
+    let frame = emu.bus.gpu.visible_frame();
+    RomReport {
+        path: path.to_path_buf(),
+        title,
+        outcome,
+        frames,
+        frame_hash: Some(frame_hash(&frame)),
+        frame,
+        stats: emu.stats(),
+    }
+}
+
+// One headless run of a single ROM, for CI scripts.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub outcome: Outcome,
+    pub frames: usize,
+    pub cycles: Cycles,
+    pub serial: String,
+    pub frame_hash: u64,
+    // The serial output contained the expected text, or the ROM ran every frame without one.
+    pub passed: bool,
+    // Game Boy time emulated and the real time it took.
+    pub emulated: Duration,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    // How many times faster than a real Game Boy the run went.
+    pub fn speed(&self) -> f64 {
+        speed(self.emulated, self.elapsed)
+    }
+}
+
+fn speed(emulated: Duration, elapsed: Duration) -> f64 {
+    emulated.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+fn emulated_time(clock: Cycles) -> Duration {
+    Duration::from_secs_f64(clock as f64 / GB_CYCLE_SPEED as f64)
+}
+
+// Progress lines for long headless runs, at most one per `interval`.
+pub struct Progress {
+    interval: Duration,
+    frames: usize,
+    start_clock: Cycles,
+    last: Instant,
+    last_clock: Cycles,
+}
+
+impl Progress {
+    pub fn new(interval: Duration, frames: usize, clock: Cycles, now: Instant) -> Self {
+        Self {
+            interval,
+            frames,
+            start_clock: clock,
+            last: now,
+            last_clock: clock,
+        }
+    }
+
+    // A line once `interval` has passed since the last one, `done` is the frames run so far.
+    pub fn update(&mut self, clock: Cycles, done: usize, now: Instant) -> Option<String> {
+        let since = now.duration_since(self.last);
+        if since < self.interval {
+            return None;
+        }
+        let cycles = clock::since(self.last_clock, clock);
+        self.last = now;
+        self.last_clock = clock;
+        Some(format!(
+            "{}/{} frames ({:.0}%), {:.1}s emulated, {:.0} cycles/s ({:.2}x)",
+            done,
+            self.frames,
+            done as f64 * 100.0 / self.frames.max(1) as f64,
+            emulated_time(clock::since(self.start_clock, clock)).as_secs_f64(),
+            cycles as f64 / since.as_secs_f64().max(f64::EPSILON),
+            speed(emulated_time(cycles), since)
+        ))
+    }
+}
+
+// Runs `emu` like `run`, but stops as soon as the serial output contains `expect`.
+pub fn run_headless(emu: &mut Emu, frames: usize, expect: Option<&str>) -> Summary {
+    run_headless_with(emu, frames, expect, |_| {})
+}
+
+// run_headless, calling `on_frame` after every frame.
+pub fn run_headless_with(
+    emu: &mut Emu,
+    frames: usize,
+    expect: Option<&str>,
+    mut on_frame: impl FnMut(&Emu),
+) -> Summary {
+    let start = emu.bus.cycles;
+    let start_clock = emu.bus.clock;
+    let started = Instant::now();
+    let mut outcome = Outcome::Completed;
+    let mut ran = 0;
+    while ran < frames {
+        let (result, done) = run(emu, 1);
+        ran += done;
+        on_frame(emu);
+        if result != Outcome::Completed {
+            outcome = result;
+            break;
+        }
+        if expect.is_some_and(|text| emu.bus.serial.output.contains(text)) {
+            break;
+        }
+    }
+    let passed = match expect {
+        Some(text) => emu.bus.serial.output.contains(text),
+        None => outcome == Outcome::Completed,
+    };
+    Summary {
+        outcome,
+        frames: ran,
+        cycles: emu.bus.cycles - start,
+        serial: emu.bus.serial.output.clone(),
+        frame_hash: frame_hash(&emu.bus.gpu.visible_frame()),
+        passed,
+        emulated: emulated_time(emu.bus.clock - start_clock),
+        elapsed: started.elapsed(),
+    }
+}
+
+pub fn summary_json(summary: &Summary) -> String {
+    format!(
+        "{{\"passed\": {}, \"outcome\": \"{}\", \"detail\": \"{}\", \"frames\": {}, \"cycles\": {}, \"serial\": \"{}\", \"frame_hash\": \"{}\", \"emulated_seconds\": {:.3}, \"speed\": {:.2}}}",
+        summary.passed,
+        summary.outcome.kind(),
+        escape_json(&summary.outcome.to_string()),
+        summary.frames,
+        summary.cycles,
+        escape_json(&summary.serial),
+        hash_str(Some(summary.frame_hash)),
+        summary.emulated.as_secs_f64(),
+        summary.speed()
+    )
+}
+
+// Runs every ROM in `dir` in parallel.
+pub fn run_dir(dir: &Path, frames: usize) -> MaybeErr<Vec<RomReport>> {
+    run_dir_with(dir, frames, |_| {})
+}
+
+// run_dir, calling `on_report` from the worker threads as each ROM finishes.
+pub fn run_dir_with(
+    dir: &Path,
+    frames: usize,
+    on_report: impl Fn(&RomReport) + Sync,
+) -> MaybeErr<Vec<RomReport>> {
+    let roms = find_roms(dir)?;
+    // Hangs and crashes are reported, not printed by the default panic hook from every thread.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let reports = roms
+        .par_iter()
+        .map(|rom| {
+            let report = run_rom(rom, frames);
+            on_report(&report);
+            report
+        })
+        .collect();
+    panic::set_hook(hook);
+    Ok(reports)
+}
+
+// Binary PPM of the visible frame.
+pub fn ppm(frame: &[u32]) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT).into_bytes();
+    for pixel in frame {
+        data.extend_from_slice(&pixel.to_be_bytes()[..3]);
+    }
+    data
+}
+
+// PNG of the visible frame.
+pub fn png(frame: &[u32]) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(frame.len() * 3);
+    for pixel in frame {
+        rgb.extend_from_slice(&pixel.to_be_bytes()[..3]);
+    }
+    printer::png_rgb(SCREEN_WIDTH, SCREEN_HEIGHT, &rgb)
+}
+
+pub fn write_screenshot(path: &Path, frame: &[u32]) -> MaybeErr<()> {
+    fs::write(path, ppm(frame))?;
+    Ok(())
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn hash_str(hash: Option<u64>) -> String {
+    hash.map(|h| format!("{:016x}", h)).unwrap_or_default()
+}
+
+pub fn json_report(reports: &[RomReport]) -> String {
+    let entries: Vec<String> = reports
+        .iter()
+        .map(|r| {
+            format!(
+                "  {{\"path\": \"{}\", \"title\": \"{}\", \"outcome\": \"{}\", \"detail\": \"{}\", \"frames\": {}, \"frame_hash\": \"{}\"}}",
+                escape_json(&r.path.to_string_lossy()),
+                escape_json(&r.title),
+                r.outcome.kind(),
+                escape_json(&r.outcome.to_string()),
+                r.frames,
+                hash_str(r.frame_hash)
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+pub fn html_report(reports: &[RomReport]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>.rsboy compatibility</title></head><body>\n<table>\n<tr><th>ROM</th><th>Title</th><th>Outcome</th><th>Frames</th><th>Frame hash</th></tr>\n",
+    );
+    for r in reports {
+        html.push_str(&format!(
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+            r.outcome.kind(),
+            escape_html(&r.path.to_string_lossy()),
+            escape_html(&r.title),
+            escape_html(&r.outcome.to_string()),
+            r.frames,
+            hash_str(r.frame_hash)
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    #[test]
+    fn detects_illegal_opcode() {
+        // NOP; NOP; 0xD3
+        let mut emu = Emu::new(rom(&[0x00, 0x00, 0xD3]), None);
+        let (outcome, _) = run(&mut emu, 1);
+        assert_eq!(
+            outcome,
+            Outcome::IllegalOpcode {
+                addr: 0x102,
+                opcode: 0xD3
+            }
+        );
+    }
+
+    #[test]
+    fn detects_hang() {
+        // JR -2
+        let mut emu = Emu::new(rom(&[0x18, 0xFE]), None);
+        emu.watchdog = Some(Watchdog::new(CYCLES_PER_FRAME, DEFAULT_LOOP_WINDOW));
+        let (outcome, frames) = run(&mut emu, 10);
+        assert_eq!(outcome.kind(), "hang");
+        assert!(frames < 10);
+    }
+
+    #[test]
+    fn headless_stops_on_expected_serial() {
+        // LD A,'P'; LDH (SB),A; LD A,81; LDH (SC),A; JR -2
+        let program = [0x3E, b'P', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+        let mut emu = Emu::new(rom(&program), None);
+        let summary = run_headless(&mut emu, 600, Some("P"));
+        assert!(summary.passed);
+        assert_eq!(summary.frames, 1);
+        assert!(summary_json(&summary).starts_with("{\"passed\": true, \"outcome\": \"completed\""));
+
+        let mut emu = Emu::new(rom(&program), None);
+        emu.watchdog = Some(Watchdog::new(CYCLES_PER_FRAME, DEFAULT_LOOP_WINDOW));
+        let summary = run_headless(&mut emu, 600, Some("Passed"));
+        assert!(!summary.passed);
+        assert_eq!(summary.outcome.kind(), "hang");
+        assert_eq!(summary.serial, "P");
+    }
+
+    #[test]
+    fn progress_reports_once_per_interval() {
+        let start = Instant::now();
+        let mut progress = Progress::new(Duration::from_secs(1), 240, 0, start);
+        let half = start + Duration::from_millis(500);
+        assert_eq!(progress.update(CYCLES_PER_FRAME * 30, 30, half), None);
+        let second = start + Duration::from_secs(1);
+        let line = progress.update(GB_CYCLE_SPEED * 2, 120, second).unwrap();
+        assert_eq!(
+            line,
+            "120/240 frames (50%), 2.0s emulated, 8388608 cycles/s (2.00x)"
+        );
+        assert_eq!(progress.update(GB_CYCLE_SPEED * 2, 120, second), None);
+        assert!((speed(Duration::from_secs(3), Duration::from_secs(2)) - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hash_depends_on_pixels() {
+        let a = vec![0u32; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let mut b = a.clone();
+        b[100] = 0xFFFFFFFF;
+        assert_eq!(frame_hash(&a), frame_hash(&a.clone()));
+        assert_ne!(frame_hash(&a), frame_hash(&b));
+    }
+
+    #[test]
+    fn reports_escape() {
+        let report = RomReport {
+            path: PathBuf::from("roms/a\"b.gb"),
+            title: "<TEST>".to_string(),
+            outcome: Outcome::Completed,
+            frames: 60,
+            frame_hash: Some(0xabc),
+            frame: vec![],
+            stats: Stats::default(),
+        };
+        let json = json_report(std::slice::from_ref(&report));
+        assert!(json.contains("roms/a\\\"b.gb"));
+        assert!(json.contains("\"frame_hash\": \"0000000000000abc\""));
+        let html = html_report(&[report]);
+        assert!(html.contains("&lt;TEST&gt;"));
+    }
+}
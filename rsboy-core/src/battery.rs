@@ -0,0 +1,176 @@
+use crate::constants::MaybeErr;
+use crate::rtc;
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Cartridge RAM window, backed by Bus::memory while there are no mappers.
+pub const SRAM_START: usize = 0xA000;
+pub const SRAM_END: usize = 0xBFFF;
+
+// Saves are written at least this often while the game runs, even if nothing was noticed as
+// dirty, so a killed process loses at most this much.
+pub const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Writes to a temporary file first so a crash mid-write never leaves a torn save behind.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("sav.tmp");
+    fs::write(&tmp, data)?;
+    File::open(&tmp)?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+// Persists battery backed RAM from a background thread.
+pub struct BatterySaver {
+    path: PathBuf,
+    interval: Duration,
+    last_save: Instant,
+    sender: Option<Sender<Vec<u8>>>,
+    thread: Option<JoinHandle<()>>,
+    // Appended to every save, the MBC3 clock.
+    pub footer: Option<Vec<u8>>,
+}
+
+impl BatterySaver {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let thread_path = path.clone();
+        let thread = thread::spawn(move || {
+            while let Ok(mut data) = receiver.recv() {
+                // Only the newest copy matters if the writer fell behind.
+                if let Some(newest) = receiver.try_iter().last() {
+                    data = newest;
+                }
+                if let Err(e) = write_atomic(&thread_path, &data) {
+                    warn!("Couldn't write battery save {:?}: {}", thread_path, e);
+                }
+            }
+        });
+        Self {
+            path,
+            interval,
+            last_save: Instant::now(),
+            sender: Some(sender),
+            thread: Some(thread),
+            footer: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Copies an existing save into `ram`, a missing file leaves it untouched.
+    // Returns the clock footer if the save has one.
+    pub fn load(&self, ram: &mut [u8]) -> MaybeErr<Option<Vec<u8>>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let (data, footer) = rtc::split_footer(&data);
+        let len = data.len().min(ram.len());
+        ram[..len].copy_from_slice(&data[..len]);
+        info!("Loaded battery save {:?}", self.path);
+        Ok(footer.map(<[u8]>::to_vec))
+    }
+
+    // Called once per frame, queues a save when RAM was written or the interval elapsed.
+    pub fn tick(&mut self, ram: &[u8], dirty: bool) -> bool {
+        if !dirty && self.last_save.elapsed() < self.interval {
+            return false;
+        }
+        self.save(ram);
+        true
+    }
+
+    fn save(&mut self, ram: &[u8]) {
+        self.last_save = Instant::now();
+        if let Some(sender) = &self.sender {
+            // The thread only stops after flush, so a send error can't happen before it.
+            let mut data = ram.to_vec();
+            data.extend(self.footer.iter().flatten());
+            let _ = sender.send(data);
+        }
+    }
+
+    // Writes `ram` and waits for every pending save to reach the disk.
+    pub fn flush(&mut self, ram: &[u8]) {
+        self.save(ram);
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Battery save thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rsboy-battery-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn saves_when_dirty_and_on_flush() {
+        let path = temp_path("dirty.sav");
+        let mut saver = BatterySaver::new(path.clone(), Duration::from_secs(3600));
+        assert!(!saver.tick(&[1, 2, 3], false));
+        assert!(saver.tick(&[1, 2, 3], true));
+        saver.flush(&[4, 5, 6]);
+        assert_eq!(fs::read(&path).unwrap(), vec![4, 5, 6]);
+        assert!(!path.with_extension("sav.tmp").exists());
+
+        let mut ram = [0; 4];
+        BatterySaver::new(path.clone(), DEFAULT_SAVE_INTERVAL)
+            .load(&mut ram)
+            .unwrap();
+        assert_eq!(ram, [4, 5, 6, 0]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saves_after_interval() {
+        let path = temp_path("interval.sav");
+        let mut saver = BatterySaver::new(path.clone(), Duration::from_millis(0));
+        assert!(saver.tick(&[7], false));
+        saver.flush(&[8]);
+        assert_eq!(fs::read(&path).unwrap(), vec![8]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn appends_and_splits_the_clock_footer() {
+        let path = temp_path("rtc.sav");
+        let mut saver = BatterySaver::new(path.clone(), DEFAULT_SAVE_INTERVAL);
+        let footer = rtc::Rtc::default().footer(1234);
+        saver.footer = Some(footer.clone());
+        saver.flush(&[3; 0x2000]);
+        assert_eq!(fs::read(&path).unwrap().len(), 0x2000 + rtc::FOOTER_LEN);
+
+        let mut ram = [0; 0x2000];
+        let loaded = BatterySaver::new(path.clone(), DEFAULT_SAVE_INTERVAL)
+            .load(&mut ram)
+            .unwrap();
+        assert_eq!(loaded, Some(footer));
+        assert_eq!(ram, [3; 0x2000]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_save_is_not_an_error() {
+        let saver = BatterySaver::new(temp_path("missing.sav"), DEFAULT_SAVE_INTERVAL);
+        let mut ram = [9; 2];
+        saver.load(&mut ram).unwrap();
+        assert_eq!(ram, [9, 9]);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::bus::Memory;
+use crate::constants::MaybeErr;
+use crate::cpu::CPUState;
+use crate::emu::Emu;
+use crate::gpu::{OAM_END, OAM_START, VRAM_START};
+use crate::import;
+use crate::model::Model;
+use crate::registers::RegisterState;
+use crate::savestate::{StateReader, StateWriter};
+
+// Best Effort Save State, the footer SameBoy and other emulators understand, see
+// https://github.com/LIJI32/SameBoy/blob/master/BESS.md
+// A file ends with the offset of the first block and "BESS". Blocks are a 4 byte id, a u32
+// length and the payload, up to an "END " block. CORE points at memory areas elsewhere in the
+// file by offset and size. Appended to a native state, the areas and blocks go in a BESS chunk
+// the native loader skips, so the file loads in both.
+pub const FOOTER_MAGIC: &[u8; 4] = b"BESS";
+pub const FOOTER_LEN: usize = 8;
+// Native chunk holding the memory areas and blocks.
+pub const CHUNK_TAG: [u8; 4] = *b"BESS";
+
+const MAJOR: u16 = 1;
+const MINOR: u16 = 1;
+const IO_LEN: usize = 0x80;
+const HRAM_START: usize = 0xFF80;
+const HRAM_LEN: usize = 0x7F;
+const WRAM_START: usize = 0xC000;
+const RAM_LEN: usize = 0x2000;
+const SRAM_START: usize = 0xA000;
+const OAM_LEN: usize = OAM_END - OAM_START + 1;
+// The unusable OAM area at FEA0-FEFF.
+const XOAM_LEN: usize = 0x60;
+
+type Block<'a> = ([u8; 4], &'a [u8]);
+
+// The blocks of a BESS file, Err if it doesn't end in a valid footer.
+fn blocks(data: &[u8]) -> MaybeErr<Vec<Block<'_>>> {
+    let footer = data
+        .len()
+        .checked_sub(FOOTER_LEN)
+        .map(|start| &data[start..])
+        .filter(|footer| footer.ends_with(FOOTER_MAGIC))
+        .ok_or("No BESS footer")?;
+    let mut r = StateReader::new(footer);
+    let start = r.u32()? as usize;
+    let mut r = StateReader::new(
+        data.get(start..data.len() - FOOTER_LEN)
+            .ok_or("Bad BESS offset")?,
+    );
+    let mut blocks = vec![];
+    loop {
+        let mut id = [0; 4];
+        r.fill(&mut id)?;
+        let payload = r.blob()?;
+        if &id == b"END " {
+            return Ok(blocks);
+        }
+        blocks.push((id, payload));
+    }
+}
+
+pub fn is_bess(data: &[u8]) -> bool {
+    blocks(data).is_ok()
+}
+
+// `data` without a BESS footer, for the native loader.
+pub fn strip_footer(data: &[u8]) -> &[u8] {
+    if is_bess(data) {
+        &data[..data.len() - FOOTER_LEN]
+    } else {
+        data
+    }
+}
+
+fn model_id(model: Model) -> &'static [u8; 4] {
+    match model {
+        Model::Dmg => b"GD  ",
+        Model::Mgb => b"GM  ",
+        Model::Cgb => b"CC  ",
+    }
+}
+
+fn block(w: &mut StateWriter, id: &[u8; 4], payload: &[u8]) {
+    w.bytes(id);
+    w.blob(payload);
+}
+
+// Appends a BESS chunk and footer to the native state in `state`.
+pub fn append(emu: &Emu, state: &mut Vec<u8>) {
+    let bus = &emu.bus;
+    // Offsets in CORE are from the start of the file, the payload starts after the chunk header.
+    let base = state.len() + 8;
+    let mut data = StateWriter::default();
+    let mut area = |bytes: &[u8]| {
+        let offset = base + data.buf.len();
+        data.bytes(bytes);
+        (bytes.len() as u32, offset as u32)
+    };
+    let sram = match &emu.header {
+        Some(header) if header.ram_size != 0 && bus.camera.is_none() => bus.battery_ram(),
+        _ => &[][..],
+    };
+    let areas = [
+        area(&bus.memory[WRAM_START..WRAM_START + RAM_LEN]),
+        area(&bus.gpu.vram),
+        area(sram),
+        area(&bus.gpu.oam[..OAM_LEN]),
+        area(&bus.memory[HRAM_START..HRAM_START + HRAM_LEN]),
+        // No CGB palettes.
+        (0, 0),
+        (0, 0),
+    ];
+
+    let mut core = StateWriter::default();
+    core.u16(MAJOR);
+    core.u16(MINOR);
+    core.bytes(model_id(bus.model));
+    let r = &emu.cpu.registers;
+    // A halted or stopped CPU has moved past the opcode, otherwise the next one is already fetched.
+    let pc = match emu.cpu.state {
+        CPUState::Halted | CPUState::Stopped => r.pc,
+        _ => emu.cpu.op_addr,
+    };
+    for &v in &[pc, r.af(), r.bc(), r.de(), r.hl(), r.sp] {
+        core.u16(v);
+    }
+    core.u8(bus.ime | bus.ei_pending as u8);
+    core.u8(bus.int_enabled);
+    core.u8(match emu.cpu.state {
+        CPUState::Halted => 1,
+        CPUState::Stopped => 2,
+        _ => 0,
+    });
+    core.u8(0);
+    for address in 0xFF00..0xFF00 + IO_LEN as u16 {
+        core.u8(bus.debug_read(address));
+    }
+    for &(size, offset) in &areas {
+        core.u32(size);
+        core.u32(offset);
+    }
+
+    let blocks_start = base + data.buf.len();
+    block(&mut data, b"NAME", b"rsboy");
+    let mut info = bus.memory[0x134..0x144].to_vec();
+    info.extend_from_slice(&bus.memory[0x14E..0x150]);
+    block(&mut data, b"INFO", &info);
+    block(&mut data, b"CORE", &core.buf);
+    block(
+        &mut data,
+        b"XOAM",
+        &bus.gpu.oam[OAM_LEN..OAM_LEN + XOAM_LEN],
+    );
+    // The last value of every mapper register, replayed on load.
+    if bus.banks.history().next().is_some() {
+        let mut mbc = StateWriter::default();
+        for (i, &value) in bus.banks.registers.iter().enumerate() {
+            mbc.u16(i as u16 * 0x2000);
+            mbc.u8(value);
+        }
+        block(&mut data, b"MBC ", &mbc.buf);
+    }
+    block(&mut data, b"END ", &[]);
+
+    let mut w = StateWriter::default();
+    w.bytes(&CHUNK_TAG);
+    w.blob(&data.buf);
+    w.u32(blocks_start as u32);
+    w.bytes(FOOTER_MAGIC);
+    state.extend_from_slice(&w.buf);
+}
+
+// Loads the state described by the BESS blocks, on top of the ROM already loaded.
+pub fn load(emu: &mut Emu, data: &[u8]) -> MaybeErr<()> {
+    let blocks = blocks(data)?;
+    let core = match blocks.iter().find(|(id, _)| id == b"CORE") {
+        Some((_, core)) => core,
+        None => return Err("BESS state has no CORE block".into()),
+    };
+    let mut r = StateReader::new(core);
+    let major = r.u16()?;
+    if major != MAJOR {
+        return Err(format!("BESS version {} is not supported", major).into());
+    }
+    r.u16()?;
+    let model = match r.bytes(4)? {
+        [b'C', ..] => Model::Cgb,
+        [b'G', b'M', ..] => Model::Mgb,
+        _ => Model::Dmg,
+    };
+    let [pc, af, bc, de, hl, sp] = [r.u16()?, r.u16()?, r.u16()?, r.u16()?, r.u16()?, r.u16()?];
+    let ime = r.u8()?;
+    let ie = r.u8()?;
+    let execution = r.u8()?;
+    r.u8()?;
+
+    // Everything goes into one address space dump for import::import.
+    let mut dump = vec![0; import::DUMP_SIZE];
+    dump[0xFF00..0xFF00 + IO_LEN].copy_from_slice(r.bytes(IO_LEN)?);
+    dump[0xFFFF] = ie;
+    let mut sram = &[][..];
+    for &(start, len) in &[
+        (Some(WRAM_START), RAM_LEN),
+        (Some(VRAM_START), RAM_LEN),
+        (Some(SRAM_START), RAM_LEN),
+        (Some(OAM_START), OAM_LEN),
+        (Some(HRAM_START), HRAM_LEN),
+        (None, 0),
+        (None, 0),
+    ] {
+        let size = r.u32()? as usize;
+        let offset = r.u32()? as usize;
+        let area = data
+            .get(offset..offset + size)
+            .ok_or("BESS memory area is out of bounds")?;
+        // Cartridge RAM can have more banks than fit the dump, it's copied after the import.
+        if start == Some(SRAM_START) {
+            sram = area;
+        } else if let Some(start) = start {
+            // CGB banks past the first ones are dropped.
+            let len = len.min(size);
+            dump[start..start + len].copy_from_slice(&area[..len]);
+        }
+    }
+    let xoam = blocks
+        .iter()
+        .find(|(id, _)| id == b"XOAM")
+        .map(|(_, oam)| *oam);
+
+    let registers = RegisterState {
+        a: (af >> 8) as u8,
+        f: af as u8 & 0xF0,
+        b: (bc >> 8) as u8,
+        c: bc as u8,
+        d: (de >> 8) as u8,
+        e: de as u8,
+        h: (hl >> 8) as u8,
+        l: hl as u8,
+        sp,
+        pc,
+    };
+    emu.bus.set_model(model);
+    import::import(emu, registers, &dump)?;
+    let ram = emu.bus.battery_ram_mut();
+    let len = ram.len().min(sram.len());
+    ram[..len].copy_from_slice(&sram[..len]);
+    emu.bus.ime = ime;
+    emu.bus.ei_pending = false;
+    if let Some(oam) = xoam {
+        let len = oam.len().min(XOAM_LEN);
+        emu.bus.gpu.oam[OAM_LEN..OAM_LEN + len].copy_from_slice(&oam[..len]);
+    }
+    if let Some((_, mbc)) = blocks.iter().find(|(id, _)| id == b"MBC ") {
+        let mut r = StateReader::new(mbc);
+        while !r.is_empty() {
+            let address = r.u16()?;
+            let value = r.u8()?;
+            emu.bus.write(address, value);
+        }
+    }
+    // Waits at PC for an interrupt or the joypad, like after executing HALT or STOP.
+    match execution {
+        1 => {
+            emu.cpu.registers.pc = pc;
+            emu.cpu.halt = true;
+            emu.cpu.state = CPUState::Halted;
+        }
+        2 => {
+            emu.cpu.registers.pc = pc;
+            emu.cpu.state = CPUState::Stopped;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::savestate;
+
+    #[test]
+    fn round_trip_through_footer() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        emu.bus.memory[0xC123] = 0x42;
+        emu.bus.memory[0xFF90] = 0x24;
+        emu.bus.gpu.vram[0x10] = 0xAA;
+        emu.bus.gpu.oam[0xA5] = 0x55;
+        emu.bus.gpu.scrollx = 0x12;
+        emu.bus.int_enabled = 0x05;
+        emu.bus.rom_start_signal = false;
+        emu.cpu.registers.sp = 0xDFF0;
+        emu.cpu.registers.b = 0x34;
+        emu.cpu.state = emu.cpu.prefetch_op(&mut emu.bus, 0x0150);
+        emu.bus.ime = 1;
+
+        let mut data = savestate::save(&emu);
+        let native = data.clone();
+        append(&emu, &mut data);
+        assert!(is_bess(&data));
+        assert!(!is_bess(&native));
+        assert_eq!(strip_footer(&data).len(), data.len() - FOOTER_LEN);
+
+        // The native loader skips the BESS chunk.
+        let mut loaded = Emu::new(vec![0; 0x8000], None);
+        savestate::load(&mut loaded, &data).unwrap();
+        assert_eq!(savestate::save(&loaded), native);
+
+        let mut loaded = Emu::new(vec![0; 0x8000], None);
+        load(&mut loaded, &data).unwrap();
+        assert_eq!(loaded.cpu.op_addr, 0x0150);
+        assert_eq!(loaded.cpu.registers.sp, 0xDFF0);
+        assert_eq!(loaded.cpu.registers.b, 0x34);
+        assert_eq!(loaded.bus.memory[0xC123], 0x42);
+        assert_eq!(loaded.bus.memory[0xFF90], 0x24);
+        assert_eq!(loaded.bus.gpu.vram[0x10], 0xAA);
+        assert_eq!(loaded.bus.gpu.oam[0xA5], 0x55);
+        assert_eq!(loaded.bus.gpu.scrollx, 0x12);
+        assert_eq!(loaded.bus.int_enabled, 0x05);
+        assert_eq!(loaded.bus.ime, 1);
+    }
+
+    #[test]
+    fn foreign_states_load_through_savestate() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        emu.bus.rom_start_signal = false;
+        emu.bus.memory[0xC000] = 0x99;
+        emu.cpu.halt = true;
+        emu.cpu.state = CPUState::Halted;
+        emu.cpu.registers.pc = 0x0200;
+        // Another emulator's native state is opaque to us.
+        let mut data = b"SAMEBOY STATE".to_vec();
+        append(&emu, &mut data);
+        let mut loaded = Emu::new(vec![0; 0x8000], None);
+        savestate::load(&mut loaded, &data).unwrap();
+        assert_eq!(loaded.bus.memory[0xC000], 0x99);
+        assert!(matches!(loaded.cpu.state, CPUState::Halted));
+        assert_eq!(loaded.cpu.registers.pc, 0x0200);
+        assert!(load(&mut loaded, b"BESS").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::cartridge::{LOGO_END, LOGO_START};
+use crate::constants::MaybeErr;
+use crate::png;
+use std::{fs, path::Path};
+
+// Custom boot logos for homebrew, see https://gbdev.io/pandocs/The_Cartridge_Header.html#0104-0133--nintendo-logo
+// The DMG bootrom scrolls down the logo in the cartridge header, then compares it with its own
+// copy at A8 and locks up on any difference. Previewing another logo takes a cartridge carrying
+// it and a bootrom with its copy patched to match, which is done on the copy in Bus::bootrom.
+
+pub const WIDTH: usize = 48;
+pub const HEIGHT: usize = 8;
+pub const LOGO_LEN: usize = 48;
+// Where the DMG bootrom keeps the logo it compares against.
+pub const BOOTROM_LOGO: usize = 0xA8;
+
+pub const NINTENDO: [u8; LOGO_LEN] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// The 48x8 one bit logo as stored in the header: the top four rows, then the bottom four. Each
+// byte is a 4 pixel wide column of two rows, high nibble first, and two bytes make a 4x4 block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BootLogo(pub [u8; LOGO_LEN]);
+
+impl BootLogo {
+    pub fn from_bytes(bytes: &[u8]) -> MaybeErr<Self> {
+        let mut logo = [0; LOGO_LEN];
+        if bytes.len() != LOGO_LEN {
+            return Err(
+                format!("Boot logo is {} bytes, expected {}", bytes.len(), LOGO_LEN).into(),
+            );
+        }
+        logo.copy_from_slice(bytes);
+        Ok(Self(logo))
+    }
+
+    // Byte and bit of pixel `x`, `y`.
+    fn position(x: usize, y: usize) -> (usize, u8) {
+        let byte = (y / 4) * 24 + (x / 4) * 2 + (y % 4) / 2;
+        let bit = 7 - (y % 2) * 4 - x % 4;
+        (byte, bit as u8)
+    }
+
+    // Whether pixel `x`, `y` is set, dark on screen.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (byte, bit) = Self::position(x, y);
+        self.0[byte] & (1 << bit) != 0
+    }
+
+    // `pixels` is WIDTH x HEIGHT, row by row.
+    pub fn from_pixels(pixels: &[bool]) -> Self {
+        let mut logo = [0; LOGO_LEN];
+        for (i, _) in pixels.iter().enumerate().filter(|(_, &set)| set) {
+            let (byte, bit) = Self::position(i % WIDTH, i / WIDTH);
+            logo[byte] |= 1 << bit;
+        }
+        Self(logo)
+    }
+
+    // A 48x8 PNG, dark opaque pixels are set.
+    pub fn from_png(data: &[u8]) -> MaybeErr<Self> {
+        let (width, height, luma) = png::decode(data)?;
+        if (width, height) != (WIDTH, HEIGHT) {
+            return Err(format!(
+                "Boot logo is {}x{}, expected {}x{}",
+                width, height, WIDTH, HEIGHT
+            )
+            .into());
+        }
+        let pixels: Vec<bool> = luma.iter().map(|&l| l < 0x80).collect();
+        Ok(Self::from_pixels(&pixels))
+    }
+
+    // A PNG, or the 48 bytes as they go in the header.
+    pub fn load(path: &Path) -> MaybeErr<Self> {
+        let data = fs::read(path)?;
+        if data.starts_with(png::SIGNATURE) {
+            Self::from_png(&data)
+        } else {
+            Self::from_bytes(&data)
+        }
+    }
+
+    // `rom` carries this logo, so the patched bootrom goes on to run it.
+    pub fn check_cartridge(&self, rom: &[u8]) -> MaybeErr<()> {
+        match rom.get(LOGO_START..=LOGO_END) {
+            Some(logo) if logo == self.0 => Ok(()),
+            Some(_) => Err("Cartridge header holds another logo, the bootrom would lock up".into()),
+            None => Err("ROM is too small to have a logo".into()),
+        }
+    }
+
+    // Patches the logo into a DMG bootrom, failing unless `rom` carries the same one.
+    pub fn patch_bootrom(&self, bootrom: &mut [u8], rom: &[u8]) -> MaybeErr<()> {
+        let copy = bootrom
+            .get_mut(BOOTROM_LOGO..BOOTROM_LOGO + LOGO_LEN)
+            .ok_or("Bootrom is too small to be a DMG bootrom")?;
+        if *copy != NINTENDO {
+            return Err("No logo at A8, not a DMG bootrom".into());
+        }
+        self.check_cartridge(rom)?;
+        copy.copy_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::printer;
+
+    #[test]
+    fn pixels_round_trip_through_the_header_layout() {
+        let logo = BootLogo(NINTENDO);
+        // The N's left edge, then its diagonal.
+        assert!(logo.pixel(0, 0) && logo.pixel(1, 0) && !logo.pixel(2, 0));
+        assert!(logo.pixel(2, 1) && !logo.pixel(3, 1));
+        let pixels: Vec<bool> = (0..WIDTH * HEIGHT)
+            .map(|i| logo.pixel(i % WIDTH, i / WIDTH))
+            .collect();
+        assert_eq!(BootLogo::from_pixels(&pixels), logo);
+
+        let gray: Vec<u8> = pixels
+            .iter()
+            .map(|&set| if set { 0 } else { 0xFF })
+            .collect();
+        let png = printer::png(WIDTH, HEIGHT, &gray);
+        assert_eq!(BootLogo::from_png(&png).unwrap(), logo);
+        assert!(BootLogo::from_png(&printer::png(8, 8, &[0; 64])).is_err());
+        assert!(BootLogo::from_bytes(&NINTENDO[1..]).is_err());
+    }
+
+    #[test]
+    fn patches_only_with_a_matching_cartridge() {
+        let mut custom = NINTENDO;
+        custom[0] = 0xFF;
+        let logo = BootLogo(custom);
+        let mut bootrom = [0; 0x100];
+        let mut rom = vec![0; 0x8000];
+        rom[LOGO_START..=LOGO_END].copy_from_slice(&NINTENDO);
+        assert!(logo.patch_bootrom(&mut bootrom, &rom).is_err());
+
+        bootrom[BOOTROM_LOGO..BOOTROM_LOGO + LOGO_LEN].copy_from_slice(&NINTENDO);
+        assert!(logo.patch_bootrom(&mut bootrom, &rom).is_err());
+        assert_eq!(bootrom[BOOTROM_LOGO], NINTENDO[0]);
+
+        rom[LOGO_START..=LOGO_END].copy_from_slice(&custom);
+        logo.patch_bootrom(&mut bootrom, &rom).unwrap();
+        assert_eq!(bootrom[BOOTROM_LOGO..BOOTROM_LOGO + LOGO_LEN], custom);
+    }
+}
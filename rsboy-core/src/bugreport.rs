@@ -0,0 +1,249 @@
+use crate::batch;
+use crate::clock::Cycles;
+use crate::emu::Emu;
+use crate::registers::{FlagChange, RegisterState};
+use crate::savestate;
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+// Instructions and IO writes kept for a bug report.
+pub const TRACE_LEN: usize = 10_000;
+pub const IO_LOG_LEN: usize = 10_000;
+
+// Registers right before an instruction ran, and the flags it left behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub clock: Cycles,
+    pub registers: RegisterState,
+    // Set once the instruction has run.
+    pub flags: Option<FlagChange>,
+}
+
+impl TraceEntry {
+    // The flag transition if the instruction changed any.
+    pub fn flag_change(&self) -> Option<FlagChange> {
+        self.flags.filter(FlagChange::changed)
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>10} {}", self.clock, self.registers)?;
+        match self.flag_change() {
+            Some(change) => write!(f, " {}", change),
+            None => Ok(()),
+        }
+    }
+}
+
+// Write to FF00-FFFF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoWrite {
+    pub clock: Cycles,
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl Display for IoWrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>10} PC:{:04x} [{:04x}] <- {:02x}",
+            self.clock, self.pc, self.address, self.value
+        )
+    }
+}
+
+// Recent history for bug reports, only collected when Bus::report_log is set.
+#[derive(Debug, Default)]
+pub struct ReportLog {
+    pub trace: VecDeque<TraceEntry>,
+    pub io: VecDeque<IoWrite>,
+}
+
+impl ReportLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn instr(&mut self, clock: Cycles, registers: RegisterState) {
+        if self.trace.len() == TRACE_LEN {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(TraceEntry {
+            clock,
+            registers,
+            flags: None,
+        });
+    }
+
+    // Completes the last instruction with F after it ran.
+    pub fn flags_after(&mut self, after: u8) {
+        if let Some(entry) = self.trace.back_mut() {
+            let before = entry.registers.f;
+            entry.flags = Some(FlagChange { before, after });
+        }
+    }
+
+    // Up to `count` of the latest instructions that changed flags, newest first.
+    pub fn flag_changes(&self, count: usize) -> impl Iterator<Item = &TraceEntry> {
+        self.trace
+            .iter()
+            .rev()
+            .filter(|entry| entry.flag_change().is_some())
+            .take(count)
+    }
+
+    pub fn io_write(&mut self, write: IoWrite) {
+        if self.io.len() == IO_LOG_LEN {
+            self.io.pop_front();
+        }
+        self.io.push_back(write);
+    }
+}
+
+fn lines<T: Display>(items: impl Iterator<Item = T>) -> Vec<u8> {
+    items
+        .map(|item| format!("{}\n", item))
+        .collect::<String>()
+        .into_bytes()
+}
+
+// Zip archive of everything needed to reproduce a problem:
+//   state.rsby    savestate, loadable with F1..F10 after copying it next to the ROM as .ss1..10
+//   trace.txt     last TRACE_LEN instructions
+//   io.txt        last IO_LOG_LEN IO register writes
+//   config.txt    `config` from the frontend, plus the cartridge, overrides and key IO registers
+//   screen.ppm    the last completed frame
+pub fn bundle(emu: &Emu, config: &str) -> Vec<u8> {
+    let mut config = config.to_string();
+    match &emu.header {
+        Some(header) => config += &format!("\ncartridge: {}", header),
+        None => config += "\ncartridge: no header",
+    }
+    config += &format!("\noverrides: {:?}\n", emu.overrides);
+    config += &format!("{}\n", emu.bus.io_map());
+    let (trace, io) = match &emu.bus.report_log {
+        Some(log) => (lines(log.trace.iter()), lines(log.io.iter())),
+        None => (b"Not recorded\n".to_vec(), b"Not recorded\n".to_vec()),
+    };
+    zip(&[
+        ("state.rsby", savestate::save(emu)),
+        ("trace.txt", trace),
+        ("io.txt", io),
+        ("config.txt", config.into_bytes()),
+        ("screen.ppm", batch::ppm(&emu.bus.gpu.visible_frame())),
+    ])
+}
+
+// CRC-32 as used by zip, bit by bit since reports are rare and small.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// DOS date of 1980-01-01, the earliest a zip can hold.
+const ZIP_DATE: u16 = 0x0021;
+
+// Uncompressed zip archive, readable by every unzip tool without pulling in a dependency.
+pub fn zip(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut central = vec![];
+    for (name, data) in files {
+        let offset = out.len() as u32;
+        let crc = crc32(data);
+        let mut common = vec![];
+        common.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        common.extend_from_slice(&0u16.to_le_bytes()); // flags
+        common.extend_from_slice(&0u16.to_le_bytes()); // stored
+        common.extend_from_slice(&0u16.to_le_bytes()); // time
+        common.extend_from_slice(&ZIP_DATE.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&common);
+        central.extend_from_slice(&[0; 10]); // comment, disk, attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Memory;
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn bundle_lists_every_file() {
+        let mut emu = Emu::new(vec![], None);
+        emu.bus.report_log = Some(ReportLog::new());
+        emu.bus.write(0xFF42, 0x12);
+        let data = bundle(&emu, "args: test");
+        let eocd = &data[data.len() - 22..];
+        assert_eq!(&eocd[..4], &0x0605_4b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 5);
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("[ff42] <- 12"));
+        assert!(text.contains("args: test"));
+        assert!(text.contains("IE: -, IF: -"));
+    }
+
+    #[test]
+    fn trace_records_flag_transitions() {
+        // XOR A, then CP 1 borrows.
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0xAF, 0xFE, 0x01, 0x00]);
+        let mut emu = Emu::new(rom, None);
+        // Straight into the ROM, the start values would reset the registers below.
+        emu.bus.rom_start_signal = false;
+        emu.cpu.registers.pc = 0x101;
+        emu.cpu.registers.f = 0;
+        emu.cpu.op_addr = 0x100;
+        emu.cpu.opcode = 0xAF;
+        emu.bus.report_log = Some(ReportLog::new());
+        emu.emulate_step();
+        emu.emulate_step();
+        let log = emu.bus.report_log.as_ref().unwrap();
+        let changes: Vec<String> = log.trace.iter().map(|entry| entry.to_string()).collect();
+        assert!(changes[0].ends_with(" Z:0>1"), "{}", changes[0]);
+        assert!(
+            changes[1].ends_with(" Z:1>0 N:0>1 H:0>1 C:0>1"),
+            "{}",
+            changes[1]
+        );
+        assert_eq!(log.flag_changes(1).next().unwrap().registers.pc, 0x101);
+    }
+}
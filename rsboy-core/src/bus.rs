@@ -0,0 +1,1143 @@
+use crate::apu::{self, ApuRegs};
+use crate::banks::Banks;
+use crate::battery;
+use crate::bugreport::{IoWrite, ReportLog};
+use crate::camera::Camera;
+use crate::cartridge::Mapper;
+use crate::clock::Cycles;
+use crate::console::{self, Console, Source};
+use crate::constants::MaybeErr;
+use crate::cpu::InterruptEvent;
+use crate::decode::{DecodeCache, Decoded};
+use crate::disasm::DirtyPages;
+use crate::dma::{self, OamDma};
+use crate::gpu::OAM_END;
+use crate::gpu::OAM_START;
+use crate::gpu::VRAM_END;
+use crate::gpu::VRAM_START;
+use crate::gpu::{GpuMode, DOTS_PER_LINE, GPU};
+use crate::hdma::{self, Hdma};
+use crate::io::{self, IoContext, IoDevice, IO_END, IO_START};
+use crate::joypad::{Joypad, JOYP};
+use crate::mbc1::Mbc1;
+use crate::mbc3::Mbc3;
+use crate::mbc5::Mbc5;
+use crate::meminit::{self, MemFill};
+use crate::model::Model;
+use crate::movie::JoypadTape;
+use crate::rtc;
+use crate::serial::{self, Serial};
+use crate::speed::{self, Speed};
+use crate::stats::{OpcodeStats, Stats};
+use crate::timer::Timer;
+use crate::trace::{Tracer, DMA_TRACK};
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::{fmt::Display, fs::File};
+
+// Number of interrupt dispatches kept in Bus::interrupt_log.
+pub const INTERRUPT_LOG_LEN: usize = 32;
+
+// Bytes in dump_ram_snapshot: WRAM followed by HRAM.
+pub const RAM_SNAPSHOT_LEN: usize =
+    (meminit::WRAM_END - meminit::WRAM_START + 1) + (meminit::HRAM_END - meminit::HRAM_START + 1);
+
+pub trait Memory {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+}
+
+// IO addresses with nothing behind them on a DMG, APU gaps are handled by ApuRegs.
+pub fn is_unmapped_io(address: u16) -> bool {
+    matches!(
+        address,
+        0xFF03 | 0xFF08..=0xFF0E | 0xFF4C..=0xFF4F | 0xFF51..=0xFF7F
+    )
+}
+
+// IO addresses only a CGB maps, unmapped on a DMG.
+pub fn is_cgb_io(address: u16) -> bool {
+    matches!(address as usize, speed::KEY1 | hdma::HDMA1..=hdma::HDMA5)
+}
+
+// Global emu struct.
+pub struct Bus {
+    pub memory: [u8; 0x10000],
+    pub bootrom: [u8; 0x100],
+    pub in_bios: u8,
+    pub int_enabled: u8,
+    pub int_flags: u8,
+    // Normal speed machine cycles, the rate the PPU runs at.
+    pub clock: Cycles,
+    // CPU cycles, equal to clock unless the CGB double speed mode was used.
+    pub cycles: Cycles,
+    pub ime: u8,
+    // Set by EI until the next instruction is fetched.
+    pub ei_pending: bool,
+    pub joypad: Joypad,
+    pub gpu: GPU,
+    pub rom_start_signal: bool,
+    pub timer: Timer,
+    // Bumped on every VRAM/OAM/serial/joypad write, used for hang detection.
+    pub activity: usize,
+    pub console: Console,
+    // When set, writes to console::DEBUG_PORT are captured as debug output.
+    pub debug_port: bool,
+    pub tracer: Option<Tracer>,
+    pub interrupt_log: VecDeque<InterruptEvent>,
+    pub apu: ApuRegs,
+    // Unmapped IO reads 0xFF and ignores writes instead of acting as RAM.
+    pub strict_io: bool,
+    // Log the first access to each unmapped IO address.
+    pub log_unmapped_io: bool,
+    unmapped_logged: RefCell<BTreeSet<u16>>,
+    // Address of the instruction being executed, for diagnostics.
+    pub pc: u16,
+    // Number of reads of the joypad register, for input latency measurement.
+    pub joypad_reads: Cell<usize>,
+    // Records or replays the values JOYP reads return, set by Input for movies.
+    pub joypad_tape: RefCell<Option<JoypadTape>>,
+    pub serial: Serial,
+    pub dma: OamDma,
+    pub banks: Banks,
+    // Per-opcode execution counts, only collected when set.
+    pub opcode_stats: Option<OpcodeStats>,
+    // Instructions fetched from ROM are decoded once and then run from here, only used when set.
+    pub decode_cache: Option<DecodeCache>,
+    // Always collected, read them through Emu::stats.
+    pub stats: Stats,
+    // Set on writes to cartridge RAM, cleared once the battery save picked them up.
+    pub sram_dirty: bool,
+    // Pages holding code that may have changed: ROM bank switches, WRAM and HRAM writes.
+    pub code_writes: DirtyPages,
+    // Set through set_model, decides the start values when there is no bootrom.
+    pub model: Model,
+    // CGB only hardware (KEY1, HDMA) is mapped in.
+    pub cgb: bool,
+    pub speed: Speed,
+    pub hdma: Hdma,
+    // Recent instructions and IO writes for bug reports, only collected when set.
+    pub report_log: Option<ReportLog>,
+    // Camera cartridge, which maps its own ROM banks, RAM and registers.
+    pub camera: Option<Camera>,
+    // MBC1 ROM banking, for cartridges larger than the 32 KiB that fit in `memory`.
+    pub mbc1: Option<Mbc1>,
+    // MBC3 cartridges, the real time clock included.
+    pub mbc3: Option<Mbc3>,
+    pub mbc5: Option<Mbc5>,
+}
+
+impl Display for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let io = self.io_map();
+        f.write_fmt(format_args!(
+            r#"CLK: {}, IE: {}, IF: {}
+[TIMER]: {}
+TAC: {}
+[BTNS]: {:08b}
+[ARWS]: {:08b}"#,
+            self.clock,
+            io.int_enabled(),
+            io.int_flags(),
+            self.timer,
+            io.tac(),
+            io.buttons(),
+            io.directions(),
+        ))
+    }
+}
+
+impl Bus {
+    // Power on state without any ROM or bootrom.
+    fn empty() -> Self {
+        Bus {
+            memory: [0; 0x10000],
+            bootrom: [0; 0x100],
+            in_bios: 0,
+            int_enabled: 0,
+            int_flags: 0,
+            clock: 0,
+            cycles: 0,
+            ime: 0,
+            ei_pending: false,
+            joypad: Joypad::new(),
+            gpu: GPU::new(),
+            rom_start_signal: false,
+            timer: Timer::new(),
+            activity: 0,
+            console: Console::new(),
+            debug_port: false,
+            tracer: None,
+            interrupt_log: VecDeque::with_capacity(INTERRUPT_LOG_LEN),
+            apu: ApuRegs::new(),
+            strict_io: false,
+            log_unmapped_io: false,
+            unmapped_logged: RefCell::new(BTreeSet::new()),
+            pc: 0,
+            joypad_reads: Cell::new(0),
+            joypad_tape: RefCell::new(None),
+            serial: Serial::new(),
+            dma: OamDma::new(),
+            banks: Banks::new(),
+            opcode_stats: None,
+            decode_cache: None,
+            stats: Stats::default(),
+            sram_dirty: false,
+            code_writes: DirtyPages::default(),
+            model: Model::Dmg,
+            cgb: false,
+            speed: Speed::new(),
+            hdma: Hdma::new(),
+            report_log: None,
+            camera: None,
+            mbc1: None,
+            mbc3: None,
+            mbc5: None,
+        }
+    }
+
+    pub fn new(rom_vec: Vec<u8>, bootrom_path: Option<PathBuf>) -> Self {
+        let mapper = Mapper::detect(&rom_vec);
+        Self::with_mapper(rom_vec, bootrom_path, mapper)
+    }
+
+    // Like new, with `mapper` wired up whatever the header says.
+    pub fn with_mapper(rom_vec: Vec<u8>, bootrom_path: Option<PathBuf>, mapper: Mapper) -> Self {
+        let mut buffer = Vec::new();
+        let mut bus = Bus::empty();
+        if let Ok(mut file) = File::open(bootrom_path.unwrap_or("dmg_boot.bin".into())) {
+            file.read_to_end(&mut buffer)
+                .expect("Couldn't read the file.");
+            bus.bootrom[..].clone_from_slice(&buffer[..]);
+        } else {
+            bus.in_bios = 1;
+            bus.rom_start_signal = true;
+            info!("No bootrom provided.");
+        }
+        // Only the first two banks fit, larger ROMs need a mapper.
+        let len = rom_vec.len().min(0x8000);
+        bus.memory[..len].clone_from_slice(&rom_vec[..len]);
+        match mapper {
+            Mapper::Camera => bus.camera = Some(Camera::new(rom_vec)),
+            Mapper::Mbc1 => bus.mbc1 = Some(Mbc1::new(rom_vec)),
+            Mapper::Mbc3 => bus.mbc3 = Some(Mbc3::new(rom_vec, false)),
+            Mapper::Mbc3Rtc => bus.mbc3 = Some(Mbc3::new(rom_vec, true)),
+            Mapper::Mbc5 => bus.mbc5 = Some(Mbc5::new(rom_vec)),
+            Mapper::None => {}
+        }
+
+        bus
+    }
+
+    // Back to power on, like pulling the power switch. ROM, bootrom, cartridge RAM, the colors
+    // and the debugging setup (serial device, tracer, stats, IO options) survive.
+    pub fn reset(&mut self) {
+        let mut bus = Bus::empty();
+        bus.memory[..0x8000].copy_from_slice(&self.memory[..0x8000]);
+        bus.memory[battery::SRAM_START..=battery::SRAM_END]
+            .copy_from_slice(&self.memory[battery::SRAM_START..=battery::SRAM_END]);
+        bus.bootrom = self.bootrom;
+        if self.bootrom.iter().all(|&b| b == 0) {
+            bus.in_bios = 1;
+            bus.rom_start_signal = true;
+        }
+        bus.debug_port = self.debug_port;
+        bus.strict_io = self.strict_io;
+        bus.log_unmapped_io = self.log_unmapped_io;
+        bus.model = self.model;
+        bus.cgb = self.cgb;
+        bus.serial.device = self.serial.device.take();
+        bus.tracer = self.tracer.take();
+        bus.opcode_stats = self.opcode_stats.take();
+        bus.decode_cache = self.decode_cache.take();
+        bus.report_log = self.report_log.take();
+        bus.console = std::mem::take(&mut self.console);
+        bus.camera = self.camera.take();
+        bus.mbc1 = self.mbc1.take().map(|mut mbc1| {
+            mbc1.reset();
+            mbc1
+        });
+        let clock = self.clock;
+        bus.mbc3 = self.mbc3.take().map(|mut mbc3| {
+            mbc3.reset(clock);
+            mbc3
+        });
+        bus.joypad_tape = RefCell::new(self.joypad_tape.take());
+        bus.mbc5 = self.mbc5.take().map(|mut mbc5| {
+            mbc5.reset();
+            mbc5
+        });
+        bus.gpu.palette = self.gpu.palette;
+        bus.gpu.color_correction = self.gpu.color_correction;
+        *self = bus;
+    }
+
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.cgb = model.is_cgb();
+    }
+
+    // Fills WRAM, VRAM, OAM and HRAM the way the RAM chips come up, cartridge RAM is left alone.
+    pub fn power_on(&mut self, fill: MemFill) {
+        fill.fill(&mut self.memory[meminit::WRAM_START..=meminit::WRAM_END]);
+        fill.fill(&mut self.memory[meminit::HRAM_START..=meminit::HRAM_END]);
+        fill.fill(&mut self.gpu.vram);
+        fill.fill(&mut self.gpu.oam);
+    }
+
+    // WRAM then HRAM as a flat file, to edit a game's variables in a hex editor and load them
+    // back. Nothing else is in it, unlike a savestate.
+    pub fn dump_ram_snapshot(&self) -> Vec<u8> {
+        let mut data = self.memory[meminit::WRAM_START..=meminit::WRAM_END].to_vec();
+        data.extend_from_slice(&self.memory[meminit::HRAM_START..=meminit::HRAM_END]);
+        data
+    }
+
+    pub fn load_ram_snapshot(&mut self, data: &[u8]) -> MaybeErr<()> {
+        if data.len() != RAM_SNAPSHOT_LEN {
+            return Err(format!(
+                "RAM snapshot is {} bytes, expected {}",
+                data.len(),
+                RAM_SNAPSHOT_LEN
+            )
+            .into());
+        }
+        let (wram, hram) = data.split_at(meminit::WRAM_END - meminit::WRAM_START + 1);
+        self.memory[meminit::WRAM_START..=meminit::WRAM_END].copy_from_slice(wram);
+        self.memory[meminit::HRAM_START..=meminit::HRAM_END].copy_from_slice(hram);
+        self.code_writes
+            .mark_range(meminit::WRAM_START as u16..=meminit::WRAM_END as u16);
+        self.code_writes
+            .mark_range(meminit::HRAM_START as u16..=meminit::HRAM_END as u16);
+        Ok(())
+    }
+
+    pub fn enable_interrupts(&mut self) {
+        self.ime = 1;
+    }
+
+    // EI, IME is only set once the instruction after it is fetched.
+    pub fn schedule_interrupts(&mut self) {
+        self.ei_pending = true;
+    }
+
+    pub fn disable_interrupts(&mut self) {
+        self.ime = 0;
+        self.ei_pending = false;
+    }
+
+    pub fn log_interrupt(&mut self, event: InterruptEvent) {
+        if self.interrupt_log.len() == INTERRUPT_LOG_LEN {
+            self.interrupt_log.pop_front();
+        }
+        self.interrupt_log.push_back(event);
+    }
+
+    pub fn ack_interrupt(&mut self, flag: u8) {
+        self.ime = 0;
+        self.int_flags &= !flag;
+    }
+
+    // Cycle refers to 1 T-cycle
+    pub fn generic_cycle(&mut self) {
+        self.cycles += 1;
+        self.timer.tick_timer_counter(&mut self.int_flags);
+        self.serial.tick(&mut self.int_flags);
+        if !self.speed.dot() {
+            return;
+        }
+        self.clock += 1;
+        self.apu.tick();
+        if let Some(camera) = &mut self.camera {
+            self.sram_dirty |= camera.update(self.clock);
+        }
+        let was_hblank = matches!(self.gpu.mode, GpuMode::HBlank);
+        self.gpu.cycle(&mut self.int_flags);
+        if self.hdma.active && !was_hblank && matches!(self.gpu.mode, GpuMode::HBlank) {
+            self.hdma_block();
+            self.hdma.block_done();
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.ppu_mode(self.gpu.mode.name(), self.clock);
+        }
+    }
+
+    // The oscillator is off in STOP mode so nothing ticks, but the clocks keep counting for the
+    // frontends pacing by them.
+    pub fn stopped_cycle(&mut self) {
+        self.cycles += 1;
+        self.timer.clock += 1;
+        self.clock += 1;
+    }
+
+    // Fast forwards a halted CPU through at most `max` cycles in which generic_cycle would only
+    // count, stopping one cycle short of the next PPU mode change, TIMA increment or serial
+    // completion so that generic_cycle still handles it. Returns the cycles skipped.
+    pub fn skip_idle(&mut self, max: Cycles) -> Cycles {
+        if self.speed.double || self.tracer.is_some() || self.int_enabled & self.int_flags != 0 {
+            return 0;
+        }
+        let next = [
+            self.gpu.dots_to_mode_change(),
+            self.timer.cycles_to_tick(),
+            self.serial.cycles_left(),
+        ];
+        // With nothing scheduled, still return every line so frontends keep their frame pace.
+        let until = next.iter().flatten().fold(DOTS_PER_LINE, |n, &c| n.min(c));
+        // Never more than a line, so it fits a usize for the subsystems.
+        let n = (until as Cycles).min(max).saturating_sub(1) as usize;
+        self.cycles += n as Cycles;
+        self.clock += n as Cycles;
+        self.timer.skip(n);
+        self.gpu.skip(n);
+        self.serial.skip(n);
+        self.apu.skip(n);
+        n as Cycles
+    }
+
+    pub fn read_cycle(&mut self, addr: u16) -> u8 {
+        self.generic_cycle();
+        self.read(addr)
+    }
+
+    // Opcode fetch, the instruction comes from decode_cache if it's set and `addr` is in ROM.
+    pub fn fetch_cycle(&mut self, addr: u16) -> (u8, Option<Decoded>) {
+        self.generic_cycle();
+        match self.decode(addr) {
+            Some(decoded) => (decoded.opcode, Some(decoded)),
+            None => (self.read(addr), None),
+        }
+    }
+
+    fn decode(&mut self, addr: u16) -> Option<Decoded> {
+        let bank = self.rom_bank_at(addr)?;
+        let cache = self.decode_cache.as_mut()?;
+        if let Some(decoded) = cache.get(bank, addr) {
+            cache.hits += 1;
+            return Some(decoded);
+        }
+        cache.misses += 1;
+        let decoded = Decoded::new(addr, |address| self.read(address))?;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.insert(bank, addr, decoded);
+        }
+        Some(decoded)
+    }
+
+    // ROM bank mapped at `address`, None outside cartridge ROM or where the bootrom covers it.
+    pub fn rom_bank_at(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000..=0x0100 if self.in_bios == 0 => None,
+            0x0000..=0x3FFF => Some(self.mbc1.as_ref().map_or(0, Mbc1::rom0_bank)),
+            0x4000..=0x7FFF => Some(
+                self.mbc1
+                    .as_ref()
+                    .map(Mbc1::rom_bank)
+                    .or_else(|| self.mbc3.as_ref().map(Mbc3::rom_bank))
+                    .or_else(|| self.mbc5.as_ref().map(Mbc5::rom_bank))
+                    .or_else(|| self.camera.as_ref().map(|camera| camera.rom_bank))
+                    .unwrap_or(1),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn read_cycle_high(&mut self, addr: u8) -> u8 {
+        self.generic_cycle();
+        self.read(0xFF00 | (addr as u16))
+    }
+
+    pub fn write_cycle(&mut self, addr: u16, value: u8) {
+        self.generic_cycle();
+        self.write(addr, value)
+    }
+
+    fn hdma_block(&mut self) {
+        let (source, dest) = self.hdma.next_block();
+        self.stats.hdma_blocks += 1;
+        for i in 0..hdma::BLOCK_LEN {
+            let value = self.read(source.wrapping_add(i));
+            self.gpu.write_vram_abs(dest + i, value);
+        }
+    }
+
+    // General purpose DMA copies everything at once while the CPU waits.
+    fn hdma_general(&mut self, blocks: u16) {
+        for _ in 0..blocks {
+            self.hdma_block();
+            for _ in 0..hdma::BLOCK_CYCLES {
+                self.generic_cycle();
+            }
+        }
+        self.hdma.remaining = 0x7F;
+    }
+
+    fn is_unmapped(&self, address: u16) -> bool {
+        is_unmapped_io(address)
+            && !(address == console::DEBUG_PORT && self.debug_port)
+            && !(self.cgb && is_cgb_io(address))
+    }
+
+    // True if the access should be dropped.
+    fn unmapped_access(&self, address: u16, write: Option<u8>) -> bool {
+        if !self.is_unmapped(address) {
+            return false;
+        }
+        if self.log_unmapped_io && self.unmapped_logged.borrow_mut().insert(address) {
+            match write {
+                Some(value) => warn!(
+                    "Write {:02x} to unmapped IO {:04x} at PC {:04x}",
+                    value, address, self.pc
+                ),
+                None => warn!("Read of unmapped IO {:04x} at PC {:04x}", address, self.pc),
+            }
+        }
+        self.strict_io
+    }
+
+    // Everything no IoDevice claims.
+    fn read_memory(&self, address: u16) -> u8 {
+        match address as usize {
+            0x0000..=0x0100 if self.in_bios == 0 => self.bootrom[address as usize],
+            0x0000..=0x7FFF if self.mbc1.is_some() => self.mbc1.as_ref().unwrap().read_rom(address),
+            0x0000..=0x7FFF if self.mbc3.is_some() => self.mbc3.as_ref().unwrap().read_rom(address),
+            0x0000..=0x7FFF if self.mbc5.is_some() => self.mbc5.as_ref().unwrap().read_rom(address),
+            0x4000..=0x7FFF if self.camera.is_some() => {
+                self.camera.as_ref().unwrap().read_rom(address)
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.camera.is_some() => {
+                self.camera.as_ref().unwrap().read_ram(address)
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.rtc_mapped() => {
+                self.mbc3.as_ref().and_then(Mbc3::read_rtc).unwrap()
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.mbc3.is_some() => {
+                self.mbc3.as_ref().unwrap().read_ram(address)
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.mbc5.is_some() => {
+                self.mbc5.as_ref().unwrap().read_ram(address)
+            }
+            hdma::HDMA1..=hdma::HDMA5 if self.cgb => self.hdma.read(address as usize),
+            0xffff => self.int_enabled,
+            0xff0f => self.int_flags,
+            0xff50 => self.in_bios,
+            // 0xFFFF => &self.gpu.,
+            // 0xFF01 => {println!("R: ACC SERIAL TRANSFER DATA"); &self.memory[ias usize]},
+            // 0xFF02 => {println!("R: ACC SERIAL TRANSFER DATA FLGS"); &self.memory[i as usize]},
+            VRAM_START..=VRAM_END => self.gpu.vram_abs(address),
+            OAM_START..=OAM_END => self.gpu.oam[address as usize - OAM_START],
+            meminit::ECHO_START..=meminit::ECHO_END => self.memory[address as usize - 0x2000],
+            meminit::UNUSABLE_START..=meminit::UNUSABLE_END => 0x00,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    // Whether A000-BFFF reads and writes an MBC3 clock register instead of RAM.
+    fn rtc_mapped(&self) -> bool {
+        self.mbc3
+            .as_ref()
+            .is_some_and(|mbc3| mbc3.rtc_register().is_some())
+    }
+
+    // What goes in the .sav file: the mapper's RAM banks, or the RAM at A000-BFFF.
+    pub fn battery_ram(&self) -> &[u8] {
+        match (&self.camera, &self.mbc3, &self.mbc5) {
+            (Some(camera), _, _) => camera.ram(),
+            (_, Some(mbc3), _) => mbc3.ram(),
+            (_, _, Some(mbc5)) => mbc5.ram(),
+            _ => &self.memory[battery::SRAM_START..=battery::SRAM_END],
+        }
+    }
+
+    pub fn battery_ram_mut(&mut self) -> &mut [u8] {
+        match (&mut self.camera, &mut self.mbc3, &mut self.mbc5) {
+            (Some(camera), _, _) => camera.ram_mut(),
+            (_, Some(mbc3), _) => mbc3.ram_mut(),
+            (_, _, Some(mbc5)) => mbc5.ram_mut(),
+            _ => &mut self.memory[battery::SRAM_START..=battery::SRAM_END],
+        }
+    }
+
+    // Whether the motor of a rumble cartridge is running, None for other cartridges.
+    pub fn rumble(&self) -> Option<bool> {
+        self.mbc5.as_ref().and_then(|mbc5| mbc5.rumble)
+    }
+
+    // The cartridge clock brought up to now as a .sav footer, None without one.
+    pub fn rtc_footer(&mut self) -> Option<Vec<u8>> {
+        let rtc = self.mbc3.as_mut()?.rtc.as_mut()?;
+        rtc.sync(self.clock);
+        Some(rtc.footer(rtc::unix_time()))
+    }
+
+    // Offers an IO read to the devices owning registers, None if none of them claims it.
+    fn device_read(&self, address: u16) -> Option<u8> {
+        if !(IO_START..=IO_END).contains(&address) || (is_cgb_io(address) && !self.cgb) {
+            return None;
+        }
+        if address as usize == JOYP {
+            self.joypad_reads.set(self.joypad_reads.get() + 1);
+            let live = self.joypad.io_read(address)?;
+            return Some(match self.joypad_tape.borrow_mut().as_mut() {
+                Some(tape) => tape.read(live),
+                None => live,
+            });
+        }
+        // Wave RAM depends on the model, which IoDevice reads aren't told.
+        if (apu::WAVE_START..=apu::APU_END).contains(&(address as usize)) {
+            return Some(self.apu.cpu_read(address as usize, self.cgb));
+        }
+        let devices: [&dyn IoDevice; 7] = [
+            &self.joypad,
+            &self.serial,
+            &self.timer,
+            &self.apu,
+            &self.gpu,
+            &self.speed,
+            &self.dma,
+        ];
+        devices.iter().find_map(|device| device.io_read(address))
+    }
+
+    // Offers an IO write to the devices owning registers, false if none of them claims it.
+    fn device_write(&mut self, address: u16, value: u8) -> bool {
+        if !(IO_START..=IO_END).contains(&address) || (is_cgb_io(address) && !self.cgb) {
+            return false;
+        }
+        // Bytes sent with the internal clock also go to the console.
+        if address as usize == serial::SC && value == 0x81 {
+            self.console_push(Source::Serial, self.serial.sb);
+        }
+        let mut ctx = IoContext {
+            clock: self.clock,
+            int_flags: &mut self.int_flags,
+            cgb: self.cgb,
+        };
+        let mut devices: [&mut dyn IoDevice; 7] = [
+            &mut self.joypad,
+            &mut self.serial,
+            &mut self.timer,
+            &mut self.apu,
+            &mut self.gpu,
+            &mut self.speed,
+            &mut self.dma,
+        ];
+        let claimed = devices
+            .iter_mut()
+            .any(|device| device.io_write(address, value, &mut ctx));
+        if let Some(source) = self.dma.take() {
+            self.oam_dma(source);
+        }
+        claimed
+    }
+
+    fn oam_dma(&mut self, source: RangeInclusive<usize>) {
+        if let Some(tracer) = &mut self.tracer {
+            let name = format!("OAM DMA {:04x}", source.start());
+            let end = self.clock + dma::TRANSFER_CYCLES;
+            tracer.span(DMA_TRACK, "dma", name, self.clock, end, None);
+        }
+        self.gpu.oam.copy_from_slice(&self.memory[source]);
+        self.stats.oam_dma += 1;
+    }
+
+    // What the CPU would read, for debuggers and other tooling. Nothing is logged or counted, JOYP
+    // comes from the live joypad rather than a movie and VRAM and OAM are readable whatever the
+    // PPU and DMA are doing, so looking never changes how emulation goes.
+    pub fn debug_read(&self, address: u16) -> u8 {
+        if self.strict_io && self.is_unmapped(address) {
+            return 0xFF;
+        }
+        let value = match address as usize {
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END => self.gpu.debug_read(address),
+            JOYP => self.joypad.io_read(address).unwrap_or(0xFF),
+            _ => match self.device_read(address) {
+                Some(value) => value,
+                None => self.read_memory(address),
+            },
+        };
+        match io::io_mask(address, self.cgb) {
+            Some(mask) => value | mask.read_or,
+            None => value,
+        }
+    }
+
+    fn console_push(&mut self, source: Source, value: u8) {
+        let frame = self.gpu._vblank_count;
+        self.console
+            .push(source, char::from(value), frame, self.clock);
+    }
+}
+
+impl Memory for Bus {
+    fn read(&self, address: u16) -> u8 {
+        if self.unmapped_access(address, None) {
+            return 0xFF;
+        }
+        let value = match self.device_read(address) {
+            Some(value) => value,
+            None => self.read_memory(address),
+        };
+        match io::io_mask(address, self.cgb) {
+            Some(mask) => value | mask.read_or,
+            None => value,
+        }
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        if let 0xff00..=0xff02 | 0xff46 | VRAM_START..=VRAM_END | OAM_START..=OAM_END =
+            address as usize
+        {
+            self.activity = self.activity.wrapping_add(1);
+        }
+        if let (0xff00..=0xffff, Some(log)) = (address, &mut self.report_log) {
+            log.io_write(IoWrite {
+                clock: self.clock,
+                pc: self.pc,
+                address,
+                value,
+            });
+        }
+        if self.unmapped_access(address, Some(value)) {
+            return;
+        }
+        let value = match io::io_mask(address, self.cgb) {
+            Some(mask) if mask.writable == 0 => return,
+            Some(mask) => value & mask.writable,
+            None => value,
+        };
+        if self.device_write(address, value) {
+            return;
+        }
+        match address as usize {
+            0x2000..=0x3FFF => self.code_writes.mark_range(0x4000..=0x7FFF),
+            0xC000..=0xDFFF | 0xFF80..=0xFFFE => self.code_writes.mark(address),
+            meminit::ECHO_START..=meminit::ECHO_END => self.code_writes.mark(address - 0x2000),
+            _ => {}
+        }
+        // IO registers left here reach into the rest of the bus: DMA, serial, interrupts, the
+        // bootrom switch.
+        match address as usize {
+            0x0000..=0x0100 if self.in_bios == 0 => panic!(),
+            0x0000..=0x7fff => {
+                self.banks.write(self.clock, self.pc, address, value);
+                if let Some(camera) = &mut self.camera {
+                    camera.write_register(address, value);
+                    self.banks.rom = camera.rom_bank;
+                    self.banks.ram = Some(camera.ram_bank).filter(|_| camera.ram_enabled);
+                }
+                if let Some(mbc1) = &mut self.mbc1 {
+                    mbc1.write_register(address, value);
+                    self.banks.rom = mbc1.rom_bank();
+                }
+                if let Some(mbc3) = &mut self.mbc3 {
+                    mbc3.write_register(address, value, self.clock);
+                    self.banks.rom = mbc3.rom_bank();
+                }
+                if let Some(mbc5) = &mut self.mbc5 {
+                    mbc5.write_register(address, value);
+                    self.banks.rom = mbc5.rom_bank();
+                    self.banks.ram = Some(mbc5.ram_bank as usize).filter(|_| mbc5.ram_enabled);
+                }
+            }
+            hdma::HDMA1..=hdma::HDMA5 if self.cgb => {
+                if let hdma::Request::General(blocks) = self.hdma.write(address as usize, value) {
+                    self.hdma_general(blocks);
+                }
+            }
+            0xffff => self.int_enabled = value,
+            0xff0f => self.int_flags = value,
+            0xff50 => {
+                if value != 0 && !self.rom_start_signal {
+                    self.rom_start_signal = true;
+                }
+                self.in_bios = value
+            }
+            0xff80 => {
+                self.memory[address as usize] = value;
+            }
+            _ if address == console::DEBUG_PORT && self.debug_port => {
+                self.console_push(Source::DebugPort, value);
+                self.memory[address as usize] = value;
+            }
+            VRAM_START..=VRAM_END => self.gpu.write_vram_abs(address, value),
+            OAM_START..=OAM_END => self.gpu.oam[address as usize - OAM_START] = value,
+            battery::SRAM_START..=battery::SRAM_END if self.camera.is_some() => {
+                let clock = self.clock;
+                let camera = self.camera.as_mut().unwrap();
+                self.sram_dirty |= camera.ram_enabled && !camera.registers_mapped;
+                camera.write_ram(address, value, clock);
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.rtc_mapped() => {
+                let clock = self.clock;
+                self.mbc3.as_mut().unwrap().write_rtc(value, clock);
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.mbc3.is_some() => {
+                self.mbc3.as_mut().unwrap().write_ram(address, value);
+                self.sram_dirty = true;
+            }
+            battery::SRAM_START..=battery::SRAM_END if self.mbc5.is_some() => {
+                self.mbc5.as_mut().unwrap().write_ram(address, value);
+                self.sram_dirty = true;
+            }
+            battery::SRAM_START..=battery::SRAM_END => {
+                self.memory[address as usize] = value;
+                self.sram_dirty = true;
+            }
+            meminit::ECHO_START..=meminit::ECHO_END => {
+                self.memory[address as usize - 0x2000] = value
+            }
+            meminit::UNUSABLE_START..=meminit::UNUSABLE_END => {}
+            _ => {
+                if address >= 0x8000 {
+                    self.memory[address as usize] = value
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::camera;
+    use crate::cartridge;
+    use crate::constants::CYCLES_PER_FRAME;
+    use crate::cpu;
+    use crate::mbc1;
+    use crate::mbc3;
+    use crate::mbc5;
+
+    #[test]
+    fn unmapped_io_acts_as_ram_by_default() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xFF03, 0x12);
+        assert_eq!(bus.read(0xFF03), 0x12);
+    }
+
+    #[test]
+    fn strict_io() {
+        let mut bus = Bus::new(vec![], None);
+        bus.strict_io = true;
+        bus.log_unmapped_io = true;
+        for &address in [0xFF03, 0xFF08, 0xFF0E, 0xFF4C, 0xFF51, 0xFF7F].iter() {
+            bus.write(address, 0x12);
+            assert_eq!(bus.read(address), 0xFF, "{:04x}", address);
+        }
+        assert_eq!(bus.unmapped_logged.borrow().len(), 6);
+        bus.unmapped_logged.borrow_mut().clear();
+        assert_eq!(bus.debug_read(0xFF03), 0xFF);
+        assert!(bus.unmapped_logged.borrow().is_empty());
+        // Mapped registers and HRAM are untouched.
+        bus.write(0xFF42, 0x12);
+        bus.write(0xFF80, 0x34);
+        assert_eq!(bus.read(0xFF42), 0x12);
+        assert_eq!(bus.read(0xFF80), 0x34);
+        bus.debug_port = true;
+        bus.write(console::DEBUG_PORT, b'a');
+        assert_eq!(bus.read(console::DEBUG_PORT), b'a');
+    }
+
+    #[test]
+    fn debug_reads_have_no_side_effects() {
+        let mut bus = Bus::new(vec![], None);
+        bus.strict_io = true;
+        bus.log_unmapped_io = true;
+        bus.gpu.write_vram_abs(0x8010, 0x5A);
+        bus.gpu.oam[3] = 0xA5;
+        for address in 0..=0xFFFF {
+            assert_eq!(
+                bus.debug_read(address),
+                bus.read(address),
+                "{:04x}",
+                address
+            );
+        }
+        bus.unmapped_logged.borrow_mut().clear();
+        let reads = bus.joypad_reads.get();
+        assert_eq!(bus.debug_read(0x8010), 0x5A);
+        assert_eq!(bus.debug_read(OAM_START as u16 + 3), 0xA5);
+        assert_eq!(bus.debug_read(JOYP as u16), bus.read(JOYP as u16));
+        assert_eq!(bus.debug_read(0xFF03), 0xFF);
+        assert_eq!(bus.joypad_reads.get(), reads + 1);
+        assert!(bus.unmapped_logged.borrow().is_empty());
+    }
+
+    #[test]
+    fn ram_snapshots_round_trip() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xC123, 0x12);
+        bus.write(0xFF90, 0x34);
+        let data = bus.dump_ram_snapshot();
+        assert_eq!(data.len(), RAM_SNAPSHOT_LEN);
+        assert_eq!(data[0x123], 0x12);
+        assert_eq!(data[0x2010], 0x34);
+
+        let mut other = Bus::new(vec![], None);
+        other.load_ram_snapshot(&data).unwrap();
+        assert_eq!(other.read(0xC123), 0x12);
+        assert_eq!(other.read(0xE123), 0x12);
+        assert_eq!(other.read(0xFF90), 0x34);
+        assert!(!other.code_writes.is_empty());
+        assert!(other.load_ram_snapshot(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn serial_transfer_completes_with_peer() {
+        let mut bus = Bus::new(vec![], None);
+        bus.serial = Serial::with_device(serial::SerialKind::Mirror);
+        bus.write(0xFF01, 0x5A);
+        bus.write(0xFF02, 0x81);
+        for _ in 1..serial::TRANSFER_CYCLES {
+            bus.generic_cycle();
+        }
+        // Unused SC bits read as 1.
+        assert_eq!(bus.read(0xFF02), 0xFF);
+        assert_eq!(bus.int_flags & cpu::SERIAL, 0);
+        bus.generic_cycle();
+        assert_eq!(bus.read(0xFF01), 0x5A);
+        assert_eq!(bus.read(0xFF02), 0x7F);
+        assert_ne!(bus.int_flags & cpu::SERIAL, 0);
+    }
+
+    #[test]
+    fn oam_dma_copies_a_page() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xC000, 0x10);
+        bus.write(0xC09F, 0x20);
+        bus.write(dma::DMA as u16, 0xC0);
+        assert_eq!((bus.gpu.oam[0], bus.gpu.oam[0x9F]), (0x10, 0x20));
+        assert_eq!(bus.read(dma::DMA as u16), 0xC0);
+        assert_eq!(bus.stats.oam_dma, 1);
+        // Past F1 the register takes the value but nothing is copied.
+        bus.write(dma::DMA as u16, 0xFE);
+        assert_eq!(bus.read(dma::DMA as u16), 0xFE);
+        assert_eq!(bus.stats.oam_dma, 1);
+    }
+
+    #[test]
+    fn skip_idle_matches_stepping() {
+        let setup = || {
+            let mut bus = Bus::new(vec![], None);
+            bus.gpu.lcdc = 0x80;
+            bus.timer.tac = 0b101;
+            bus.timer.tma = 0xF0;
+            bus.serial = Serial::with_device(serial::SerialKind::Mirror);
+            bus.write(0xFF02, 0x81);
+            bus
+        };
+        let end = CYCLES_PER_FRAME * 2;
+        let mut stepped = setup();
+        while stepped.cycles < end {
+            stepped.generic_cycle();
+        }
+        let mut skipped = setup();
+        let mut skips = 0;
+        while skipped.cycles < end {
+            skips += skipped.skip_idle(end - skipped.cycles);
+            skipped.generic_cycle();
+        }
+        assert!(skips > end / 2);
+        assert_eq!(skipped.clock, stepped.clock);
+        assert_eq!(skipped.timer.snapshot(), stepped.timer.snapshot());
+        assert_eq!(skipped.int_flags, stepped.int_flags);
+        assert_eq!(skipped.gpu.scanline, stepped.gpu.scanline);
+        assert_eq!(skipped.gpu.dot(), stepped.gpu.dot());
+        assert_eq!(skipped.read(0xFF02), stepped.read(0xFF02));
+    }
+
+    #[test]
+    fn mbc1_switches_rom_banks() {
+        let mut rom = vec![0; 0x20000];
+        for (bank, data) in rom.chunks_mut(mbc1::ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+        }
+        rom[cartridge::CARTRIDGE_TYPE] = 0x01;
+        let mut bus = Bus::new(rom, None);
+        assert_eq!(bus.read(0x4000), 1);
+        bus.write(0x2000, 0x05);
+        assert_eq!(bus.read(0x4000), 5);
+        assert_eq!(bus.banks.rom, 5);
+        bus.reset();
+        assert_eq!(bus.read(0x4000), 1);
+    }
+
+    #[test]
+    fn mbc3_maps_the_clock_over_ram() {
+        let mut rom = vec![0; 0x20000];
+        rom[cartridge::CARTRIDGE_TYPE] = 0x10;
+        let mut bus = Bus::new(rom, None);
+        bus.write(0x2000, 0x05);
+        assert_eq!(bus.banks.rom, 5);
+        bus.write(0xA000, 0x12);
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, 0x08);
+        bus.write(0xA000, 30);
+        bus.write(0x6000, 0);
+        bus.write(0x6000, 1);
+        assert_eq!(bus.read(0xA000), 30);
+        bus.write(0x4000, 0x00);
+        assert_eq!(bus.read(0xA000), 0x12);
+        assert_eq!(bus.rtc_footer().unwrap()[0], 30);
+        assert!(Bus::new(vec![], None).rtc_footer().is_none());
+    }
+
+    #[test]
+    fn mbc3_ram_is_the_battery_ram() {
+        let mut rom = vec![0; 0x20000];
+        rom[cartridge::CARTRIDGE_TYPE] = 0x13;
+        rom[cartridge::RAM_SIZE] = 0x03;
+        let mut bus = Bus::new(rom, None);
+        assert_eq!(
+            bus.battery_ram().len(),
+            mbc3::RAM_BANKS * mbc3::RAM_BANK_SIZE
+        );
+        bus.write(0x4000, 1);
+        bus.write(0xA010, 0x42);
+        assert!(bus.sram_dirty);
+        bus.write(0x4000, 0);
+        assert_eq!(bus.read(0xA010), 0);
+        assert_eq!(bus.battery_ram()[mbc3::RAM_BANK_SIZE + 0x10], 0x42);
+    }
+
+    #[test]
+    fn mbc5_banks_rom_and_ram() {
+        let mut rom = vec![0; 0x200 * mbc5::ROM_BANK_SIZE];
+        for (bank, data) in rom.chunks_mut(mbc5::ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+        }
+        rom[cartridge::CARTRIDGE_TYPE] = 0x1B;
+        rom[cartridge::RAM_SIZE] = 0x03;
+        let mut bus = Bus::new(rom, None);
+        bus.write(0x2000, 0x42);
+        bus.write(0x3000, 0x01);
+        assert_eq!(bus.read(0x4000), 0x42);
+        assert_eq!(bus.rom_bank_at(0x4000), Some(0x142));
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, 0x02);
+        assert_eq!(bus.banks.ram, Some(2));
+        bus.write(0xA010, 0x42);
+        assert_eq!(bus.battery_ram()[2 * mbc5::RAM_BANK_SIZE + 0x10], 0x42);
+        assert_eq!(bus.rumble(), None);
+        bus.reset();
+        assert_eq!(bus.rom_bank_at(0x4000), Some(1));
+    }
+
+    #[test]
+    fn camera_ram_is_the_battery_ram() {
+        let mut rom = vec![0; 0x8000];
+        rom[cartridge::CARTRIDGE_TYPE] = camera::CARTRIDGE_TYPE;
+        let mut bus = Bus::new(rom, None);
+        assert_eq!(
+            bus.battery_ram().len(),
+            camera::RAM_BANK_SIZE * camera::RAM_BANKS
+        );
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, 2);
+        bus.write(0xA010, 0x42);
+        assert!(bus.sram_dirty);
+        assert_eq!(bus.battery_ram()[2 * camera::RAM_BANK_SIZE + 0x10], 0x42);
+    }
+
+    #[test]
+    fn sram_writes_mark_dirty() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xC000, 0x12);
+        assert!(!bus.sram_dirty);
+        bus.write(0xA123, 0x34);
+        assert!(bus.sram_dirty);
+        assert_eq!(bus.read(0xA123), 0x34);
+    }
+
+    #[test]
+    fn code_writes_mark_pages() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xC000, 0x12);
+        let mut expected = DirtyPages::default();
+        expected.mark(0xC000);
+        assert_eq!(bus.code_writes, expected);
+        // A bank switch dirties all of the switchable bank.
+        bus.write(0x2000, 0x02);
+        expected.mark_range(0x4000..=0x7FFF);
+        assert_eq!(bus.code_writes, expected);
+    }
+
+    #[test]
+    fn double_speed_halves_ppu_rate() {
+        let mut bus = Bus::new(vec![], None);
+        bus.cgb = true;
+        bus.write(speed::KEY1 as u16, 1);
+        assert!(bus.speed.stop());
+        assert_eq!(bus.read(speed::KEY1 as u16), 0xFE);
+        for _ in 0..100 {
+            bus.generic_cycle();
+        }
+        assert_eq!(bus.cycles, 100);
+        assert_eq!(bus.timer.clock, 100);
+        assert_eq!(bus.clock, 50);
+    }
+
+    #[test]
+    fn hdma_general_and_hblank_transfers() {
+        let mut bus = Bus::new(vec![], None);
+        for i in 0..0x40 {
+            bus.memory[0xC000 + i] = i as u8;
+        }
+        bus.write(0xFF51, 0xC0);
+        assert_eq!(bus.hdma.source, 0, "DMG leaves HDMA unmapped");
+        bus.cgb = true;
+        bus.write(0xFF51, 0xC0);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x00);
+        bus.write(0xFF54, 0x00);
+        bus.write(0xFF55, 0x01);
+        assert_eq!(bus.gpu.vram[0x1F], 0x1F);
+        assert_eq!(bus.read(0xFF55), 0xFF);
+        assert_eq!(bus.clock, 2 * hdma::BLOCK_CYCLES);
+
+        bus.gpu.lcdc = 0x80;
+        bus.write(0xFF55, 0x81);
+        assert_eq!(bus.gpu.vram[0x20], 0);
+        while bus.read(0xFF55) != 0x00 {
+            bus.generic_cycle();
+        }
+        assert_eq!(bus.gpu.vram[0x2F], 0x2F);
+        assert_eq!(bus.gpu.vram[0x30], 0);
+        while bus.read(0xFF55) != 0xFF {
+            bus.generic_cycle();
+        }
+        assert_eq!(bus.gpu.vram[0x3F], 0x3F);
+    }
+
+    #[test]
+    fn reset_keeps_rom_and_cartridge_ram() {
+        let mut bus = Bus::new(vec![0x12; 0x8000], None);
+        bus.write(0xA000, 0x34);
+        bus.write(0xC000, 0x56);
+        bus.strict_io = true;
+        bus.clock = 1234;
+        bus.reset();
+        assert_eq!(bus.read(0x0150), 0x12);
+        assert_eq!(bus.read(0xA000), 0x34);
+        assert_eq!(bus.read(0xC000), 0x00);
+        assert_eq!(bus.clock, 0);
+        assert!(bus.strict_io && bus.rom_start_signal);
+    }
+
+    #[test]
+    fn echo_ram_and_unusable_region() {
+        let mut bus = Bus::new(vec![], None);
+        bus.power_on(MemFill::Ones);
+        assert_eq!(bus.read(0xC000), 0xFF);
+        assert_eq!(bus.read(0xFF80), 0xFF);
+        bus.write(0xE010, 0x12);
+        assert_eq!(bus.read(0xC010), 0x12);
+        bus.write(0xC020, 0x34);
+        assert_eq!(bus.read(0xE020), 0x34);
+        bus.write(0xFEA0, 0x56);
+        assert_eq!(bus.read(0xFEA0), 0x00);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::clock::{self, Cycles};
+use crate::constants::MaybeErr;
+use crate::png;
+use std::fs;
+use std::path::Path;
+
+// Game Boy Camera (POCKET CAMERA) cartridge, see https://gbdev.io/pandocs/Gameboy_Camera.html
+// There is no image sensor to read, the picture comes from the host with Camera::set_image.
+pub const CARTRIDGE_TYPE: u8 = 0xFC;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+pub const RAM_BANKS: usize = 16;
+
+// Picture the sensor hands over, the top and bottom rows of the real sensor are cut off.
+pub const IMAGE_WIDTH: usize = 128;
+pub const IMAGE_HEIGHT: usize = 112;
+// Where a capture is written to, in RAM bank 0, as 16x14 2bpp tiles.
+pub const IMAGE_OFFSET: usize = 0x100;
+
+// Exposure (A002-A003) at which the image is used as given, higher is brighter.
+pub const EXPOSURE_NORMAL: usize = 0x0800;
+const REGISTERS: usize = 0x36;
+const DITHER_START: usize = 6;
+
+// Clock cycles a capture takes. Roughly what the sensor needs plus the exposure time.
+pub fn capture_cycles(exposure: usize) -> Cycles {
+    32446 + exposure as Cycles * 16
+}
+
+pub struct Camera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    pub rom_bank: usize,
+    // RAM bank, or the camera registers when bit 4 of the bank register is set.
+    pub ram_bank: usize,
+    pub registers_mapped: bool,
+    pub ram_enabled: bool,
+    pub(crate) registers: [u8; REGISTERS],
+    // Clock at which the running capture completes.
+    pub(crate) capture_done: Option<Cycles>,
+    // IMAGE_WIDTH * IMAGE_HEIGHT gray levels, 0 black to 255 white.
+    image: Vec<u8>,
+}
+
+impl Camera {
+    pub fn new(rom: Vec<u8>) -> Self {
+        // A gradient until the host gives a picture, so there is something to take.
+        let image = (0..IMAGE_WIDTH * IMAGE_HEIGHT)
+            .map(|i| {
+                ((i % IMAGE_WIDTH + i / IMAGE_WIDTH) * 255 / (IMAGE_WIDTH + IMAGE_HEIGHT)) as u8
+            })
+            .collect();
+        Self {
+            rom,
+            ram: vec![0; RAM_BANK_SIZE * RAM_BANKS],
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_mapped: false,
+            ram_enabled: false,
+            registers: [0; REGISTERS],
+            capture_done: None,
+            image,
+        }
+    }
+
+    // Scales a `width` x `height` gray image to the sensor size.
+    pub fn set_image(&mut self, width: usize, height: usize, gray: &[u8]) {
+        if width == 0 || height == 0 || gray.len() < width * height {
+            return;
+        }
+        for y in 0..IMAGE_HEIGHT {
+            for x in 0..IMAGE_WIDTH {
+                let (sx, sy) = (x * width / IMAGE_WIDTH, y * height / IMAGE_HEIGHT);
+                self.image[y * IMAGE_WIDTH + x] = gray[sy * width + sx];
+            }
+        }
+    }
+
+    // All RAM_BANKS banks, battery backed and kept in the .sav file.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // Writes to 0000-7FFF.
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x3F) as usize,
+            0x4000..=0x5FFF => {
+                self.registers_mapped = value & 0x10 != 0;
+                self.ram_bank = (value & 0x0F) as usize;
+            }
+            _ => {}
+        }
+    }
+
+    // Reads of 4000-7FFF.
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let offset = self.rom_bank * ROM_BANK_SIZE + (address as usize - 0x4000);
+        self.rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    // Reads of A000-BFFF.
+    pub fn read_ram(&self, address: u16) -> u8 {
+        let offset = address as usize - 0xA000;
+        if self.registers_mapped {
+            // Only A000 reads back, with bit 0 set while a capture runs.
+            return match offset & 0x7F {
+                0 => (self.registers[0] & 0b110) | self.capture_done.is_some() as u8,
+                _ => 0,
+            };
+        }
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        self.ram[self.ram_bank * RAM_BANK_SIZE + offset]
+    }
+
+    // Writes to A000-BFFF, `clock` times a capture started by writing A000.
+    pub fn write_ram(&mut self, address: u16, value: u8, clock: Cycles) {
+        let offset = address as usize - 0xA000;
+        if self.registers_mapped {
+            let register = offset & 0x7F;
+            if register < REGISTERS {
+                self.registers[register] = value;
+            }
+            if register == 0 && value & 1 != 0 && self.capture_done.is_none() {
+                self.capture_done = Some(clock::deadline(clock, capture_cycles(self.exposure())));
+            }
+        } else if self.ram_enabled {
+            self.ram[self.ram_bank * RAM_BANK_SIZE + offset] = value;
+        }
+    }
+
+    fn exposure(&self) -> usize {
+        u16::from_be_bytes([self.registers[2], self.registers[3]]) as usize
+    }
+
+    // Called every clock, finishes the running capture once it's due. True when a capture was
+    // written to RAM.
+    pub fn update(&mut self, clock: Cycles) -> bool {
+        match self.capture_done {
+            Some(done) if clock >= done => {
+                self.capture_done = None;
+                self.capture();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Turns the image into tiles through the dither matrix at A006-A035: 3 thresholds per
+    // position in a 4x4 grid, a pixel darker than the first is black, lighter than all white.
+    fn capture(&mut self) {
+        let exposure = self.exposure();
+        for y in 0..IMAGE_HEIGHT {
+            for x in 0..IMAGE_WIDTH {
+                let gray = self.image[y * IMAGE_WIDTH + x] as usize;
+                let value = (gray * exposure / EXPOSURE_NORMAL).min(255) as u8;
+                let matrix = DITHER_START + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let color = 3 - thresholds.iter().filter(|&&t| value >= t).count() as u8;
+                let tile = (y / 8) * (IMAGE_WIDTH / 8) + x / 8;
+                let line = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+                for (i, byte) in self.ram[line..line + 2].iter_mut().enumerate() {
+                    if (color >> i) & 1 != 0 {
+                        *byte |= bit;
+                    } else {
+                        *byte &= !bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Width, height and gray levels of a PNG, or a binary PGM (P5) or PPM (P6) image as written by
+// batch::ppm.
+pub fn decode_image(data: &[u8]) -> MaybeErr<(usize, usize, Vec<u8>)> {
+    if data.starts_with(png::SIGNATURE) {
+        png::decode(data)
+    } else {
+        decode_pnm(data)
+    }
+}
+
+pub fn load_image(path: &Path) -> MaybeErr<(usize, usize, Vec<u8>)> {
+    decode_image(&fs::read(path)?).map_err(|e| format!("{:?}: {}", path, e).into())
+}
+
+fn decode_pnm(data: &[u8]) -> MaybeErr<(usize, usize, Vec<u8>)> {
+    // Magic, width, height and maximum value separated by whitespace, then the pixels.
+    let mut fields = vec![];
+    let mut i = 0;
+    while fields.len() < 4 {
+        while data.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+            i += 1;
+        }
+        let start = i;
+        while data.get(i).is_some_and(|b| !b.is_ascii_whitespace()) {
+            i += 1;
+        }
+        if start == i {
+            return Err("Not a PNG, PGM or PPM image".into());
+        }
+        fields.push(String::from_utf8_lossy(&data[start..i]).into_owned());
+    }
+    let pixels = data.get(i + 1..).unwrap_or(&[]);
+    let (width, height): (usize, usize) = (fields[1].parse()?, fields[2].parse()?);
+    let channels = match fields[0].as_str() {
+        "P5" => 1,
+        "P6" => 3,
+        _ => return Err("Not a binary PGM or PPM image".into()),
+    };
+    if fields[3] != "255" || pixels.len() < width * height * channels {
+        return Err("PGM or PPM image has an unsupported depth or is truncated".into());
+    }
+    let gray = pixels
+        .chunks_exact(channels)
+        .take(width * height)
+        .map(|p| (p.iter().map(|&c| c as usize).sum::<usize>() / channels) as u8)
+        .collect();
+    Ok((width, height, gray))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::printer;
+
+    #[test]
+    fn decodes_png_and_pnm() {
+        let gray = [0, 0x40, 0x80, 0xC0, 0xFF, 0x10];
+        let png = printer::png(3, 2, &gray);
+        assert_eq!(decode_image(&png).unwrap(), (3, 2, gray.to_vec()));
+        let mut pgm = b"P5\n3 2\n255\n".to_vec();
+        pgm.extend_from_slice(&gray);
+        assert_eq!(decode_image(&pgm).unwrap(), (3, 2, gray.to_vec()));
+        assert!(decode_image(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn switches_banks() {
+        let mut rom = vec![0; ROM_BANK_SIZE * 4];
+        rom[ROM_BANK_SIZE * 3] = 0x33;
+        let mut camera = Camera::new(rom);
+        camera.write_register(0x2000, 3);
+        assert_eq!(camera.read_rom(0x4000), 0x33);
+
+        assert_eq!(camera.read_ram(0xA000), 0xFF);
+        camera.write_register(0x0000, 0x0A);
+        camera.write_register(0x4000, 2);
+        camera.write_ram(0xA010, 0x42, 0);
+        assert_eq!(camera.read_ram(0xA010), 0x42);
+        camera.write_register(0x4000, 0);
+        assert_eq!(camera.read_ram(0xA010), 0);
+    }
+
+    #[test]
+    fn capture_dithers_into_ram() {
+        let mut camera = Camera::new(vec![]);
+        // Left half black, right half white.
+        let image: Vec<u8> = (0..4).map(|x| if x < 2 { 0 } else { 255 }).collect();
+        camera.set_image(4, 1, &image);
+        camera.write_register(0x0000, 0x0A);
+        camera.write_register(0x4000, 0x10);
+        camera.write_ram(0xA002, (EXPOSURE_NORMAL >> 8) as u8, 0);
+        camera.write_ram(0xA003, 0, 0);
+        for i in 0..16 {
+            for (j, &t) in [0x40, 0x80, 0xC0].iter().enumerate() {
+                camera.write_ram(0xA006 + i * 3 + j as u16, t, 0);
+            }
+        }
+        camera.write_ram(0xA000, 1, 100);
+        assert_eq!(camera.read_ram(0xA000), 1);
+        let done = 100 + capture_cycles(EXPOSURE_NORMAL);
+        camera.update(done - 1);
+        assert_eq!(camera.read_ram(0xA000), 1);
+        camera.update(done);
+        assert_eq!(camera.read_ram(0xA000), 0);
+
+        camera.write_register(0x4000, 0);
+        let tile = |t: usize| camera.read_ram(0xA000 + (IMAGE_OFFSET + t * 16) as u16);
+        // First tile is black, the last tile of the top row white.
+        assert_eq!(tile(0), 0xFF);
+        assert_eq!(tile(IMAGE_WIDTH / 8 - 1), 0);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{camera, mbc1, mbc3, mbc5};
+use std::{fmt::Display, str::FromStr};
+
+// Cartridge header fields, see https://gbdev.io/pandocs/The_Cartridge_Header.html
+pub const LOGO_START: usize = 0x104;
+pub const LOGO_END: usize = 0x133;
+pub const TITLE_START: usize = 0x134;
+pub const TITLE_END: usize = 0x143;
+pub const CARTRIDGE_TYPE: usize = 0x147;
+pub const ROM_SIZE: usize = 0x148;
+pub const RAM_SIZE: usize = 0x149;
+pub const HEADER_CHECKSUM: usize = 0x14D;
+pub const GLOBAL_CHECKSUM: usize = 0x14E;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl Header {
+    // None if the ROM is too small to have a header.
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        if rom.len() <= GLOBAL_CHECKSUM + 1 {
+            return None;
+        }
+        let title = rom[TITLE_START..=TITLE_END]
+            .iter()
+            .take_while(|b| **b != 0)
+            .filter(|b| b.is_ascii_graphic() || **b == b' ')
+            .map(|b| *b as char)
+            .collect::<String>();
+        Some(Self {
+            title: title.trim_end().to_string(),
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            rom_size: rom[ROM_SIZE],
+            ram_size: rom[RAM_SIZE],
+            header_checksum: rom[HEADER_CHECKSUM],
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]),
+        })
+    }
+
+    // Cartridge types with battery backed RAM.
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFF
+        )
+    }
+
+    // MBC5 cartridges with a rumble motor, driven by bit 3 of the RAM bank register.
+    pub fn has_rumble(&self) -> bool {
+        matches!(self.cartridge_type, 0x1C..=0x1E)
+    }
+
+    // Checksum the bootrom verifies over 0x134-0x14C.
+    pub fn computed_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_START..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+    }
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (type {:02x}, header checksum {:02x}, global checksum {:04x})",
+            self.title, self.cartridge_type, self.header_checksum, self.global_checksum
+        )
+    }
+}
+
+// Memory bank controller wired to the cartridge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapper {
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc3Rtc,
+    Mbc5,
+    Camera,
+}
+
+impl Mapper {
+    // The one the header asks for, ROMs that fit in 32K don't need MBC1 banking.
+    pub fn detect(rom: &[u8]) -> Self {
+        match rom.get(CARTRIDGE_TYPE) {
+            Some(&camera::CARTRIDGE_TYPE) => Mapper::Camera,
+            Some(kind) if rom.len() > 0x8000 && mbc1::CARTRIDGE_TYPES.contains(kind) => {
+                Mapper::Mbc1
+            }
+            Some(kind) if mbc3::RTC_CARTRIDGE_TYPES.contains(kind) => Mapper::Mbc3Rtc,
+            Some(kind) if mbc3::CARTRIDGE_TYPES.contains(kind) => Mapper::Mbc3,
+            Some(kind) if mbc5::CARTRIDGE_TYPES.contains(kind) => Mapper::Mbc5,
+            _ => Mapper::None,
+        }
+    }
+}
+
+// Names used by the mapper key of compat.toml.
+impl FromStr for Mapper {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Mapper::None),
+            "mbc1" => Ok(Mapper::Mbc1),
+            "mbc3" => Ok(Mapper::Mbc3),
+            "mbc3+rtc" => Ok(Mapper::Mbc3Rtc),
+            "mbc5" => Ok(Mapper::Mbc5),
+            "camera" => Ok(Mapper::Camera),
+            _ => Err(format!(
+                "Unknown mapper {}, expected none, mbc1, mbc3, mbc3+rtc, mbc5 or camera",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom_with_title(title: &str) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE_START..TITLE_START + title.len()].copy_from_slice(title.as_bytes());
+        rom[HEADER_CHECKSUM] = Header::computed_checksum(&rom);
+        rom
+    }
+
+    #[test]
+    fn parses_title_and_checksum() {
+        let rom = rom_with_title("TETRIS");
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.header_checksum, Header::computed_checksum(&rom));
+        assert_eq!(Header::parse(&[0; 0x100]), None);
+        assert!(!header.has_battery());
+        assert!(!header.has_rumble());
+    }
+
+    #[test]
+    fn detects_mappers() {
+        let mut rom = rom_with_title("TETRIS");
+        assert_eq!(Mapper::detect(&rom), Mapper::None);
+        rom[CARTRIDGE_TYPE] = 0x01;
+        // 32K MBC1 carts run unbanked.
+        assert_eq!(Mapper::detect(&rom), Mapper::None);
+        rom.resize(0x10000, 0);
+        assert_eq!(Mapper::detect(&rom), Mapper::Mbc1);
+        rom[CARTRIDGE_TYPE] = 0x10;
+        assert_eq!(Mapper::detect(&rom), Mapper::Mbc3Rtc);
+        rom[CARTRIDGE_TYPE] = 0x11;
+        assert_eq!(Mapper::detect(&rom), Mapper::Mbc3);
+        rom[CARTRIDGE_TYPE] = 0x1C;
+        assert_eq!(Mapper::detect(&rom), Mapper::Mbc5);
+        assert_eq!("MBC3+RTC".parse(), Ok(Mapper::Mbc3Rtc));
+        assert!("mbc7".parse::<Mapper>().is_err());
+    }
+}
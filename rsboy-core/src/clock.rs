@@ -0,0 +1,35 @@
+// Bus clocks, cycle counts and anything compared against them. Always 64 bit, so even a 32 bit
+// or wasm build can run for centuries before one wraps.
+pub type Cycles = u64;
+
+// Deadline `after` cycles from `now`. Saturates instead of wrapping, so a far off deadline is
+// never reached early.
+pub fn deadline(now: Cycles, after: Cycles) -> Cycles {
+    now.saturating_add(after)
+}
+
+// Cycles left until `deadline`, 0 once it has been reached.
+pub fn until(now: Cycles, deadline: Cycles) -> Cycles {
+    deadline.saturating_sub(now)
+}
+
+// Cycles from `earlier` to `now`, 0 if the clock went back since, as after a reset or a loaded
+// state.
+pub fn since(earlier: Cycles, now: Cycles) -> Cycles {
+    now.saturating_sub(earlier)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn saturates_at_the_ends() {
+        assert_eq!(deadline(10, 5), 15);
+        assert_eq!(deadline(Cycles::MAX - 1, 5), Cycles::MAX);
+        assert_eq!(until(10, 15), 5);
+        assert_eq!(until(20, 15), 0);
+        assert_eq!(since(10, 15), 5);
+        assert_eq!(since(15, 10), 0);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::cartridge::Header;
+use crate::constants::MaybeErr;
+use std::{fs, path::Path};
+
+// Database shipped with the emulator.
+pub const BUILTIN: &str = include_str!("compat.toml");
+// Users can add or override entries with a file of the same format.
+pub const USER_FILE: &str = "compat.toml";
+
+// Settings forced for a specific game.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub palette: Option<String>,
+    pub mapper: Option<String>,
+    pub flags: Vec<String>,
+}
+
+impl Overrides {
+    // Fields set in `other` win.
+    fn merge(&mut self, other: &Overrides) {
+        if other.palette.is_some() {
+            self.palette = other.palette.clone();
+        }
+        if other.mapper.is_some() {
+            self.mapper = other.mapper.clone();
+        }
+        for flag in &other.flags {
+            if !self.flags.contains(flag) {
+                self.flags.push(flag.clone());
+            }
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub title: Option<String>,
+    pub header_checksum: Option<u8>,
+    pub overrides: Overrides,
+}
+
+impl Entry {
+    pub fn matches(&self, header: &Header) -> bool {
+        let title = self.title.as_ref().map(|t| *t == header.title);
+        let checksum = self.header_checksum.map(|c| c == header.header_checksum);
+        match (title, checksum) {
+            (None, None) => false,
+            (title, checksum) => title.unwrap_or(true) && checksum.unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        Some(s[1..s.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    let s = s.trim();
+    if let Some(string) = parse_string(s) {
+        return Some(Value::Str(string));
+    }
+    if s.starts_with('[') && s.ends_with(']') {
+        let inner = s[1..s.len() - 1].trim();
+        if inner.is_empty() {
+            return Some(Value::List(vec![]));
+        }
+        let items: Option<Vec<_>> = inner
+            .split(',')
+            .filter(|i| !i.trim().is_empty())
+            .map(parse_string)
+            .collect();
+        return items.map(Value::List);
+    }
+    match s {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(hex) = s.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok().map(Value::Int)
+    } else {
+        s.parse().ok().map(Value::Int)
+    }
+}
+
+// Reads the small subset of TOML the database needs:
+// [[game]] tables of key = "string" | integer | bool | ["list", "of", "strings"].
+pub fn parse(text: &str) -> MaybeErr<Vec<Entry>> {
+    let mut entries: Vec<Entry> = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| format!("compat database line {}: {}", n + 1, msg);
+        if line == "[[game]]" {
+            entries.push(Entry::default());
+            continue;
+        }
+        let entry = entries
+            .last_mut()
+            .ok_or_else(|| err("key outside of [[game]]"))?;
+        let mut parts = line.splitn(2, '=');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key.trim(), value),
+            _ => return Err(err("expected key = value").into()),
+        };
+        let value = parse_value(value).ok_or_else(|| err("unreadable value"))?;
+        match (key, value) {
+            ("title", Value::Str(title)) => entry.title = Some(title),
+            ("header_checksum", Value::Int(c)) if (0..=0xFF).contains(&c) => {
+                entry.header_checksum = Some(c as u8)
+            }
+            ("palette", Value::Str(p)) => entry.overrides.palette = Some(p),
+            ("mapper", Value::Str(m)) => entry.overrides.mapper = Some(m),
+            ("flags", Value::List(flags)) => entry.overrides.flags = flags,
+            // Single boolean flags are accepted as shorthand for flags = [...].
+            (flag, Value::Bool(true)) => entry.overrides.flags.push(flag.to_string()),
+            (_, Value::Bool(false)) => {}
+            (key, _) => return Err(err(&format!("unknown key or wrong type for {}", key)).into()),
+        }
+    }
+    Ok(entries)
+}
+
+pub struct CompatDb {
+    pub entries: Vec<Entry>,
+}
+
+impl CompatDb {
+    pub fn builtin() -> Self {
+        Self {
+            entries: parse(BUILTIN).expect("builtin compat database is malformed"),
+        }
+    }
+
+    // The builtin database extended by `path`, if it exists.
+    pub fn load(path: &Path) -> MaybeErr<Self> {
+        let mut db = Self::builtin();
+        if path.exists() {
+            db.entries.extend(parse(&fs::read_to_string(path)?)?);
+        }
+        Ok(db)
+    }
+
+    // Every matching entry merged in order, so later (user) entries win.
+    pub fn lookup(&self, header: &Header) -> Overrides {
+        let mut overrides = Overrides::default();
+        for entry in self.entries.iter().filter(|e| e.matches(header)) {
+            overrides.merge(&entry.overrides);
+        }
+        overrides
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::TITLE_START;
+    use crate::emu::Emu;
+    use crate::video::palette::Palette;
+
+    fn header(title: &str, checksum: u8) -> Header {
+        Header {
+            title: title.into(),
+            header_checksum: checksum,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builtin_parses() {
+        let db = CompatDb::builtin();
+        assert!(!db.entries.is_empty());
+        assert_eq!(
+            db.lookup(&header("TETRIS", 0)).palette.as_deref(),
+            Some("green")
+        );
+    }
+
+    #[test]
+    fn parses_subset() {
+        let entries = parse(
+            r#"
+            # comment
+            [[game]]
+            title = "FOO # BAR" # trailing
+            header_checksum = 0x3C
+            mapper = "mbc1"
+            flags = ["strict_io", "open_bus"]
+            idle_skip = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title.as_deref(), Some("FOO # BAR"));
+        assert_eq!(entries[0].header_checksum, Some(0x3C));
+        assert_eq!(
+            entries[0].overrides.flags,
+            ["strict_io", "open_bus", "idle_skip"]
+        );
+        assert!(parse("title = \"x\"").is_err());
+        assert!(parse("[[game]]\nheader_checksum = 0x100").is_err());
+    }
+
+    #[test]
+    fn later_entries_win() {
+        let mut db = CompatDb::builtin();
+        db.entries.extend(
+            parse("[[game]]\ntitle = \"TETRIS\"\nheader_checksum = 0x0A\npalette = \"gray\"")
+                .unwrap(),
+        );
+        assert_eq!(
+            db.lookup(&header("TETRIS", 0x0A)).palette.as_deref(),
+            Some("gray")
+        );
+        // Checksum doesn't match, only the builtin entry applies.
+        assert_eq!(
+            db.lookup(&header("TETRIS", 0x0B)).palette.as_deref(),
+            Some("green")
+        );
+        assert_eq!(db.lookup(&header("UNKNOWN", 0)), Overrides::default());
+    }
+
+    #[test]
+    fn overrides_change_emulator_state() {
+        let mut rom = vec![0; 0x10000];
+        rom[TITLE_START..TITLE_START + 6].copy_from_slice(b"TETRIS");
+        let db = CompatDb {
+            entries: parse("[[game]]\ntitle = \"TETRIS\"\npalette = \"gray\"\nmapper = \"mbc1\"")
+                .unwrap(),
+        };
+        let overrides = db.lookup(&Header::parse(&rom).unwrap());
+        let emu = Emu::with_overrides(rom.clone(), None, overrides.clone()).unwrap();
+        assert_eq!(emu.bus.gpu.palette, Palette::GRAY);
+        assert!(emu.bus.mbc1.is_some());
+
+        let plain = Emu::new(rom.clone(), None);
+        assert_eq!(plain.bus.gpu.palette, Palette::GREEN);
+        assert!(plain.bus.mbc1.is_none());
+
+        let unknown = Overrides {
+            mapper: Some("mbc7".into()),
+            ..overrides
+        };
+        assert!(Emu::with_overrides(rom, None, unknown).is_err());
+    }
+}
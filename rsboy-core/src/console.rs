@@ -0,0 +1,124 @@
+use crate::clock::Cycles;
+use log::info;
+use std::fmt::Display;
+
+// Unused IO address homebrew can write characters to, when Bus::debug_port is enabled.
+pub const DEBUG_PORT: u16 = 0xFF7F;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Serial,
+    DebugPort,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleLine {
+    pub source: Source,
+    // Frame and cycle of the first character of the line.
+    pub frame: usize,
+    pub cycle: Cycles,
+    pub text: String,
+}
+
+// Text output from the running program, split into timestamped lines.
+#[derive(Default)]
+pub struct Console {
+    pub lines: Vec<ConsoleLine>,
+    pending: Option<ConsoleLine>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn push(&mut self, source: Source, c: char, frame: usize, cycle: Cycles) {
+        if let Some(line) = &self.pending {
+            if line.source != source {
+                self.flush();
+            }
+        }
+        if c == '\n' {
+            let line = self.pending.take().unwrap_or(ConsoleLine {
+                source,
+                frame,
+                cycle,
+                text: String::new(),
+            });
+            if source == Source::DebugPort {
+                info!(target: "debug_port", "{}", line);
+            }
+            self.lines.push(line);
+            return;
+        }
+        self.pending
+            .get_or_insert_with(|| ConsoleLine {
+                source,
+                frame,
+                cycle,
+                text: String::new(),
+            })
+            .text
+            .push(c);
+    }
+
+    // Moves any unterminated line into `lines`.
+    pub fn flush(&mut self) {
+        if let Some(line) = self.pending.take() {
+            self.lines.push(line);
+        }
+    }
+
+    // Completed lines followed by the line currently being written.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &ConsoleLine> {
+        self.lines.iter().chain(self.pending.iter())
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.pending = None;
+    }
+}
+
+impl Display for ConsoleLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = match self.source {
+            Source::Serial => "SB",
+            Source::DebugPort => "DBG",
+        };
+        write!(
+            f,
+            "[{:>6}:{:>10}][{}] {}",
+            self.frame, self.cycle, tag, self.text
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_lines_with_timestamps() {
+        let mut console = Console::new();
+        for (i, c) in "ab\ncd".chars().enumerate() {
+            console.push(Source::Serial, c, 1, 100 + i as Cycles);
+        }
+        assert_eq!(console.lines.len(), 1);
+        assert_eq!(console.lines[0].text, "ab");
+        assert_eq!(console.lines[0].cycle, 100);
+        let all: Vec<_> = console.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(all, ["ab", "cd"]);
+    }
+
+    #[test]
+    fn sources_dont_interleave() {
+        let mut console = Console::new();
+        console.push(Source::Serial, 'a', 0, 0);
+        console.push(Source::DebugPort, 'b', 0, 1);
+        console.push(Source::DebugPort, '\n', 0, 2);
+        assert_eq!(console.lines.len(), 2);
+        assert_eq!(console.lines[0].source, Source::Serial);
+        assert_eq!(console.lines[1].text, "b");
+    }
+}
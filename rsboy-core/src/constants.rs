@@ -1,13 +1,14 @@
 
+use crate::clock::Cycles;
 use std::error::Error;
 
 use std::time::Duration;
 
 // Constants for cycle times
 // These are definitely inaccurate, here for tweaking
-pub const CYCLES_PER_FRAME: usize = GB_CYCLE_SPEED / 60;
+pub const CYCLES_PER_FRAME: Cycles = GB_CYCLE_SPEED / 60;
 pub const FRAME_TIME: Duration = Duration::from_nanos(16670000);
-pub const GB_CYCLE_SPEED: usize = 4194304;
+pub const GB_CYCLE_SPEED: Cycles = 4194304;
 
 pub type MaybeErr<T> = Result<T, Box<dyn Error>>;
 
@@ -3,8 +3,11 @@ pub mod value;
 use std::fmt::Display;
 
 use crate::bus::{Bus, Memory};
+use crate::clock::Cycles;
+use crate::decode::Decoded;
 
 use crate::instructions::*;
+use crate::iomap::Interrupts;
 use crate::registers::RegisterState;
 use value::Value;
 use value::Value::*;
@@ -15,6 +18,8 @@ pub enum CPUState {
     Running,
     Interrupted,
     Halted,
+    // After STOP, see instructions::misc::stop.
+    Stopped,
 }
 // Global emu struct.
 #[derive(Debug, Clone)]
@@ -24,6 +29,9 @@ pub struct CPU {
     pub opcode: u8,
     pub op_addr: u16,
     pub halt: bool,
+    // The instruction at op_addr and the address it was fetched from, when it came from
+    // Bus::decode_cache. Its operands are read from here.
+    pub decoded: Option<(u16, Decoded)>,
 }
 
 pub const VBLANK: u8 = 0b1;
@@ -32,6 +40,45 @@ pub const TIMER: u8 = 0b100;
 pub const SERIAL: u8 = 0b1000;
 pub const JOYPAD: u8 = 0b10000;
 
+// Interrupt sources in priority order.
+pub const INTERRUPTS: [u8; 5] = [VBLANK, LCDSTAT, TIMER, SERIAL, JOYPAD];
+
+pub fn interrupt_name(flag: u8) -> &'static str {
+    match flag {
+        VBLANK => "VBLANK",
+        LCDSTAT => "LCDSTAT",
+        TIMER => "TIMER",
+        SERIAL => "SERIAL",
+        JOYPAD => "JOYPAD",
+        _ => "NONE",
+    }
+}
+
+// One interrupt dispatch, as recorded in Bus::interrupt_log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterruptEvent {
+    pub kind: u8,
+    pub clock: Cycles,
+    // Return address pushed to the stack.
+    pub pc: u16,
+    pub ie: u8,
+    pub flags: u8,
+}
+
+impl Display for InterruptEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>10} {:<7} PC:{:04x} IE:{} IF:{}",
+            self.clock,
+            interrupt_name(self.kind),
+            self.pc,
+            Interrupts(self.ie),
+            Interrupts(self.flags)
+        )
+    }
+}
+
 impl Default for CPU {
     fn default() -> Self {
         Self::new()
@@ -47,18 +94,33 @@ impl CPU {
             op_addr: 0,
             state: CPUState::Running,
             halt: false,
+            decoded: None,
         }
     }
 
     fn execute_op(&mut self, bus: &mut Bus) {
+        bus.stats.instructions += 1;
+        if let Some(stats) = &mut bus.opcode_stats {
+            stats.record(self.opcode);
+        }
+        #[cfg(feature = "fast-dispatch")]
+        crate::instructions::dispatch::run(self.opcode, self, bus);
+        #[cfg(not(feature = "fast-dispatch"))]
         Instr::from(self.opcode).run(self, bus);
     }
 
     pub fn prefetch_op(&mut self, bus: &mut Bus, addr: u16) -> CPUState {
-        let opcode = bus.read_cycle(addr);
+        let (opcode, decoded) = bus.fetch_cycle(addr);
+        self.decoded = decoded.map(|decoded| (addr, decoded));
         self.op_addr = addr;
         self.opcode = opcode;
-        if self.interrupt_detected(bus) {
+        let interrupted = self.interrupt_detected(bus);
+        // EI takes effect after the instruction following it, RETI right away.
+        if bus.ei_pending {
+            bus.ei_pending = false;
+            bus.ime = 1;
+        }
+        if interrupted {
             return CPUState::Interrupted;
         }
         self.registers.pc = self.registers.pc.wrapping_add(1);
@@ -67,7 +129,22 @@ impl CPU {
     pub fn next_u8(&mut self, bus: &mut Bus) -> u8 {
         let addr = self.registers.pc;
         self.registers.pc = self.registers.pc.wrapping_add(1);
-        bus.read_cycle(addr)
+        match self.cached_operand(addr) {
+            Some(value) => {
+                bus.generic_cycle();
+                value
+            }
+            None => bus.read_cycle(addr),
+        }
+    }
+
+    // The operand at `addr` if the current instruction came from the decode cache.
+    fn cached_operand(&self, addr: u16) -> Option<u8> {
+        let (at, decoded) = self.decoded?;
+        if at != self.op_addr || decoded.opcode != self.opcode {
+            return None;
+        }
+        decoded.operand(addr.wrapping_sub(at).wrapping_sub(1) as usize)
     }
 
     pub fn next_u16(&mut self, bus: &mut Bus) -> u16 {
@@ -176,52 +253,37 @@ impl CPU {
 
     pub fn handle_interrupts(&mut self, bus: &mut Bus) {
         let fired = bus.int_enabled & bus.int_flags;
+        let kind = INTERRUPTS.iter().copied().find(|i| fired & i != 0);
+        bus.log_interrupt(InterruptEvent {
+            kind: kind.unwrap_or(0),
+            clock: bus.clock,
+            pc: self.registers.pc,
+            ie: bus.int_enabled,
+            flags: bus.int_flags,
+        });
         bus.generic_cycle();
         self.push_stack(self.registers.pc, bus);
-        if fired & VBLANK != 0 {
-            bus.ack_interrupt(VBLANK);
-            self.registers.pc = 0x40;
-            let opcode = self.next_u8(bus);
-            self.opcode = opcode;
-        } else if fired & LCDSTAT != 0 {
-            bus.ack_interrupt(LCDSTAT);
-            self.registers.pc = 0x48;
-            let opcode = self.next_u8(bus);
-            self.opcode = opcode;
-        } else if fired & TIMER != 0 {
-            bus.ack_interrupt(TIMER);
-            self.registers.pc = 0x50;
-            let opcode = self.next_u8(bus);
-            self.opcode = opcode;
-        } else if fired & SERIAL != 0 {
-            bus.ack_interrupt(SERIAL);
-            self.registers.pc = 0x58;
-            let opcode = self.next_u8(bus);
-            self.opcode = opcode;
-        } else if fired & JOYPAD != 0 {
-            bus.ack_interrupt(JOYPAD);
-            self.registers.pc = 0x60;
+        // Only the highest priority interrupt is serviced, the rest stay pending in IF.
+        if let Some(i) = INTERRUPTS.iter().position(|&i| fired & i != 0) {
+            bus.ack_interrupt(INTERRUPTS[i]);
+            bus.stats.interrupts[i] += 1;
+            self.registers.pc = 0x40 + 8 * i as u16;
+            self.decoded = None;
             let opcode = self.next_u8(bus);
             self.opcode = opcode;
         }
     }
 
-    // TODO hide this
-    fn load_start_values(&mut self, bus: &mut Bus) {
-        self.registers.a = 0x11;
-        self.registers.f = 0xb0;
-        self.registers.b = 0x00;
-        self.registers.c = 0x13;
-        self.registers.d = 0x00;
-        self.registers.e = 0xd8;
-        self.registers.h = 0x01;
-        self.registers.l = 0x4d;
-        self.registers.sp = 0xfffe;
-        self.registers.pc = 0x100;
+    // State the bootrom of bus.model leaves behind, for running without one.
+    pub(crate) fn load_start_values(&mut self, bus: &mut Bus) {
+        self.registers = bus.model.start_registers();
         bus.in_bios = 1;
-        bus.timer.internal = 0x1ea0;
+        // Pan Docs leaves the CGB's DIV open, it depends on how long the boot animation ran.
+        bus.timer.internal = if bus.model.is_cgb() { 0x1ea0 } else { 0xabcc };
         bus.write(0xFF06, 0x00); // TMA
         bus.write(0xFF07, 0x00); // TAC
+        bus.write(0xFF0F, 0xE1); // IF, the bootrom's last VBlank is still pending
+        bus.write(0xFF26, 0xF1); // NR52, first as the APU ignores writes while off
         bus.write(0xFF10, 0x80); // NR10
         bus.write(0xFF11, 0xBF); // NR11
         bus.write(0xFF12, 0xF3); // NR12
@@ -239,7 +301,6 @@ impl CPU {
         bus.write(0xFF23, 0xBF); // NR30
         bus.write(0xFF24, 0x77); // NR50
         bus.write(0xFF25, 0xF3); // NR51
-        bus.write(0xFF26, 0xF1); // NR52
         bus.write(0xFF40, 0x91); // LCDC
         bus.write(0xFF42, 0x00); // SCY
         bus.write(0xFF43, 0x00); // SCX
@@ -250,10 +311,11 @@ impl CPU {
         bus.write(0xFF4A, 0x00); // WY
         bus.write(0xFF4B, 0x00); // WX
         bus.write(0xFFFF, 0x00); // IE
-                                 // assert_eq!(bus.memory[0xFF04], 0xAB);
     }
 
     pub fn step(&mut self, bus: &mut Bus) {
+        // Both count CPU cycles in Bus::generic_cycle, so they only drift apart from a bad restore.
+        debug_assert_eq!(bus.cycles, bus.timer.clock, "timer clock out of sync");
         if bus.rom_start_signal {
             bus.rom_start_signal = false;
             self.load_start_values(bus);
@@ -262,14 +324,35 @@ impl CPU {
             CPUState::Running => {
                 // self.opcode.execute(self, bus);
                 self.execute_op(bus);
+                if self.halt {
+                    self.state = CPUState::Halted;
+                    return;
+                }
+                if let CPUState::Stopped = self.state {
+                    return;
+                }
                 self.state = self.prefetch_op(bus, self.registers.pc);
             }
             CPUState::Interrupted => {
                 self.handle_interrupts(bus);
                 self.state = CPUState::Running;
             }
+            // Wakes on any enabled interrupt, which is only serviced if IME is set.
             CPUState::Halted => {
-                panic!();
+                if bus.int_enabled & bus.int_flags != 0 {
+                    self.halt = false;
+                    self.state = self.prefetch_op(bus, self.registers.pc);
+                } else {
+                    bus.generic_cycle();
+                }
+            }
+            // Wakes when a selected joypad line is low, nothing else runs until then.
+            CPUState::Stopped => {
+                if bus.joypad.read() & 0x0F != 0x0F {
+                    self.state = self.prefetch_op(bus, self.registers.pc);
+                } else {
+                    bus.stopped_cycle();
+                }
             }
         }
     }
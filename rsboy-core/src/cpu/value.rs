@@ -11,9 +11,9 @@ impl From<u8> for Value {
         Value::U8(v)
     }
 }
-impl Into<u8> for Value {
-    fn into(self) -> u8 {
-        if let Value::U8(value) = self {
+impl From<Value> for u8 {
+    fn from(v: Value) -> Self {
+        if let Value::U8(value) = v {
             value
         } else {
             panic!("Tried to convert U16 into U8.")
@@ -26,9 +26,9 @@ impl From<u16> for Value {
         Value::U16(v)
     }
 }
-impl Into<u16> for Value {
-    fn into(self) -> u16 {
-        if let Value::U16(value) = self {
+impl From<Value> for u16 {
+    fn from(v: Value) -> Self {
+        if let Value::U16(value) = v {
             value
         } else {
             panic!("Tried to convert U16 into U8.")
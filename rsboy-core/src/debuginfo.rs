@@ -0,0 +1,118 @@
+use crate::constants::MaybeErr;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLoc {
+    pub file: String,
+    pub line: usize,
+}
+
+// Address to source line mapping for homebrew built with RGBDS.
+// One entry per line, in the same spirit as rgblink's .sym files:
+//   BB:AAAA path/to/file.asm:LINE
+// Banks are parsed but ignored since the bus has no mapper yet.
+// Blank lines and lines starting with ';' are skipped.
+#[derive(Default)]
+pub struct DebugInfo {
+    by_addr: BTreeMap<u16, SourceLoc>,
+    // Source files are resolved relative to the debug file.
+    root: PathBuf,
+    sources: HashMap<String, Option<Vec<String>>>,
+}
+
+impl DebugInfo {
+    pub fn parse(text: &str) -> MaybeErr<Self> {
+        let mut info = DebugInfo::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let err = || format!("Malformed debug info on line {}: {}", n + 1, line);
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let (address, location) = match (parts.next(), parts.next()) {
+                (Some(address), Some(location)) => (address, location.trim()),
+                _ => return Err(err().into()),
+            };
+            let address = address.rsplit(':').next().ok_or_else(err)?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| err())?;
+            let split = location.rfind(':').ok_or_else(err)?;
+            let (file, line) = (&location[..split], &location[split + 1..]);
+            let line = line.parse().map_err(|_| err())?;
+            info.by_addr.insert(
+                address,
+                SourceLoc {
+                    file: file.to_string(),
+                    line,
+                },
+            );
+        }
+        Ok(info)
+    }
+
+    pub fn load(path: &Path) -> MaybeErr<Self> {
+        let mut info = Self::parse(&fs::read_to_string(path)?)?;
+        info.root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(info)
+    }
+
+    pub fn lookup(&self, address: u16) -> Option<&SourceLoc> {
+        self.by_addr.get(&address)
+    }
+
+    // Every address generated by the given source line, used to set breakpoints per line.
+    pub fn addresses_for(&self, file: &str, line: usize) -> Vec<u16> {
+        self.by_addr
+            .iter()
+            .filter(|(_, loc)| loc.file == file && loc.line == line)
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    // Text of the source line, if the file can be found on disk.
+    pub fn source_text(&mut self, loc: &SourceLoc) -> Option<&str> {
+        let root = &self.root;
+        let lines = self.sources.entry(loc.file.clone()).or_insert_with(|| {
+            fs::read_to_string(root.join(&loc.file))
+                .ok()
+                .map(|text| text.lines().map(str::to_string).collect())
+        });
+        lines
+            .as_ref()?
+            .get(loc.line.checked_sub(1)?)
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_entries() {
+        let info = DebugInfo::parse(
+            "; generated\n00:0150 src/main.asm:12\n00:0153 src/main.asm:12\n01:4000 src/bank1.asm:3\n",
+        )
+        .unwrap();
+        assert_eq!(
+            info.lookup(0x0150),
+            Some(&SourceLoc {
+                file: "src/main.asm".into(),
+                line: 12
+            })
+        );
+        assert_eq!(info.addresses_for("src/main.asm", 12), [0x0150, 0x0153]);
+        assert_eq!(info.lookup(0x4000).unwrap().line, 3);
+        assert_eq!(info.lookup(0x0151), None);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(DebugInfo::parse("00:zzzz main.asm:1").is_err());
+        assert!(DebugInfo::parse("00:0150 main.asm").is_err());
+    }
+}
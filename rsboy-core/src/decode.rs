@@ -0,0 +1,178 @@
+use crate::instructions::INSTR_DATA_LENGTHS;
+
+// Size of the ROM windows at 0000-3FFF and 4000-7FFF, cached instructions never cross one.
+pub const WINDOW_SIZE: usize = 0x4000;
+
+// An instruction fetched from cartridge ROM: the opcode and its operand bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decoded {
+    pub opcode: u8,
+    pub operands: [u8; 2],
+}
+
+impl Decoded {
+    // Decodes the instruction at `address` through `read`, None if it runs past `address`'s window.
+    pub fn new(address: u16, read: impl Fn(u16) -> u8) -> Option<Self> {
+        let opcode = read(address);
+        let len = INSTR_DATA_LENGTHS[opcode as usize];
+        if address as usize % WINDOW_SIZE + len >= WINDOW_SIZE {
+            return None;
+        }
+        let mut operands = [0; 2];
+        for (i, operand) in operands.iter_mut().enumerate().take(len) {
+            *operand = read(address + 1 + i as u16);
+        }
+        Some(Self { opcode, operands })
+    }
+
+    pub fn data_len(&self) -> usize {
+        INSTR_DATA_LENGTHS[self.opcode as usize]
+    }
+
+    // Operand `i` bytes past the opcode, counting from 0.
+    pub fn operand(&self, i: usize) -> Option<u8> {
+        if i < self.data_len() {
+            Some(self.operands[i])
+        } else {
+            None
+        }
+    }
+}
+
+// ROM instructions decoded the first time they run, see Bus::decode_cache.
+// Entries are keyed by ROM bank and offset, so a bank switch picks other entries rather than
+// invalidating any, and ROM never changes under them. Code running from RAM isn't cached, so
+// self-modifying code is always read fresh.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeCache {
+    // Per bank, allocated when it first runs code.
+    banks: Vec<Option<Box<[Option<Decoded>]>>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, bank: usize, address: u16) -> Option<Decoded> {
+        let entries = self.banks.get(bank)?.as_ref()?;
+        entries[address as usize % WINDOW_SIZE]
+    }
+
+    pub fn insert(&mut self, bank: usize, address: u16, decoded: Decoded) {
+        if self.banks.len() <= bank {
+            self.banks.resize(bank + 1, None);
+        }
+        let entries = self.banks[bank].get_or_insert_with(|| vec![None; WINDOW_SIZE].into());
+        entries[address as usize % WINDOW_SIZE] = Some(decoded);
+    }
+
+    // Instructions decoded so far.
+    pub fn len(&self) -> usize {
+        self.banks
+            .iter()
+            .flatten()
+            .map(|entries| entries.iter().flatten().count())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::{Bus, Memory};
+    use crate::emu::Emu;
+    use crate::mbc1;
+
+    #[test]
+    fn decodes_within_a_window() {
+        // LD BC,d16 then JP d16 straddling 3FFF.
+        let rom = |address: u16| match address {
+            0x1000 => 0x01,
+            0x1001 => 0x34,
+            0x1002 => 0x12,
+            0x3FFE => 0xC3,
+            _ => 0,
+        };
+        let decoded = Decoded::new(0x1000, rom).unwrap();
+        assert_eq!(decoded.operands, [0x34, 0x12]);
+        assert_eq!((decoded.operand(1), decoded.operand(2)), (Some(0x12), None));
+        assert_eq!(Decoded::new(0x3FFE, rom), None);
+        assert!(Decoded::new(0x3FFF, rom).is_some());
+
+        let mut cache = DecodeCache::new();
+        cache.insert(3, 0x5000, decoded);
+        assert_eq!(cache.get(3, 0x5000), Some(decoded));
+        assert_eq!(cache.get(2, 0x5000), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    // Runs `emu` until PC reaches `pc`.
+    fn run_to(emu: &mut Emu, pc: u16) {
+        for _ in 0..10000 {
+            if emu.cpu.op_addr == pc {
+                return;
+            }
+            emu.emulate_step();
+        }
+        panic!("never reached {:04x}", pc);
+    }
+
+    #[test]
+    fn runs_the_same_with_and_without_the_cache() {
+        // Bank 1 and 2 both hold LD A,d8 with their number at 4000, called twice from bank 0
+        // with a bank switch in between, then the code copies itself to WRAM and runs it there.
+        let mut rom = vec![0; 4 * mbc1::ROM_BANK_SIZE];
+        rom[0x147] = 0x01;
+        let main = [
+            0xCD, 0x00, 0x40, // CALL 4000
+            0x47, // LD B,A
+            0x3E, 0x02, // LD A,2
+            0xEA, 0x00, 0x20, // LD (2000),A
+            0xCD, 0x00, 0x40, // CALL 4000
+            0x4F, // LD C,A
+            0x21, 0x00, 0xC0, // LD HL,C000
+            0x36, 0x3C, // LD (HL),INC A
+            0x23, // INC HL
+            0x36, 0xC9, // LD (HL),RET
+            0xCD, 0x00, 0xC0, // CALL C000
+            0x21, 0x00, 0xC0, // LD HL,C000
+            0x36, 0x3D, // LD (HL),DEC A
+            0xCD, 0x00, 0xC0, // CALL C000
+            0x18, 0xFE, // JR -2
+        ];
+        rom[0x100..0x100 + main.len()].copy_from_slice(&main);
+        let end = 0x100 + main.len() as u16 - 2;
+        for bank in 1..=2 {
+            let start = bank * mbc1::ROM_BANK_SIZE;
+            rom[start..start + 3].copy_from_slice(&[0x3E, bank as u8, 0xC9]);
+        }
+
+        let mut plain = Emu::new(rom.clone(), None);
+        let mut cached = Emu::new(rom, None);
+        cached.bus.decode_cache = Some(DecodeCache::new());
+        for emu in [&mut plain, &mut cached].iter_mut() {
+            run_to(emu, end);
+            // Twice through, the second time from the cache.
+            emu.cpu.registers.pc = 0x101;
+            emu.cpu.op_addr = 0x100;
+            emu.cpu.opcode = 0xCD;
+            emu.bus.write(0x2000, 1);
+            run_to(emu, end);
+        }
+        assert_eq!(plain.cpu.registers, cached.cpu.registers);
+        assert_eq!(plain.bus.cycles, cached.bus.cycles);
+        assert_eq!((cached.cpu.registers.b, cached.cpu.registers.c), (1, 2));
+        // INC A then DEC A from WRAM.
+        assert_eq!(cached.cpu.registers.a, 2);
+        let cache = cached.bus.decode_cache.as_ref().unwrap();
+        assert!(cache.hits > 0 && cache.misses > 0);
+        assert!(Bus::new(vec![], None).decode_cache.is_none());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::instructions::{Instr, INSTR_DATA_LENGTHS, INSTR_TABLE};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+// Instructions per page of Disassembler::page, a screenful in the debugger.
+pub const PAGE_LEN: usize = 32;
+
+#[derive(Clone, Debug, Default)]
+pub struct InstrListing {
+    pub instr: Instr,
+    pub data: Option<u16>,
+    pub addr: u16,
+}
+
+// The instruction at `addr`, operands past the end of `mem` read as 0.
+pub fn decode(mem: &[u8], addr: usize) -> InstrListing {
+    let byte = |i: usize| mem.get(i).copied().unwrap_or(0);
+    let op = byte(addr);
+    let data = match INSTR_DATA_LENGTHS[op as usize] {
+        0 => None,
+        1 => Some(byte(addr + 1) as u16),
+        _ => Some(u16::from_le_bytes([byte(addr + 1), byte(addr + 2)])),
+    };
+    InstrListing {
+        instr: INSTR_TABLE[op as usize],
+        data,
+        addr: addr as u16,
+    }
+}
+
+// Straight line disassembly of all of `mem`.
+pub fn gen_il(mem: &[u8]) -> Vec<InstrListing> {
+    let mut view = vec![];
+    let mut i = 0;
+    while i < mem.len() {
+        view.push(decode(mem, i));
+        i += 1 + INSTR_DATA_LENGTHS[mem[i] as usize];
+    }
+    view
+}
+
+// 256 byte pages of the address space written since the disassembly was last refreshed, set by
+// Bus::write for ROM bank switches, WRAM and HRAM.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirtyPages([u64; 4]);
+
+impl DirtyPages {
+    pub fn mark(&mut self, address: u16) {
+        let page = (address >> 8) as usize;
+        self.0[page / 64] |= 1 << (page % 64);
+    }
+
+    pub fn mark_range(&mut self, range: RangeInclusive<u16>) {
+        for page in (range.start() >> 8)..=(range.end() >> 8) {
+            self.mark(page << 8);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&bits| bits == 0)
+    }
+
+    // First address of every dirty page, clearing them.
+    fn take(&mut self) -> Vec<usize> {
+        let bits = std::mem::take(&mut self.0);
+        (0..256)
+            .filter(|page| bits[page / 64] & (1 << (page % 64)) != 0)
+            .map(|page| page << 8)
+            .collect()
+    }
+}
+
+// Disassembly of the address space that only redoes the pages written to, with user labels.
+#[derive(Debug, Clone, Default)]
+pub struct Disassembler {
+    listing: Vec<InstrListing>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl Disassembler {
+    pub fn new(mem: &[u8]) -> Self {
+        Self {
+            listing: gen_il(mem),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn listing(&self) -> &[InstrListing] {
+        &self.listing
+    }
+
+    // Disassembles the dirty pages again, from the instruction covering each one's first byte
+    // until decoding lines up with the old listing past its end. Returns the instructions decoded.
+    pub fn refresh(&mut self, mem: &[u8], dirty: &mut DirtyPages) -> usize {
+        let mut decoded = 0;
+        for start in dirty.take() {
+            // The instruction covering `start` may begin on the page before.
+            let first = self
+                .listing
+                .partition_point(|l| l.addr as usize <= start)
+                .saturating_sub(1);
+            let mut addr = self.listing.get(first).map_or(start, |l| l.addr as usize);
+            let mut fresh = vec![];
+            let last = loop {
+                if addr >= mem.len() {
+                    break self.listing.len();
+                }
+                if addr >= start + 0x100 {
+                    let synced = self
+                        .listing
+                        .binary_search_by_key(&addr, |l| l.addr as usize);
+                    if let Ok(last) = synced {
+                        break last;
+                    }
+                }
+                fresh.push(decode(mem, addr));
+                addr += 1 + INSTR_DATA_LENGTHS[mem[addr] as usize];
+            };
+            decoded += fresh.len();
+            self.listing.splice(first..last, fresh);
+        }
+        decoded
+    }
+
+    // Index of the instruction at `addr`, or the next one after it.
+    pub fn index_of(&self, addr: u16) -> usize {
+        self.listing.partition_point(|l| l.addr < addr)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.listing.len().div_ceil(PAGE_LEN)
+    }
+
+    pub fn page_of(&self, addr: u16) -> usize {
+        self.index_of(addr) / PAGE_LEN
+    }
+
+    pub fn page(&self, page: usize) -> &[InstrListing] {
+        let start = (page * PAGE_LEN).min(self.listing.len());
+        &self.listing[start..(start + PAGE_LEN).min(self.listing.len())]
+    }
+
+    pub fn set_label(&mut self, addr: u16, name: &str) {
+        self.labels.insert(addr, name.to_string());
+    }
+
+    pub fn remove_label(&mut self, addr: u16) -> Option<String> {
+        self.labels.remove(&addr)
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(&addr, name)| (addr, name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addrs(listing: &[InstrListing]) -> Vec<u16> {
+        listing.iter().map(|l| l.addr).collect()
+    }
+
+    #[test]
+    fn refreshes_only_dirty_pages() {
+        // NOPs, with LD BC,d16 at C000.
+        let mut mem = vec![0; 0x10000];
+        mem[0xC000] = 0x01;
+        let mut disasm = Disassembler::new(&mem);
+        assert_eq!(disasm.listing()[disasm.index_of(0xC001)].addr, 0xC003);
+
+        // JP d16 at BFFF swallows the first two bytes of C000 now.
+        mem[0xBFFF] = 0xC3;
+        mem[0xC000] = 0x00;
+        let mut dirty = DirtyPages::default();
+        dirty.mark(0xBFFF);
+        dirty.mark(0xC000);
+        let decoded = disasm.refresh(&mem, &mut dirty);
+        assert!(dirty.is_empty());
+        assert!(decoded < 0x300);
+        assert_eq!(addrs(disasm.listing()), addrs(&gen_il(&mem)));
+        assert_eq!(disasm.refresh(&mem, &mut dirty), 0);
+    }
+
+    #[test]
+    fn pages_and_labels() {
+        let mem = vec![0; 0x100];
+        let mut disasm = Disassembler::new(&mem);
+        assert_eq!(disasm.page_count(), 0x100 / PAGE_LEN);
+        assert_eq!(disasm.page_of(0x45), 2);
+        assert_eq!(disasm.page(2)[0].addr, 0x40);
+        assert!(disasm.page(100).is_empty());
+
+        disasm.set_label(0x40, "main");
+        assert_eq!(disasm.label(0x40), Some("main"));
+        assert_eq!(disasm.labels().collect::<Vec<_>>(), vec![(0x40, "main")]);
+        assert_eq!(disasm.remove_label(0x40), Some("main".to_string()));
+        assert_eq!(disasm.label(0x40), None);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::clock::Cycles;
+use crate::io::{IoContext, IoDevice};
+use std::ops::RangeInclusive;
+
+pub const DMA: usize = 0xFF46;
+
+// Machine cycles a transfer takes on hardware, here it's done at once when DMA is written.
+pub const TRANSFER_CYCLES: Cycles = 160;
+
+// Pages past F1 would copy from echo RAM and IO, the transfer doesn't start.
+const LAST_SOURCE: u8 = 0xF1;
+
+// OAM DMA, see https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+// The device only takes the register write, Bus does the copy since it reads memory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OamDma {
+    // Reads back the last value written, even if no transfer started.
+    pub source: u8,
+    pending: bool,
+}
+
+impl OamDma {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // The 160 bytes to copy, for a transfer started since the last call.
+    pub fn take(&mut self) -> Option<RangeInclusive<usize>> {
+        if !std::mem::take(&mut self.pending) {
+            return None;
+        }
+        let start = (self.source as usize) << 8;
+        Some(start..=start | 0xFF)
+    }
+}
+
+impl IoDevice for OamDma {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        Some(self.source).filter(|_| address as usize == DMA)
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, _ctx: &mut IoContext) -> bool {
+        if address as usize != DMA {
+            return false;
+        }
+        self.source = value;
+        self.pending = value <= LAST_SOURCE;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn starts_transfers_from_pages_up_to_f1() {
+        let mut dma = OamDma::new();
+        let mut flags = 0;
+        let mut ctx = IoContext {
+            clock: 0,
+            int_flags: &mut flags,
+            cgb: false,
+        };
+        assert_eq!(dma.take(), None);
+        assert!(dma.io_write(DMA as u16, 0xC1, &mut ctx));
+        assert_eq!(dma.take(), Some(0xC100..=0xC1FF));
+        assert_eq!(dma.take(), None);
+
+        assert!(dma.io_write(DMA as u16, 0xFE, &mut ctx));
+        assert_eq!(dma.take(), None);
+        assert_eq!(dma.io_read(DMA as u16), Some(0xFE));
+        assert!(!dma.io_write(0xFF47, 0, &mut ctx));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::bus::Bus;
+use crate::gpu::{OAM_ENTRIES, OAM_START, TILE_SIZE, VRAM_START};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+// Annotated hex dumps of the address space, for the `dump` command and the debugger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    // Tile data and both tile maps, one tile or half a map row per line.
+    Vram,
+    // One sprite per line.
+    Oam,
+    Wram,
+    Hram,
+}
+
+impl FromStr for Region {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vram" => Ok(Region::Vram),
+            "oam" => Ok(Region::Oam),
+            "wram" => Ok(Region::Wram),
+            "hram" => Ok(Region::Hram),
+            _ => Err(format!(
+                "Unknown region {}, expected vram, oam, wram or hram",
+                s
+            )),
+        }
+    }
+}
+
+const MAP_WIDTH: usize = 32;
+const MAPS_START: usize = 0x9800;
+
+impl Region {
+    // First address and contents.
+    fn bytes(self, bus: &Bus) -> (usize, &[u8]) {
+        match self {
+            Region::Vram => (VRAM_START, &bus.gpu.vram[..]),
+            Region::Oam => (OAM_START, &bus.gpu.oam[..OAM_ENTRIES * 4]),
+            Region::Wram => (0xC000, &bus.memory[0xC000..0xE000]),
+            Region::Hram => (0xFF80, &bus.memory[0xFF80..0xFFFF]),
+        }
+    }
+
+    fn line_len(self) -> usize {
+        match self {
+            Region::Oam => 4,
+            _ => 16,
+        }
+    }
+}
+
+// Header printed before the line at `address`, where a new area starts.
+fn section(address: usize) -> Option<&'static str> {
+    match address {
+        0x8000 => Some("tile data, block 0 (tiles 00-7F from 8000)"),
+        0x8800 => Some("tile data, block 1 (tiles 80-FF in either mode)"),
+        0x9000 => Some("tile data, block 2 (tiles 00-7F from 8800)"),
+        0x9800 => Some("tile map 9800"),
+        0x9C00 => Some("tile map 9C00"),
+        _ => None,
+    }
+}
+
+// What `line`, starting at `address`, holds.
+fn annotation(region: Region, address: usize, line: &[u8]) -> String {
+    match region {
+        Region::Vram if address < MAPS_START => {
+            let tile = (address - VRAM_START) / TILE_SIZE;
+            format!("tile {:02X}", tile & 0xFF)
+        }
+        Region::Vram => {
+            let offset = (address - MAPS_START) % 0x400;
+            format!("row {:2} col {:2}", offset / MAP_WIDTH, offset % MAP_WIDTH)
+        }
+        Region::Oam => {
+            let sprite = (address - OAM_START) / 4;
+            format!(
+                "sprite {:2}: y {:3} x {:3} tile {:02X} attr {:02X}",
+                sprite, line[0], line[1], line[2], line[3]
+            )
+        }
+        Region::Wram | Region::Hram => line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect(),
+    }
+}
+
+// Writes `region` to `out`, a line of hex bytes each with what it holds.
+pub fn write_region<W: Write>(bus: &Bus, region: Region, out: &mut W) -> io::Result<()> {
+    let (start, bytes) = region.bytes(bus);
+    for (i, line) in bytes.chunks(region.line_len()).enumerate() {
+        let address = start + i * region.line_len();
+        if let Some(header) = section(address).filter(|_| region == Region::Vram) {
+            writeln!(out, "-- {} --", header)?;
+        }
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        writeln!(
+            out,
+            "{:04x}: {}  {}",
+            address,
+            hex.join(" "),
+            annotation(region, address, line)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn region_string(bus: &Bus, region: Region) -> String {
+    let mut out = vec![];
+    // Writing to a Vec can't fail.
+    write_region(bus, region, &mut out).unwrap();
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn annotates_tiles_maps_and_sprites() {
+        let mut bus = Bus::new(vec![], None);
+        bus.gpu.vram[0x10] = 0xAB;
+        bus.gpu.vram[0x1800 + MAP_WIDTH * 3 + 16] = 0x42;
+        bus.gpu.oam[4..8].copy_from_slice(&[16, 8, 0x2A, 0x20]);
+        let vram = region_string(&bus, Region::Vram);
+        let lines: Vec<&str> = vram.lines().collect();
+        assert_eq!(lines[0], "-- tile data, block 0 (tiles 00-7F from 8000) --");
+        assert!(lines[2].starts_with("8010: ab 00"));
+        assert!(lines[2].ends_with("tile 01"));
+        assert!(vram.contains("9870: 42 00"));
+        assert!(vram.contains("-- tile map 9C00 --"));
+        assert!(vram
+            .lines()
+            .any(|l| l.starts_with("9870:") && l.ends_with("row  3 col 16")));
+
+        let oam = region_string(&bus, Region::Oam);
+        assert_eq!(oam.lines().count(), OAM_ENTRIES);
+        assert_eq!(
+            oam.lines().nth(1),
+            Some("fe04: 10 08 2a 20  sprite  1: y  16 x   8 tile 2A attr 20")
+        );
+        assert_eq!("WRAM".parse::<Region>(), Ok(Region::Wram));
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+
+use crate::battery::BatterySaver;
+use crate::bus::{Bus, Memory};
+use crate::cartridge::{Header, Mapper};
+use crate::clock::{self, Cycles};
+use crate::compat::{CompatDb, Overrides, USER_FILE};
+use crate::constants::MaybeErr;
+use crate::cpu::{CPUState, CPU};
+use crate::debuginfo::DebugInfo;
+use crate::disasm::{DirtyPages, Disassembler};
+use crate::exec::ExecMap;
+use crate::input::{InputEvent, InputQueue};
+use crate::instructions::Instr;
+use crate::instructions::INSTR_DATA_LENGTHS;
+use crate::instructions::INSTR_TABLE;
+use crate::rtc::{self, RtcMode};
+use crate::stats::Stats;
+use crate::timeline::Timeline;
+use crate::trace::CPU_TRACK;
+use crate::video::overlay::Overlay;
+use crate::watch::Watches;
+use crate::watchdog::Watchdog;
+
+pub use crate::disasm::{gen_il, InstrListing};
+
+pub fn str_il(il: &[InstrListing]) -> String {
+    il.iter().fold(String::new(), |res, il| {
+        res + &format!("{:04x}: {:?} {:?}\n", il.addr, il.instr, il.data)
+    })
+}
+
+// Why emulate_step asked the frontend to stop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    // PC stayed within start..=end for `cycles` without touching VRAM/OAM/serial/joypad.
+    SuspectedHang {
+        start: u16,
+        end: u16,
+        cycles: Cycles,
+    },
+    // The next instruction to execute is at a breakpoint.
+    Breakpoint(u16),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::SuspectedHang { start, end, cycles } => write!(
+                f,
+                "Suspected hang: PC looped in {:04x}-{:04x} for {} cycles with no I/O activity",
+                start, end, cycles
+            ),
+            StopReason::Breakpoint(address) => write!(f, "Breakpoint at {:04x}", address),
+        }
+    }
+}
+
+// Number of executed instructions kept for the debugger.
+pub const HISTORY_LEN: usize = 32;
+
+// Global emu struct.
+pub struct Emu {
+    pub cpu: CPU,
+    pub bus: Bus,
+    pub watchdog: Option<Watchdog>,
+    pub history: VecDeque<InstrListing>,
+    pub watches: Watches,
+    pub debug_info: Option<DebugInfo>,
+    pub breakpoints: BTreeSet<u16>,
+    pub header: Option<Header>,
+    // Per-game settings from the compatibility database.
+    pub overrides: Overrides,
+    // Whether a cartridge clock catches up on the time between saving and loading.
+    pub rtc_mode: RtcMode,
+    // Debug drawing over the game output, for tools and scripts.
+    pub overlay: Overlay,
+    // Keeps cartridge RAM on disk, flushed a final time when the Emu is dropped.
+    pub battery: Option<BatterySaver>,
+    // Scripted or replayed input, applied as the bus clock reaches each event.
+    pub input_queue: InputQueue,
+    // Skip ahead to the next event while halted instead of stepping every cycle.
+    pub idle_skip: bool,
+    // Executed addresses, only collected when set.
+    pub exec_map: Option<ExecMap>,
+    // Built by the first call to disassembly.
+    pub disasm: Option<Disassembler>,
+    // Savestates and input for seek_to_cycle, only recorded when set.
+    pub timeline: Option<Timeline>,
+}
+
+impl Emu {
+    pub fn emulate_step(&mut self) -> Option<StopReason> {
+        // self.prev = self.cpu.clone();
+        // println!("{}", self.cpu);
+        if let CPUState::Running = self.cpu.state {
+            self.record_history();
+            if let Some(log) = &mut self.bus.report_log {
+                log.instr(self.bus.clock, self.cpu.registers.jump(self.cpu.op_addr));
+            }
+            if let Some(map) = &mut self.exec_map {
+                map.record(self.cpu.op_addr);
+            }
+        }
+        let (before, state, opcode, op_addr) = (
+            self.bus.clock,
+            self.cpu.state.clone(),
+            self.cpu.opcode,
+            self.cpu.op_addr,
+        );
+        self.bus.pc = op_addr;
+        self.input_queue.apply_due(&mut self.bus);
+        self.record_timeline();
+        if self.idle_skip && self.cpu.halt {
+            let max = match self.input_queue.next_cycle() {
+                Some(cycle) => clock::until(self.bus.clock, cycle),
+                None => Cycles::MAX,
+            };
+            self.bus.skip_idle(max);
+        }
+        self.cpu.step(&mut self.bus);
+        if let (CPUState::Running, Some(log)) = (&state, &mut self.bus.report_log) {
+            log.flags_after(self.cpu.registers.f);
+        }
+        if let Some(tracer) = &mut self.bus.tracer {
+            let clock = self.bus.clock;
+            match state {
+                CPUState::Running => {
+                    let name = format!("{:?}", Instr::from(opcode));
+                    tracer.span(CPU_TRACK, "instr", name, before, clock, Some(op_addr));
+                }
+                CPUState::Interrupted => {
+                    let name = format!("Interrupt -> {:04x}", self.cpu.registers.pc);
+                    tracer.span(CPU_TRACK, "interrupt", name, before, clock, Some(op_addr));
+                }
+                CPUState::Halted | CPUState::Stopped => {}
+            }
+        }
+        if let CPUState::Running = self.cpu.state {
+            if self.breakpoints.contains(&self.cpu.op_addr) {
+                return Some(StopReason::Breakpoint(self.cpu.op_addr));
+            }
+        }
+        // Waiting in STOP for the player isn't a hang.
+        if let CPUState::Stopped = self.cpu.state {
+            return None;
+        }
+        let watchdog = self.watchdog.as_mut()?;
+        watchdog.observe(self.cpu.op_addr, self.bus.clock, self.bus.activity)
+    }
+
+    fn record_history(&mut self) {
+        let addr = self.cpu.op_addr;
+        let data = match INSTR_DATA_LENGTHS[self.cpu.opcode as usize] {
+            1 => Some(self.bus.read(addr.wrapping_add(1)) as u16),
+            2 => Some(u16::from_le_bytes([
+                self.bus.read(addr.wrapping_add(1)),
+                self.bus.read(addr.wrapping_add(2)),
+            ])),
+            _ => None,
+        };
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(InstrListing {
+            instr: Instr::from(self.cpu.opcode),
+            data,
+            addr,
+        });
+    }
+
+    pub fn new(rom: Vec<u8>, bootrom: Option<PathBuf>) -> Emu {
+        let header = Header::parse(&rom);
+        let cpu = CPU::new();
+        let bus = Bus::new(rom, bootrom);
+        Emu {
+            cpu,
+            bus,
+            watchdog: None,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            watches: Watches::new(),
+            debug_info: None,
+            breakpoints: BTreeSet::new(),
+            header,
+            overrides: Overrides::default(),
+            rtc_mode: RtcMode::default(),
+            overlay: Overlay::new(),
+            battery: None,
+            input_queue: InputQueue::new(),
+            idle_skip: false,
+            exec_map: None,
+            disasm: None,
+            timeline: None,
+        }
+    }
+
+    pub fn from_path(input: PathBuf, bootrom: Option<PathBuf>) -> Result<Emu, Box<dyn Error>> {
+        let mut file = File::open(input)?;
+        let mut rom = Vec::new();
+        file.read_to_end(&mut rom)?;
+        let overrides = match Header::parse(&rom) {
+            Some(header) => CompatDb::load(Path::new(USER_FILE))?.lookup(&header),
+            None => Overrides::default(),
+        };
+        if overrides != Overrides::default() {
+            info!("Applying compatibility overrides: {:?}", overrides);
+        }
+        Self::with_overrides(rom, bootrom, overrides)
+    }
+
+    // Like new, with a compat database entry's mapper, palette and flags applied.
+    pub fn with_overrides(
+        rom: Vec<u8>,
+        bootrom: Option<PathBuf>,
+        overrides: Overrides,
+    ) -> MaybeErr<Emu> {
+        let header = Header::parse(&rom);
+        let mapper = match &overrides.mapper {
+            Some(name) => name.parse()?,
+            None => Mapper::detect(&rom),
+        };
+        let cpu = CPU::new();
+        let mut bus = Bus::with_mapper(rom, bootrom, mapper);
+        if let Some(name) = &overrides.palette {
+            bus.gpu.palette = name.parse()?;
+        }
+        Ok(Emu {
+            cpu,
+            bus,
+            watchdog: None,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            watches: Watches::new(),
+            debug_info: None,
+            breakpoints: BTreeSet::new(),
+            header,
+            rtc_mode: RtcMode::for_flags(overrides.has_flag(rtc::FROZEN_FLAG)),
+            overrides,
+            overlay: Overlay::new(),
+            battery: None,
+            input_queue: InputQueue::new(),
+            idle_skip: false,
+            exec_map: None,
+            disasm: None,
+            timeline: None,
+        })
+    }
+
+    // Hard reset. Breakpoints, watches, debug info and the battery save are kept.
+    pub fn reset(&mut self) {
+        self.cpu = CPU::new();
+        self.bus.reset();
+        self.history.clear();
+        self.input_queue.clear();
+    }
+
+    // Queues `event` for when the bus clock reaches `at_cycle`, or the next step if None.
+    // Frontends that latch their own keys every frame will overwrite queued joypad state.
+    pub fn queue_input(&mut self, event: InputEvent, at_cycle: Option<Cycles>) {
+        let at_cycle = at_cycle.unwrap_or(self.bus.clock);
+        self.input_queue.push(event, at_cycle);
+    }
+
+    // Loads an existing save into cartridge RAM and the clock, and keeps `saver` up to date from
+    // then on.
+    pub fn enable_battery(&mut self, saver: BatterySaver) -> MaybeErr<()> {
+        let footer = saver.load(self.bus.battery_ram_mut())?;
+        let rtc = self.bus.mbc3.as_mut().and_then(|mbc3| mbc3.rtc.as_mut());
+        if let (Some(rtc), Some(footer)) = (rtc, footer) {
+            rtc.load_footer(&footer, rtc::unix_time(), self.rtc_mode)?;
+            rtc.last = self.bus.clock;
+        }
+        self.bus.sram_dirty = false;
+        self.battery = Some(saver);
+        Ok(())
+    }
+
+    // Called once per frame by the frontend.
+    pub fn tick_battery(&mut self) {
+        if let Some(battery) = &mut self.battery {
+            battery.footer = self.bus.rtc_footer();
+            if battery.tick(self.bus.battery_ram(), self.bus.sram_dirty) {
+                self.bus.sram_dirty = false;
+            }
+        }
+    }
+
+    // The disassembly of the address space, redoing only what was written since the last call.
+    pub fn disassembly(&mut self) -> &Disassembler {
+        let bus = &mut self.bus;
+        // Through the bus so the mapped ROM bank is what gets decoded.
+        let memory: Vec<u8> = (0..=0xFFFF).map(|addr| bus.debug_read(addr)).collect();
+        match &mut self.disasm {
+            Some(disasm) => {
+                disasm.refresh(&memory, &mut bus.code_writes);
+            }
+            None => {
+                bus.code_writes = DirtyPages::default();
+                self.disasm = Some(Disassembler::new(&memory));
+            }
+        }
+        self.disasm.as_ref().unwrap()
+    }
+
+    // Counters since power on.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            cycles: self.bus.cycles,
+            frames: self.bus.gpu._vblank_count as u64,
+            ..self.bus.stats.clone()
+        }
+    }
+
+    pub fn view(&self) -> Vec<InstrListing> {
+        let pc = self.cpu.op_addr;
+        let mem = if self.bus.in_bios == 0 {
+            &self.bus.bootrom[..]
+        } else {
+            &self.bus.memory[..]
+        };
+        let il = gen_il(mem);
+        il.chunks(10)
+            .find(|chunk| chunk.iter().any(|e| e.addr == pc))
+            .unwrap_or_else(|| {
+                panic!(
+                    "PC: {:04x} {:?}",
+                    pc, INSTR_TABLE[mem[pc as usize] as usize]
+                )
+            })
+            .to_vec()
+    }
+}
+
+impl Drop for Emu {
+    fn drop(&mut self) {
+        if let Some(battery) = &mut self.battery {
+            battery.footer = self.bus.rtc_footer();
+            battery.flush(self.bus.battery_ram());
+        }
+    }
+}
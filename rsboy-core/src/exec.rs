@@ -0,0 +1,90 @@
+const ADDRESSES: usize = 0x10000;
+
+// Where the CPU has been, for the disassembly view. Fed once per instruction from
+// Emu::emulate_step; Emu::history already keeps the most recent instructions in order.
+#[derive(Debug, Clone)]
+pub struct ExecMap {
+    // One bit per address, set once an instruction started there.
+    ever: Vec<u64>,
+    // Instructions started at each address during the current and the last completed frame.
+    current: Vec<u32>,
+    last: Vec<u32>,
+}
+
+impl Default for ExecMap {
+    fn default() -> Self {
+        Self {
+            ever: vec![0; ADDRESSES / 64],
+            current: vec![0; ADDRESSES],
+            last: vec![0; ADDRESSES],
+        }
+    }
+}
+
+impl ExecMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, pc: u16) {
+        let pc = pc as usize;
+        self.ever[pc / 64] |= 1 << (pc % 64);
+        self.current[pc] = self.current[pc].saturating_add(1);
+    }
+
+    // Called by the frontend at the start of each frame.
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.last);
+        for count in self.current.iter_mut() {
+            *count = 0;
+        }
+    }
+
+    pub fn executed(&self, pc: u16) -> bool {
+        let pc = pc as usize;
+        self.ever[pc / 64] & (1 << (pc % 64)) != 0
+    }
+
+    // Times an instruction at `pc` ran in the last completed frame.
+    pub fn hits(&self, pc: u16) -> u32 {
+        self.last[pc as usize]
+    }
+
+    // The `n` addresses that ran most often in the last frame, hottest first.
+    pub fn hottest(&self, n: usize) -> Vec<(u16, u32)> {
+        let mut hot: Vec<(u16, u32)> = self
+            .last
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(pc, &count)| (pc as u16, count))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(n);
+        hot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_per_frame() {
+        let mut map = ExecMap::new();
+        for _ in 0..3 {
+            map.record(0x150);
+        }
+        map.record(0xFFFF);
+        assert!(map.executed(0x150) && map.executed(0xFFFF));
+        assert!(!map.executed(0x151));
+        assert_eq!(map.hits(0x150), 0);
+
+        map.end_frame();
+        assert_eq!(map.hits(0x150), 3);
+        assert_eq!(map.hottest(1), vec![(0x150, 3)]);
+        map.end_frame();
+        assert_eq!(map.hits(0x150), 0);
+        assert!(map.executed(0x150));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::clock::{self, Cycles};
+use crate::constants::MaybeErr;
+use crate::emu::{Emu, StopReason};
+use crate::gpu::{PixelData, DOTS_PER_LINE, LINES_PER_FRAME};
+
+pub const FRAME_CYCLES: Cycles = (DOTS_PER_LINE * LINES_PER_FRAME) as Cycles;
+// Longest run_frame goes on. With the LCD off there is no VBlank to end a frame, so this does;
+// the extra line keeps a VBlank landing just past FRAME_CYCLES in the frame it belongs to.
+pub const MAX_FRAME_CYCLES: Cycles = FRAME_CYCLES + DOTS_PER_LINE as Cycles;
+
+// Longest fast_boot waits for the bootrom to unmap itself, 10 seconds.
+pub const BOOT_TIMEOUT: Cycles = FRAME_CYCLES * 600;
+
+// Something that happened during Emu::run_frame, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmuEvent {
+    // VBlank started, so `pixels` holds a new picture. Always the last event when present.
+    VBlank,
+    // emulate_step asked to stop, the frame ends right there.
+    Stopped(StopReason),
+}
+
+// What one call to Emu::run_frame did.
+#[derive(Debug)]
+pub struct Frame<'a> {
+    // Last completed picture, only new if `events` has a VBlank.
+    pub pixels: &'a PixelData,
+    pub cycles: Cycles,
+    // GPU VBlank counter at the end of the frame.
+    pub vblank_count: usize,
+    // Bytes the game sent over serial during the frame.
+    pub serial_out: Option<String>,
+    pub events: Vec<EmuEvent>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn stopped(&self) -> Option<&StopReason> {
+        self.events.iter().find_map(|event| match event {
+            EmuEvent::Stopped(reason) => Some(reason),
+            _ => None,
+        })
+    }
+}
+
+impl Emu {
+    // Runs until the next VBlank starts, the emulator asks to stop or MAX_FRAME_CYCLES pass.
+    pub fn run_frame(&mut self) -> Frame<'_> {
+        let start = self.bus.clock;
+        let vblanks = self.bus.gpu._vblank_count;
+        let serial = self.bus.serial.output.len();
+        let mut events = vec![];
+        let end = clock::deadline(start, MAX_FRAME_CYCLES);
+        while self.bus.clock < end {
+            if let Some(reason) = self.emulate_step() {
+                events.push(EmuEvent::Stopped(reason));
+                break;
+            }
+            if self.bus.gpu._vblank_count != vblanks {
+                events.push(EmuEvent::VBlank);
+                break;
+            }
+        }
+        let serial_out = self
+            .bus
+            .serial
+            .output
+            .get(serial..)
+            .filter(|out| !out.is_empty());
+        Frame {
+            pixels: self.bus.gpu.screen(),
+            cycles: self.bus.clock - start,
+            vblank_count: self.bus.gpu._vblank_count,
+            serial_out: serial_out.map(str::to_string),
+            events,
+        }
+    }
+
+    // Runs until VBlank `n` since power on, for screenshots that come out the same every run.
+    // With the LCD off there is none, so it also stops after `n` frames' worth of cycles.
+    pub fn run_to_frame(&mut self, n: usize) -> Option<StopReason> {
+        let end = (n as Cycles).saturating_mul(MAX_FRAME_CYCLES);
+        while self.bus.gpu._vblank_count < n && self.bus.clock < end {
+            if let Some(reason) = self.run_frame().stopped() {
+                return Some(reason.clone());
+            }
+        }
+        None
+    }
+
+    // Runs the bootrom as fast as possible until it unmaps itself by writing FF50, so everything
+    // it leaves behind (registers, VRAM, IO) is there without waiting through the logo. Does
+    // nothing without a bootrom or once it is done.
+    pub fn fast_boot(&mut self) -> MaybeErr<()> {
+        let end = clock::deadline(self.bus.clock, BOOT_TIMEOUT);
+        while self.bus.in_bios == 0 {
+            if let Some(reason) = self.emulate_step() {
+                return Err(format!("Bootrom stopped: {}", reason).into());
+            }
+            if self.bus.clock >= end {
+                return Err("Bootrom didn't finish".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ends_on_vblank_or_stop() {
+        // A ROM of NOPs, the start values turn the LCD on.
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        let first = emu.run_frame();
+        assert_eq!(first.events, vec![EmuEvent::VBlank]);
+        let count = first.vblank_count;
+        let frame = emu.run_frame();
+        assert_eq!(frame.vblank_count, count + 1);
+        assert!(frame.cycles >= FRAME_CYCLES - 4 && frame.cycles <= FRAME_CYCLES + 4);
+        assert_eq!(frame.serial_out, None);
+
+        let pc = emu.cpu.op_addr.wrapping_add(0x10);
+        emu.breakpoints.insert(pc);
+        let frame = emu.run_frame();
+        assert_eq!(frame.stopped(), Some(&StopReason::Breakpoint(pc)));
+        assert!(frame.cycles < FRAME_CYCLES);
+    }
+
+    #[test]
+    fn runs_to_a_frame() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        assert_eq!(emu.run_to_frame(3), None);
+        assert_eq!(emu.bus.gpu._vblank_count, 3);
+        // Already there.
+        emu.run_to_frame(2);
+        assert_eq!(emu.bus.gpu._vblank_count, 3);
+
+        emu.bus.gpu.lcdc = 0;
+        emu.run_to_frame(10);
+        assert_eq!(emu.bus.gpu._vblank_count, 3);
+        assert!(emu.bus.clock >= 10 * MAX_FRAME_CYCLES);
+    }
+
+    #[test]
+    fn lcd_off_is_capped() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        // The first step loads the start values, which turn the LCD on.
+        emu.emulate_step();
+        emu.bus.gpu.lcdc = 0;
+        let frame = emu.run_frame();
+        assert!(frame.events.is_empty());
+        assert!(frame.cycles >= MAX_FRAME_CYCLES);
+        assert_eq!(frame.vblank_count, 0);
+    }
+
+    #[test]
+    fn fast_boot_keeps_what_the_bootrom_did() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        // LD B,0x40; DEC B; JR NZ,-3; LD A,1; LDH (0x50),A
+        let boot = [0x06, 0x40, 0x05, 0x20, 0xFD, 0x3E, 0x01, 0xE0, 0x50];
+        emu.bus.bootrom = [0; 0x100];
+        emu.bus.bootrom[..boot.len()].copy_from_slice(&boot);
+        emu.bus.in_bios = 0;
+        emu.bus.rom_start_signal = false;
+        emu.fast_boot().unwrap();
+        assert_eq!(emu.bus.in_bios, 1);
+        assert_eq!((emu.cpu.registers.a, emu.cpu.registers.b), (1, 0));
+        let clock = emu.bus.clock;
+        assert!(clock > 0x40 * 3);
+        emu.fast_boot().unwrap();
+        assert_eq!(emu.bus.clock, clock);
+    }
+}
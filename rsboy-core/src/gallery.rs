@@ -0,0 +1,150 @@
+use crate::batch::{self, escape_html};
+use crate::constants::MaybeErr;
+use crate::emu::Emu;
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Screenshots of ROMs at fixed frames, for tracking rendering across versions. The list has one
+// `<rom> <frame>` per line, # starts a comment and ROM paths are relative to the list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shot {
+    pub rom: PathBuf,
+    pub frame: usize,
+}
+
+impl Shot {
+    // Where the PNG goes in the gallery directory.
+    pub fn image(&self) -> PathBuf {
+        let name = self.rom.file_stem().unwrap_or_default();
+        Path::new(name).join(format!("{:06}.png", self.frame))
+    }
+}
+
+pub fn parse_list(text: &str, base: &Path) -> MaybeErr<Vec<Shot>> {
+    let mut shots = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        // The frame is last, so ROM paths may have spaces.
+        let mut fields = line.rsplitn(2, char::is_whitespace);
+        let frame = fields.next().unwrap_or_default();
+        let rom = match fields.next() {
+            Some(rom) => rom.trim(),
+            None => return Err(format!("Line {}: expected <rom> <frame>", i + 1).into()),
+        };
+        let frame = frame
+            .parse()
+            .map_err(|e| format!("Line {}: bad frame {:?}: {}", i + 1, frame, e))?;
+        shots.push(Shot {
+            rom: base.join(rom),
+            frame,
+        });
+    }
+    Ok(shots)
+}
+
+// One screenshot taken, or why it couldn't be.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub shot: Shot,
+    pub result: Result<u64, String>,
+}
+
+// Runs every shot from power on without a bootrom and writes its PNG under `dir`.
+pub fn run(shots: &[Shot], dir: &Path) -> Vec<Entry> {
+    shots
+        .par_iter()
+        .map(|shot| Entry {
+            shot: shot.clone(),
+            result: take(shot, dir).map_err(|e| e.to_string()),
+        })
+        .collect()
+}
+
+fn take(shot: &Shot, dir: &Path) -> MaybeErr<u64> {
+    let mut emu = Emu::from_path(shot.rom.clone(), None)?;
+    if let Some(reason) = emu.run_to_frame(shot.frame) {
+        return Err(reason.to_string().into());
+    }
+    let frame = emu.bus.gpu.visible_frame();
+    let path = dir.join(shot.image());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, batch::png(&frame))?;
+    Ok(batch::frame_hash(&frame))
+}
+
+// index.html for the gallery directory, one figure per shot in list order.
+pub fn html(entries: &[Entry]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>.rsboy gallery</title>\n<style>figure { display: inline-block; margin: 8px; } img { width: 320px; image-rendering: pixelated; }</style>\n</head><body>\n",
+    );
+    for entry in entries {
+        let name = entry.shot.rom.file_name().unwrap_or_default();
+        let caption = format!("{} @ {}", name.to_string_lossy(), entry.shot.frame);
+        let body = match &entry.result {
+            Ok(hash) => format!(
+                "<img src=\"{}\" alt=\"{}\"><figcaption>{}<br><code>{:016x}</code></figcaption>",
+                escape_html(&entry.shot.image().to_string_lossy().replace('\\', "/")),
+                escape_html(&caption),
+                escape_html(&caption),
+                hash
+            ),
+            Err(e) => format!(
+                "<figcaption>{}<br>{}</figcaption>",
+                escape_html(&caption),
+                escape_html(e)
+            ),
+        };
+        html.push_str(&format!("<figure>{}</figure>\n", body));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_lists() {
+        let list = "# title screens\nroms/tetris.gb 300\nroms/Pokemon Red.gb\t1200 # intro\n\n";
+        let shots = parse_list(list, Path::new("/data")).unwrap();
+        assert_eq!(shots.len(), 2);
+        assert_eq!(shots[1].rom, Path::new("/data/roms/Pokemon Red.gb"));
+        assert_eq!(shots[1].frame, 1200);
+        assert_eq!(shots[0].image(), Path::new("tetris").join("000300.png"));
+        assert!(parse_list("tetris.gb", Path::new(".")).is_err());
+        assert!(parse_list("tetris.gb three", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn writes_images_and_index() {
+        let dir = std::env::temp_dir().join(format!("rsboy-gallery-{}", std::process::id()));
+        let rom = dir.join("nops.gb");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&rom, vec![0; 0x8000]).unwrap();
+        let shots = vec![
+            Shot { rom, frame: 2 },
+            Shot {
+                rom: dir.join("missing.gb"),
+                frame: 2,
+            },
+        ];
+        let entries = run(&shots, &dir);
+        let hash = *entries[0].result.as_ref().unwrap();
+        assert!(entries[1].result.is_err());
+        let png = fs::read(dir.join(shots[0].image())).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        // Same ROM and frame, same picture.
+        assert_eq!(run(&shots[..1], &dir)[0].result, Ok(hash));
+        let index = html(&entries);
+        assert!(index.contains("src=\"nops/000002.png\""));
+        assert!(index.contains("missing.gb @ 2"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
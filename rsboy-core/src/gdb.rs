@@ -0,0 +1,449 @@
+use crate::bus::Memory;
+use crate::constants::MaybeErr;
+use crate::cpu::CPUState;
+use crate::emu::Emu;
+use log::{info, warn};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// GDB remote serial protocol stub, see https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+// `target remote :<port>` attaches and holds emulation, `continue` runs until a breakpoint or
+// Ctrl-C and `detach` lets the game run on. Registers are AF, BC, DE, HL, SP and PC as 16 bit
+// little endian values, the first six of GDB's z80 target.
+
+const SIGINT: &str = "S02";
+const SIGTRAP: &str = "S05";
+const INTERRUPT: u8 = 0x03;
+const REGISTER_COUNT: usize = 6;
+
+// Protocol state of one attached client, without the socket.
+#[derive(Debug, Default)]
+pub struct Session {
+    // The client continued and waits for a stop reply.
+    pub running: bool,
+    // The client detached or killed the session.
+    pub detached: bool,
+}
+
+impl Session {
+    // Answers one packet, None if the reply comes later (continue) or never (kill).
+    pub fn handle(&mut self, emu: &mut Emu, packet: &str) -> Option<String> {
+        let command = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+        let reply = match command {
+            "?" => SIGTRAP.to_string(),
+            "g" => registers(emu)
+                .iter()
+                .map(|r| hex_bytes(&r.to_le_bytes()))
+                .collect(),
+            "G" => ok_or_error(set_registers(emu, args)),
+            "p" => match parse_hex(args).map(|n| n as usize) {
+                Some(n) if n < REGISTER_COUNT => hex_bytes(&registers(emu)[n].to_le_bytes()),
+                _ => "E01".to_string(),
+            },
+            "P" => ok_or_error(set_register(emu, args)),
+            "m" => read_memory(emu, args).unwrap_or_else(|| "E01".to_string()),
+            "M" => ok_or_error(write_memory(emu, args)),
+            "Z" | "z" => ok_or_error(breakpoint(emu, args, command == "Z")),
+            "s" => {
+                step(emu);
+                SIGTRAP.to_string()
+            }
+            "c" => {
+                self.running = true;
+                return None;
+            }
+            "D" => {
+                self.detached = true;
+                "OK".to_string()
+            }
+            "k" => {
+                self.detached = true;
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => query(args).to_string(),
+            // An empty reply tells the client the packet isn't supported.
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+}
+
+fn query(args: &str) -> &'static str {
+    match args.split(':').next() {
+        Some("Supported") => "PacketSize=1000",
+        Some("Attached") => "1",
+        Some("C") => "QC1",
+        Some("fThreadInfo") => "m1",
+        Some("sThreadInfo") => "l",
+        _ => "",
+    }
+}
+
+// Address of the next instruction, a running CPU has already fetched its opcode.
+fn pc(emu: &Emu) -> u16 {
+    match emu.cpu.state {
+        CPUState::Running => emu.cpu.op_addr,
+        _ => emu.cpu.registers.pc,
+    }
+}
+
+// Refetches so the CPU continues at `address`.
+fn set_pc(emu: &mut Emu, address: u16) {
+    if address == pc(emu) {
+        return;
+    }
+    emu.cpu.opcode = emu.bus.debug_read(address);
+    emu.cpu.op_addr = address;
+    emu.cpu.registers.pc = address.wrapping_add(1);
+    emu.cpu.state = CPUState::Running;
+    emu.cpu.halt = false;
+}
+
+fn registers(emu: &Emu) -> [u16; REGISTER_COUNT] {
+    let r = &emu.cpu.registers;
+    let pair = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+    [
+        pair(r.a, r.f),
+        pair(r.b, r.c),
+        pair(r.d, r.e),
+        pair(r.h, r.l),
+        r.sp,
+        pc(emu),
+    ]
+}
+
+fn write_register(emu: &mut Emu, n: usize, value: u16) {
+    let [hi, lo] = value.to_be_bytes();
+    let r = &mut emu.cpu.registers;
+    match n {
+        0 => {
+            r.a = hi;
+            r.f = lo & 0xF0;
+        }
+        1 => {
+            r.b = hi;
+            r.c = lo;
+        }
+        2 => {
+            r.d = hi;
+            r.e = lo;
+        }
+        3 => {
+            r.h = hi;
+            r.l = lo;
+        }
+        4 => r.sp = value,
+        _ => set_pc(emu, value),
+    }
+}
+
+fn set_registers(emu: &mut Emu, args: &str) -> Option<()> {
+    let bytes = parse_bytes(args)?;
+    if bytes.len() < REGISTER_COUNT * 2 {
+        return None;
+    }
+    for (n, value) in bytes.chunks_exact(2).take(REGISTER_COUNT).enumerate() {
+        write_register(emu, n, u16::from_le_bytes([value[0], value[1]]));
+    }
+    Some(())
+}
+
+// `n=value`.
+fn set_register(emu: &mut Emu, args: &str) -> Option<()> {
+    let mut parts = args.splitn(2, '=');
+    let n = parse_hex(parts.next()?)? as usize;
+    let bytes = parse_bytes(parts.next()?)?;
+    if n >= REGISTER_COUNT || bytes.len() != 2 {
+        return None;
+    }
+    write_register(emu, n, u16::from_le_bytes([bytes[0], bytes[1]]));
+    Some(())
+}
+
+// `address,length`.
+fn parse_range(args: &str) -> Option<(u16, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let address = parse_hex(parts.next()?)? as u16;
+    let len = parse_hex(parts.next()?)? as usize;
+    Some((address, len))
+}
+
+fn read_memory(emu: &Emu, args: &str) -> Option<String> {
+    let (address, len) = parse_range(args)?;
+    // Past the whole address space the read would only wrap around.
+    let bytes: Vec<u8> = (0..len.min(0x10000))
+        .map(|i| emu.bus.debug_read(address.wrapping_add(i as u16)))
+        .collect();
+    Some(hex_bytes(&bytes))
+}
+
+// `address,length:data`, written through the bus like the CPU would.
+fn write_memory(emu: &mut Emu, args: &str) -> Option<()> {
+    let mut parts = args.splitn(2, ':');
+    let (address, len) = parse_range(parts.next()?)?;
+    let bytes = parse_bytes(parts.next()?)?;
+    if bytes.len() != len {
+        return None;
+    }
+    // Bus::write panics on the bootrom while it's mapped.
+    let bootrom = (0..len).any(|i| address.wrapping_add(i as u16) <= 0x0100);
+    if bootrom && emu.bus.in_bios == 0 {
+        return None;
+    }
+    for (i, &value) in bytes.iter().enumerate() {
+        emu.bus.write(address.wrapping_add(i as u16), value);
+    }
+    Some(())
+}
+
+// `type,address,kind`, software and hardware breakpoints both go to Emu::breakpoints.
+fn breakpoint(emu: &mut Emu, args: &str, insert: bool) -> Option<()> {
+    let mut parts = args.split(',');
+    if !matches!(parts.next()?, "0" | "1") {
+        return None;
+    }
+    let address = parse_hex(parts.next()?)? as u16;
+    if insert {
+        emu.breakpoints.insert(address);
+    } else {
+        emu.breakpoints.remove(&address);
+    }
+    Some(())
+}
+
+// One instruction, or into the handler when an interrupt is dispatched first.
+fn step(emu: &mut Emu) {
+    emu.emulate_step();
+    while let CPUState::Interrupted = emu.cpu.state {
+        emu.emulate_step();
+    }
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => "OK".to_string(),
+        None => "E01".to_string(),
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+fn parse_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// `$data#checksum`.
+pub fn frame(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum)
+}
+
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+// Takes the next packet or Ctrl-C off `buffer`, acknowledgements are dropped.
+fn take_incoming(buffer: &mut Vec<u8>) -> Option<Incoming> {
+    loop {
+        match *buffer.first()? {
+            b'$' => break,
+            INTERRUPT => {
+                buffer.remove(0);
+                return Some(Incoming::Interrupt);
+            }
+            _ => {
+                buffer.remove(0);
+            }
+        }
+    }
+    let end = buffer.iter().position(|&b| b == b'#')?;
+    // The two checksum digits, TCP already keeps the data intact.
+    if buffer.len() < end + 3 {
+        return None;
+    }
+    let packet = String::from_utf8_lossy(&buffer[1..end]).into_owned();
+    buffer.drain(..end + 3);
+    Some(Incoming::Packet(packet))
+}
+
+// Listens for one client at a time, polled by the frontend between frames.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    session: Session,
+}
+
+impl GdbStub {
+    pub fn bind(port: u16) -> MaybeErr<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        info!("GDB stub listening on port {}", port);
+        Ok(Self {
+            listener,
+            client: None,
+            buffer: vec![],
+            session: Session::default(),
+        })
+    }
+
+    pub fn attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Accepts a client and answers what it sent. `paused` is whether emulation is stopped right
+    // now, say on a breakpoint. Returns whether it should stay stopped.
+    pub fn poll(&mut self, emu: &mut Emu, paused: bool) -> bool {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        warn!("GDB client {} refused: {}", address, e);
+                        return paused;
+                    }
+                    info!("GDB client {} attached", address);
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.session = Session::default();
+                }
+                Err(_) => return paused,
+            }
+        }
+        if let Err(e) = self.serve(emu, paused) {
+            warn!("GDB client dropped: {}", e);
+            self.session.detached = true;
+        }
+        if self.session.detached {
+            info!("GDB client detached");
+            self.client = None;
+            return false;
+        }
+        !self.session.running
+    }
+
+    fn serve(&mut self, emu: &mut Emu, paused: bool) -> io::Result<()> {
+        let stream = match &mut self.client {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        if paused && self.session.running {
+            self.session.running = false;
+            stream.write_all(frame(SIGTRAP).as_bytes())?;
+        }
+        let mut chunk = [0; 1024];
+        loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        while let Some(incoming) = take_incoming(&mut self.buffer) {
+            match incoming {
+                Incoming::Interrupt if self.session.running => {
+                    self.session.running = false;
+                    stream.write_all(frame(SIGINT).as_bytes())?;
+                }
+                Incoming::Interrupt => {}
+                Incoming::Packet(packet) => {
+                    stream.write_all(b"+")?;
+                    if let Some(reply) = self.session.handle(emu, &packet) {
+                        stream.write_all(frame(&reply).as_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn answers_packets() {
+        // NOP; LD A,$42; JR -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x105].copy_from_slice(&[0x00, 0x3E, 0x42, 0x18, 0xFE]);
+        let mut emu = Emu::new(rom, None);
+        let mut session = Session::default();
+        let mut handle = |emu: &mut Emu, packet: &str| session.handle(emu, packet).unwrap();
+
+        // The first step starts the ROM at 0100 without a bootrom.
+        assert_eq!(handle(&mut emu, "s"), SIGTRAP);
+        let regs = handle(&mut emu, "g");
+        assert_eq!(regs.len(), REGISTER_COUNT * 4);
+        assert_eq!(&regs[20..], "0001");
+        assert_eq!(handle(&mut emu, "m100,3"), "003e42");
+        assert_eq!(handle(&mut emu, "Mc000,2:beef"), "OK");
+        assert_eq!(handle(&mut emu, "mc000,2"), "beef");
+        assert_eq!(handle(&mut emu, "Z0,103,1"), "OK");
+        assert!(emu.breakpoints.contains(&0x103));
+        assert_eq!(handle(&mut emu, "z0,103,1"), "OK");
+        assert!(emu.breakpoints.is_empty());
+        assert_eq!(handle(&mut emu, "s"), SIGTRAP);
+        assert_eq!(handle(&mut emu, "p5"), "0101");
+        assert_eq!(handle(&mut emu, "s"), SIGTRAP);
+        assert_eq!(&handle(&mut emu, "p0")[2..], "42");
+
+        // Jump back to the NOP by writing PC.
+        assert_eq!(handle(&mut emu, "P5=0001"), "OK");
+        assert_eq!(handle(&mut emu, "p5"), "0001");
+        assert_eq!(handle(&mut emu, "p9"), "E01");
+        assert_eq!(handle(&mut emu, "vMustReplyEmpty"), "");
+        assert_eq!(session.handle(&mut emu, "c"), None);
+        assert!(session.running);
+        assert_eq!(session.handle(&mut emu, "D"), Some("OK".to_string()));
+        assert!(session.detached);
+    }
+
+    #[test]
+    fn guards_memory_packets() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        let mut session = Session::default();
+        emu.bus.in_bios = 0;
+        assert_eq!(session.handle(&mut emu, "M100,1:00").unwrap(), "E01");
+        assert_eq!(session.handle(&mut emu, "Mfffe,4:00000000").unwrap(), "E01");
+        assert_eq!(session.handle(&mut emu, "Mc000,1:00").unwrap(), "OK");
+        emu.bus.in_bios = 1;
+        assert_eq!(session.handle(&mut emu, "M100,1:00").unwrap(), "OK");
+        let reply = session.handle(&mut emu, "m0,ffffffff").unwrap();
+        assert_eq!(reply.len(), 0x10000 * 2);
+    }
+
+    #[test]
+    fn frames_packets() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        let mut buffer = b"+$g#67\x03$m0".to_vec();
+        assert_eq!(
+            take_incoming(&mut buffer),
+            Some(Incoming::Packet("g".to_string()))
+        );
+        assert_eq!(take_incoming(&mut buffer), Some(Incoming::Interrupt));
+        assert_eq!(take_incoming(&mut buffer), None);
+        buffer.extend_from_slice(b",1#fa");
+        assert_eq!(
+            take_incoming(&mut buffer),
+            Some(Incoming::Packet("m0,1".to_string()))
+        );
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::constants::MaybeErr;
+use crate::cpu::CPUState;
+use crate::emu::{str_il, Emu, InstrListing};
+use crate::import::parse_registers;
+use crate::registers::RegisterState;
+use std::{fmt::Display, io::BufRead};
+
+// Compares execution against a reference log with one line per instruction,
+// holding the registers right before it runs (gameboy-doctor format):
+//   A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+
+pub struct Divergence {
+    // 1-based line of the reference log.
+    pub line: usize,
+    pub expected: RegisterState,
+    pub actual: RegisterState,
+    pub history: Vec<InstrListing>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Diverged from reference at line {}\nExpected: {}\nActual:   {}\nLast {} instructions:\n{}",
+            self.line,
+            self.expected,
+            self.actual,
+            self.history.len(),
+            str_il(&self.history)
+        )
+    }
+}
+
+// Registers as they were before the instruction at op_addr ran.
+fn current(emu: &Emu) -> RegisterState {
+    emu.cpu.registers.jump(emu.cpu.op_addr)
+}
+
+// Runs until the CPU is about to execute an instruction of the cartridge.
+fn sync(emu: &mut Emu) {
+    while emu.bus.rom_start_signal || emu.bus.in_bios == 0 {
+        emu.emulate_step();
+    }
+    while let CPUState::Interrupted = emu.cpu.state {
+        emu.emulate_step();
+    }
+}
+
+pub fn compare<R: BufRead>(emu: &mut Emu, reference: R) -> MaybeErr<Option<Divergence>> {
+    sync(emu);
+    for (i, line) in reference.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let expected = parse_registers(&line)?;
+        let actual = current(emu);
+        if actual != expected {
+            return Ok(Some(Divergence {
+                line: i + 1,
+                expected,
+                actual,
+                history: emu.history.iter().cloned().collect(),
+            }));
+        }
+        emu.emulate_step();
+        // Interrupt dispatch isn't an instruction, the reference doesn't log it.
+        while let CPUState::Interrupted = emu.cpu.state {
+            emu.emulate_step();
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // LD A,$42; INC B; NOP; JR -2
+    const PROGRAM: [u8; 6] = [0x3E, 0x42, 0x04, 0x00, 0x18, 0xFE];
+
+    const REFERENCE: &str = "\
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100
+A:42 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102
+A:42 F:10 B:01 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103
+A:42 F:10 B:01 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0104
+A:42 F:10 B:01 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0104
+";
+
+    fn emu() -> Emu {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x106].copy_from_slice(&PROGRAM);
+        Emu::new(rom, None)
+    }
+
+    #[test]
+    fn matching_log() {
+        let divergence = compare(&mut emu(), REFERENCE.as_bytes()).unwrap();
+        assert!(divergence.is_none());
+    }
+
+    #[test]
+    fn reports_first_divergence() {
+        let reference = REFERENCE.replace(
+            "B:01 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103",
+            "B:02 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103",
+        );
+        let divergence = compare(&mut emu(), reference.as_bytes()).unwrap().unwrap();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.expected.b, 2);
+        assert_eq!(divergence.actual.b, 1);
+        assert_eq!(divergence.actual.pc, 0x103);
+        assert_eq!(divergence.history.last().unwrap().addr, 0x102);
+    }
+}
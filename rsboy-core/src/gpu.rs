@@ -0,0 +1,883 @@
+use crate::io::{IoContext, IoDevice};
+use crate::iomap::{Lcdc, Stat};
+use crate::video::color::ColorCorrection;
+use crate::video::palette::Palette;
+use crate::{cpu, texture::*};
+use std::{convert::TryInto, fmt::Display, ops::Range, time};
+
+pub const VRAM_START: usize = 0x8000;
+pub const VRAM_END: usize = 0x9FFF;
+pub const OAM_START: usize = 0xFE00;
+pub const OAM_END: usize = 0xFE9F;
+pub const TILE_DATA_RANGE: Range<usize> = 0..0x1800;
+pub const MAP_DATA_RANGE: Range<usize> = 0x1800..0x1C00;
+pub const TILE_SIZE: usize = 16;
+pub const OAM_ENTRIES: usize = 40;
+// Sprites the OAM scan picks per line, later ones in OAM order aren't drawn on it.
+pub const SPRITES_PER_LINE: usize = 10;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+// Dots (bus clocks) per scanline and scanlines per frame, VBlank included.
+pub const DOTS_PER_LINE: usize = 456;
+pub const LINES_PER_FRAME: usize = END_VBLANK as usize;
+// LY already reads 0 this many dots into the last line, 153, see https://gbdev.io/pandocs/STAT.html
+pub const LINE_153_DOTS: usize = 4;
+
+#[derive(Debug)]
+pub(crate) enum GpuMode {
+    HBlank, // 0
+    VBlank, // 1
+    Oam,    // 2
+    Vram,   // 3
+}
+impl GpuMode {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            GpuMode::HBlank => "HBlank",
+            GpuMode::VBlank => "VBlank",
+            GpuMode::Oam => "OAM",
+            GpuMode::Vram => "VRAM",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SpriteSize {
+    Square,
+    Tall,
+}
+
+// Global GPU struct.
+// Holds I/O Registers relevant to GPU. Make sure these are available from bus struct.
+pub struct GPU {
+    pub(crate) mode: GpuMode,
+    pub(crate) clock: usize,
+    pub scanline: u8,
+    pub vram: [u8; 0x2000],
+    pub oam: [u8; 0x100],
+    pub lcdc: u8,
+    pub lcdstat: u8,
+    pub scrollx: u8,
+    pub scrolly: u8,
+    pub bgrdpal: u8, //Background Palette
+    pub obj0pal: u8, //Object0 Palette
+    pub obj1pal: u8, //Object1 Palette
+    pub windowx: u8, //
+    pub windowy: u8, //
+    pub _vblank_count: usize,
+    // Finished frame, only replaced at VBlank. The frontend reads from here.
+    screen: Box<PixelData>,
+    // The same frame as shades 0-3, `screen` is these through `palette`.
+    shades: Box<ShadeData>,
+    pub palette: Palette,
+    pub color_correction: ColorCorrection,
+    // Rows of `screen` that changed since take_dirty_lines, all of them to begin with.
+    dirty: [bool; SCREEN_HEIGHT],
+    // Scratch map the frame is drawn into before cropping. Only None while render_map() borrows it.
+    map: Option<Box<ShadeMap>>,
+}
+
+const END_HBLANK: u8 = 144;
+const END_VBLANK: u8 = 154;
+
+// The visible screen.
+pub type PixelData = [[u32; SCREEN_WIDTH]; SCREEN_HEIGHT];
+// The whole 32x32 tile background map.
+pub type PixelData256 = [[u32; 256]; 256];
+// Shades (colors after the BGP/OBP palette registers, 0 lightest) of the screen and the map.
+pub type ShadeData = [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT];
+pub type ShadeMap = [[u8; 256]; 256];
+pub type PixelMap = [u8; 256 * 256 * 4];
+
+struct SpriteAttribute {
+    above: bool,
+    yflip: bool,
+    xflip: bool,
+    obj0: bool, //True for OBJ0, OBJ1 otherwise.
+}
+impl From<&u8> for SpriteAttribute {
+    fn from(byte: &u8) -> Self {
+        Self {
+            above: byte & 0x80 != 0,
+            yflip: byte & 0x40 != 0,
+            xflip: byte & 0x20 != 0,
+            obj0: byte & 0x10 == 0,
+        }
+    }
+}
+
+// Decoded OAM entry.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    pub index: usize,
+    // Raw OAM position, the sprite's top left is at (x - 8, y - 16) on screen.
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub obp1: bool,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    // Drawn behind background colors 1-3.
+    pub behind_bg: bool,
+    // 8 or 16 rows as drawn, flips and palette applied, transparent pixels have alpha 0.
+    pub pixels: Vec<[u32; 8]>,
+}
+
+impl Sprite {
+    pub fn screen_pos(&self) -> (i32, i32) {
+        (self.x as i32 - 8, self.y as i32 - 16)
+    }
+
+    // Off screen sprites are hidden (X=0 or X>=168, Y=0 or Y>=160).
+    pub fn visible(&self) -> bool {
+        let (x, y) = self.screen_pos();
+        let h = self.pixels.len() as i32;
+        x > -8 && x < SCREEN_WIDTH as i32 && y > -h && y < SCREEN_HEIGHT as i32
+    }
+}
+
+impl Display for Sprite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "#{:02} X:{:3} Y:{:3} Tile:{:02x} {} {}{} {}",
+            self.index,
+            self.x,
+            self.y,
+            self.tile,
+            if self.obp1 { "OBP1" } else { "OBP0" },
+            if self.x_flip { "X" } else { "-" },
+            if self.y_flip { "Y" } else { "-" },
+            if self.behind_bg { "BG" } else { "OBJ" },
+        )
+    }
+}
+
+impl Default for GPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GPU {
+    pub fn new() -> Self {
+        Self {
+            mode: GpuMode::Oam,
+            clock: 0,
+            scanline: 0,
+            // FFxx Values
+            lcdc: 0,
+            lcdstat: 0,
+            scrolly: 0,
+            scrollx: 0,
+            bgrdpal: 0,
+            obj0pal: 0,
+            obj1pal: 0,
+            windowx: 0,
+            windowy: 0,
+            // FFxx Values end
+            _vblank_count: 0,
+            vram: [0; 0x2000],
+            oam: [0; 0x100],
+            screen: Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]),
+            shades: Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]),
+            palette: Palette::default(),
+            color_correction: ColorCorrection::default(),
+            dirty: [true; SCREEN_HEIGHT],
+            map: Some(Box::new([[0; 256]; 256])),
+        }
+    }
+    //   Bit 7 - LCD Display Enable             (0=Off, 1=On)
+    pub fn is_on(&self) -> bool {
+        self.lcdc & 0b1000_0000 == 0b1000_0000
+    }
+    fn bg_tile_data(&self, value: u8) -> Range<usize> {
+        if self.lcdc & 0b0001_0000 != 0 {
+            let start_address = value as usize * 16;
+            let end_address = start_address + 16;
+            start_address..end_address
+        } else {
+            let offset = value as i8 as i32;
+            let start_address = (0x1000 + offset * 16) as usize;
+            let end_address = start_address + 16;
+            start_address..end_address
+        }
+    }
+    //   Bit 2 - OBJ (Sprite) Size              (0=8x8, 1=8x16)
+    fn sprite_size(&self) -> SpriteSize {
+        if self.lcdc & 0b100 == 0b100 {
+            SpriteSize::Tall
+        } else {
+            SpriteSize::Square
+        }
+    }
+    fn sprite_height(&self) -> usize {
+        match self.sprite_size() {
+            SpriteSize::Tall => 16,
+            SpriteSize::Square => 8,
+        }
+    }
+    //   Bit 1 - OBJ (Sprite) Display Enable    (0=Off, 1=On)
+    fn sprite_display_enabled(&self) -> bool {
+        self.lcdc & 0b10 == 0b10
+    }
+    //   Bit 0 - BG Display (for CGB see below) (0=Off, 1=On)
+
+    pub fn print_sprite_table(&self) {
+        for sprite in self.sprites() {
+            println!("{}", sprite);
+        }
+    }
+
+    // Sprites covering each screen line, including any past SPRITES_PER_LINE.
+    pub fn sprites_per_line(&self) -> [usize; SCREEN_HEIGHT] {
+        let mut counts = [0; SCREEN_HEIGHT];
+        let height = self.sprite_height();
+        for index in 0..OAM_ENTRIES {
+            // OAM Y is 16 more than the screen line of the sprite's top row.
+            let y = self.oam_entry(index)[0] as usize;
+            let bottom = (y + height).saturating_sub(16).min(SCREEN_HEIGHT);
+            for count in &mut counts[y.saturating_sub(16).min(bottom)..bottom] {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    // Decodes every OAM entry with the current sprite size and palettes.
+    pub fn sprites(&self) -> Vec<Sprite> {
+        let tall = match self.sprite_size() {
+            SpriteSize::Tall => true,
+            SpriteSize::Square => false,
+        };
+        let colors = self.palette.corrected(self.color_correction);
+        (0..OAM_ENTRIES)
+            .map(|index| {
+                let &[y, x, tile, flags] = self.oam_entry(index);
+                let attributes = SpriteAttribute::from(&flags);
+                let palette = if attributes.obj0 {
+                    self.obj0pal
+                } else {
+                    self.obj1pal
+                };
+                // 8x16 sprites ignore bit 0 of the tile index.
+                let tiles = if tall {
+                    vec![tile & 0xFE, tile | 1]
+                } else {
+                    vec![tile]
+                };
+                let mut pixels: Vec<[u32; 8]> = tiles
+                    .iter()
+                    .flat_map(|&t| {
+                        let data = &self.vram[Tile::range(t as usize * TILE_SIZE)];
+                        Tile::sprite_construct(colors, palette, data)
+                            .texture()
+                            .to_vec()
+                    })
+                    .collect();
+                if attributes.yflip {
+                    pixels.reverse();
+                }
+                if attributes.xflip {
+                    pixels.iter_mut().for_each(|row| row.reverse());
+                }
+                Sprite {
+                    index,
+                    y,
+                    x,
+                    tile,
+                    obp1: !attributes.obj0,
+                    x_flip: attributes.xflip,
+                    y_flip: attributes.yflip,
+                    behind_bg: attributes.above,
+                    pixels,
+                }
+            })
+            .collect()
+    }
+
+    // VRAM by CPU address, 0x8000-0x9FFF.
+    pub fn vram_abs(&self, addr: u16) -> u8 {
+        self.vram[addr as usize - VRAM_START]
+    }
+
+    pub fn write_vram_abs(&mut self, addr: u16, value: u8) {
+        self.vram[addr as usize - VRAM_START] = value;
+    }
+
+    // VRAM and OAM by CPU address for debuggers, readable in any mode.
+    pub fn debug_read(&self, addr: u16) -> u8 {
+        match addr as usize {
+            VRAM_START..=VRAM_END => self.vram_abs(addr),
+            OAM_START..=OAM_END => self.oam[addr as usize - OAM_START],
+            _ => 0xFF,
+        }
+    }
+
+    // VRAM by offset from 0x8000.
+    pub fn vram_rel(&self, offset: usize) -> u8 {
+        self.vram[offset]
+    }
+
+    // The 4 bytes of sprite `i`: Y, X, tile index, attributes.
+    pub fn oam_entry(&self, i: usize) -> &[u8; 4] {
+        self.oam[i * 4..i * 4 + 4].try_into().unwrap()
+    }
+
+    // Returns true if IRQ is requested.
+    pub fn cycle(&mut self, flag: &mut u8) {
+        if !self.is_on() {
+            return;
+        }
+        self.clock += 1;
+        self.step(flag)
+    }
+
+    pub fn scroll(&self) -> (u32, u32) {
+        (self.scrollx as u32, self.scrolly as u32)
+    }
+
+    pub fn mode_name(&self) -> &'static str {
+        self.mode.name()
+    }
+
+    // LY as the game reads it. Line 153 only reports itself for its first few dots, then 0 until
+    // line 0 actually starts.
+    pub fn ly(&self) -> u8 {
+        if self.scanline == END_VBLANK - 1 && self.dot() >= LINE_153_DOTS {
+            0
+        } else {
+            self.scanline
+        }
+    }
+
+    // Moves to the start of `line` in the mode that line starts in. Lines past the last one start
+    // a new frame, so `scanline` stays below END_VBLANK.
+    pub fn set_scanline(&mut self, line: u8) {
+        self.enter_line(if line < END_VBLANK { line } else { 0 });
+        self.clock = 0;
+    }
+
+    // Line after the current one, wrapping to 0 after the last line and from any past it.
+    fn next_line(&self) -> u8 {
+        if self.scanline < END_VBLANK - 1 {
+            self.scanline + 1
+        } else {
+            0
+        }
+    }
+
+    // Sets the mode from the line rather than from the mode it leaves, so a desynced mode gets
+    // back in step by the next line.
+    fn enter_line(&mut self, line: u8) {
+        self.scanline = line;
+        self.mode = if line >= END_HBLANK {
+            GpuMode::VBlank
+        } else {
+            GpuMode::Oam
+        };
+    }
+
+    // Dots since the start of the current scanline.
+    pub fn dot(&self) -> usize {
+        match self.mode {
+            GpuMode::Oam | GpuMode::VBlank => self.clock,
+            GpuMode::Vram => 80 + self.clock,
+            GpuMode::HBlank => 80 + 172 + self.clock,
+        }
+    }
+
+    // Last completed frame, safe to copy at any point of the emulated frame.
+    pub fn screen(&self) -> &PixelData {
+        &self.screen
+    }
+
+    // Last completed frame as shades, for palette swaps and frame diffing.
+    pub fn shades(&self) -> &ShadeData {
+        &self.shades
+    }
+
+    // Redraws `screen` from the shades, after changing `palette` or `color_correction`.
+    pub fn recolor(&mut self) {
+        self.dirty = [true; SCREEN_HEIGHT];
+        self.apply_palette();
+    }
+
+    fn apply_palette(&mut self) {
+        let palette = self.palette.corrected(self.color_correction);
+        palette.apply(&self.shades, &mut self.screen);
+    }
+
+    // Runs of rows of `screen` that changed since the last call, so a frontend only has to
+    // upload those rows of its texture.
+    pub fn take_dirty_lines(&mut self) -> Vec<Range<usize>> {
+        let mut lines: Vec<Range<usize>> = vec![];
+        for y in (0..SCREEN_HEIGHT).filter(|&y| self.dirty[y]) {
+            match lines.last_mut() {
+                Some(run) if run.end == y => run.end += 1,
+                _ => lines.push(y..y + 1),
+            }
+        }
+        self.dirty = [false; SCREEN_HEIGHT];
+        lines
+    }
+
+    // The screen row by row.
+    pub fn visible_frame(&self) -> Vec<u32> {
+        self.screen
+            .iter()
+            .flat_map(|row| row.iter().copied())
+            .collect()
+    }
+
+    // Draws the frame into the map buffer and crops the scrolled screen area out of it.
+    fn swap_buffers(&mut self) {
+        if let Some(mut map) = self.map.take() {
+            self.render_map(&mut map);
+            let previous = self.shades.clone();
+            crop(&map, self.scroll(), &mut self.shades);
+            self.map = Some(map);
+            for (dirty, (row, old)) in self
+                .dirty
+                .iter_mut()
+                .zip(self.shades.iter().zip(previous.iter()))
+            {
+                *dirty |= row != old;
+            }
+            self.apply_palette();
+        }
+    }
+
+    // Every tile in VRAM through the BGP/OBP `register`, in the current colors.
+    pub fn tiles(&self, register: u8) -> Vec<Tile> {
+        let colors = self.palette.corrected(self.color_correction);
+        self.vram[TILE_DATA_RANGE]
+            .chunks_exact(TILE_SIZE) // Tile
+            .map(|tile| Tile::construct(colors, register, tile))
+            .collect()
+    }
+
+    fn blit_tile(&self, pixels: &mut ShadeMap, vram_index: usize) {
+        let tile = self.bg_tile_data(self.vram_rel(vram_index));
+        let mapx = (vram_index - 0x1800) % 32;
+        let mapy = (vram_index - 0x1800) / 32;
+        Tile::write_shades(self.bgrdpal, pixels, (mapx, mapy), &self.vram[tile]);
+    }
+
+    // Draws a sprite tile at a screen position into the map, color 0 is transparent.
+    fn blit_sprite(
+        &self,
+        pixels: &mut ShadeMap,
+        (screenx, screeny): (usize, usize),
+        palette: u8,
+        tile_data: &[u8],
+        drawn: &[bool],
+    ) {
+        let (x, y) = self.scroll();
+        for (row, bytes) in tile_data.chunks_exact(2).enumerate() {
+            if !drawn[row] {
+                continue;
+            }
+            for col in 0..8 {
+                let index = Tile::pixel_index(bytes[0], bytes[1], col);
+                let x = screenx + col + x as usize;
+                let y = screeny + row + y as usize;
+                if index != 0 && y < pixels.len() && x < pixels[0].len() {
+                    pixels[y][x] = Tile::shade(palette, index);
+                }
+            }
+        }
+    }
+
+    fn render_bg(&self, pixels: &mut ShadeMap) {
+        for i in MAP_DATA_RANGE {
+            self.blit_tile(pixels, i);
+        }
+    }
+
+    // The whole background map without sprites, for the map viewer.
+    pub fn render_full_bg(&self, pixels: &mut PixelData256) {
+        let mut shades = Box::new([[0; 256]; 256]);
+        self.render_bg(&mut shades);
+        let palette = self.palette.corrected(self.color_correction);
+        for (shades, pixels) in shades.iter().zip(pixels.iter_mut()) {
+            for (&shade, pixel) in shades.iter().zip(pixels.iter_mut()) {
+                *pixel = palette.color(shade);
+            }
+        }
+    }
+
+    fn render_map(&self, pixels: &mut ShadeMap) {
+        let _start = time::Instant::now();
+        self.render_bg(pixels);
+        if self.sprite_display_enabled() {
+            self.render_sprites(pixels);
+        }
+    }
+
+    // Renders sprites to the framebuffer using the oam table.
+    fn render_sprites(&self, pixels: &mut ShadeMap) {
+        // TODO
+        // Need to emulate scanline, and priority rendering
+        let mut selected = [0; SCREEN_HEIGHT];
+        let height = self.sprite_height() as i32;
+        for i in 0..OAM_ENTRIES {
+            let sprite_attributes = self.oam_entry(i);
+            if sprite_attributes.iter().all(|x| *x == 0) {
+                continue;
+            }
+            let [y, x, pattern, flags] = sprite_attributes;
+            let flags = SpriteAttribute::from(flags);
+            let idx = *pattern as usize * 16;
+
+            let palette = if flags.obj0 {
+                self.obj0pal
+            } else {
+                self.obj1pal
+            };
+            let screen_x = (*x).wrapping_sub(8) as usize;
+            let screen_y = (*y).wrapping_sub(16) as usize;
+            // Rows on lines that already have SPRITES_PER_LINE sprites are dropped.
+            let mut drawn = [true; 8];
+            for row in 0..height {
+                let line = *y as i32 - 16 + row;
+                if line < 0 || line >= SCREEN_HEIGHT as i32 {
+                    continue;
+                }
+                selected[line as usize] += 1;
+                if selected[line as usize] > SPRITES_PER_LINE && row < 8 {
+                    drawn[row as usize] = false;
+                }
+            }
+            let data = &self.vram[Tile::range(idx)];
+            self.blit_sprite(pixels, (screen_x, screen_y), palette, data, &drawn);
+        }
+    }
+
+    // Dots until the next mode change, None while the LCD is off.
+    pub fn dots_to_mode_change(&self) -> Option<usize> {
+        if !self.is_on() {
+            return None;
+        }
+        let length = match self.mode {
+            GpuMode::Oam => 80,
+            GpuMode::Vram => 172,
+            GpuMode::HBlank => 204,
+            GpuMode::VBlank => DOTS_PER_LINE,
+        };
+        Some(length.saturating_sub(self.clock).max(1))
+    }
+
+    // Advances within the current mode, see Bus::skip_idle.
+    pub fn skip(&mut self, n: usize) {
+        if self.is_on() {
+            self.clock += n;
+        }
+    }
+
+    fn check_clock<F: FnOnce(&mut Self)>(&mut self, criteria: usize, f: F) {
+        if self.clock >= criteria {
+            f(self);
+            self.clock = 0;
+        }
+    }
+
+    // This is a huge can of worms to correct emulate the state of the scanline during emulation.
+    // I would revisit this later.
+    // Invariant: `scanline` stays below END_VBLANK. Savestates check it on load, everything else
+    // moves lines through set_scanline or next_line.
+    pub fn step(&mut self, flag: &mut u8) {
+        debug_assert!(
+            self.scanline < END_VBLANK,
+            "LY {} out of range",
+            self.scanline
+        );
+        match self.mode {
+            GpuMode::Oam => self.check_clock(80, |gpu| gpu.mode = GpuMode::Vram),
+            GpuMode::Vram => self.check_clock(172, |gpu| gpu.mode = GpuMode::HBlank),
+            GpuMode::HBlank => self.check_clock(204, |gpu| {
+                gpu.enter_line(gpu.next_line());
+                if gpu.scanline == END_HBLANK {
+                    gpu._vblank_count += 1;
+                    gpu.swap_buffers();
+                    *flag |= cpu::VBLANK;
+                }
+            }),
+            GpuMode::VBlank => self.check_clock(456, |gpu| gpu.enter_line(gpu.next_line())),
+        }
+    }
+}
+
+impl Display for GPU {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "LCDC: {}", Lcdc(self.lcdc))?;
+        write!(f, "STAT: {}", Stat(self.lcdstat))
+    }
+}
+
+// Copies the visible area of a scrolled map into `screen`, wrapping around the map edges.
+fn crop<T: Copy>(
+    map: &[[T; 256]; 256],
+    (h, v): (u32, u32),
+    screen: &mut [[T; SCREEN_WIDTH]; SCREEN_HEIGHT],
+) {
+    for (y, line) in screen.iter_mut().enumerate() {
+        let row = &map[(v as usize + y) % 256];
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = row[(h as usize + x) % 256];
+        }
+    }
+}
+
+// LYC stays in Bus::memory.
+impl IoDevice for GPU {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        match address {
+            0xFF40 => Some(self.lcdc),
+            0xFF41 => Some(self.lcdstat),
+            0xFF42 => Some(self.scrolly),
+            0xFF43 => Some(self.scrollx),
+            0xFF44 => Some(self.ly()),
+            0xFF47 => Some(self.bgrdpal),
+            0xFF48 => Some(self.obj0pal),
+            0xFF49 => Some(self.obj1pal),
+            0xFF4A => Some(self.windowy),
+            0xFF4B => Some(self.windowx),
+            _ => None,
+        }
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, _: &mut IoContext) -> bool {
+        match address {
+            0xFF40 => self.lcdc = value,
+            // The mode and LYC=LY bits can't be written.
+            0xFF41 => self.lcdstat = (self.lcdstat & 0x07) | value,
+            0xFF42 => self.scrolly = value,
+            0xFF43 => self.scrollx = value,
+            // LY is read-only, writing any value resets the line counter to 0.
+            0xFF44 => self.set_scanline(0),
+            0xFF47 => self.bgrdpal = value,
+            0xFF48 => self.obj0pal = value,
+            0xFF49 => self.obj1pal = value,
+            0xFF4A => self.windowy = value,
+            0xFF4B => self.windowx = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meminit::MemFill;
+
+    #[test]
+    fn vram_accessors_agree() {
+        let mut gpu = GPU::new();
+        gpu.write_vram_abs(0x8010, 0xAB);
+        assert_eq!(gpu.vram_abs(0x8010), 0xAB);
+        assert_eq!(gpu.vram_rel(0x10), 0xAB);
+        gpu.write_vram_abs(VRAM_END as u16, 0xCD);
+        assert_eq!(gpu.vram_rel(0x1FFF), 0xCD);
+    }
+
+    #[test]
+    fn ly_reads_0_during_line_153() {
+        let mut gpu = GPU::new();
+        gpu.lcdc = 0x80;
+        let mut flags = 0;
+        while gpu.scanline != END_VBLANK - 1 {
+            gpu.cycle(&mut flags);
+        }
+        assert_eq!(gpu.dot(), 0);
+        let mut seen = vec![];
+        for dot in 0..DOTS_PER_LINE {
+            if [0, LINE_153_DOTS - 1, LINE_153_DOTS, DOTS_PER_LINE - 1].contains(&dot) {
+                seen.push((dot, gpu.scanline, gpu.ly()));
+            }
+            gpu.cycle(&mut flags);
+        }
+        assert_eq!(
+            seen,
+            vec![
+                (0, 153, 153),
+                (LINE_153_DOTS - 1, 153, 153),
+                (LINE_153_DOTS, 153, 0),
+                (DOTS_PER_LINE - 1, 153, 0),
+            ]
+        );
+        assert_eq!((gpu.scanline, gpu.ly()), (0, 0));
+    }
+
+    #[test]
+    fn ly_writes_and_desynced_modes_stay_in_range() {
+        let mut gpu = GPU::new();
+        gpu.lcdc = 0x80;
+        let mut flags = 0;
+        for _ in 0..DOTS_PER_LINE * 3 + 10 {
+            gpu.cycle(&mut flags);
+        }
+        let mut ctx = IoContext {
+            clock: 0,
+            int_flags: &mut flags,
+            cgb: false,
+        };
+        assert!(gpu.io_write(0xFF44, 0x99, &mut ctx));
+        assert_eq!((gpu.scanline, gpu.dot(), gpu.mode_name()), (0, 0, "OAM"));
+
+        gpu.set_scanline(200);
+        assert_eq!(gpu.scanline, 0);
+        gpu.set_scanline(150);
+        assert_eq!(gpu.mode_name(), "VBlank");
+
+        // HBlank on the last line, as a bad savestate could leave it, wraps to a new frame.
+        gpu.mode = GpuMode::HBlank;
+        gpu.scanline = END_VBLANK - 1;
+        gpu.clock = 204;
+        gpu.cycle(&mut flags);
+        assert_eq!((gpu.scanline, gpu.mode_name()), (0, "OAM"));
+    }
+
+    #[test]
+    fn oam_entries() {
+        let mut gpu = GPU::new();
+        gpu.oam[4..8].copy_from_slice(&[16, 8, 0x42, 0x20]);
+        assert_eq!(gpu.oam_entry(1), &[16, 8, 0x42, 0x20]);
+        assert_eq!(gpu.oam_entry(OAM_ENTRIES - 1), &[0; 4]);
+    }
+
+    #[test]
+    fn decodes_sprites() {
+        let mut gpu = GPU::new();
+        gpu.obj1pal = 0b1110_0100;
+        // Tile 2: top row color 3 on the left half only.
+        gpu.vram[2 * TILE_SIZE] = 0xF0;
+        gpu.vram[2 * TILE_SIZE + 1] = 0xF0;
+        gpu.oam[0..4].copy_from_slice(&[16, 8, 2, 0x10 | 0x20 | 0x80]);
+        let sprite = &gpu.sprites()[0];
+        assert_eq!(sprite.screen_pos(), (0, 0));
+        assert!(sprite.obp1 && sprite.x_flip && !sprite.y_flip && sprite.behind_bg);
+        assert_eq!(sprite.pixels.len(), 8);
+        // X flipped, so the right half is opaque.
+        assert_eq!(sprite.pixels[0][0] & 0xFF, 0);
+        assert_ne!(sprite.pixels[0][7] & 0xFF, 0);
+        assert!(sprite.visible());
+        assert!(!gpu.sprites()[1].visible());
+    }
+
+    #[test]
+    fn lcdc_bit_2_selects_8x16_sprites() {
+        let mut gpu = GPU::new();
+        gpu.obj0pal = 0b1110_0100;
+        // Tile 2 has its top row set, tile 3 its bottom row.
+        gpu.vram[2 * TILE_SIZE] = 0xFF;
+        gpu.vram[3 * TILE_SIZE + 14] = 0xFF;
+        gpu.oam[0..4].copy_from_slice(&[16, 8, 3, 0]);
+        assert_eq!(gpu.sprites()[0].pixels.len(), 8);
+
+        gpu.lcdc |= 0b100;
+        let sprite = &gpu.sprites()[0];
+        assert_eq!(sprite.pixels.len(), 16);
+        // The tile index ignores bit 0, tile 2 on top and tile 3 below.
+        assert_ne!(sprite.pixels[0][0] & 0xFF, 0);
+        assert_eq!(sprite.pixels[7][0] & 0xFF, 0);
+        assert_ne!(sprite.pixels[15][0] & 0xFF, 0);
+    }
+
+    #[test]
+    fn rendered_sprites_match_their_decoded_pixels() {
+        for seed in 0..64 {
+            // The tile, then both palettes and the flags.
+            let mut random = [0; TILE_SIZE + 3];
+            MemFill::Random(0x8765_4321 + seed).fill(&mut random);
+            let mut gpu = GPU::new();
+            gpu.lcdc = 0x82;
+            gpu.obj0pal = random[TILE_SIZE];
+            gpu.obj1pal = random[TILE_SIZE + 1];
+            gpu.vram[Tile::range(5 * TILE_SIZE)].copy_from_slice(&random[..TILE_SIZE]);
+            // Either palette, at the top left of the screen.
+            gpu.oam[0..4].copy_from_slice(&[16, 8, 5, random[TILE_SIZE + 2] & 0x10]);
+            let mut shades = Box::new([[4; 256]; 256]);
+            gpu.render_sprites(&mut shades);
+            let colors = gpu.palette.corrected(gpu.color_correction);
+            let sprite = &gpu.sprites()[0];
+            for (y, row) in sprite.pixels.iter().enumerate() {
+                for (x, &pixel) in row.iter().enumerate() {
+                    match shades[y][x] {
+                        4 => assert_eq!(pixel & 0xFF, 0),
+                        shade => assert_eq!(pixel, colors.color(shade)),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn crop_wraps() {
+        let mut map = Box::new([[0; 256]; 256]);
+        map[250][200] = 1;
+        map[0][0] = 2;
+        let mut screen = Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        crop(&map, (200, 250), &mut screen);
+        assert_eq!(screen[0][0], 1);
+        assert_eq!(screen[6][56], 2);
+
+        let mut gpu = GPU::new();
+        gpu.screen = screen;
+        let frame = gpu.visible_frame();
+        assert_eq!(frame.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        assert_eq!(frame[6 * SCREEN_WIDTH + 56], 2);
+    }
+
+    #[test]
+    fn palette_applies_to_last_frame() {
+        let mut gpu = GPU::new();
+        gpu.lcdc = 0x91;
+        gpu.bgrdpal = 0b1110_0100;
+        // Tile 0 all color 3, the map points every tile at it.
+        gpu.vram[..TILE_SIZE].copy_from_slice(&[0xFF; TILE_SIZE]);
+        gpu.swap_buffers();
+        assert_eq!(gpu.shades()[10][10], 3);
+        assert_eq!(gpu.screen()[10][10], Palette::GREEN.color(3));
+        gpu.palette = Palette::GRAY;
+        gpu.recolor();
+        assert_eq!(gpu.screen()[10][10], 0x000000FF);
+    }
+
+    #[test]
+    fn tracks_changed_lines() {
+        let mut gpu = GPU::new();
+        gpu.lcdc = 0x91;
+        gpu.bgrdpal = 0b1110_0100;
+        assert_eq!(gpu.take_dirty_lines(), vec![0..SCREEN_HEIGHT]);
+        gpu.swap_buffers();
+        assert!(gpu.take_dirty_lines().is_empty());
+        // Rows 2 and 3 of tile 0, which every map entry points at, so lines 2, 3, 10, 11, ...
+        gpu.vram[4..8].copy_from_slice(&[0xFF; 4]);
+        gpu.swap_buffers();
+        let lines = gpu.take_dirty_lines();
+        assert_eq!(lines.len(), SCREEN_HEIGHT / 8);
+        assert_eq!(lines[0], 2..4);
+        assert_eq!(lines[1], 10..12);
+        gpu.recolor();
+        assert_eq!(gpu.take_dirty_lines(), vec![0..SCREEN_HEIGHT]);
+    }
+
+    #[test]
+    fn drops_sprites_past_the_line_limit() {
+        let mut gpu = GPU::new();
+        gpu.lcdc = 0x93;
+        gpu.obj0pal = 0b1110_0100;
+        // Tile 1 all color 3, eleven sprites in a row on the top line.
+        gpu.vram[TILE_SIZE..TILE_SIZE * 2].copy_from_slice(&[0xFF; TILE_SIZE]);
+        for i in 0..=SPRITES_PER_LINE {
+            gpu.oam[i * 4..i * 4 + 4].copy_from_slice(&[16, 8 + 8 * i as u8, 1, 0]);
+        }
+        assert_eq!(gpu.sprites_per_line()[0], SPRITES_PER_LINE + 1);
+        assert_eq!(gpu.sprites_per_line()[8], 0);
+        gpu.swap_buffers();
+        assert_eq!(gpu.shades()[0][8 * (SPRITES_PER_LINE - 1)], 3);
+        assert_eq!(gpu.shades()[0][8 * SPRITES_PER_LINE], 0);
+    }
+}
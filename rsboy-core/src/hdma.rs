@@ -0,0 +1,131 @@
+use crate::clock::Cycles;
+
+pub const HDMA1: usize = 0xFF51;
+pub const HDMA2: usize = 0xFF52;
+pub const HDMA3: usize = 0xFF53;
+pub const HDMA4: usize = 0xFF54;
+pub const HDMA5: usize = 0xFF55;
+
+pub const BLOCK_LEN: u16 = 16;
+// CPU cycles the CPU is stalled for each block of a general purpose transfer.
+pub const BLOCK_CYCLES: Cycles = 32;
+
+// What a write to HDMA5 asks the bus to do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    // Copy this many 16 byte blocks right away.
+    General(u16),
+    // Copy one block at the start of each HBlank.
+    HBlank,
+    Nothing,
+}
+
+// CGB VRAM DMA registers, see https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+// The copying itself is done by the Bus, which owns both sides of the transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hdma {
+    pub source: u16,
+    // Offset into VRAM, 0x0000-0x1FF0.
+    pub dest: u16,
+    // Blocks left minus one, as reported in HDMA5.
+    pub remaining: u8,
+    // An HBlank transfer is in progress.
+    pub active: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            dest: 0,
+            remaining: 0x7F,
+            active: false,
+        }
+    }
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // HDMA1-4 are write only.
+    pub fn read(&self, address: usize) -> u8 {
+        match address {
+            HDMA5 if self.active => self.remaining,
+            HDMA5 => 0x80 | self.remaining,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) -> Request {
+        match address {
+            HDMA1 => self.source = (self.source & 0x00FF) | (value as u16) << 8,
+            HDMA2 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.dest = (self.dest & 0x00FF) | ((value & 0x1F) as u16) << 8,
+            HDMA4 => self.dest = (self.dest & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5 => {
+                // Clearing bit 7 during an HBlank transfer stops it, the remaining count stays.
+                if self.active && value & 0x80 == 0 {
+                    self.active = false;
+                    return Request::Nothing;
+                }
+                self.remaining = value & 0x7F;
+                if value & 0x80 != 0 {
+                    self.active = true;
+                    return Request::HBlank;
+                }
+                return Request::General(self.remaining as u16 + 1);
+            }
+            _ => {}
+        }
+        Request::Nothing
+    }
+
+    // Source and VRAM destination of the next block, advancing both.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | (self.dest & 0x1FF0));
+        self.source = self.source.wrapping_add(BLOCK_LEN);
+        self.dest = (self.dest + BLOCK_LEN) & 0x1FF0;
+        block
+    }
+
+    // Counts down after a block of an HBlank transfer, ending it after the last one.
+    pub fn block_done(&mut self) {
+        if self.remaining == 0 {
+            self.remaining = 0x7F;
+            self.active = false;
+        } else {
+            self.remaining -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registers_and_termination() {
+        let mut hdma = Hdma::new();
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+        hdma.write(HDMA1, 0xC1);
+        hdma.write(HDMA2, 0x2F);
+        hdma.write(HDMA3, 0xE8);
+        hdma.write(HDMA4, 0x1F);
+        assert_eq!(hdma.next_block(), (0xC120, 0x8810));
+        assert_eq!(hdma.write(HDMA5, 0x02), Request::General(3));
+
+        assert_eq!(hdma.write(HDMA5, 0x81), Request::HBlank);
+        assert_eq!(hdma.read(HDMA5), 0x01);
+        hdma.block_done();
+        assert_eq!(hdma.read(HDMA5), 0x00);
+        assert_eq!(hdma.write(HDMA5, 0x00), Request::Nothing);
+        assert_eq!(hdma.read(HDMA5), 0x80);
+
+        hdma.write(HDMA5, 0x80);
+        hdma.block_done();
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+        assert!(!hdma.active);
+    }
+}
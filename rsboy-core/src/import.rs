@@ -0,0 +1,156 @@
+use crate::apu::NR52;
+use crate::bus::Memory;
+use crate::constants::MaybeErr;
+use crate::cpu::CPUState;
+use crate::dma;
+use crate::emu::Emu;
+use crate::gpu::{OAM_END, OAM_START, VRAM_END, VRAM_START};
+use crate::registers::RegisterState;
+use crate::serial;
+use std::{fs, path::Path};
+
+// Importer for the plain "registers + memory dump" snapshots other emulators can export:
+//  - a register line, as written by gameboy-doctor style logs:
+//      A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100
+//  - a 64KiB dump of the address space as seen by the CPU.
+// ROM is not restored, the dump is applied on top of the cartridge already loaded.
+
+pub const DUMP_SIZE: usize = 0x10000;
+
+pub fn parse_registers(line: &str) -> MaybeErr<RegisterState> {
+    let mut registers = RegisterState::new();
+    let mut seen = 0;
+    for token in line.split_whitespace() {
+        let mut parts = token.splitn(2, ':');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name, value),
+            _ => continue,
+        };
+        let byte = || u8::from_str_radix(value, 16);
+        match name.to_ascii_uppercase().as_str() {
+            "A" => registers.a = byte()?,
+            "F" => registers.f = byte()? & 0xF0,
+            "B" => registers.b = byte()?,
+            "C" => registers.c = byte()?,
+            "D" => registers.d = byte()?,
+            "E" => registers.e = byte()?,
+            "H" => registers.h = byte()?,
+            "L" => registers.l = byte()?,
+            "SP" => registers.sp = u16::from_str_radix(value, 16)?,
+            "PC" => registers.pc = u16::from_str_radix(value, 16)?,
+            _ => continue,
+        }
+        seen += 1;
+    }
+    if seen < 10 {
+        return Err(format!("Expected A F B C D E H L SP PC in register line: {}", line).into());
+    }
+    Ok(registers)
+}
+
+// IO registers that need special handling instead of a plain bus write.
+fn import_io(emu: &mut Emu, address: usize, value: u8) {
+    let bus = &mut emu.bus;
+    match address {
+        // Writing DIV resets it, restore the upper byte of the internal counter instead.
+        0xFF04 => bus.timer.internal = (value as u16) << 8,
+        // IF is OR'ed on write.
+        0xFF0F => bus.int_flags = value,
+        // Writing LY resets it, restore the line instead.
+        0xFF44 => bus.gpu.set_scanline(value),
+        // Side effects: serial transfer start, OAM DMA.
+        serial::SC => bus.serial.sc = value,
+        dma::DMA => bus.dma.source = value,
+        // Side effects: joypad select, bootrom unmap.
+        0xFF00 | 0xFF50 => bus.memory[address] = value,
+        _ => bus.write(address as u16, value),
+    }
+}
+
+pub fn import(emu: &mut Emu, registers: RegisterState, dump: &[u8]) -> MaybeErr<()> {
+    if dump.len() != DUMP_SIZE {
+        return Err(format!(
+            "Memory dump must be {} bytes, got {}",
+            DUMP_SIZE,
+            dump.len()
+        )
+        .into());
+    }
+    emu.bus.in_bios = 1;
+    emu.bus.rom_start_signal = false;
+    emu.bus
+        .gpu
+        .vram
+        .copy_from_slice(&dump[VRAM_START..=VRAM_END]);
+    emu.bus.memory[0xA000..0xE000].copy_from_slice(&dump[0xA000..0xE000]);
+    emu.bus.gpu.oam[..=OAM_END - OAM_START].copy_from_slice(&dump[OAM_START..=OAM_END]);
+    // LCDC first so the GPU sees the final state of the display when the rest is written, NR52
+    // so a powered APU takes the sound registers.
+    import_io(emu, 0xFF40, dump[0xFF40]);
+    import_io(emu, NR52, dump[NR52]);
+    for (address, &value) in (0xFF00..).zip(&dump[0xFF00..0xFF80]) {
+        import_io(emu, address, value);
+    }
+    emu.bus.memory[0xFF80..0xFFFF].copy_from_slice(&dump[0xFF80..0xFFFF]);
+    emu.bus.int_enabled = dump[0xFFFF];
+
+    // The CPU executes the prefetched opcode on its next step.
+    let pc = registers.pc;
+    emu.cpu.registers = registers;
+    emu.cpu.halt = false;
+    emu.cpu.state = CPUState::Running;
+    emu.cpu.state = emu.cpu.prefetch_op(&mut emu.bus, pc);
+    emu.history.clear();
+    Ok(())
+}
+
+pub fn import_files(emu: &mut Emu, registers: &Path, dump: &Path) -> MaybeErr<()> {
+    let line = fs::read_to_string(registers)?;
+    let line = line.lines().next().ok_or("Register file is empty")?;
+    let registers = parse_registers(line)?;
+    let dump = fs::read(dump)?;
+    import(emu, registers, &dump)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_doctor_line() {
+        let regs = parse_registers(
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02",
+        )
+        .unwrap();
+        assert_eq!(regs.af(), 0x01B0);
+        assert_eq!(regs.bc(), 0x0013);
+        assert_eq!(regs.de(), 0x00D8);
+        assert_eq!(regs.hl(), 0x014D);
+        assert_eq!(regs.sp, 0xFFFE);
+        assert_eq!(regs.pc, 0x0100);
+        assert!(parse_registers("A:01 F:B0").is_err());
+    }
+
+    #[test]
+    fn imports_memory_regions() {
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        let mut dump = vec![0; DUMP_SIZE];
+        dump[0x8010] = 0xAA;
+        dump[0xC000] = 0xBB;
+        dump[0xFE00] = 0xCC;
+        dump[0xFF42] = 0x12;
+        dump[0xFF80] = 0xDD;
+        dump[0xFFFF] = 0x01;
+        let regs =
+            parse_registers("A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150").unwrap();
+        import(&mut emu, regs, &dump).unwrap();
+        assert_eq!(emu.bus.gpu.vram[0x10], 0xAA);
+        assert_eq!(emu.bus.read(0xC000), 0xBB);
+        assert_eq!(emu.bus.gpu.oam[0], 0xCC);
+        assert_eq!(emu.bus.gpu.scrolly, 0x12);
+        assert_eq!(emu.bus.read(0xFF80), 0xDD);
+        assert_eq!(emu.bus.int_enabled, 0x01);
+        assert_eq!(emu.cpu.op_addr, 0x0150);
+        assert_eq!(emu.cpu.registers.sp, 0xFFFE);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::constants::MaybeErr;
+
+// zlib decompression (RFC 1950 wrapping RFC 1951 deflate), enough to read PNGs back in.
+// Decodes one symbol at a time by walking the canonical code lengths, slow but small.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths of a dynamic block come in.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    // Bit position in `data`, least significant bit of each byte first.
+    position: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> MaybeErr<u32> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or("Deflate stream ends early")?;
+        let bit = (byte >> (self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, n: u8) -> MaybeErr<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// Canonical Huffman code as symbol counts per length and the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = vec![];
+        for len in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l == len) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> MaybeErr<u16> {
+        // First code and symbol index of the current length.
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.bit()? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code in deflate stream".into())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8; 288];
+    lengths[144..256].iter_mut().for_each(|l| *l = 9);
+    lengths[256..280].iter_mut().for_each(|l| *l = 7);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits: &mut Bits) -> MaybeErr<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    let mut lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => {
+                let last = *lengths.last().ok_or("Repeat of no code length")?;
+                (last, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err("Code lengths overflow in deflate stream".into());
+    }
+    let (literal, distance) = lengths.split_at(literals);
+    Ok((Huffman::new(literal), Huffman::new(distance)))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> MaybeErr<()> {
+    loop {
+        let symbol = literal.decode(bits)? as usize;
+        let i = match symbol {
+            0..=255 => {
+                out.push(symbol as u8);
+                continue;
+            }
+            256 => return Ok(()),
+            _ => symbol - 257,
+        };
+        if i >= LENGTH_BASE.len() {
+            return Err("Invalid length in deflate stream".into());
+        }
+        let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i])? as usize;
+        let d = distance.decode(bits)? as usize;
+        if d >= DISTANCE_BASE.len() {
+            return Err("Invalid distance in deflate stream".into());
+        }
+        let back = DISTANCE_BASE[d] as usize + bits.bits(DISTANCE_EXTRA[d])? as usize;
+        if back > out.len() {
+            return Err("Distance past the start of the deflate stream".into());
+        }
+        for _ in 0..len {
+            out.push(out[out.len() - back]);
+        }
+    }
+}
+
+// Decompresses a zlib stream, the Adler-32 checksum at the end isn't checked.
+pub fn zlib_decompress(data: &[u8]) -> MaybeErr<Vec<u8>> {
+    if data.len() < 2
+        || data[0] & 0x0F != 8
+        || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31)
+    {
+        return Err("Not a zlib stream".into());
+    }
+    let mut bits = Bits {
+        data: &data[2..],
+        position: 0,
+    };
+    let mut out = vec![];
+    loop {
+        let last = bits.bit()? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let start = bits.position / 8;
+                let header = bits
+                    .data
+                    .get(start..start + 4)
+                    .ok_or("Deflate stream ends early")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = bits
+                    .data
+                    .get(start + 4..start + 4 + len)
+                    .ok_or("Deflate stream ends early")?;
+                out.extend_from_slice(block);
+                bits.position = (start + 4 + len) * 8;
+            }
+            1 => {
+                let (literal, distance) = fixed_codes();
+                inflate_block(&mut bits, &mut out, &literal, &distance)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literal, &distance)?;
+            }
+            _ => return Err("Invalid deflate block type".into()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decompresses_every_block_type() {
+        let text = b"rsboy rsboy rsboy, a Game Boy emulator";
+        let mut stored = vec![
+            0x78,
+            0x01,
+            0x01,
+            text.len() as u8,
+            0,
+            !text.len() as u8,
+            0xFF,
+        ];
+        stored.extend_from_slice(text);
+        assert_eq!(zlib_decompress(&stored).unwrap(), text);
+        // Written by zlib with the fixed strategy.
+        let fixed = [
+            0x78, 0x01, 0x2B, 0x2A, 0x4E, 0xCA, 0xAF, 0x54, 0x28, 0x42, 0x90, 0x3A, 0x0A, 0x89,
+            0x0A, 0xEE, 0x89, 0xB9, 0xA9, 0x0A, 0x4E, 0x40, 0xF1, 0xD4, 0xDC, 0xD2, 0x9C, 0xC4,
+            0x92, 0xFC, 0x22, 0x00, 0x10, 0xE0, 0x0D, 0xE8,
+        ];
+        assert_eq!(zlib_decompress(&fixed).unwrap(), text);
+        // Written by zlib at level 9, a dynamic block.
+        let dynamic = [
+            0x78, 0xDA, 0x25, 0x8A, 0xB1, 0x0D, 0x00, 0x00, 0x08, 0xC2, 0x6E, 0x05, 0xE2, 0xFF,
+            0x2F, 0x48, 0x95, 0x01, 0x9A, 0x06, 0x39, 0x89, 0x9A, 0x96, 0x75, 0x34, 0x60, 0xA5,
+            0x99, 0xF6, 0x20, 0xCD, 0x62, 0xFE, 0x65, 0xD4, 0x02, 0xBB, 0x88, 0x16, 0xEA,
+        ];
+        assert_eq!(
+            zlib_decompress(&dynamic).unwrap(),
+            &b"abcccaaaacaabacaaaaecaabccabaabcabaeaaaabbaeabaababacaabaaab"[..]
+        );
+        assert!(zlib_decompress(&[0x78, 0x02]).is_err());
+        assert!(zlib_decompress(&stored[..10]).is_err());
+    }
+}
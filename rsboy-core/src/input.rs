@@ -0,0 +1,567 @@
+use crate::bus::Bus;
+use crate::clock::Cycles;
+use crate::movie::{JoypadTape, Movie, MovieFrame, Reset};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Joypad buttons. Each one is a bit in either the button or direction nibble of 0xFF00, active low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+    ];
+
+    pub(crate) fn is_direction(self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+
+    pub(crate) fn mask(self) -> u8 {
+        match self {
+            Button::A | Button::Right => 0b0001,
+            Button::B | Button::Left => 0b0010,
+            Button::Select | Button::Up => 0b0100,
+            Button::Start | Button::Down => 0b1000,
+        }
+    }
+
+    pub fn press(self, bus: &mut Bus) {
+        bus.joypad.press(self, bus.clock, &mut bus.int_flags);
+    }
+
+    pub fn release(self, bus: &mut Bus) {
+        bus.joypad.release(self, bus.clock, &mut bus.int_flags);
+    }
+
+    pub fn is_pressed(self, bus: &Bus) -> bool {
+        bus.joypad.is_pressed(self)
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::Select),
+            "start" => Ok(Button::Start),
+            "right" => Ok(Button::Right),
+            "left" => Ok(Button::Left),
+            "up" => Ok(Button::Up),
+            "down" => Ok(Button::Down),
+            _ => Err(format!("Unknown button {}", s)),
+        }
+    }
+}
+
+// A change to the joypad lines, independent of any frontend's key names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Press(Button),
+    Release(Button),
+}
+
+impl InputEvent {
+    pub fn apply(self, bus: &mut Bus) {
+        match self {
+            InputEvent::Press(button) => button.press(bus),
+            InputEvent::Release(button) => button.release(bus),
+        }
+    }
+}
+
+// Input events waiting for the bus clock to reach their timestamp, for scripted and replayed input.
+// Events at the same cycle are applied in the order they were queued.
+#[derive(Debug, Default)]
+pub struct InputQueue {
+    pending: BTreeMap<Cycles, Vec<InputEvent>>,
+}
+
+impl InputQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: InputEvent, at_cycle: Cycles) {
+        self.pending.entry(at_cycle).or_default().push(event);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    // Bus clock of the earliest queued event.
+    pub fn next_cycle(&self) -> Option<Cycles> {
+        self.pending.keys().next().copied()
+    }
+
+    // Applies every event due at or before the bus clock.
+    pub fn apply_due(&mut self, bus: &mut Bus) {
+        while let Some(&cycle) = self.pending.keys().next() {
+            if cycle > bus.clock {
+                break;
+            }
+            for event in self.pending.remove(&cycle).unwrap_or_default() {
+                event.apply(bus);
+            }
+        }
+    }
+}
+
+// What a key is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Button(Button),
+    // Toggles the button while held.
+    Turbo(Button),
+    // Holds A+B+Start+Select for exactly one frame.
+    SoftReset,
+    // Emu::reset() at the start of the next frame.
+    HardReset,
+}
+
+// Frames a turbo button stays pressed, then released.
+pub const DEFAULT_TURBO_RATE: u32 = 2;
+
+// Keyboard to joypad mapping. Keys are named like SDL key names ("Z", "Return", "Up").
+pub struct Input {
+    bindings: HashMap<String, Binding>,
+    held: BTreeSet<Button>,
+    turbo: BTreeSet<Button>,
+    pub turbo_rate: u32,
+    frame: u32,
+    // Earliest key press the game hasn't read yet, and whether it reached the joypad lines.
+    pressed_at: Option<Instant>,
+    applied: bool,
+    // Time from the last measured key press to the game reading the joypad.
+    pub latency: Option<Duration>,
+    // Reset requested for the next frame.
+    reset: Option<Reset>,
+    // Every applied frame is appended when recording.
+    pub movie: Option<Movie>,
+    // Frames of a movie being played back, which replace the keys until it runs out.
+    playback: Option<VecDeque<MovieFrame>>,
+    // Forward a rumble cartridge's motor to the controller.
+    pub rumble: bool,
+    motor: bool,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        let mut input = Input {
+            bindings: HashMap::new(),
+            held: BTreeSet::new(),
+            turbo: BTreeSet::new(),
+            turbo_rate: DEFAULT_TURBO_RATE,
+            frame: 0,
+            pressed_at: None,
+            applied: false,
+            latency: None,
+            reset: None,
+            movie: None,
+            playback: None,
+            rumble: true,
+            motor: false,
+        };
+        for &(key, button) in [
+            ("Up", Button::Up),
+            ("Down", Button::Down),
+            ("Left", Button::Left),
+            ("Right", Button::Right),
+            ("Z", Button::A),
+            ("X", Button::B),
+            ("Right Shift", Button::Select),
+            ("Return", Button::Start),
+        ]
+        .iter()
+        {
+            input.bind(key, Binding::Button(button));
+        }
+        input.bind("A", Binding::Turbo(Button::A));
+        input.bind("S", Binding::Turbo(Button::B));
+        input
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Binds `key`, replacing whatever was bound to it and any other key bound to `binding`.
+    pub fn bind(&mut self, key: &str, binding: Binding) {
+        self.bindings.retain(|_, b| *b != binding);
+        self.bindings.insert(key.to_string(), binding);
+    }
+
+    // Returns false if the key isn't bound.
+    pub fn key_down(&mut self, key: &str) -> bool {
+        let pressed = match self.bindings.get(key) {
+            Some(Binding::Button(button)) => self.held.insert(*button),
+            Some(Binding::Turbo(button)) => {
+                // Start a fresh cycle so the first frame is always a press.
+                if self.turbo.is_empty() {
+                    self.frame = 0;
+                }
+                self.turbo.insert(*button)
+            }
+            Some(Binding::SoftReset) => {
+                self.reset.get_or_insert(Reset::Soft);
+                false
+            }
+            Some(Binding::HardReset) => {
+                self.reset = Some(Reset::Hard);
+                false
+            }
+            None => return false,
+        };
+        if pressed && self.pressed_at.is_none() {
+            self.pressed_at = Some(Instant::now());
+            self.applied = false;
+        }
+        true
+    }
+
+    pub fn key_up(&mut self, key: &str) {
+        match self.bindings.get(key) {
+            Some(Binding::Button(button)) => self.held.remove(button),
+            Some(Binding::Turbo(button)) => self.turbo.remove(button),
+            Some(Binding::SoftReset) | Some(Binding::HardReset) | None => false,
+        };
+    }
+
+    // Turbo buttons currently held down.
+    pub fn turbo_active(&self) -> impl Iterator<Item = &Button> {
+        self.turbo.iter()
+    }
+
+    fn turbo_phase(&self) -> bool {
+        (self.frame / self.turbo_rate.max(1)).is_multiple_of(2)
+    }
+
+    // Call when the game read the joypad register, completes a latency measurement.
+    pub fn joypad_read(&mut self) {
+        if self.applied {
+            if let Some(pressed_at) = self.pressed_at.take() {
+                self.latency = Some(pressed_at.elapsed());
+            }
+            self.applied = false;
+        }
+    }
+
+    // True if the frame about to start should begin with Emu::reset().
+    pub fn hard_reset_pending(&self) -> bool {
+        match self.playback.as_ref().and_then(|frames| frames.front()) {
+            Some(frame) => frame.reset == Some(Reset::Hard),
+            None => self.reset == Some(Reset::Hard),
+        }
+    }
+
+    // Plays `movie` back from the next frame on, JOYP reads included.
+    pub fn play(&mut self, movie: Movie) {
+        self.playback = Some(movie.frames.into());
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // Moves the JOYP reads since the last frame started into the last recorded frame. apply does
+    // this every frame, call it once more before saving the movie.
+    pub fn flush_reads(&mut self, bus: &Bus) {
+        let frame = match self
+            .movie
+            .as_mut()
+            .and_then(|movie| movie.frames.last_mut())
+        {
+            Some(frame) => frame,
+            None => return,
+        };
+        if let Some(JoypadTape::Record(reads)) = bus.joypad_tape.borrow_mut().as_mut() {
+            frame.reads.append(reads);
+        }
+    }
+
+    // The motor state to send to the controller when it changed since the last call. Always off
+    // with rumble disabled.
+    pub fn rumble_change(&mut self, bus: &Bus) -> Option<bool> {
+        let motor = self.rumble && bus.rumble() == Some(true);
+        if motor == self.motor {
+            return None;
+        }
+        self.motor = motor;
+        Some(motor)
+    }
+
+    // Updates the joypad lines from the keys currently held, or the movie being played back.
+    pub fn apply(&mut self, bus: &mut Bus) {
+        self.applied = self.pressed_at.is_some();
+        self.flush_reads(bus);
+        let turbo_on = self.turbo_phase();
+        self.frame = self.frame.wrapping_add(1);
+        let reset = self.reset.take();
+        let played = self.playback.as_mut().map(|frames| frames.pop_front());
+        let exhausted = played == Some(None);
+        let (buttons, reset) = match played {
+            Some(Some(frame)) => {
+                *bus.joypad_tape.borrow_mut() = Some(JoypadTape::Replay(frame.reads.into()));
+                (frame.buttons, frame.reset)
+            }
+            _ => {
+                if exhausted {
+                    self.playback = None;
+                    *bus.joypad_tape.borrow_mut() = None;
+                }
+                let mut buttons = 0;
+                for (i, button) in Button::ALL.iter().enumerate() {
+                    let soft_reset = reset == Some(Reset::Soft) && !button.is_direction();
+                    if soft_reset
+                        || self.held.contains(button)
+                        || (turbo_on && self.turbo.contains(button))
+                    {
+                        buttons |= 1 << i;
+                    }
+                }
+                (buttons, reset)
+            }
+        };
+        for (i, &button) in Button::ALL.iter().enumerate() {
+            if buttons & (1 << i) != 0 {
+                button.press(bus);
+            } else {
+                button.release(bus);
+            }
+        }
+        if let Some(movie) = &mut self.movie {
+            movie.record(MovieFrame {
+                buttons,
+                reset,
+                reads: vec![],
+            });
+            let mut tape = bus.joypad_tape.borrow_mut();
+            if tape.is_none() {
+                *tape = Some(JoypadTape::Record(vec![]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Memory;
+    use crate::cartridge::CARTRIDGE_TYPE;
+    use crate::cpu::JOYPAD;
+    use crate::joypad::JOYP;
+
+    #[test]
+    fn press_and_release() {
+        let mut bus = Bus::new(vec![], None);
+        Button::Start.press(&mut bus);
+        Button::Up.press(&mut bus);
+        assert_eq!(bus.joypad.buttons, 0b0111);
+        assert_eq!(bus.joypad.directions, 0b1011);
+        assert!(Button::Start.is_pressed(&bus));
+        assert!(!Button::A.is_pressed(&bus));
+        assert_ne!(bus.int_flags & JOYPAD, 0);
+        Button::Start.release(&mut bus);
+        assert_eq!(bus.joypad.buttons, 0x0F);
+    }
+
+    #[test]
+    fn held_keys() {
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        assert!(input.key_down("Z"));
+        assert!(!input.key_down("F12"));
+        input.apply(&mut bus);
+        assert!(Button::A.is_pressed(&bus));
+        bus.int_flags = 0;
+        input.apply(&mut bus);
+        assert!(Button::A.is_pressed(&bus));
+        assert_eq!(bus.int_flags & JOYPAD, 0);
+        input.key_up("Z");
+        input.apply(&mut bus);
+        assert!(!Button::A.is_pressed(&bus));
+    }
+
+    #[test]
+    fn turbo_toggles_at_rate() {
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        input.turbo_rate = 3;
+        input.key_down("A");
+        assert_eq!(input.turbo_active().collect::<Vec<_>>(), vec![&Button::A]);
+        let states: Vec<bool> = (0..12)
+            .map(|_| {
+                input.apply(&mut bus);
+                Button::A.is_pressed(&bus)
+            })
+            .collect();
+        let expected = [true, true, true, false, false, false];
+        assert_eq!(&states[..6], &expected);
+        assert_eq!(&states[6..], &expected);
+        input.key_up("A");
+        input.apply(&mut bus);
+        assert!(!Button::A.is_pressed(&bus));
+        assert_eq!(input.turbo_active().count(), 0);
+    }
+
+    #[test]
+    fn latency_needs_apply_then_read() {
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        input.key_down("Return");
+        // A read before the press reached the joypad lines doesn't count.
+        input.joypad_read();
+        assert_eq!(input.latency, None);
+        input.apply(&mut bus);
+        input.joypad_read();
+        assert!(input.latency.is_some());
+        // Held keys don't start new measurements.
+        input.latency = None;
+        input.key_down("Return");
+        input.apply(&mut bus);
+        input.joypad_read();
+        assert_eq!(input.latency, None);
+    }
+
+    #[test]
+    fn queued_events_wait_for_their_cycle() {
+        let mut bus = Bus::new(vec![], None);
+        let mut queue = InputQueue::new();
+        queue.push(InputEvent::Release(Button::A), 20);
+        queue.push(InputEvent::Press(Button::A), 10);
+        queue.push(InputEvent::Press(Button::B), 20);
+        assert_eq!(queue.len(), 3);
+        bus.clock = 9;
+        queue.apply_due(&mut bus);
+        assert!(!Button::A.is_pressed(&bus));
+        bus.clock = 10;
+        queue.apply_due(&mut bus);
+        assert!(Button::A.is_pressed(&bus));
+        bus.clock = 25;
+        queue.apply_due(&mut bus);
+        assert!(!Button::A.is_pressed(&bus));
+        assert!(Button::B.is_pressed(&bus));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn soft_reset_lasts_one_frame_and_is_recorded() {
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        input.bind("Backspace", Binding::SoftReset);
+        input.bind("Delete", Binding::HardReset);
+        input.movie = Some(Movie::new());
+        input.key_down("Backspace");
+        input.apply(&mut bus);
+        for button in [Button::A, Button::B, Button::Start, Button::Select].iter() {
+            assert!(button.is_pressed(&bus));
+        }
+        assert!(!Button::Up.is_pressed(&bus));
+        input.apply(&mut bus);
+        assert!(!Button::Start.is_pressed(&bus));
+        input.key_down("Delete");
+        assert!(input.hard_reset_pending());
+        input.apply(&mut bus);
+        assert!(!input.hard_reset_pending());
+        let frames = &input.movie.as_ref().unwrap().frames;
+        assert_eq!(frames[0].reset, Some(Reset::Soft));
+        assert_eq!(frames[0].buttons, 0b1111);
+        assert_eq!(frames[1], MovieFrame::default());
+        assert_eq!(frames[2].reset, Some(Reset::Hard));
+    }
+
+    #[test]
+    fn replays_recorded_joypad_reads() {
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        input.movie = Some(Movie::new());
+        input.key_down("Z");
+        input.apply(&mut bus);
+        bus.write(JOYP as u16, 0x10);
+        let read = bus.read(JOYP as u16);
+        assert_eq!(read & 0x0F, 0x0E);
+        input.flush_reads(&bus);
+        let movie = input.movie.take().unwrap();
+        assert_eq!(movie.frames[0].reads, vec![read & 0x3F]);
+
+        // Played back the read comes from the movie even with the directions selected.
+        let mut bus = Bus::new(vec![], None);
+        let mut input = Input::new();
+        input.play(movie);
+        input.apply(&mut bus);
+        assert!(Button::A.is_pressed(&bus));
+        bus.write(JOYP as u16, 0x20);
+        assert_eq!(bus.read(JOYP as u16), read);
+        // Then live again once it runs out.
+        input.apply(&mut bus);
+        assert!(!input.is_playing());
+        assert_eq!(bus.read(JOYP as u16) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn rebinding_replaces() {
+        let mut input = Input::new();
+        input.bind("Q", Binding::Turbo(Button::A));
+        assert!(!input.key_down("A"));
+        assert!(input.key_down("Q"));
+    }
+
+    #[test]
+    fn parses_names() {
+        for button in Button::ALL.iter() {
+            let name = format!("{:?}", button);
+            assert_eq!(name.parse::<Button>(), Ok(*button));
+        }
+    }
+
+    #[test]
+    fn rumble_follows_ram_bank_bit_3() {
+        let mut rom = vec![0; 0x8000];
+        rom[CARTRIDGE_TYPE] = 0x1C;
+        let mut bus = Bus::new(rom, None);
+        let mut input = Input::new();
+        assert_eq!(input.rumble_change(&bus), None);
+        bus.write(0x4000, 0x08);
+        assert_eq!(input.rumble_change(&bus), Some(true));
+        assert_eq!(input.rumble_change(&bus), None);
+        input.rumble = false;
+        assert_eq!(input.rumble_change(&bus), Some(false));
+        input.rumble = true;
+        bus.write(0x4000, 0x01);
+        assert_eq!(input.rumble_change(&bus), None);
+        assert_eq!(Bus::new(vec![0; 0x8000], None).rumble(), None);
+    }
+}
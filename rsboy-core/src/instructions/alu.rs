@@ -227,16 +227,9 @@ pub fn rlca(cpu: &mut CPU, _bus: &mut Bus) {
 }
 
 pub fn addsp(cpu: &mut CPU, bus: &mut Bus) {
-    let offset = cpu.next_u8(bus) as i8 as i16 as u16;
-    let sp = cpu.registers.sp;
-    let result = cpu.registers.sp.wrapping_add(offset);
+    let offset = cpu.next_u8(bus);
+    let result = cpu.registers.sp_plus_offset(offset);
     bus.generic_cycle();
     bus.generic_cycle();
-    let half_carry = ((sp & 0x0f) + (offset & 0x0f)) > 0x0f;
-    let overflow = ((sp & 0xff) + (offset & 0xff)) > 0xff;
     cpu.registers.sp = result;
-    cpu.registers.set_zf(false);
-    cpu.registers.set_nf(false);
-    cpu.registers.set_hf(half_carry);
-    cpu.registers.set_cf(overflow);
 }
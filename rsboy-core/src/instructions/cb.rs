@@ -3,19 +3,19 @@ use crate::{bus::Bus, cpu::CPU, instructions::Location, instructions::Register::
 
 pub fn cb(cpu: &mut CPU, bus: &mut Bus) {
     let opcode = cpu.next_u8(bus);
-    let target = {
-        let opcode = opcode;
-        match opcode & 0x0F {
-            0x00 | 0x08 => Location::Register(B),
-            0x01 | 0x09 => Location::Register(C),
-            0x02 | 0x0a => Location::Register(D),
-            0x03 | 0x0b => Location::Register(E),
-            0x04 | 0x0c => Location::Register(H),
-            0x05 | 0x0d => Location::Register(L),
-            0x06 | 0x0e => Location::Memory(HL),
-            0x07 | 0x0f => Location::Register(A),
-            _ => panic!(),
-        }
+    if let Some(stats) = &mut bus.opcode_stats {
+        stats.record_cb(opcode);
+    }
+    let target = match opcode & 0x0F {
+        0x00 | 0x08 => Location::Register(B),
+        0x01 | 0x09 => Location::Register(C),
+        0x02 | 0x0a => Location::Register(D),
+        0x03 | 0x0b => Location::Register(E),
+        0x04 | 0x0c => Location::Register(H),
+        0x05 | 0x0d => Location::Register(L),
+        0x06 | 0x0e => Location::Memory(HL),
+        0x07 | 0x0f => Location::Register(A),
+        _ => panic!(),
     };
     if let U8(value) = cpu.read_from(target, bus) {
         match opcode {
@@ -138,12 +138,6 @@ pub fn swapped_nibbles(byte: u8) -> u8 {
 
 #[cfg(test)]
 mod test {
-    use crate::{
-        bus::Bus,
-        cpu::CPU,
-        instructions::{Instr, Location},
-    };
-
     // #[test]
     // fn ticks_cb_instr() {
     //     for instr in 0x00..=0xFF {
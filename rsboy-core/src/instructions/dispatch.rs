@@ -0,0 +1,86 @@
+use super::INSTR_TABLE;
+use crate::{bus::Bus, cpu::CPU};
+
+// A handler per opcode instead of matching on the Instr read from INSTR_TABLE every time. Each
+// handler runs a constant Instr, so the match in Instr::run folds away once it's inlined.
+// Used by CPU::execute_op with the fast-dispatch feature, `cargo bench` compares the two.
+pub type Handler = fn(&mut CPU, &mut Bus);
+
+fn handler<const OPCODE: usize>(cpu: &mut CPU, bus: &mut Bus) {
+    INSTR_TABLE[OPCODE].run(cpu, bus)
+}
+
+// The sixteen handlers of opcodes `$n` to `$n + 15`.
+macro_rules! row {
+    ($n:literal) => {
+        [
+            handler::<$n>,
+            handler::<{ $n + 1 }>,
+            handler::<{ $n + 2 }>,
+            handler::<{ $n + 3 }>,
+            handler::<{ $n + 4 }>,
+            handler::<{ $n + 5 }>,
+            handler::<{ $n + 6 }>,
+            handler::<{ $n + 7 }>,
+            handler::<{ $n + 8 }>,
+            handler::<{ $n + 9 }>,
+            handler::<{ $n + 10 }>,
+            handler::<{ $n + 11 }>,
+            handler::<{ $n + 12 }>,
+            handler::<{ $n + 13 }>,
+            handler::<{ $n + 14 }>,
+            handler::<{ $n + 15 }>,
+        ]
+    };
+}
+
+// Indexed by the high then the low nibble of the opcode.
+pub static DISPATCH_TABLE: [[Handler; 16]; 16] = [
+    row!(0x00),
+    row!(0x10),
+    row!(0x20),
+    row!(0x30),
+    row!(0x40),
+    row!(0x50),
+    row!(0x60),
+    row!(0x70),
+    row!(0x80),
+    row!(0x90),
+    row!(0xA0),
+    row!(0xB0),
+    row!(0xC0),
+    row!(0xD0),
+    row!(0xE0),
+    row!(0xF0),
+];
+
+pub fn run(opcode: u8, cpu: &mut CPU, bus: &mut Bus) {
+    DISPATCH_TABLE[opcode as usize >> 4][opcode as usize & 0xF](cpu, bus)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emu::Emu;
+    use crate::instructions::Instr;
+
+    #[test]
+    fn table_matches_instr_table() {
+        let mut matched = Emu::new(vec![], None);
+        let mut table = Emu::new(vec![], None);
+        // Register and (HL) opcodes with HL in WRAM, HALT aside.
+        for opcode in (0x40..=0xBF).filter(|&op| op != 0x76) {
+            matched.cpu.registers.h = 0xC0;
+            table.cpu.registers.h = 0xC0;
+            Instr::from(opcode).run(&mut matched.cpu, &mut matched.bus);
+            run(opcode, &mut table.cpu, &mut table.bus);
+            assert_eq!(matched.cpu.registers, table.cpu.registers, "{:02x}", opcode);
+            assert_eq!(
+                matched.bus.memory[..],
+                table.bus.memory[..],
+                "{:02x}",
+                opcode
+            );
+        }
+    }
+}
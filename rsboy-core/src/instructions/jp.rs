@@ -0,0 +1,221 @@
+use crate::instructions::Bus;
+
+use crate::instructions::CPU;
+
+use super::Flag;
+
+pub fn rst(size: u16, cpu: &mut CPU, bus: &mut Bus) {
+    bus.generic_cycle();
+    cpu.push_stack(cpu.registers.pc, bus);
+    cpu.registers.pc = size;
+}
+
+fn check_flag(cpu: &mut CPU, flag: Flag) -> bool {
+    match flag {
+        Flag::FlagC => cpu.registers.flg_c(),
+        Flag::FlagNC => cpu.registers.flg_nc(),
+        Flag::FlagZ => cpu.registers.flg_z(),
+        Flag::FlagNZ => cpu.registers.flg_nz(),
+    }
+}
+
+// Runs `f` if the condition holds, followed by the internal cycle every taken branch pays.
+// A branch that isn't taken costs nothing beyond its operand reads.
+// Returns whether the branch was taken.
+pub fn jumping<F: FnOnce(&mut CPU, &mut Bus)>(
+    jt: Option<Flag>,
+    cpu: &mut CPU,
+    bus: &mut Bus,
+    f: F,
+) -> bool {
+    if let Some(false) = jt.map(|flag| check_flag(cpu, flag)) {
+        return false;
+    }
+    f(cpu, bus);
+    bus.generic_cycle();
+    true
+}
+
+pub fn jp(jump_type: Option<Flag>, cpu: &mut CPU, bus: &mut Bus) {
+    let address = cpu.next_u16(bus);
+    jumping(jump_type, cpu, bus, |cpu, _| cpu.registers.pc = address);
+}
+
+pub fn jr(jump_type: Option<Flag>, cpu: &mut CPU, bus: &mut Bus) {
+    let offset = cpu.next_u8(bus) as i8;
+    let address = cpu.registers.pc.wrapping_add(offset as u16);
+    jumping(jump_type, cpu, bus, |cpu, _| {
+        cpu.registers.pc = address;
+    });
+}
+
+pub fn jp_hl(cpu: &mut CPU, _bus: &mut Bus) {
+    cpu.registers.pc = cpu.registers.hl();
+}
+
+pub fn ret(jump_type: Option<Flag>, cpu: &mut CPU, bus: &mut Bus) {
+    // RET cc spends a cycle evaluating the condition, whether or not it's taken.
+    // Unconditional RET doesn't.
+    if jump_type.is_some() {
+        bus.generic_cycle();
+    }
+    jumping(jump_type, cpu, bus, |cpu, bus| {
+        cpu.registers.pc = cpu.pop_stack(bus);
+    });
+}
+pub fn reti(cpu: &mut CPU, bus: &mut Bus) {
+    bus.enable_interrupts();
+    let addr = cpu.pop_stack(bus);
+    cpu.registers.pc = addr;
+    bus.generic_cycle();
+}
+
+pub fn call(jump_type: Option<Flag>, cpu: &mut CPU, bus: &mut Bus) {
+    let address = cpu.next_u16(bus);
+    jumping(jump_type, cpu, bus, |cpu, bus| {
+        cpu.push_stack(cpu.registers.pc, bus);
+        cpu.registers.pc = address;
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bus::Bus,
+        clock::Cycles,
+        cpu::CPU,
+        instructions::{jp::jr, Flag, Flag::*, Instr, INSTR_TABLE},
+    };
+
+    const Z: u8 = 0b1000_0000;
+    const C: u8 = 0b0001_0000;
+
+    // Flags that make the condition pass, then fail.
+    fn flags_for(flag: Flag) -> (u8, u8) {
+        match flag {
+            FlagZ => (Z, 0),
+            FlagNZ => (0, Z),
+            FlagC => (C, 0),
+            FlagNC => (0, C),
+        }
+    }
+
+    // Runs an instruction from 0xC000 with operands 0x1234 and a return address of 0x4321 on the stack.
+    // Returns the machine cycles spent, including the opcode fetch done by the previous step.
+    fn run(instr: Instr, f: u8) -> (Cycles, CPU) {
+        let mut cpu = CPU::new();
+        let mut bus = Bus::new(vec![], None);
+        bus.in_bios = 1;
+        bus.memory[0xC000] = 0x34;
+        bus.memory[0xC001] = 0x12;
+        bus.memory[0xD000] = 0x21;
+        bus.memory[0xD001] = 0x43;
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xD000;
+        cpu.registers.f = f;
+        cpu.registers.h = 0x56;
+        cpu.registers.l = 0x78;
+        let before = bus.clock;
+        instr.run(&mut cpu, &mut bus);
+        (bus.clock - before + 1, cpu)
+    }
+
+    #[test]
+    fn conditional_timings() {
+        // (instruction, taken, not taken, pc when taken) per Pan Docs.
+        for flag in &[FlagNZ, FlagZ, FlagNC, FlagC] {
+            let flag = *flag;
+            let cases = [
+                (Instr::JR(Some(flag)), 3, 2, 0xC001 + 0x34),
+                (Instr::JP(Some(flag)), 4, 3, 0x1234),
+                (Instr::CALL(Some(flag)), 6, 3, 0x1234),
+                (Instr::RET(Some(flag)), 5, 2, 0x4321),
+            ];
+            let (pass, fail) = flags_for(flag);
+            for (instr, taken, not_taken, target) in cases.iter() {
+                let (cycles, cpu) = run(*instr, pass);
+                assert_eq!(cycles, *taken, "{:?} taken", instr);
+                assert_eq!(cpu.registers.pc, *target, "{:?} taken", instr);
+
+                let (cycles, cpu) = run(*instr, fail);
+                assert_eq!(cycles, *not_taken, "{:?} not taken", instr);
+                let operands = match instr {
+                    Instr::JR(_) => 1,
+                    Instr::RET(_) => 0,
+                    _ => 2,
+                };
+                assert_eq!(cpu.registers.pc, 0xC000 + operands, "{:?} not taken", instr);
+                assert_eq!(cpu.registers.sp, 0xD000, "{:?} not taken", instr);
+            }
+        }
+    }
+
+    #[test]
+    fn unconditional_timings() {
+        let cases = [
+            (Instr::JR(None), 3, 0xC001 + 0x34),
+            (Instr::JP(None), 4, 0x1234),
+            (Instr::CALL(None), 6, 0x1234),
+            (Instr::RET(None), 4, 0x4321),
+            (Instr::RETI, 4, 0x4321),
+            (Instr::JpHl, 1, 0x5678),
+            (Instr::RST(0x38), 4, 0x38),
+        ];
+        // Unconditional branches ignore the flags entirely.
+        for f in &[0, Z | C] {
+            for (instr, cycles, target) in cases.iter() {
+                let (spent, cpu) = run(*instr, *f);
+                assert_eq!(spent, *cycles, "{:?}", instr);
+                assert_eq!(cpu.registers.pc, *target, "{:?}", instr);
+            }
+        }
+    }
+
+    #[test]
+    fn table_covers_every_conditional_opcode() {
+        let conditional = INSTR_TABLE
+            .iter()
+            .filter(|i| {
+                matches!(
+                    i,
+                    Instr::JR(Some(_))
+                        | Instr::JP(Some(_))
+                        | Instr::CALL(Some(_))
+                        | Instr::RET(Some(_))
+                )
+            })
+            .count();
+        assert_eq!(conditional, 16);
+    }
+
+    #[test]
+    fn _jr() {
+        let mut cpu = CPU::new();
+        let mut bus = Bus::new(vec![], None);
+        // Map the bootrom even when there's no dmg_boot.bin to load it from.
+        bus.in_bios = 0;
+        cpu.registers.pc = 0x000A + 1;
+        bus.bootrom[0x0007] = 0x76;
+        bus.bootrom[0x000A] = 0x20;
+        bus.bootrom[0x000B] = 0xFB; // -3
+
+        assert_eq!(cpu.registers.pc, 0x000B);
+        jr(Some(Flag::FlagNZ), &mut cpu, &mut bus);
+        assert_eq!(cpu.registers.pc, 0x0007);
+    }
+
+    // Enumerate all possible values for JR
+    fn _jr2() {
+        for i in 0..0xFF {
+            let mut cpu = CPU::new();
+            let mut bus = Bus::new(vec![], None);
+            cpu.registers.pc = 0x000A + 1;
+            bus.bootrom[i] = i as u8;
+            bus.bootrom[0x000A] = 0x20;
+            bus.bootrom[0x000B] = i as u8;
+            assert_eq!(cpu.registers.pc, 0x000B);
+            jr(Some(Flag::FlagNZ), &mut cpu, &mut bus);
+            assert_eq!(cpu.registers.pc, i as u16);
+        }
+    }
+}
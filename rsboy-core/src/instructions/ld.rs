@@ -0,0 +1,79 @@
+use crate::cpu::value::Value::U16;
+use crate::instructions::Bus;
+use crate::instructions::Location;
+use crate::instructions::Register;
+use crate::instructions::CPU;
+
+pub fn ld((into, from): (Location, Location), cpu: &mut CPU, bus: &mut Bus) {
+    let from_value = cpu.read_from(from, bus);
+    cpu.write_into(into, from_value, bus)
+}
+
+pub fn ldi(location: (Location, Location), cpu: &mut CPU, bus: &mut Bus) {
+    ld(location, cpu, bus);
+    cpu.registers.inc(Register::HL);
+}
+
+pub fn ldd(location: (Location, Location), cpu: &mut CPU, bus: &mut Bus) {
+    ld(location, cpu, bus);
+    cpu.registers.dec(Register::HL);
+}
+
+pub fn ldsp(cpu: &mut CPU, bus: &mut Bus) {
+    let offset = cpu.next_u8(bus);
+    let result = cpu.registers.sp_plus_offset(offset);
+    cpu.write_into(Location::Register(Register::HL), U16(result), bus);
+    bus.generic_cycle();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bus::Bus,
+        cpu::CPU,
+        instructions::{alu, ld, Register, Register::*},
+        registers::flags,
+    };
+
+    // CPU and bus about to read the offset operand from WRAM.
+    fn with_offset(sp: u16, offset: u8) -> (CPU, Bus) {
+        let mut cpu = CPU::new();
+        let mut bus = Bus::new(vec![], None);
+        bus.memory[0xC000] = offset;
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = sp;
+        (cpu, bus)
+    }
+
+    #[test]
+    fn ld_hl_sp_offset() {
+        let (mut cpu, mut bus) = with_offset(0xFFF8, 0x02);
+        ld::ldsp(&mut cpu, &mut bus);
+        assert_eq!(cpu.registers.hl(), 0xFFFA);
+        assert_eq!(cpu.registers.sp, 0xFFF8);
+        assert_eq!(cpu.registers.f, flags(false, false, false, false));
+        // Operand read and one internal cycle.
+        assert_eq!(bus.clock, 2);
+    }
+
+    #[test]
+    fn add_sp_negative_offset() {
+        let (mut cpu, mut bus) = with_offset(0x0001, 0xFF);
+        alu::addsp(&mut cpu, &mut bus);
+        assert_eq!(cpu.registers.sp, 0x0000);
+        assert_eq!(cpu.registers.f, flags(false, false, true, true));
+        // Operand read and two internal cycles.
+        assert_eq!(bus.clock, 3);
+    }
+
+    #[test]
+    fn _ld() {
+        let mut cpu = CPU::new();
+        let mut bus = Bus::new(vec![], None);
+        cpu.registers.a = 5;
+        cpu.registers.b = 8;
+        assert_eq!(cpu.registers.a, 0x5);
+        ld::ld((Register(A), Register(B)), &mut cpu, &mut bus);
+        assert_eq!(cpu.registers.a, 0x8);
+    }
+}
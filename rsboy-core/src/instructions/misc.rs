@@ -0,0 +1,39 @@
+use crate::{
+    bus::Bus,
+    cpu::{value::Writable, CPUState, CPU},
+};
+
+use super::Register;
+
+pub fn daa(cpu: &mut CPU, _bus: &mut Bus) {
+    cpu.registers.a = cpu.bcd_adjust(cpu.registers.a);
+}
+pub fn push(register: Register, cpu: &mut CPU, bus: &mut Bus) {
+    let value = cpu.registers.fetch_u16(register);
+    cpu.push_stack(value, bus);
+    bus.generic_cycle();
+}
+pub fn pop(register: Register, cpu: &mut CPU, bus: &mut Bus) {
+    let addr = cpu.pop_stack(bus);
+    addr.to_register(&mut cpu.registers, register);
+}
+
+// Resets DIV and, unless it switches the CGB speed, stops until a selected joypad line goes low.
+// With one already low nothing happens, a simplification of the button held cases in Pan Docs.
+pub fn stop(cpu: &mut CPU, bus: &mut Bus) {
+    // STOP is 10 00, the second byte is skipped without being read.
+    cpu.registers.pc = cpu.registers.pc.wrapping_add(1);
+    if bus.joypad.read() & 0x0F != 0x0F {
+        return;
+    }
+    bus.timer.internal = 0;
+    if !bus.speed.stop() {
+        cpu.state = CPUState::Stopped;
+    }
+}
+
+// CPU::step stops fetching until an enabled interrupt is pending.
+// TODO the HALT bug, where IME=0 with a pending interrupt runs the next byte twice.
+pub fn halt(cpu: &mut CPU, _bus: &mut Bus) {
+    cpu.halt = true;
+}
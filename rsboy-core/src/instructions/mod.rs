@@ -1,5 +1,6 @@
 mod alu;
 mod cb;
+pub mod dispatch;
 mod jp;
 mod ld;
 mod misc;
@@ -63,13 +64,7 @@ pub trait Executable {
 
 impl Register {
     pub fn is_dual_register(self) -> bool {
-        match self {
-            HL => true,
-            BC => true,
-            DE => true,
-            SP => true,
-            _ => false,
-        }
+        matches!(self, HL | BC | DE | SP)
     }
 }
 
@@ -83,8 +78,9 @@ impl Location {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub enum Instr {
+    #[default]
     NOOP,
     UNIMPLEMENTED,
     LD(Location, Location), // (To, From)
@@ -127,12 +123,6 @@ pub enum Instr {
     RST(u8),
 }
 
-impl Default for Instr {
-    fn default() -> Instr {
-        NOOP
-    }
-}
-
 impl From<u8> for Instr {
     fn from(op: u8) -> Self {
         INSTR_TABLE[op as usize]
@@ -140,6 +130,8 @@ impl From<u8> for Instr {
 }
 
 impl Instr {
+    // Inlined into every dispatch handler so each one only keeps its own arm.
+    #[cfg_attr(feature = "fast-dispatch", inline(always))]
     pub fn run(self, cpu: &mut CPU, bus: &mut Bus) {
         match self {
             NOOP => {} // empty !
@@ -174,9 +166,9 @@ impl Instr {
             RETI => jp::reti(cpu, bus),
             CALL(flag) => jp::call(flag, cpu, bus),
             CB => cb::cb(cpu, bus),
-            STOP => {} // TODO
+            STOP => misc::stop(cpu, bus),
             DisableInterrupts => bus.disable_interrupts(),
-            EnableInterrupts => bus.enable_interrupts(),
+            EnableInterrupts => bus.schedule_interrupts(),
             DAA => misc::daa(cpu, bus),
             POP(l) => misc::pop(l, cpu, bus),
             PUSH(l) => misc::push(l, cpu, bus),
@@ -0,0 +1,130 @@
+use crate::clock::Cycles;
+use crate::{apu, bus, hdma, joypad, serial, speed, timer};
+
+pub const IO_START: u16 = 0xFF00;
+pub const IO_END: u16 = 0xFF7F;
+pub const IF: usize = 0xFF0F;
+pub const STAT: usize = 0xFF41;
+pub const LY: usize = 0xFF44;
+pub const BOOT: usize = 0xFF50;
+
+// Bus state a register write can touch besides the device's own.
+pub struct IoContext<'a> {
+    pub clock: Cycles,
+    pub int_flags: &'a mut u8,
+    pub cgb: bool,
+}
+
+// A subsystem owning some of the registers at FF00-FF7F. Bus::read and Bus::write offer every IO
+// access to the devices in turn, registers none of them claims are handled by the bus itself.
+pub trait IoDevice {
+    // None if `address` isn't one of the device's registers.
+    fn io_read(&self, address: u16) -> Option<u8>;
+    // False if `address` isn't one of the device's registers.
+    fn io_write(&mut self, address: u16, value: u8, ctx: &mut IoContext) -> bool;
+}
+
+// How the bits of an IO register behave. Bits in `read_or` always read as 1, whatever the
+// device holds, and only `writable` bits of a write reach the device. Read only registers have
+// no writable bits, writes to them are dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoMask {
+    pub read_or: u8,
+    pub writable: u8,
+}
+
+const PLAIN: IoMask = mask(0x00, 0xFF);
+// Reads as 0xFF, see Hdma::read.
+const WRITE_ONLY: IoMask = mask(0xFF, 0xFF);
+
+const fn mask(read_or: u8, writable: u8) -> IoMask {
+    IoMask { read_or, writable }
+}
+
+// Mask of the register at `address` in FF00-FF7F, None where nothing is mapped. Bus::read and
+// Bus::write apply it on top of every IoDevice.
+// See https://gbdev.io/pandocs/Hardware_Reg_List.html
+pub fn io_mask(address: u16, cgb: bool) -> Option<IoMask> {
+    if bus::is_unmapped_io(address) && !(cgb && bus::is_cgb_io(address)) {
+        return None;
+    }
+    let m = match address as usize {
+        // Bits 4-5 select the lines, the low nibble is the lines themselves.
+        joypad::JOYP => mask(0xC0, 0x30),
+        serial::SB => PLAIN,
+        // Bit 1 picks the CGB fast clock.
+        serial::SC if cgb => mask(0x7C, 0x83),
+        serial::SC => mask(0x7E, 0x81),
+        timer::DIV | timer::TIMA | timer::TMA => PLAIN,
+        timer::TAC => mask(0xF8, 0x07),
+        IF => mask(0xE0, 0x1F),
+        // NR52 bits 0-3 are the channel status.
+        apu::NR52 => mask(apu::READ_MASKS[apu::NR52 - apu::APU_START], 0x80),
+        apu::APU_START..=apu::APU_END => {
+            mask(apu::READ_MASKS[address as usize - apu::APU_START], 0xFF)
+        }
+        // STAT bits 0-2 are the mode and LYC=LY, set by the PPU.
+        STAT => mask(0x80, 0x78),
+        // Writes don't store a value, any of them resets the line, see GPU::set_scanline.
+        LY => PLAIN,
+        speed::KEY1 => mask(0x7E, 0x01),
+        hdma::HDMA1..=hdma::HDMA4 => WRITE_ONLY,
+        BOOT => mask(0xFE, 0x01),
+        _ => PLAIN,
+    };
+    Some(m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::{Bus, Memory};
+
+    // Registers the hardware changes on its own, so a write doesn't simply read back.
+    const LIVE: [usize; 5] = [joypad::JOYP, timer::DIV, LY, apu::NR52, hdma::HDMA5];
+
+    #[test]
+    fn registers_read_back_through_masks() {
+        for &cgb in [false, true].iter() {
+            for address in IO_START..=IO_END {
+                for &value in [0x00, 0xFF, 0x5A].iter() {
+                    let mut bus = Bus::new(vec![0; 0x8000], None);
+                    bus.strict_io = true;
+                    bus.cgb = cgb;
+                    // Sound registers only take writes with the APU on.
+                    bus.write(apu::NR52 as u16, 0x80);
+                    bus.write(address, value);
+                    let read = bus.read(address);
+                    let context = format!("{:04X} <- {:02X}, cgb {}", address, value, cgb);
+                    match io_mask(address, cgb) {
+                        None => assert_eq!(read, 0xFF, "{}", context),
+                        Some(mask) if LIVE.contains(&(address as usize)) => {
+                            assert_eq!(read & mask.read_or, mask.read_or, "{}", context)
+                        }
+                        Some(mask) => {
+                            let expected = (value & mask.writable) | mask.read_or;
+                            assert_eq!(read, expected, "{}", context)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stat_keeps_ppu_bits() {
+        let mut bus = Bus::new(vec![0; 0x8000], None);
+        bus.gpu.lcdstat = 0x07;
+        bus.write(STAT as u16, 0x00);
+        assert_eq!(bus.read(STAT as u16), 0x87);
+    }
+
+    #[test]
+    fn ly_writes_reset_the_line() {
+        let mut bus = Bus::new(vec![0; 0x8000], None);
+        bus.gpu.set_scanline(100);
+        assert_eq!(bus.read(LY as u16), 100);
+        bus.write(LY as u16, 0x42);
+        assert_eq!(bus.read(LY as u16), 0);
+    }
+}
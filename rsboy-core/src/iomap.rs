@@ -0,0 +1,329 @@
+use crate::bus::Bus;
+use crate::cpu::{interrupt_name, INTERRUPTS};
+use std::fmt::Display;
+
+pub const LYC: usize = 0xFF45;
+
+// LCDC (0xFF40) decoded, see https://gbdev.io/pandocs/LCDC.html
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Lcdc(pub u8);
+
+impl Lcdc {
+    pub fn lcd_on(self) -> bool {
+        self.0 & 0x80 != 0
+    }
+    // Window tile map at 9C00 instead of 9800.
+    pub fn window_map_high(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+    pub fn window_on(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+    // BG and window tiles addressed unsigned from 8000 instead of signed from 9000.
+    pub fn tile_data_low(self) -> bool {
+        self.0 & 0x10 != 0
+    }
+    // BG tile map at 9C00 instead of 9800.
+    pub fn bg_map_high(self) -> bool {
+        self.0 & 0x08 != 0
+    }
+    pub fn tall_sprites(self) -> bool {
+        self.0 & 0x04 != 0
+    }
+    pub fn sprites_on(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+    pub fn bg_on(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+}
+
+// STAT (0xFF41) decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stat(pub u8);
+
+impl Stat {
+    pub fn lyc_interrupt(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+    pub fn oam_interrupt(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+    pub fn vblank_interrupt(self) -> bool {
+        self.0 & 0x10 != 0
+    }
+    pub fn hblank_interrupt(self) -> bool {
+        self.0 & 0x08 != 0
+    }
+    pub fn coincidence(self) -> bool {
+        self.0 & 0x04 != 0
+    }
+    // 0 HBlank, 1 VBlank, 2 OAM scan, 3 drawing.
+    pub fn mode(self) -> u8 {
+        self.0 & 0b11
+    }
+}
+
+// TAC (0xFF07) decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tac(pub u8);
+
+impl Tac {
+    pub fn enabled(self) -> bool {
+        self.0 & 0b100 != 0
+    }
+    pub fn clock_select(self) -> u8 {
+        self.0 & 0b11
+    }
+    // TIMA increment rate.
+    pub fn frequency_hz(self) -> u32 {
+        match self.clock_select() {
+            0b00 => 4096,
+            0b01 => 262_144,
+            0b10 => 65536,
+            _ => 16384,
+        }
+    }
+}
+
+// IE (0xFFFF) or IF (0xFF0F) decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Interrupts(pub u8);
+
+// Read-only typed view of the IO registers.
+// Reads the backing fields directly, so unlike Bus::read it has no side effects and also shows
+// write-only registers.
+pub struct IoMap<'a> {
+    bus: &'a Bus,
+}
+
+impl Bus {
+    pub fn io_map(&self) -> IoMap<'_> {
+        IoMap { bus: self }
+    }
+}
+
+impl<'a> IoMap<'a> {
+    // Selected joypad lines, as the game would read them.
+    pub fn joyp(&self) -> u8 {
+        self.bus.joypad.read()
+    }
+    pub fn buttons(&self) -> u8 {
+        self.bus.joypad.buttons
+    }
+    pub fn directions(&self) -> u8 {
+        self.bus.joypad.directions
+    }
+    pub fn div(&self) -> u8 {
+        self.bus.timer.div()
+    }
+    pub fn tima(&self) -> u8 {
+        self.bus.timer.tima
+    }
+    pub fn tma(&self) -> u8 {
+        self.bus.timer.tma
+    }
+    pub fn tac(&self) -> Tac {
+        Tac(self.bus.timer.tac)
+    }
+    pub fn sb(&self) -> u8 {
+        self.bus.serial.sb
+    }
+    pub fn sc(&self) -> u8 {
+        self.bus.serial.sc
+    }
+    pub fn dma(&self) -> u8 {
+        self.bus.dma.source
+    }
+    pub fn int_flags(&self) -> Interrupts {
+        Interrupts(self.bus.int_flags)
+    }
+    pub fn int_enabled(&self) -> Interrupts {
+        Interrupts(self.bus.int_enabled)
+    }
+    pub fn ime(&self) -> bool {
+        self.bus.ime != 0
+    }
+    pub fn lcdc(&self) -> Lcdc {
+        Lcdc(self.bus.gpu.lcdc)
+    }
+    pub fn stat(&self) -> Stat {
+        Stat(self.bus.gpu.lcdstat)
+    }
+    pub fn scy(&self) -> u8 {
+        self.bus.gpu.scrolly
+    }
+    pub fn scx(&self) -> u8 {
+        self.bus.gpu.scrollx
+    }
+    pub fn ly(&self) -> u8 {
+        self.bus.gpu.ly()
+    }
+    pub fn lyc(&self) -> u8 {
+        self.bus.memory[LYC]
+    }
+    pub fn bgp(&self) -> u8 {
+        self.bus.gpu.bgrdpal
+    }
+    pub fn obp0(&self) -> u8 {
+        self.bus.gpu.obj0pal
+    }
+    pub fn obp1(&self) -> u8 {
+        self.bus.gpu.obj1pal
+    }
+    pub fn wy(&self) -> u8 {
+        self.bus.gpu.windowy
+    }
+    pub fn wx(&self) -> u8 {
+        self.bus.gpu.windowx
+    }
+}
+
+// Names of the set flags joined with |, or "-" when none are.
+fn join(flags: &[(bool, &str)]) -> String {
+    let names: Vec<&str> = flags
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() {
+        "-".to_string()
+    } else {
+        names.join("|")
+    }
+}
+
+// Tile map address for the "use 9C00" bits.
+fn map_base(high: bool) -> &'static str {
+    if high {
+        "9C00"
+    } else {
+        "9800"
+    }
+}
+
+impl Display for Interrupts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags: Vec<(bool, &str)> = INTERRUPTS
+            .iter()
+            .map(|&flag| (self.0 & flag != 0, interrupt_name(flag)))
+            .collect();
+        write!(f, "{}", join(&flags))
+    }
+}
+
+impl Display for Lcdc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02x} ({}, BG map {}, WIN map {}, tiles {}, OBJ {})",
+            self.0,
+            join(&[
+                (self.lcd_on(), "LCD"),
+                (self.window_on(), "WIN"),
+                (self.sprites_on(), "OBJ"),
+                (self.bg_on(), "BG"),
+            ]),
+            map_base(self.bg_map_high()),
+            map_base(self.window_map_high()),
+            if self.tile_data_low() { "8000" } else { "8800" },
+            if self.tall_sprites() { "8x16" } else { "8x8" },
+        )
+    }
+}
+
+impl Display for Stat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = ["HBlank", "VBlank", "OAM", "Drawing"][self.mode() as usize];
+        write!(
+            f,
+            "{:02x} (mode {} {}, {}, int {})",
+            self.0,
+            self.mode(),
+            mode,
+            if self.coincidence() {
+                "LYC=LY"
+            } else {
+                "LYC!=LY"
+            },
+            join(&[
+                (self.lyc_interrupt(), "LYC"),
+                (self.oam_interrupt(), "OAM"),
+                (self.vblank_interrupt(), "VBLANK"),
+                (self.hblank_interrupt(), "HBLANK"),
+            ])
+        )
+    }
+}
+
+// The registers that matter most when something goes wrong, one per line.
+impl<'a> Display for IoMap<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "LCDC: {}", self.lcdc())?;
+        writeln!(
+            f,
+            "STAT: {}, LY: {}, LYC: {}",
+            self.stat(),
+            self.ly(),
+            self.lyc()
+        )?;
+        writeln!(
+            f,
+            "IE: {}, IF: {}, IME: {}",
+            self.int_enabled(),
+            self.int_flags(),
+            self.ime()
+        )?;
+        write!(f, "TAC: {}", self.tac())
+    }
+}
+
+impl Display for Tac {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:08b} ({} {}Hz)",
+            self.0,
+            if self.enabled() { "on" } else { "off" },
+            self.frequency_hz()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_registers() {
+        let mut bus = Bus::new(vec![], None);
+        bus.gpu.lcdc = 0x91;
+        bus.gpu.lcdstat = 0x45;
+        bus.timer.tac = 0b101;
+        bus.memory[LYC] = 0x90;
+        let io = bus.io_map();
+        let lcdc = io.lcdc();
+        assert!(lcdc.lcd_on() && lcdc.tile_data_low() && lcdc.bg_on());
+        assert!(!lcdc.window_on() && !lcdc.tall_sprites());
+        assert!(io.stat().lyc_interrupt() && io.stat().coincidence());
+        assert_eq!(io.stat().mode(), 1);
+        assert!(io.tac().enabled());
+        assert_eq!(io.tac().frequency_hz(), 262_144);
+        assert_eq!(io.lyc(), 0x90);
+    }
+
+    #[test]
+    fn prints_bit_names() {
+        assert_eq!(Interrupts(0b10101).to_string(), "VBLANK|TIMER|JOYPAD");
+        assert_eq!(Interrupts(0xE0).to_string(), "-");
+        assert_eq!(
+            Lcdc(0x91).to_string(),
+            "91 (LCD|BG, BG map 9800, WIN map 9800, tiles 8000, OBJ 8x8)"
+        );
+        assert_eq!(
+            Stat(0x45).to_string(),
+            "45 (mode 1 VBlank, LYC=LY, int LYC)"
+        );
+        assert_eq!(Stat(0x02).to_string(), "02 (mode 2 OAM, LYC!=LY, int -)");
+    }
+}
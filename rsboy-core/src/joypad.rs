@@ -0,0 +1,183 @@
+use crate::clock::Cycles;
+use crate::constants::CYCLES_PER_FRAME;
+use crate::cpu::JOYPAD;
+use crate::input::Button;
+use crate::io::{IoContext, IoDevice};
+
+pub const JOYP: usize = 0xFF00;
+
+// Further joypad interrupts within this many cycles of the last one are dropped, so a batch of
+// presses landing in the same frame wakes the game once.
+pub const DEBOUNCE_CYCLES: Cycles = CYCLES_PER_FRAME;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Select {
+    Buttons,
+    Directions,
+    None,
+}
+
+// The P1/JOYP register and its interrupt, see https://gbdev.io/pandocs/Joypad_Input.html
+// Every change goes through here so the interrupt only fires when a line the game selected
+// goes from high to low, never on a release.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joypad {
+    pub select: Select,
+    // Active low, A/Right in bit 0 up to Start/Down in bit 3.
+    pub buttons: u8,
+    pub directions: u8,
+    // Bus clock of the last interrupt.
+    last_interrupt: Option<Cycles>,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self {
+            select: Select::Buttons,
+            buttons: 0x0F,
+            directions: 0x0F,
+            last_interrupt: None,
+        }
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The selected lines, as the game reads them.
+    pub fn read(&self) -> u8 {
+        match self.select {
+            Select::Buttons => self.buttons,
+            Select::Directions => self.directions,
+            Select::None => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, value: u8, clock: Cycles, flags: &mut u8) {
+        self.update(clock, flags, |joypad| {
+            joypad.select = match value & 0xF0 {
+                0b0001_0000 => Select::Buttons,
+                0b0010_0000 => Select::Directions,
+                _ => Select::None,
+            }
+        });
+    }
+
+    pub fn press(&mut self, button: Button, clock: Cycles, flags: &mut u8) {
+        self.update(clock, flags, |joypad| {
+            *joypad.lines(button) &= !button.mask()
+        });
+    }
+
+    pub fn release(&mut self, button: Button, clock: Cycles, flags: &mut u8) {
+        self.update(clock, flags, |joypad| {
+            *joypad.lines(button) |= button.mask()
+        });
+    }
+
+    // Both sets of lines at once, for replaying recorded input.
+    pub fn set_lines(&mut self, directions: u8, buttons: u8, clock: Cycles, flags: &mut u8) {
+        self.update(clock, flags, |joypad| {
+            joypad.directions = directions;
+            joypad.buttons = buttons;
+        });
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        let lines = if button.is_direction() {
+            self.directions
+        } else {
+            self.buttons
+        };
+        lines & button.mask() == 0
+    }
+
+    fn lines(&mut self, button: Button) -> &mut u8 {
+        if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.buttons
+        }
+    }
+
+    fn update<F: FnOnce(&mut Self)>(&mut self, clock: Cycles, flags: &mut u8, f: F) {
+        let before = self.read();
+        f(self);
+        let fell = before & !self.read() & 0x0F != 0;
+        // A clock before the last interrupt means a reset or a loaded state, not a bounce.
+        let debounced = match self.last_interrupt {
+            Some(last) => clock >= last && clock - last < DEBOUNCE_CYCLES,
+            None => false,
+        };
+        if fell && !debounced {
+            *flags |= JOYPAD;
+            self.last_interrupt = Some(clock);
+        }
+    }
+}
+
+impl IoDevice for Joypad {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        let select = match self.select {
+            Select::Buttons => 0x10,
+            Select::Directions => 0x20,
+            Select::None => 0x30,
+        };
+        Some(select | self.read()).filter(|_| address as usize == JOYP)
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, ctx: &mut IoContext) -> bool {
+        if address as usize != JOYP {
+            return false;
+        }
+        self.write(value, ctx.clock, ctx.int_flags);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interrupt_on_selected_press_only() {
+        let mut joypad = Joypad::new();
+        let mut flags = 0;
+        joypad.press(Button::Up, 0, &mut flags);
+        assert_eq!(flags, 0);
+        joypad.release(Button::Up, 0, &mut flags);
+        joypad.press(Button::A, 0, &mut flags);
+        assert_eq!(flags, JOYPAD);
+        assert_eq!(joypad.read(), 0x0E);
+
+        flags = 0;
+        let later = DEBOUNCE_CYCLES * 2;
+        joypad.release(Button::A, later, &mut flags);
+        assert_eq!(flags, 0);
+        // Selecting a group with a line already low is a falling edge too.
+        joypad.press(Button::Down, later, &mut flags);
+        joypad.write(0x20, later, &mut flags);
+        assert_eq!(flags, JOYPAD);
+        assert!(joypad.is_pressed(Button::Down));
+    }
+
+    #[test]
+    fn presses_within_a_frame_fire_once() {
+        let mut joypad = Joypad::new();
+        let mut flags = 0;
+        joypad.press(Button::A, 100, &mut flags);
+        flags = 0;
+        joypad.press(Button::B, 100, &mut flags);
+        joypad.press(Button::Start, 100 + DEBOUNCE_CYCLES - 1, &mut flags);
+        assert_eq!(flags, 0);
+        joypad.press(Button::Select, 100 + DEBOUNCE_CYCLES, &mut flags);
+        assert_eq!(flags, JOYPAD);
+        // An earlier clock after a reset isn't held back.
+        flags = 0;
+        joypad.release(Button::A, 0, &mut flags);
+        joypad.press(Button::A, 0, &mut flags);
+        assert_eq!(flags, JOYPAD);
+    }
+}
@@ -0,0 +1,63 @@
+pub mod apu;
+pub mod banks;
+pub mod batch;
+pub mod battery;
+pub mod bess;
+pub mod bootlogo;
+pub mod bugreport;
+pub mod bus;
+pub mod camera;
+pub mod cartridge;
+pub mod clock;
+pub mod compat;
+pub mod console;
+pub mod constants;
+pub mod cpu;
+pub mod debuginfo;
+pub mod decode;
+pub mod disasm;
+pub mod dma;
+pub mod dump;
+pub mod emu;
+pub mod exec;
+pub mod frame;
+pub mod gallery;
+pub mod gdb;
+pub mod golden;
+pub mod gpu;
+pub mod hdma;
+pub mod import;
+pub mod inflate;
+pub mod input;
+pub mod instructions;
+pub mod io;
+pub mod iomap;
+pub mod joypad;
+pub mod mbc1;
+pub mod mbc3;
+pub mod mbc5;
+pub mod meminit;
+pub mod model;
+pub mod movie;
+pub mod pacing;
+pub mod png;
+pub mod printer;
+pub mod ramsearch;
+pub mod registers;
+pub mod rtc;
+pub mod savestate;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod serial;
+pub mod slots;
+pub mod snapshot;
+pub mod speed;
+pub mod splash;
+pub mod stats;
+pub mod texture;
+pub mod timeline;
+pub mod timer;
+pub mod trace;
+pub mod video;
+pub mod watch;
+pub mod watchdog;
@@ -0,0 +1,158 @@
+use std::ops::Range;
+
+// MBC1 cartridges, see https://gbdev.io/pandocs/MBC1.html
+// Only ROM banking, cartridge RAM stays the single bank in Bus::memory.
+pub const CARTRIDGE_TYPES: [u8; 3] = [0x01, 0x02, 0x03];
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+// Multicarts (MBC1M) put the next game's header, Nintendo logo included, at the start of bank 0x10.
+const LOGO: Range<usize> = 0x104..0x134;
+const MULTICART_GAME_SIZE: usize = 0x10 * ROM_BANK_SIZE;
+const MULTICART_SIZE: usize = 4 * MULTICART_GAME_SIZE;
+
+#[derive(Debug, Clone)]
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    // MBC1M wiring: BANK2 is one bit lower and picks one of four games, BANK1 only has 4 bits.
+    pub multicart: bool,
+    pub ram_enabled: bool,
+    // 2000-3FFF, 5 bits.
+    pub bank1: u8,
+    // 4000-5FFF, 2 bits.
+    pub bank2: u8,
+    // 6000-7FFF, when set BANK2 also switches 0000-3FFF.
+    pub mode: bool,
+}
+
+// Whether `rom` looks like a multi-game compilation, every game having its own header.
+pub fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == MULTICART_SIZE
+        && rom[LOGO] == rom[MULTICART_GAME_SIZE + LOGO.start..MULTICART_GAME_SIZE + LOGO.end]
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            multicart: is_multicart(&rom),
+            rom,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: false,
+        }
+    }
+
+    // Registers as at power on, the ROM stays.
+    pub fn reset(&mut self) {
+        *self = Self::new(std::mem::take(&mut self.rom));
+    }
+
+    // Writes to 0000-7FFF.
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.bank1 = value & 0x1F,
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 1 != 0,
+        }
+    }
+
+    fn bank2_shift(&self) -> u32 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    // Bank at 4000-7FFF. BANK1 reads 0 as 1 by all 5 bits, even on a multicart where the top one
+    // isn't wired, so 0x10 there maps bank 0 of the selected game.
+    pub fn rom_bank(&self) -> usize {
+        let bank1 = self.bank1.max(1);
+        let low = if self.multicart { bank1 & 0x0F } else { bank1 };
+        ((self.bank2 as usize) << self.bank2_shift()) | low as usize
+    }
+
+    // Bank at 0000-3FFF.
+    pub fn rom0_bank(&self) -> usize {
+        if self.mode {
+            (self.bank2 as usize) << self.bank2_shift()
+        } else {
+            0
+        }
+    }
+
+    // Reads of 0000-7FFF, banks past the end of the ROM wrap around.
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            self.rom0_bank()
+        } else {
+            self.rom_bank()
+        };
+        let offset = bank * ROM_BANK_SIZE + address as usize % ROM_BANK_SIZE;
+        match self.rom.len() {
+            0 => 0xFF,
+            len => self.rom[offset % len],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Every bank starts with its number.
+    fn rom(multicart: bool) -> Vec<u8> {
+        let mut rom = vec![0; MULTICART_SIZE];
+        for (bank, data) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+        }
+        rom[LOGO].copy_from_slice(&[0xCE; 0x30]);
+        if multicart {
+            for game in 1..4 {
+                let logo = game * MULTICART_GAME_SIZE + LOGO.start;
+                rom[logo..logo + 0x30].copy_from_slice(&[0xCE; 0x30]);
+            }
+        }
+        rom
+    }
+
+    #[test]
+    fn banks_like_a_plain_mbc1() {
+        let mut mbc1 = Mbc1::new(rom(false));
+        assert!(!mbc1.multicart);
+        assert_eq!(mbc1.read_rom(0x4000), 1);
+        mbc1.write_register(0x2000, 0);
+        assert_eq!(mbc1.read_rom(0x4000), 1);
+        mbc1.write_register(0x2000, 0x03);
+        mbc1.write_register(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x4000), 0x23);
+        assert_eq!(mbc1.read_rom(0x0000), 0);
+        mbc1.write_register(0x6000, 1);
+        assert_eq!(mbc1.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn multicart_selects_games_with_bank2() {
+        let mut mbc1 = Mbc1::new(rom(true));
+        assert!(mbc1.multicart);
+        mbc1.write_register(0x2000, 0x03);
+        mbc1.write_register(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x4000), 0x13);
+        // Bit 4 of BANK1 goes nowhere, but still counts for the 0 check.
+        mbc1.write_register(0x2000, 0x10);
+        assert_eq!(mbc1.read_rom(0x4000), 0x10);
+        mbc1.write_register(0x2000, 0x00);
+        assert_eq!(mbc1.read_rom(0x4000), 0x11);
+
+        // The menu switches the selected game's header in at 0000.
+        mbc1.write_register(0x6000, 1);
+        mbc1.write_register(0x4000, 0x02);
+        assert_eq!(mbc1.read_rom(0x0000), 0x20);
+        assert_eq!(mbc1.read_rom(0x0104), 0xCE);
+        mbc1.reset();
+        assert_eq!((mbc1.rom0_bank(), mbc1.rom_bank()), (0, 1));
+        assert!(mbc1.multicart);
+    }
+}
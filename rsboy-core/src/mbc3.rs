@@ -0,0 +1,194 @@
+use crate::cartridge;
+use crate::clock::Cycles;
+use crate::rtc::Rtc;
+
+// MBC3 cartridges, see https://gbdev.io/pandocs/MBC3.html
+pub const CARTRIDGE_TYPES: [u8; 5] = [0x0F, 0x10, 0x11, 0x12, 0x13];
+// With a real time clock.
+pub const RTC_CARTRIDGE_TYPES: [u8; 2] = [0x0F, 0x10];
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+// 32 KiB, the most the header can ask an MBC3 for.
+pub const RAM_BANKS: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    pub ram_enabled: bool,
+    // 2000-3FFF, 7 bits.
+    pub rom_bank: u8,
+    // 4000-5FFF, a RAM bank (0-3) or an RTC register (08-0C).
+    pub select: u8,
+    pub rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, has_rtc: bool) -> Self {
+        // Anything but 32 KiB gets a single bank.
+        let banks = match rom.get(cartridge::RAM_SIZE) {
+            Some(0x03) => RAM_BANKS,
+            _ => 1,
+        };
+        Self {
+            rom,
+            ram: vec![0; banks * RAM_BANK_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+            select: 0,
+            rtc: Some(Rtc::default()).filter(|_| has_rtc),
+        }
+    }
+
+    // Registers as at power on, the ROM, RAM and the clock stay.
+    pub fn reset(&mut self, clock: Cycles) {
+        self.ram_enabled = false;
+        self.rom_bank = 1;
+        self.select = 0;
+        if let Some(rtc) = &mut self.rtc {
+            rtc.reset(clock);
+        }
+    }
+
+    // Writes to 0000-7FFF.
+    pub fn write_register(&mut self, address: u16, value: u8, clock: Cycles) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.select = value,
+            _ => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.sync(clock);
+                    rtc.latch(value);
+                }
+            }
+        }
+    }
+
+    // Bank at 4000-7FFF, 0 reads as 1.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank.max(1) as usize
+    }
+
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank() };
+        let offset = bank * ROM_BANK_SIZE + address as usize % ROM_BANK_SIZE;
+        match self.rom.len() {
+            0 => 0xFF,
+            len => self.rom[offset % len],
+        }
+    }
+
+    // All RAM banks, battery backed and kept in the .sav file.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // Offset into ram of A000-BFFF, banked by the low bits of select.
+    fn ram_offset(&self, address: u16) -> usize {
+        let bank = (self.select & 0x03) as usize % (self.ram.len() / RAM_BANK_SIZE);
+        bank * RAM_BANK_SIZE + address as usize % RAM_BANK_SIZE
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.ram[self.ram_offset(address)]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        let offset = self.ram_offset(address);
+        self.ram[offset] = value;
+    }
+
+    // The RTC register mapped at A000-BFFF instead of RAM, if any.
+    pub fn rtc_register(&self) -> Option<u8> {
+        match self.select {
+            0x08..=0x0C if self.rtc.is_some() => Some(self.select),
+            _ => None,
+        }
+    }
+
+    pub fn read_rtc(&self) -> Option<u8> {
+        let register = self.rtc_register()?;
+        let rtc = self.rtc.as_ref()?;
+        Some(if self.ram_enabled {
+            rtc.read(register)
+        } else {
+            0xFF
+        })
+    }
+
+    // True if the write went to the clock rather than RAM.
+    pub fn write_rtc(&mut self, value: u8, clock: Cycles) -> bool {
+        let register = match self.rtc_register() {
+            Some(register) => register,
+            None => return false,
+        };
+        if let (Some(rtc), true) = (&mut self.rtc, self.ram_enabled) {
+            rtc.sync(clock);
+            rtc.write(register, value);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::GB_CYCLE_SPEED;
+
+    #[test]
+    fn banks_and_maps_the_clock() {
+        let mut rom = vec![0; 0x10 * ROM_BANK_SIZE];
+        for (bank, data) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+        }
+        let mut mbc3 = Mbc3::new(rom, true);
+        mbc3.write_register(0x2000, 0, 0);
+        assert_eq!(mbc3.read_rom(0x4000), 1);
+        mbc3.write_register(0x2000, 0x0B, 0);
+        assert_eq!(mbc3.read_rom(0x4000), 0x0B);
+        assert_eq!(mbc3.read_rom(0x0000), 0);
+
+        // RAM stays mapped until an RTC register is selected.
+        assert_eq!(mbc3.read_rtc(), None);
+        mbc3.write_register(0x0000, 0x0A, 0);
+        mbc3.write_register(0x4000, 0x09, 0);
+        assert!(mbc3.write_rtc(5, 0));
+        mbc3.write_register(0x6000, 0, 0);
+        mbc3.write_register(0x6000, 1, GB_CYCLE_SPEED * 60);
+        assert_eq!(mbc3.read_rtc(), Some(6));
+        mbc3.write_register(0x4000, 0x00, 0);
+        assert!(!mbc3.write_rtc(5, 0));
+
+        mbc3.reset(GB_CYCLE_SPEED * 60);
+        assert_eq!(mbc3.rom_bank(), 1);
+        assert_eq!(mbc3.rtc.as_ref().unwrap().regs[1], 6);
+        assert!(Mbc3::new(vec![], false).rtc.is_none());
+    }
+
+    #[test]
+    fn banks_32k_of_ram() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[cartridge::RAM_SIZE] = 0x03;
+        let mut mbc3 = Mbc3::new(rom, false);
+        assert_eq!(mbc3.ram().len(), RAM_BANKS * RAM_BANK_SIZE);
+        for bank in 0..RAM_BANKS as u8 {
+            mbc3.write_register(0x4000, bank, 0);
+            mbc3.write_ram(0xA010, bank + 1);
+        }
+        mbc3.write_register(0x4000, 2, 0);
+        assert_eq!(mbc3.read_ram(0xA010), 3);
+        assert_eq!(mbc3.ram()[3 * RAM_BANK_SIZE + 0x10], 4);
+
+        // 8 KiB carts mirror their one bank.
+        let mut mbc3 = Mbc3::new(vec![], false);
+        mbc3.write_ram(0xA010, 0x42);
+        mbc3.write_register(0x4000, 1, 0);
+        assert_eq!(mbc3.read_ram(0xA010), 0x42);
+    }
+}
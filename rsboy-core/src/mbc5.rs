@@ -0,0 +1,154 @@
+use crate::cartridge::{self, Header};
+
+// MBC5 cartridges, see https://gbdev.io/pandocs/MBC5.html
+pub const CARTRIDGE_TYPES: [u8; 6] = [0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E];
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+// 128 KiB, the most the header can ask for.
+pub const RAM_BANKS: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    pub ram_enabled: bool,
+    // 2000-2FFF the low 8 bits, 3000-3FFF bit 8.
+    pub rom_bank: u16,
+    // 4000-5FFF, 4 bits.
+    pub ram_bank: u8,
+    // Whether the motor runs, None without one. Rumble carts wire it to bit 3 of the RAM bank.
+    pub rumble: Option<bool>,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        // Anything without RAM still gets a bank.
+        let banks = match rom.get(cartridge::RAM_SIZE) {
+            Some(0x03) => 4,
+            Some(0x04) => RAM_BANKS,
+            Some(0x05) => 8,
+            _ => 1,
+        };
+        let has_rumble = Header::parse(&rom).is_some_and(|h| h.has_rumble());
+        Self {
+            rom,
+            ram: vec![0; banks * RAM_BANK_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble: Some(false).filter(|_| has_rumble),
+        }
+    }
+
+    // Registers as at power on with the motor off, the ROM and RAM stay.
+    pub fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.rumble = self.rumble.map(|_| false);
+    }
+
+    // Writes to 0000-7FFF.
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 1) << 8),
+            0x4000..=0x5FFF => match &mut self.rumble {
+                Some(motor) => {
+                    *motor = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                }
+                None => self.ram_bank = value & 0x0F,
+            },
+            _ => {}
+        }
+    }
+
+    // Bank at 4000-7FFF, unlike MBC1 and MBC3 0 maps bank 0.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank as usize
+    }
+
+    pub fn read_rom(&self, address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { self.rom_bank() };
+        let offset = bank * ROM_BANK_SIZE + address as usize % ROM_BANK_SIZE;
+        match self.rom.len() {
+            0 => 0xFF,
+            len => self.rom[offset % len],
+        }
+    }
+
+    // All RAM banks, battery backed and kept in the .sav file.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // Offset into ram of A000-BFFF, smaller RAMs mirror their banks.
+    fn ram_offset(&self, address: u16) -> usize {
+        let bank = self.ram_bank as usize % (self.ram.len() / RAM_BANK_SIZE);
+        bank * RAM_BANK_SIZE + address as usize % RAM_BANK_SIZE
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.ram[self.ram_offset(address)]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        let offset = self.ram_offset(address);
+        self.ram[offset] = value;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom(kind: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x200 * ROM_BANK_SIZE];
+        for (bank, data) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+            data[1] = (bank >> 8) as u8;
+        }
+        rom[cartridge::CARTRIDGE_TYPE] = kind;
+        rom[cartridge::RAM_SIZE] = 0x04;
+        rom
+    }
+
+    #[test]
+    fn banks_nine_bits_of_rom() {
+        let mut mbc5 = Mbc5::new(rom(0x19));
+        assert_eq!(mbc5.read_rom(0x4000), 1);
+        mbc5.write_register(0x2000, 0x34);
+        mbc5.write_register(0x3000, 0x01);
+        assert_eq!((mbc5.read_rom(0x4000), mbc5.read_rom(0x4001)), (0x34, 1));
+        mbc5.write_register(0x2000, 0);
+        assert_eq!(mbc5.rom_bank(), 0x100);
+        mbc5.write_register(0x3000, 0);
+        assert_eq!(mbc5.read_rom(0x4000), 0);
+        mbc5.reset();
+        assert_eq!(mbc5.rom_bank(), 1);
+    }
+
+    #[test]
+    fn banks_ram_and_drives_the_motor() {
+        let mut mbc5 = Mbc5::new(rom(0x1B));
+        assert_eq!(mbc5.ram().len(), RAM_BANKS * RAM_BANK_SIZE);
+        mbc5.write_register(0x4000, 0x0F);
+        mbc5.write_ram(0xA010, 0x42);
+        assert_eq!(mbc5.ram()[0x0F * RAM_BANK_SIZE + 0x10], 0x42);
+        assert_eq!(mbc5.rumble, None);
+
+        // Bit 3 runs the motor instead of selecting a bank.
+        let mut mbc5 = Mbc5::new(rom(0x1E));
+        mbc5.write_register(0x4000, 0x0F);
+        assert_eq!((mbc5.rumble, mbc5.ram_bank), (Some(true), 0x07));
+        mbc5.reset();
+        assert_eq!(mbc5.rumble, Some(false));
+    }
+}
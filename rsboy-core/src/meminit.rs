@@ -0,0 +1,109 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+pub const WRAM_START: usize = 0xC000;
+pub const WRAM_END: usize = 0xDFFF;
+// Echo RAM mirrors C000-DDFF.
+pub const ECHO_START: usize = 0xE000;
+pub const ECHO_END: usize = 0xFDFF;
+// Not usable on a DMG, reads give 0 and writes are dropped.
+pub const UNUSABLE_START: usize = 0xFEA0;
+pub const UNUSABLE_END: usize = 0xFEFF;
+pub const HRAM_START: usize = 0xFF80;
+pub const HRAM_END: usize = 0xFFFE;
+
+// Seed used by "random" without an explicit one, so runs stay reproducible.
+pub const DEFAULT_SEED: u32 = 0x1234_5678;
+
+// What RAM holds at power on, before the bootrom or game writes to it.
+// Defaults to Zero, which is what the emulator always did, real DMGs come up with noise.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MemFill {
+    #[default]
+    Zero,
+    Ones,
+    // 0x0F/0xF0 in alternating blocks of 8 bytes, close to what many DMG WRAM chips show.
+    Nibble,
+    // xorshift noise from the given seed.
+    Random(u32),
+}
+
+impl MemFill {
+    pub fn fill(self, mem: &mut [u8]) {
+        match self {
+            MemFill::Zero => mem.iter_mut().for_each(|b| *b = 0x00),
+            MemFill::Ones => mem.iter_mut().for_each(|b| *b = 0xFF),
+            MemFill::Nibble => {
+                for (i, b) in mem.iter_mut().enumerate() {
+                    *b = if (i / 8) % 2 == 0 { 0x0F } else { 0xF0 };
+                }
+            }
+            MemFill::Random(seed) => {
+                // xorshift never leaves 0.
+                let mut state = seed.max(1);
+                for b in mem.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    *b = state as u8;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for MemFill {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "zero" | "00" => return Ok(MemFill::Zero),
+            "ones" | "ff" => return Ok(MemFill::Ones),
+            "nibble" => return Ok(MemFill::Nibble),
+            "random" => return Ok(MemFill::Random(DEFAULT_SEED)),
+            _ => {}
+        }
+        match s.strip_prefix("random:").map(|seed| seed.parse()) {
+            Some(Ok(seed)) => Ok(MemFill::Random(seed)),
+            _ => Err(format!(
+                "Unknown memory fill {}, expected zero, ones, nibble, random or random:<seed>",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for MemFill {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemFill::Zero => write!(f, "zero"),
+            MemFill::Ones => write!(f, "ones"),
+            MemFill::Nibble => write!(f, "nibble"),
+            MemFill::Random(seed) => write!(f, "random:{}", seed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_and_fills() {
+        assert_eq!("FF".parse(), Ok(MemFill::Ones));
+        assert_eq!("random:7".parse(), Ok(MemFill::Random(7)));
+        assert_eq!("random".parse(), Ok(MemFill::Random(DEFAULT_SEED)));
+        assert!("random:x".parse::<MemFill>().is_err());
+
+        let mut mem = [0xAA; 16];
+        MemFill::Nibble.fill(&mut mem);
+        assert_eq!(mem[7], 0x0F);
+        assert_eq!(mem[8], 0xF0);
+
+        let (mut a, mut b) = ([0; 32], [0; 32]);
+        MemFill::Random(7).fill(&mut a);
+        MemFill::Random(7).fill(&mut b);
+        assert_eq!(a, b);
+        assert!(a.iter().any(|&x| x != a[0]));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::registers::RegisterState;
+use std::str::FromStr;
+
+// Hardware the emulator pretends to be. Games tell them apart by the registers the bootrom
+// leaves behind, see https://gbdev.io/pandocs/Power_Up_Sequence.html
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Model {
+    #[default]
+    Dmg,
+    // Game Boy Pocket, a DMG apart from A.
+    Mgb,
+    Cgb,
+}
+
+impl FromStr for Model {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(Model::Dmg),
+            "mgb" | "pocket" => Ok(Model::Mgb),
+            "cgb" | "gbc" => Ok(Model::Cgb),
+            _ => Err(format!("Unknown model {}, expected dmg, mgb or cgb", s)),
+        }
+    }
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        self == Model::Cgb
+    }
+
+    // Registers right after the bootrom hands over to the cartridge at 0x100. F assumes a
+    // cartridge with a non-zero header checksum, which is every released one.
+    pub fn start_registers(self) -> RegisterState {
+        let dmg = RegisterState {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        };
+        match self {
+            Model::Dmg => dmg,
+            Model::Mgb => RegisterState { a: 0xFF, ..dmg },
+            Model::Cgb => RegisterState {
+                a: 0x11,
+                f: 0x80,
+                b: 0x00,
+                c: 0x00,
+                d: 0xFF,
+                e: 0x56,
+                h: 0x00,
+                l: 0x0D,
+                ..dmg
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Memory;
+    use crate::emu::Emu;
+
+    // IO registers every model leaves the same way.
+    const START_IO: [(u16, u8); 14] = [
+        (0xFF05, 0x00), // TIMA
+        (0xFF06, 0x00), // TMA
+        (0xFF07, 0xF8), // TAC
+        (0xFF0F, 0xE1), // IF
+        (0xFF12, 0xF3), // NR12
+        (0xFF24, 0x77), // NR50
+        (0xFF25, 0xF3), // NR51
+        (0xFF40, 0x91), // LCDC
+        (0xFF42, 0x00), // SCY
+        (0xFF43, 0x00), // SCX
+        (0xFF45, 0x00), // LYC
+        (0xFF47, 0xFC), // BGP
+        (0xFF4A, 0x00), // WY
+        (0xFFFF, 0x00), // IE
+    ];
+
+    #[test]
+    fn power_on_state() {
+        let table = [
+            (Model::Dmg, [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]),
+            (Model::Mgb, [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]),
+            (Model::Cgb, [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D]),
+        ];
+        for &(model, [a, f, b, c, d, e, h, l]) in table.iter() {
+            let mut emu = Emu::new(vec![0; 0x8000], None);
+            emu.bus.set_model(model);
+            emu.cpu.load_start_values(&mut emu.bus);
+            let expected = RegisterState {
+                a,
+                f,
+                b,
+                c,
+                d,
+                e,
+                h,
+                l,
+                sp: 0xFFFE,
+                pc: 0x0100,
+            };
+            assert_eq!(emu.cpu.registers, expected, "{:?}", model);
+            for &(address, value) in START_IO.iter() {
+                let read = emu.bus.read(address);
+                assert_eq!(read, value, "{:?} {:04X}", model, address);
+            }
+            // Pan Docs only pins DIV down on the DMG and MGB.
+            if !model.is_cgb() {
+                assert_eq!(emu.bus.io_map().div(), 0xAB, "{:?}", model);
+            }
+            assert_eq!(emu.bus.cgb, model.is_cgb());
+        }
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!("pocket".parse::<Model>(), Ok(Model::Mgb));
+        assert_eq!("GBC".parse::<Model>(), Ok(Model::Cgb));
+        assert!("sgb".parse::<Model>().is_err());
+    }
+}
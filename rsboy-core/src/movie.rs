@@ -0,0 +1,180 @@
+use crate::constants::MaybeErr;
+use crate::input::Button;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+// One letter per button in Button::ALL order, '.' when released.
+const BUTTON_LETTERS: [char; 8] = ['A', 'B', 's', 'S', 'R', 'L', 'U', 'D'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    // A+B+Start+Select held for the frame, which most games treat as a soft reset.
+    Soft,
+    // Emu::reset() before the frame.
+    Hard,
+}
+
+// Input for one frame, as latched at its start.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    // Bit i set when Button::ALL[i] is pressed.
+    pub buttons: u8,
+    pub reset: Option<Reset>,
+    // Every JOYP value the game read during the frame, in order.
+    pub reads: Vec<u8>,
+}
+
+impl MovieFrame {
+    pub fn is_pressed(&self, button: Button) -> bool {
+        let i = Button::ALL.iter().position(|&b| b == button).unwrap_or(0);
+        self.buttons & (1 << i) != 0
+    }
+}
+
+// JOYP reads as they go through Bus::read. Recording collects the values the game saw, replaying
+// hands them back in order whatever the joypad lines hold, so a replay doesn't depend on where a
+// read lands relative to the game's select writes. Past the end of a replay reads are live.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoypadTape {
+    Record(Vec<u8>),
+    Replay(VecDeque<u8>),
+}
+
+impl JoypadTape {
+    // The value a JOYP read returns, given what the joypad itself holds.
+    pub fn read(&mut self, live: u8) -> u8 {
+        match self {
+            JoypadTape::Record(reads) => {
+                reads.push(live);
+                live
+            }
+            JoypadTape::Replay(reads) => reads.pop_front().unwrap_or(live),
+        }
+    }
+}
+
+// Recorded input, one frame per line:
+//   AB.S....             buttons in Button::ALL order
+//   ABsS.... soft        optional reset marker, "soft" or "hard"
+//   A....... joyp=2e,1f  optional JOYP values read during the frame, in hex
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Movie {
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame: MovieFrame) {
+        self.frames.push(frame);
+    }
+
+    pub fn parse(text: &str) -> MaybeErr<Self> {
+        let mut movie = Movie::new();
+        for (n, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let buttons = match parts.next() {
+                Some(buttons) if buttons.chars().count() == BUTTON_LETTERS.len() => buttons,
+                Some(_) => return Err(format!("Line {}: expected 8 button columns", n + 1).into()),
+                None => continue,
+            };
+            let buttons = buttons
+                .chars()
+                .zip(BUTTON_LETTERS.iter())
+                .enumerate()
+                .filter(|(_, (c, letter))| c == *letter)
+                .fold(0, |bits, (i, _)| bits | 1 << i);
+            let mut frame = MovieFrame {
+                buttons,
+                ..MovieFrame::default()
+            };
+            for part in parts {
+                match part {
+                    "soft" => frame.reset = Some(Reset::Soft),
+                    "hard" => frame.reset = Some(Reset::Hard),
+                    _ if part.starts_with("joyp=") => {
+                        frame.reads = part["joyp=".len()..]
+                            .split(',')
+                            .map(|value| u8::from_str_radix(value, 16))
+                            .collect::<Result<_, _>>()
+                            .map_err(|e| format!("Line {}: {}", n + 1, e))?;
+                    }
+                    other => return Err(format!("Line {}: unknown marker {}", n + 1, other).into()),
+                }
+            }
+            movie.record(frame);
+        }
+        Ok(movie)
+    }
+
+    pub fn load(path: &Path) -> MaybeErr<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> MaybeErr<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl Display for Movie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for frame in &self.frames {
+            for (i, letter) in BUTTON_LETTERS.iter().enumerate() {
+                let c = if frame.buttons & (1 << i) != 0 {
+                    *letter
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            match frame.reset {
+                Some(Reset::Soft) => write!(f, " soft")?,
+                Some(Reset::Hard) => write!(f, " hard")?,
+                None => {}
+            }
+            if !frame.reads.is_empty() {
+                let reads: Vec<String> = frame.reads.iter().map(|r| format!("{:02x}", r)).collect();
+                write!(f, " joyp={}", reads.join(","))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let mut movie = Movie::new();
+        movie.record(MovieFrame {
+            buttons: 0b0000_1001,
+            reset: None,
+            reads: vec![],
+        });
+        movie.record(MovieFrame {
+            buttons: 0b0000_1111,
+            reset: Some(Reset::Soft),
+            reads: vec![0x2E, 0x1F],
+        });
+        movie.record(MovieFrame {
+            buttons: 0,
+            reset: Some(Reset::Hard),
+            reads: vec![],
+        });
+        let text = movie.to_string();
+        assert_eq!(text, "A..S....\nABsS.... soft joyp=2e,1f\n........ hard\n");
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+        assert!(movie.frames[0].is_pressed(Button::Start));
+        assert!(Movie::parse("AB\n").is_err());
+        assert!(Movie::parse("........ warm\n").is_err());
+        assert!(Movie::parse("........ joyp=2e,zz\n").is_err());
+    }
+}
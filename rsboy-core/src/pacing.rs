@@ -0,0 +1,124 @@
+use crate::clock::Cycles;
+use crate::constants::{CYCLES_PER_FRAME, GB_CYCLE_SPEED};
+use std::{str::FromStr, time::Duration};
+
+// Cycles in a single scanline, the unit drift is corrected in.
+pub const SCANLINE_CYCLES: Cycles = 456;
+// Falling further behind than this resyncs instead of trying to catch up.
+const MAX_DEBT: Cycles = CYCLES_PER_FRAME * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pacing {
+    // Sleep until FRAME_TIME has passed.
+    Spin,
+    // Let the display's swap interval pace us.
+    Vsync,
+}
+
+impl FromStr for Pacing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spin" => Ok(Pacing::Spin),
+            "vsync" => Ok(Pacing::Vsync),
+            _ => Err(format!("Unknown pacing {}, expected spin or vsync", s)),
+        }
+    }
+}
+
+// Vsync only works out if the display refreshes at roughly the Game Boy's ~59.7Hz.
+pub fn vsync_usable(refresh_rate: i32) -> bool {
+    (58..=62).contains(&refresh_rate)
+}
+
+// Keeps emulated time in line with wall clock time when vsync paces frames.
+// Each frame runs CYCLES_PER_FRAME, plus or minus a scanline when we've drifted.
+#[derive(Default)]
+pub struct DriftCorrector {
+    emulated: Cycles,
+}
+
+impl DriftCorrector {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.emulated = 0;
+    }
+
+    // Cycles to run this frame, given the wall clock time since the last reset.
+    pub fn cycles_for_frame(&mut self, elapsed: Duration) -> Cycles {
+        let ideal = (elapsed.as_secs_f64() * GB_CYCLE_SPEED as f64) as Cycles + CYCLES_PER_FRAME;
+        let debt = ideal.saturating_sub(self.emulated);
+        let cycles = if debt > MAX_DEBT || ideal < self.emulated {
+            // Stalled or way ahead (e.g. after a pause), resync instead of correcting.
+            self.emulated = ideal - CYCLES_PER_FRAME;
+            CYCLES_PER_FRAME
+        } else if debt > CYCLES_PER_FRAME + SCANLINE_CYCLES {
+            CYCLES_PER_FRAME + SCANLINE_CYCLES
+        } else if debt + SCANLINE_CYCLES < CYCLES_PER_FRAME {
+            CYCLES_PER_FRAME - SCANLINE_CYCLES
+        } else {
+            CYCLES_PER_FRAME
+        };
+        self.emulated += cycles;
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(hz: f64, frames: usize) -> (DriftCorrector, Vec<Cycles>) {
+        let mut drift = DriftCorrector::new();
+        let cycles = (0..frames)
+            .map(|i| drift.cycles_for_frame(Duration::from_secs_f64(i as f64 / hz)))
+            .collect();
+        (drift, cycles)
+    }
+
+    #[test]
+    fn steady_at_matching_rate() {
+        let hz = GB_CYCLE_SPEED as f64 / CYCLES_PER_FRAME as f64;
+        let (_, cycles) = run(hz, 600);
+        assert!(cycles.iter().all(|c| *c == CYCLES_PER_FRAME));
+    }
+
+    #[test]
+    fn corrects_slow_and_fast_displays() {
+        for hz in &[59.7, 60.3] {
+            let (drift, _) = run(*hz, 600);
+            let real = 599.0 / hz * GB_CYCLE_SPEED as f64 + CYCLES_PER_FRAME as f64;
+            let error = (drift.emulated as f64 - real).abs();
+            assert!(
+                error < (CYCLES_PER_FRAME + SCANLINE_CYCLES) as f64,
+                "{} Hz off by {}",
+                hz,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn resyncs_after_stall() {
+        let mut drift = DriftCorrector::new();
+        drift.cycles_for_frame(Duration::from_secs(0));
+        assert_eq!(
+            drift.cycles_for_frame(Duration::from_secs(10)),
+            CYCLES_PER_FRAME
+        );
+        assert_eq!(
+            drift.cycles_for_frame(Duration::from_secs_f64(10.0 + 1.0 / 60.0)),
+            CYCLES_PER_FRAME
+        );
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!("vsync".parse(), Ok(Pacing::Vsync));
+        assert_eq!("spin".parse(), Ok(Pacing::Spin));
+        assert!("gsync".parse::<Pacing>().is_err());
+    }
+}
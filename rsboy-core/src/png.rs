@@ -0,0 +1,141 @@
+use crate::constants::MaybeErr;
+use crate::inflate::zlib_decompress;
+
+// PNG decoding for images given on the command line, boot logos and camera pictures. Writing
+// them is printer::png.
+
+pub const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// Larger images are refused rather than allocated, far more than any use here needs.
+const MAX_PIXELS: usize = 4096 * 4096;
+
+// Paeth predictor of PNG filter type 4.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Width, height and the luma of every pixel, row by row, transparent pixels read as white.
+// Non-interlaced grayscale, RGB, palette and alpha images at 8 bits, or grayscale and palette at
+// fewer.
+pub fn decode(data: &[u8]) -> MaybeErr<(usize, usize, Vec<u8>)> {
+    let mut rest = data.strip_prefix(SIGNATURE).ok_or("Not a PNG file")?;
+    let (mut header, mut palette, mut compressed) = (None, vec![], vec![]);
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let body = rest.get(8..8 + len).ok_or("PNG chunk runs past the end")?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + len..).ok_or("PNG chunk runs past the end")?;
+    }
+    let header = header
+        .filter(|h| h.len() == 13)
+        .ok_or("PNG has no header")?;
+    let size = |i: usize| {
+        u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]) as usize
+    };
+    let (width, height) = (size(0), size(4));
+    if width == 0 || height == 0 || width * height > MAX_PIXELS {
+        return Err(format!("PNG is {}x{}, which isn't supported", width, height).into());
+    }
+    let (depth, color) = (header[8] as usize, header[9]);
+    let channels = match (color, depth) {
+        (0, 1) | (0, 2) | (0, 4) | (0, 8) | (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (4, 8) => 2,
+        (2, 8) => 3,
+        (6, 8) => 4,
+        _ => return Err(format!("Unsupported PNG color type {} at {} bits", color, depth).into()),
+    };
+    if header[12] != 0 {
+        return Err("Interlaced PNGs aren't supported".into());
+    }
+
+    let raw = zlib_decompress(&compressed)?;
+    let stride = (width * channels * depth).div_ceil(8);
+    // Bytes a pixel is away from the one to its left, for filtering.
+    let step = (channels * depth / 8).max(1);
+    let mut rows = vec![vec![0; stride]; height + 1];
+    for y in 0..height {
+        let line = raw
+            .get(y * (stride + 1)..(y + 1) * (stride + 1))
+            .ok_or("PNG image data ends early")?;
+        let (filter, line) = (line[0], &line[1..]);
+        let (above, row) = rows.split_at_mut(y + 1);
+        let (above, row) = (&above[y], &mut row[0]);
+        for x in 0..stride {
+            let left = if x >= step { row[x - step] } else { 0 };
+            let corner = if x >= step { above[x - step] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => above[x],
+                3 => ((left as u16 + above[x] as u16) / 2) as u8,
+                4 => paeth(left, above[x], corner),
+                _ => return Err(format!("Unknown PNG filter {}", filter).into()),
+            };
+            row[x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    let mut luma = Vec::with_capacity(width * height);
+    for row in &rows[1..] {
+        for x in 0..width {
+            let sample = |i: usize| {
+                let bit = (x * channels + i) * depth;
+                (row[bit / 8] >> (8 - depth - bit % 8)) as usize & ((1 << depth) - 1)
+            };
+            let gray = |value: usize| (value * 255 / ((1 << depth) - 1)) as u8;
+            luma.push(match color {
+                0 => gray(sample(0)),
+                3 => {
+                    let rgb = palette
+                        .get(sample(0) * 3..sample(0) * 3 + 3)
+                        .ok_or("PNG palette index out of range")?;
+                    rgb_luma(rgb[0], rgb[1], rgb[2])
+                }
+                4 if sample(1) < 0x80 => 0xFF,
+                4 => sample(0) as u8,
+                2 => rgb_luma(sample(0) as u8, sample(1) as u8, sample(2) as u8),
+                _ if sample(3) < 0x80 => 0xFF,
+                _ => rgb_luma(sample(0) as u8, sample(1) as u8, sample(2) as u8),
+            });
+        }
+    }
+    Ok((width, height, luma))
+}
+
+fn rgb_luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::printer;
+
+    #[test]
+    fn decodes_any_size() {
+        let gray: Vec<u8> = (0..300 * 7).map(|i| (i * 3) as u8).collect();
+        let png = printer::png(300, 7, &gray);
+        assert_eq!(decode(&png).unwrap(), (300, 7, gray));
+        let rgb = printer::png_rgb(2, 1, &[0, 0, 0, 255, 255, 255]);
+        assert_eq!(decode(&rgb).unwrap(), (2, 1, vec![0, 255]));
+        assert!(decode(&png[..png.len() / 2]).is_err());
+        assert!(decode(b"P5 1 1 255 x").is_err());
+    }
+}
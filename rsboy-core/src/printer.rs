@@ -0,0 +1,402 @@
+use crate::bugreport::crc32;
+use crate::serial::SerialDevice;
+use crate::texture::Tile;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Game Boy Printer, see https://gbdev.io/pandocs/Gameboy_Printer.html
+// A packet is 88 33, command, compression, length (LE), data, checksum (LE), then two bytes
+// during which the printer answers with 81 and its status.
+pub const INIT: u8 = 0x01;
+pub const PRINT: u8 = 0x02;
+pub const DATA: u8 = 0x04;
+pub const BREAK: u8 = 0x08;
+pub const STATUS: u8 = 0x0F;
+
+pub const ALIVE: u8 = 0x81;
+
+// Status bits.
+pub const CHECKSUM_ERROR: u8 = 0b1;
+pub const PRINTING: u8 = 0b10;
+pub const IMAGE_FULL: u8 = 0b100;
+pub const UNPROCESSED: u8 = 0b1000;
+
+// Paper is 20 tiles wide, the buffer holds up to 9 DATA packets of two tile rows each.
+pub const PAPER_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PAPER_WIDTH / 8;
+const BUFFER_SIZE: usize = 0x280 * 9;
+// Status replies that still report PRINTING after a print, games wait for it to clear.
+const PRINT_POLLS: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Magic,
+    Magic2,
+    Command,
+    Compression,
+    Length,
+    Length2,
+    Data,
+    Checksum,
+    Checksum2,
+    Alive,
+    Status,
+}
+
+pub struct Printer {
+    dir: PathBuf,
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    packet: Vec<u8>,
+    checksum: u16,
+    // Image data received since the last INIT or print.
+    buffer: Vec<u8>,
+    status: u8,
+    printing: u8,
+    prints: usize,
+}
+
+impl Printer {
+    // Prints are saved into `dir` as print-NNN.png.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            stage: Stage::Magic,
+            command: 0,
+            compressed: false,
+            length: 0,
+            packet: vec![],
+            checksum: 0,
+            buffer: vec![],
+            status: 0,
+            printing: 0,
+            prints: 0,
+        }
+    }
+
+    // Sum of every byte from the command to the end of the data.
+    fn expected_checksum(&self) -> u16 {
+        let header = [
+            self.command,
+            self.compressed as u8,
+            self.length as u8,
+            (self.length >> 8) as u8,
+        ];
+        header
+            .iter()
+            .chain(self.packet.iter())
+            .fold(0u16, |sum, &b| sum.wrapping_add(b as u16))
+    }
+
+    fn run_command(&mut self) {
+        if self.checksum != self.expected_checksum() {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.printing = 0;
+            }
+            DATA if self.packet.is_empty() => self.status |= IMAGE_FULL,
+            DATA => {
+                let data = if self.compressed {
+                    decompress(&self.packet)
+                } else {
+                    std::mem::take(&mut self.packet)
+                };
+                let room = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(room));
+                self.status |= UNPROCESSED;
+            }
+            PRINT if self.packet.len() == 4 => {
+                // Some games leave the palette at 0 and mean the usual one.
+                let palette = match self.packet[2] {
+                    0 => 0xE4,
+                    palette => palette,
+                };
+                self.save(&render(&self.buffer, palette));
+                self.buffer.clear();
+                self.status &= !(UNPROCESSED | IMAGE_FULL);
+                self.printing = PRINT_POLLS;
+            }
+            BREAK => {
+                self.buffer.clear();
+                self.status &= !(UNPROCESSED | IMAGE_FULL);
+                self.printing = 0;
+            }
+            _ => {}
+        }
+    }
+
+    // Status as reported after a packet, PRINTING stays on for a few packets after a print.
+    fn reply_status(&mut self) -> u8 {
+        if self.printing > 0 {
+            self.printing -= 1;
+            self.status | PRINTING
+        } else {
+            self.status
+        }
+    }
+
+    fn save(&mut self, pixels: &[u8]) {
+        if pixels.is_empty() {
+            return;
+        }
+        let height = pixels.len() / PAPER_WIDTH;
+        let path = loop {
+            self.prints += 1;
+            let path = self.dir.join(format!("print-{:03}.png", self.prints));
+            if !path.exists() {
+                break path;
+            }
+        };
+        match write_png(&path, PAPER_WIDTH, height, pixels) {
+            Ok(()) => info!("Printed to {:?}", path),
+            Err(e) => warn!("Couldn't save print to {:?}: {}", path, e),
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, out: u8) -> u8 {
+        let (next, reply) = match self.stage {
+            Stage::Magic if out == 0x88 => (Stage::Magic2, 0),
+            Stage::Magic => (Stage::Magic, 0),
+            Stage::Magic2 if out == 0x33 => (Stage::Command, 0),
+            Stage::Magic2 => (Stage::Magic, 0),
+            Stage::Command => {
+                self.command = out;
+                (Stage::Compression, 0)
+            }
+            Stage::Compression => {
+                self.compressed = out & 1 != 0;
+                (Stage::Length, 0)
+            }
+            Stage::Length => {
+                self.length = out as usize;
+                (Stage::Length2, 0)
+            }
+            Stage::Length2 => {
+                self.length |= (out as usize) << 8;
+                self.packet.clear();
+                match self.length {
+                    0 => (Stage::Checksum, 0),
+                    _ => (Stage::Data, 0),
+                }
+            }
+            Stage::Data => {
+                self.packet.push(out);
+                if self.packet.len() == self.length {
+                    (Stage::Checksum, 0)
+                } else {
+                    (Stage::Data, 0)
+                }
+            }
+            Stage::Checksum => {
+                self.checksum = out as u16;
+                (Stage::Checksum2, 0)
+            }
+            Stage::Checksum2 => {
+                self.checksum |= (out as u16) << 8;
+                self.run_command();
+                (Stage::Alive, 0)
+            }
+            Stage::Alive => (Stage::Status, ALIVE),
+            Stage::Status => (Stage::Magic, self.reply_status()),
+        };
+        self.stage = next;
+        reply
+    }
+}
+
+// Runs of 2-129 copies of one byte (length - 2 | 0x80, byte) and literals (length - 1, bytes).
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            let count = (control & 0x7F) as usize + 2;
+            if let Some(&byte) = data.get(i) {
+                out.extend(std::iter::repeat_n(byte, count));
+            }
+            i += 1;
+        } else {
+            let count = control as usize + 1;
+            let end = (i + count).min(data.len());
+            out.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    out
+}
+
+// 2bpp tiles, 20 per row, to one gray byte per pixel. `palette` maps color 0 in bits 0-1 up to
+// color 3 in bits 6-7 to a shade, 0 white to 3 black.
+pub fn render(buffer: &[u8], palette: u8) -> Vec<u8> {
+    const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+    let rows = buffer.len() / (TILES_PER_ROW * 16);
+    let mut pixels = vec![0xFF; rows * 8 * PAPER_WIDTH];
+    for (i, tile) in buffer
+        .chunks_exact(16)
+        .take(rows * TILES_PER_ROW)
+        .enumerate()
+    {
+        let (tile_x, tile_y) = (i % TILES_PER_ROW, i / TILES_PER_ROW);
+        for (y, line) in tile.chunks_exact(2).enumerate() {
+            for x in 0..8 {
+                let shade = Tile::shade(palette, Tile::pixel_index(line[0], line[1], x));
+                let row = tile_y * 8 + y;
+                pixels[row * PAPER_WIDTH + tile_x * 8 + x] = SHADES[shade as usize];
+            }
+        }
+    }
+    pixels
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// 8-bit grayscale PNG.
+pub fn png(width: usize, height: usize, gray: &[u8]) -> Vec<u8> {
+    encode_png(width, height, false, gray)
+}
+
+// 8-bit RGB PNG, three bytes a pixel, for screenshots.
+pub fn png_rgb(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    encode_png(width, height, true, rgb)
+}
+
+// PNG with stored (uncompressed) deflate blocks, prints and screenshots are small enough.
+fn encode_png(width: usize, height: usize, rgb: bool, pixels: &[u8]) -> Vec<u8> {
+    let stride = if rgb { width * 3 } else { width };
+    let mut raw = Vec::with_capacity((stride + 1) * height);
+    for row in pixels.chunks_exact(stride).take(height) {
+        raw.push(0); // no filter
+        raw.extend_from_slice(row);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Depth, grayscale or truecolor, compression, filter, interlace.
+    header.extend_from_slice(&[8, if rgb { 2 } else { 0 }, 0, 0, 0]);
+    let mut out = crate::png::SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib);
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_png(path: &Path, width: usize, height: usize, gray: &[u8]) -> std::io::Result<()> {
+    fs::write(path, png(width, height, gray))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let len = data.len() as u16;
+        let mut packet = vec![command, compressed as u8];
+        packet.extend_from_slice(&len.to_le_bytes());
+        packet.extend_from_slice(data);
+        let sum = packet.iter().fold(0u16, |s, &b| s.wrapping_add(b as u16));
+        let mut bytes = vec![0x88, 0x33];
+        bytes.extend_from_slice(&packet);
+        bytes.extend_from_slice(&sum.to_le_bytes());
+        for &b in &bytes {
+            assert_eq!(printer.exchange(b), 0);
+        }
+        (printer.exchange(0), printer.exchange(0))
+    }
+
+    #[test]
+    fn prints_received_tiles() {
+        let dir = std::env::temp_dir().join(format!("rsboy-printer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut printer = Printer::new(dir.clone());
+        assert_eq!(send(&mut printer, INIT, false, &[]), (ALIVE, 0));
+        // Two tile rows, the first tile all color 3 and the rest color 0.
+        let mut data = vec![0; 0x280];
+        data[..16].copy_from_slice(&[0xFF; 16]);
+        assert_eq!(send(&mut printer, DATA, false, &data), (ALIVE, UNPROCESSED));
+        assert_eq!(
+            send(&mut printer, DATA, false, &[]),
+            (ALIVE, UNPROCESSED | IMAGE_FULL)
+        );
+        assert_eq!(
+            send(&mut printer, PRINT, false, &[1, 0x13, 0xE4, 0x40]),
+            (ALIVE, PRINTING)
+        );
+        for _ in 1..PRINT_POLLS {
+            assert_eq!(send(&mut printer, STATUS, false, &[]), (ALIVE, PRINTING));
+        }
+        assert_eq!(send(&mut printer, STATUS, false, &[]), (ALIVE, 0));
+
+        let file = fs::read(dir.join(format!("print-{:03}.png", printer.prints))).unwrap();
+        assert_eq!(&file[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&file[16..24], &[0, 0, 0, 160, 0, 0, 0, 16]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_checksum_is_reported() {
+        let mut printer = Printer::new(std::env::temp_dir());
+        for &b in &[0x88, 0x33, INIT, 0, 0, 0, 0x02, 0x00] {
+            printer.exchange(b);
+        }
+        assert_eq!(printer.exchange(0), ALIVE);
+        assert_eq!(printer.exchange(0), CHECKSUM_ERROR);
+    }
+
+    #[test]
+    fn decompresses_runs_and_literals() {
+        assert_eq!(decompress(&[0x81, 7, 1, 1, 2]), vec![7, 7, 7, 1, 2]);
+    }
+
+    #[test]
+    fn renders_with_palette() {
+        let mut tiles = vec![0; TILES_PER_ROW * 16];
+        tiles[0] = 0b1000_0000;
+        let pixels = render(&tiles, 0xE4);
+        assert_eq!(pixels.len(), 8 * PAPER_WIDTH);
+        assert_eq!(&pixels[..2], &[0xAA, 0xFF]);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::bus::Bus;
+use crate::meminit::{WRAM_END, WRAM_START};
+
+// How a candidate's value now compares with the last snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    Equal(u8),
+}
+
+impl Comparison {
+    pub fn matches(self, before: u8, now: u8) -> bool {
+        match self {
+            Comparison::Changed => now != before,
+            Comparison::Unchanged => now == before,
+            Comparison::Increased => now > before,
+            Comparison::Decreased => now < before,
+            Comparison::Equal(value) => now == value,
+        }
+    }
+}
+
+// Cheat search over WRAM: snapshot, play a bit, keep the addresses that moved the way the value
+// being looked for did, repeat until few are left.
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    pub candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    // Every WRAM address is a candidate to start with.
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            snapshot: Self::wram(bus),
+            candidates: (WRAM_START..=WRAM_END).map(|a| a as u16).collect(),
+        }
+    }
+
+    fn wram(bus: &Bus) -> Vec<u8> {
+        bus.memory[WRAM_START..=WRAM_END].to_vec()
+    }
+
+    // Drops the candidates not matching `comparison` and takes a new snapshot to compare against.
+    pub fn filter(&mut self, bus: &Bus, comparison: Comparison) {
+        let now = Self::wram(bus);
+        let before = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let i = address as usize - WRAM_START;
+            comparison.matches(before[i], now[i])
+        });
+        self.snapshot = now;
+    }
+
+    // Value of a candidate in the last snapshot.
+    pub fn value(&self, address: u16) -> u8 {
+        self.snapshot[address as usize - WRAM_START]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Memory;
+
+    #[test]
+    fn narrows_down_to_the_counter() {
+        let mut bus = Bus::new(vec![], None);
+        let mut search = RamSearch::new(&bus);
+        assert_eq!(search.candidates.len(), WRAM_END - WRAM_START + 1);
+
+        bus.write(0xC123, 3);
+        bus.write(0xC200, 9);
+        search.filter(&bus, Comparison::Increased);
+        assert_eq!(search.candidates, [0xC123, 0xC200]);
+        search.filter(&bus, Comparison::Unchanged);
+        assert_eq!(search.candidates, [0xC123, 0xC200]);
+
+        bus.write(0xC123, 2);
+        bus.write(0xC200, 10);
+        search.filter(&bus, Comparison::Decreased);
+        assert_eq!(search.candidates, [0xC123]);
+        search.filter(&bus, Comparison::Equal(2));
+        assert_eq!(search.candidates, [0xC123]);
+        assert_eq!(search.value(0xC123), 2);
+        search.filter(&bus, Comparison::Changed);
+        assert!(search.candidates.is_empty());
+    }
+}
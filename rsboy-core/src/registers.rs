@@ -5,7 +5,7 @@ use crate::instructions::Register::*;
 use std::fmt;
 
 // Global emu struct.
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct RegisterState {
     pub a: u8,
     pub b: u8,
@@ -79,6 +79,16 @@ impl RegisterState {
         self.f = (self.f & !(1 << 7)) | ((b as u8) << 7);
     }
 
+    // SP + e for LD HL,SP+e and ADD SP,e. The offset is signed but H and C come from the
+    // unsigned add of e to the low byte of SP, Z and N are always reset.
+    pub fn sp_plus_offset(&mut self, offset: u8) -> u16 {
+        let low = self.sp as u8;
+        let half_carry = (low & 0x0F) + (offset & 0x0F) > 0x0F;
+        let carry = low as u16 + offset as u16 > 0xFF;
+        self.f = flags(false, false, half_carry, carry);
+        self.sp.wrapping_add(offset as i8 as u16)
+    }
+
     pub fn jump(&self, address: u16) -> Self {
         Self {
             pc: address,
@@ -256,6 +266,35 @@ impl RegisterState {
 pub fn flags(z: bool, n: bool, h: bool, c: bool) -> u8 {
     ((z as u8) << 7) | ((n as u8) << 6) | ((h as u8) << 5) | ((c as u8) << 4)
 }
+
+// F before and after an instruction, for tracking down ALU flag bugs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlagChange {
+    pub before: u8,
+    pub after: u8,
+}
+
+impl FlagChange {
+    pub fn changed(&self) -> bool {
+        (self.before ^ self.after) & 0xF0 != 0
+    }
+}
+
+// Only the flags that changed, as "Z:0>1 C:1>0".
+impl fmt::Display for FlagChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes: Vec<String> = [('Z', 7), ('N', 6), ('H', 5), ('C', 4)]
+            .iter()
+            .filter(|&&(_, bit)| (self.before ^ self.after) & (1 << bit) != 0)
+            .map(|&(name, bit)| {
+                let (before, after) = ((self.before >> bit) & 1, (self.after >> bit) & 1);
+                format!("{}:{}>{}", name, before, after)
+            })
+            .collect();
+        write!(f, "{}", changes.join(" "))
+    }
+}
+
 impl fmt::Display for RegisterState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -284,6 +323,22 @@ mod tests {
         assert_eq!(zn, 0b1010_0000);
     }
 
+    #[test]
+    fn flag_change_lists_changed_flags() {
+        let change = FlagChange {
+            before: flags(false, true, true, true),
+            after: flags(true, true, false, true),
+        };
+        assert!(change.changed());
+        assert_eq!(change.to_string(), "Z:0>1 H:1>0");
+        // The low nibble of F always reads 0.
+        assert!(!FlagChange {
+            before: 0x80,
+            after: 0x8F
+        }
+        .changed());
+    }
+
     #[test]
     fn hl() {
         let reg = RegisterState {
@@ -314,4 +369,30 @@ mod tests {
         reg.dec(Register::HL);
         assert_eq!(reg.hl(), 0xFEFF);
     }
+
+    #[test]
+    fn sp_plus_offset() {
+        // (sp, offset, result, h, c)
+        let cases: [(u16, u8, u16, bool, bool); 8] = [
+            (0xFFFF, 0x01, 0x0000, true, true),
+            (0x000F, 0x01, 0x0010, true, false),
+            (0x00F0, 0x10, 0x0100, false, true),
+            (0x1234, 0x00, 0x1234, false, false),
+            (0x0000, 0xFF, 0xFFFF, false, false),
+            (0x0001, 0xFF, 0x0000, true, true),
+            (0xD000, 0x80, 0xCF80, false, false),
+            (0xFFF8, 0xFE, 0xFFF6, true, true),
+        ];
+        for &(sp, offset, result, h, c) in cases.iter() {
+            let mut reg = RegisterState {
+                sp,
+                f: 0xF0,
+                ..Default::default()
+            };
+            let case = format!("{:04x}+{:02x}", sp, offset);
+            assert_eq!(reg.sp_plus_offset(offset), result, "{}", case);
+            assert_eq!(reg.f, flags(false, false, h, c), "{}", case);
+            assert_eq!(reg.sp, sp);
+        }
+    }
 }
@@ -0,0 +1,235 @@
+use crate::clock::{self, Cycles};
+use crate::constants::{MaybeErr, GB_CYCLE_SPEED};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// MBC3 real time clock, see https://gbdev.io/pandocs/MBC3.html
+
+// Compatibility database flag for games that should find the clock where they left it.
+pub const FROZEN_FLAG: &str = "rtc_frozen";
+
+// .sav footer: the counters and the latched counters as little endian u32s, then the unix time of
+// the save as a u64. VBA-M, BGB and SameBoy write it, older versions with a 32 bit time.
+pub const FOOTER_LEN: usize = 48;
+const OLD_FOOTER_LEN: usize = 44;
+
+const SECONDS: usize = 0;
+const DAY_HIGH: usize = 4;
+// Writable bits of seconds, minutes, hours, day low and day high.
+const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+// What happens to the time that passed while the game wasn't running, between a save and loading
+// it again.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RtcMode {
+    // The clock moves on by the real time that passed, like the cartridge's own battery would.
+    #[default]
+    CatchUp,
+    // The clock continues from where it was saved.
+    Frozen,
+}
+
+impl RtcMode {
+    pub fn for_flags(frozen: bool) -> Self {
+        if frozen {
+            RtcMode::Frozen
+        } else {
+            RtcMode::CatchUp
+        }
+    }
+}
+
+// Seconds since the unix epoch, what footers and savestates are stamped with.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rtc {
+    // Seconds, minutes, hours, day low and day high: bit 0 is day bit 8, then halt and day carry.
+    pub regs: [u8; 5],
+    // What the game reads, copied from `regs` by writing 0 then 1 to 6000-7FFF.
+    pub latched: [u8; 5],
+    // Bus clock `regs` are up to date with and cycles into the current second.
+    pub last: Cycles,
+    pub subsecond: Cycles,
+    latch_armed: bool,
+}
+
+impl Rtc {
+    pub fn halted(&self) -> bool {
+        self.regs[DAY_HIGH] & HALT != 0
+    }
+
+    // Counts the emulated time up to `clock`.
+    pub fn sync(&mut self, clock: Cycles) {
+        let elapsed = clock::since(self.last, clock);
+        self.last = clock;
+        if self.halted() {
+            return;
+        }
+        self.subsecond += elapsed;
+        let seconds = self.subsecond / GB_CYCLE_SPEED;
+        self.subsecond %= GB_CYCLE_SPEED;
+        self.advance(seconds);
+    }
+
+    // Moves the counters on by `seconds`, days past 511 wrap and set the carry until it's cleared.
+    pub fn advance(&mut self, seconds: u64) {
+        if self.halted() || seconds == 0 {
+            return;
+        }
+        let [s, m, h, dl, dh] = self.regs;
+        let days = ((dh as u64 & 1) << 8) | dl as u64;
+        let total = s as u64 + m as u64 * 60 + h as u64 * 3600 + days * 86400 + seconds;
+        let days = total / 86400;
+        let mut high = dh & (HALT | DAY_CARRY);
+        if days > 0x1FF {
+            high |= DAY_CARRY;
+        }
+        self.regs = [
+            (total % 60) as u8,
+            (total / 60 % 60) as u8,
+            (total / 3600 % 24) as u8,
+            days as u8,
+            high | ((days >> 8) & 1) as u8,
+        ];
+    }
+
+    // Writes to 6000-7FFF.
+    pub fn latch(&mut self, value: u8) {
+        if self.latch_armed && value == 1 {
+            self.latched = self.regs;
+        }
+        self.latch_armed = value == 0;
+    }
+
+    // Register 08-0C as selected through 4000-5FFF.
+    pub fn read(&self, register: u8) -> u8 {
+        let i = register as usize - 0x08;
+        self.latched[i] & MASKS[i]
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let i = register as usize - 0x08;
+        if i == SECONDS {
+            self.subsecond = 0;
+        }
+        self.regs[i] = value & MASKS[i];
+        self.latched[i] = self.regs[i];
+    }
+
+    pub fn footer(&self, timestamp: u64) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        for &reg in self.regs.iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(reg as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    // Restores a footer, catching up to `now` unless `mode` is Frozen.
+    pub fn load_footer(&mut self, footer: &[u8], now: u64, mode: RtcMode) -> MaybeErr<()> {
+        if footer.len() != FOOTER_LEN && footer.len() != OLD_FOOTER_LEN {
+            return Err(format!("RTC footer is {} bytes", footer.len()).into());
+        }
+        let u32_at = |i: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&footer[i * 4..i * 4 + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        for (i, &mask) in MASKS.iter().enumerate() {
+            self.regs[i] = u32_at(i) as u8 & mask;
+            self.latched[i] = u32_at(i + 5) as u8 & mask;
+        }
+        let mut timestamp = [0; 8];
+        timestamp[..footer.len() - 40].copy_from_slice(&footer[40..]);
+        self.catch_up(u64::from_le_bytes(timestamp), now, mode);
+        Ok(())
+    }
+
+    // Adds the real time from `saved` to `now` in CatchUp mode.
+    pub fn catch_up(&mut self, saved: u64, now: u64, mode: RtcMode) {
+        if mode == RtcMode::CatchUp {
+            self.advance(now.saturating_sub(saved));
+        }
+    }
+
+    // The clock keeps running through a reset, only the latch sequence starts over.
+    pub fn reset(&mut self, clock: Cycles) {
+        self.sync(clock);
+        self.last = 0;
+        self.latch_armed = false;
+    }
+}
+
+// Splits a .sav file into cartridge RAM and the RTC footer, if it has one.
+pub fn split_footer(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    for &len in [FOOTER_LEN, OLD_FOOTER_LEN].iter() {
+        if data.len() >= len && (data.len() - len).is_multiple_of(0x2000) {
+            let (ram, footer) = data.split_at(data.len() - len);
+            return (ram, Some(footer));
+        }
+    }
+    (data, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_latches_and_carries() {
+        let mut rtc = Rtc::default();
+        rtc.sync(GB_CYCLE_SPEED * 61 + 5);
+        assert_eq!(rtc.read(0x08), 0);
+        rtc.latch(0);
+        rtc.latch(1);
+        assert_eq!((rtc.read(0x08), rtc.read(0x09)), (1, 1));
+        assert_eq!(rtc.subsecond, 5);
+
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+        rtc.write(0x0A, 23);
+        rtc.write(0x09, 59);
+        rtc.write(0x08, 59);
+        rtc.advance(1);
+        assert_eq!(rtc.regs, [0, 0, 0, 0, DAY_CARRY]);
+
+        // Halted, nothing moves.
+        rtc.write(0x0C, HALT);
+        rtc.sync(GB_CYCLE_SPEED * 1000);
+        rtc.advance(100);
+        assert_eq!(rtc.regs, [0, 0, 0, 0, HALT]);
+    }
+
+    #[test]
+    fn footers_catch_up_or_stay_frozen() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x09, 10);
+        let footer = rtc.footer(1000);
+        assert_eq!(footer.len(), FOOTER_LEN);
+
+        let mut frozen = Rtc::default();
+        frozen
+            .load_footer(&footer, 1000 + 3600, RtcMode::Frozen)
+            .unwrap();
+        assert_eq!(frozen.regs, [0, 10, 0, 0, 0]);
+        let mut caught_up = Rtc::default();
+        caught_up
+            .load_footer(&footer[..OLD_FOOTER_LEN], 1000 + 3661, RtcMode::CatchUp)
+            .unwrap();
+        assert_eq!(caught_up.regs, [1, 11, 1, 0, 0]);
+        assert!(Rtc::default()
+            .load_footer(&[0; 8], 0, RtcMode::CatchUp)
+            .is_err());
+
+        let mut sav = vec![0; 0x2000];
+        sav.extend_from_slice(&footer);
+        assert_eq!(split_footer(&sav), (&sav[..0x2000], Some(&footer[..])));
+        assert_eq!(split_footer(&sav[..0x2000]).1, None);
+    }
+}
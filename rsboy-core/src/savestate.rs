@@ -0,0 +1,826 @@
+use crate::apu::ApuRegs;
+use crate::bess;
+use crate::bus::Bus;
+use crate::camera::Camera;
+use crate::constants::MaybeErr;
+use crate::cpu::{CPUState, CPU};
+use crate::dma;
+use crate::emu::Emu;
+use crate::gpu::{GpuMode, GPU, LINES_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::hdma::Hdma;
+use crate::iomap::IoMap;
+use crate::joypad::Select;
+use crate::rtc::{self, RtcMode};
+use crate::serial;
+use crate::timer::{Timer, TimerSnapshot};
+
+// Savestate layout:
+//   MAGIC, version: u16, then a sequence of chunks.
+//   Each chunk is a 4 byte tag, a u32 payload length and the payload itself.
+// All integers are little endian.
+// Unknown chunks are skipped on load, so adding a subsystem doesn't need a version bump.
+// A state may end in a BESS footer (see bess::append), which parse strips.
+// Changing the payload of an existing chunk does: bump CURRENT_VERSION and add a migration.
+pub const MAGIC: &[u8; 4] = b"RSBY";
+pub const CURRENT_VERSION: u16 = 5;
+
+pub const CPU_TAG: [u8; 4] = *b"CPU ";
+pub const BUS_TAG: [u8; 4] = *b"BUS ";
+pub const GPU_TAG: [u8; 4] = *b"GPU ";
+pub const TIMER_TAG: [u8; 4] = *b"TIMR";
+pub const MAPPER_TAG: [u8; 4] = *b"MAPR";
+pub const APU_TAG: [u8; 4] = *b"APU ";
+pub const SPEED_TAG: [u8; 4] = *b"SPED";
+pub const HDMA_TAG: [u8; 4] = *b"HDMA";
+// Only in states of Game Boy Camera cartridges.
+pub const CAMERA_TAG: [u8; 4] = *b"CAMR";
+// Optional, describes the state for slot pickers and is ignored by load.
+pub const META_TAG: [u8; 4] = *b"META";
+
+pub type Chunk = ([u8; 4], Vec<u8>);
+
+// Upgrades the chunks of a state saved with version `i + 1` to version `i + 2`.
+type Migration = fn(&mut Vec<Chunk>) -> MaybeErr<()>;
+// Versions 2, 3 and 5 added MBC1, MBC3 and MBC5 state to the MAPR chunk, 4 channel 3's timing
+// to APU.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize - 1] = [
+    drop_empty_mapper,
+    drop_empty_mapper,
+    idle_wave_channel,
+    drop_empty_mapper,
+];
+
+// The MAPR chunk is empty if the cartridge's mapper wasn't emulated yet when the state was saved.
+// Without it the cartridge keeps its power on banking.
+fn drop_empty_mapper(chunks: &mut Vec<Chunk>) -> MaybeErr<()> {
+    chunks.retain(|(tag, payload)| *tag != MAPPER_TAG || !payload.is_empty());
+    Ok(())
+}
+
+// Channel 3 starts from the first sample with nothing fetched, as when it's triggered.
+#[allow(clippy::ptr_arg)] // Every migration takes the Vec.
+fn idle_wave_channel(chunks: &mut Vec<Chunk>) -> MaybeErr<()> {
+    for (_, payload) in chunks.iter_mut().filter(|(tag, _)| *tag == APU_TAG) {
+        let mut w = StateWriter {
+            buf: std::mem::take(payload),
+        };
+        w.u16(0);
+        w.u8(0);
+        w.bool(false);
+        *payload = w.buf;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    pub buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+    // Length prefixed byte string.
+    pub fn blob(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.bytes(v);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+    pub fn bytes(&mut self, n: usize) -> MaybeErr<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len());
+        let end = end.ok_or("Savestate is truncated")?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+    pub fn u8(&mut self) -> MaybeErr<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn bool(&mut self) -> MaybeErr<bool> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> MaybeErr<u16> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(b))
+    }
+    pub fn u32(&mut self) -> MaybeErr<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+    pub fn u64(&mut self) -> MaybeErr<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+    pub fn blob(&mut self) -> MaybeErr<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+    pub fn fill(&mut self, into: &mut [u8]) -> MaybeErr<()> {
+        into.copy_from_slice(self.bytes(into.len())?);
+        Ok(())
+    }
+}
+
+fn save_cpu(cpu: &CPU, w: &mut StateWriter) {
+    let r = &cpu.registers;
+    for v in &[r.a, r.b, r.c, r.d, r.e, r.f, r.h, r.l] {
+        w.u8(*v);
+    }
+    w.u16(r.sp);
+    w.u16(r.pc);
+    w.u8(match cpu.state {
+        CPUState::Running => 0,
+        CPUState::Interrupted => 1,
+        CPUState::Halted => 2,
+        CPUState::Stopped => 3,
+    });
+    w.u8(cpu.opcode);
+    w.u16(cpu.op_addr);
+    w.bool(cpu.halt);
+}
+
+fn load_cpu(cpu: &mut CPU, r: &mut StateReader) -> MaybeErr<()> {
+    let regs = &mut cpu.registers;
+    regs.a = r.u8()?;
+    regs.b = r.u8()?;
+    regs.c = r.u8()?;
+    regs.d = r.u8()?;
+    regs.e = r.u8()?;
+    regs.f = r.u8()?;
+    regs.h = r.u8()?;
+    regs.l = r.u8()?;
+    regs.sp = r.u16()?;
+    regs.pc = r.u16()?;
+    cpu.state = match r.u8()? {
+        0 => CPUState::Running,
+        1 => CPUState::Interrupted,
+        2 => CPUState::Halted,
+        3 => CPUState::Stopped,
+        s => return Err(format!("Unknown CPU state {}", s).into()),
+    };
+    cpu.opcode = r.u8()?;
+    cpu.op_addr = r.u16()?;
+    cpu.halt = r.bool()?;
+    Ok(())
+}
+
+// SB, SC and DMA have their own components now, the chunk keeps them where Bus::memory had them.
+fn save_bus(bus: &Bus, w: &mut StateWriter) {
+    let mut memory = bus.memory.to_vec();
+    memory[serial::SB] = bus.serial.sb;
+    memory[serial::SC] = bus.serial.sc;
+    memory[dma::DMA] = bus.dma.source;
+    w.bytes(&memory);
+    w.bytes(&bus.bootrom);
+    w.u8(bus.in_bios);
+    w.u8(bus.int_enabled);
+    w.u8(bus.int_flags);
+    w.u64(bus.clock);
+    // A pending EI is saved as done, letting an interrupt in one instruction early at worst.
+    w.u8(bus.ime | bus.ei_pending as u8);
+    w.u8(match bus.joypad.select {
+        Select::Buttons => 0,
+        Select::Directions => 1,
+        Select::None => 2,
+    });
+    w.u8(bus.joypad.directions);
+    w.u8(bus.joypad.buttons);
+    w.bool(bus.rom_start_signal);
+    w.blob(bus.serial.output.as_bytes());
+}
+
+fn load_bus(bus: &mut Bus, r: &mut StateReader) -> MaybeErr<()> {
+    r.fill(&mut bus.memory)?;
+    bus.serial.sb = bus.memory[serial::SB];
+    bus.serial.sc = bus.memory[serial::SC];
+    bus.dma.source = bus.memory[dma::DMA];
+    r.fill(&mut bus.bootrom)?;
+    bus.in_bios = r.u8()?;
+    bus.int_enabled = r.u8()?;
+    bus.int_flags = r.u8()?;
+    bus.clock = r.u64()?;
+    bus.ime = r.u8()?;
+    bus.ei_pending = false;
+    bus.joypad.select = match r.u8()? {
+        0 => Select::Buttons,
+        1 => Select::Directions,
+        2 => Select::None,
+        s => return Err(format!("Unknown joypad select {}", s).into()),
+    };
+    bus.joypad.directions = r.u8()?;
+    bus.joypad.buttons = r.u8()?;
+    bus.rom_start_signal = r.bool()?;
+    bus.serial.output = String::from_utf8(r.blob()?.to_vec())?;
+    // States without a speed chunk never ran in double speed.
+    bus.cycles = bus.clock;
+    Ok(())
+}
+
+fn save_gpu(gpu: &GPU, io: &IoMap, w: &mut StateWriter) {
+    w.u8(match gpu.mode {
+        GpuMode::HBlank => 0,
+        GpuMode::VBlank => 1,
+        GpuMode::Oam => 2,
+        GpuMode::Vram => 3,
+    });
+    w.u64(gpu.clock as u64);
+    w.u8(gpu.scanline);
+    w.bytes(&gpu.vram);
+    w.bytes(&gpu.oam);
+    for v in &[
+        io.lcdc().0,
+        io.stat().0,
+        io.scx(),
+        io.scy(),
+        io.bgp(),
+        io.obp0(),
+        io.obp1(),
+        io.wx(),
+        io.wy(),
+    ] {
+        w.u8(*v);
+    }
+    w.u64(gpu._vblank_count as u64);
+}
+
+fn load_gpu(gpu: &mut GPU, r: &mut StateReader) -> MaybeErr<()> {
+    // Checked before anything is assigned, a bad state leaves the GPU as it was.
+    let mode = match r.u8()? {
+        0 => GpuMode::HBlank,
+        1 => GpuMode::VBlank,
+        2 => GpuMode::Oam,
+        3 => GpuMode::Vram,
+        m => return Err(format!("Unknown GPU mode {}", m).into()),
+    };
+    let clock = r.u64()? as usize;
+    let scanline = r.u8()?;
+    if scanline as usize >= LINES_PER_FRAME {
+        return Err(format!("GPU scanline {} out of range", scanline).into());
+    }
+    gpu.mode = mode;
+    gpu.clock = clock;
+    gpu.scanline = scanline;
+    r.fill(&mut gpu.vram)?;
+    r.fill(&mut gpu.oam)?;
+    gpu.lcdc = r.u8()?;
+    gpu.lcdstat = r.u8()?;
+    gpu.scrollx = r.u8()?;
+    gpu.scrolly = r.u8()?;
+    gpu.bgrdpal = r.u8()?;
+    gpu.obj0pal = r.u8()?;
+    gpu.obj1pal = r.u8()?;
+    gpu.windowx = r.u8()?;
+    gpu.windowy = r.u8()?;
+    gpu._vblank_count = r.u64()? as usize;
+    Ok(())
+}
+
+fn save_timer(timer: &Timer, w: &mut StateWriter) {
+    let regs = timer.snapshot();
+    w.u8(regs.tima);
+    w.u8(regs.tma);
+    w.u8(regs.tac);
+    w.u64(timer.clock);
+    w.u16(regs.internal);
+}
+
+fn load_timer(timer: &mut Timer, r: &mut StateReader) -> MaybeErr<()> {
+    let tima = r.u8()?;
+    let tma = r.u8()?;
+    let tac = r.u8()?;
+    timer.clock = r.u64()?;
+    let internal = r.u16()?;
+    timer.restore(&TimerSnapshot {
+        div: (internal >> 8) as u8,
+        tima,
+        tma,
+        tac,
+        internal,
+    });
+    Ok(())
+}
+
+fn save_apu(apu: &ApuRegs, w: &mut StateWriter) {
+    w.bytes(&apu.regs);
+    w.u8(apu.status);
+    w.u16(apu.wave_timer);
+    w.u8(apu.wave_position);
+    w.bool(apu.wave_fetched);
+}
+
+fn load_apu(apu: &mut ApuRegs, r: &mut StateReader) -> MaybeErr<()> {
+    r.fill(&mut apu.regs)?;
+    apu.status = r.u8()?;
+    apu.wave_timer = r.u16()?;
+    apu.wave_position = r.u8()?;
+    apu.wave_fetched = r.bool()?;
+    Ok(())
+}
+
+fn save_speed(bus: &Bus, w: &mut StateWriter) {
+    w.u64(bus.cycles);
+    w.bool(bus.cgb);
+    w.bool(bus.speed.double);
+    w.bool(bus.speed.armed);
+    w.bool(bus.speed.half);
+}
+
+fn load_speed(bus: &mut Bus, r: &mut StateReader) -> MaybeErr<()> {
+    bus.cycles = r.u64()?;
+    bus.cgb = r.bool()?;
+    bus.speed.double = r.bool()?;
+    bus.speed.armed = r.bool()?;
+    bus.speed.half = r.bool()?;
+    Ok(())
+}
+
+fn save_hdma(hdma: &Hdma, w: &mut StateWriter) {
+    w.u16(hdma.source);
+    w.u16(hdma.dest);
+    w.u8(hdma.remaining);
+    w.bool(hdma.active);
+}
+
+fn load_hdma(hdma: &mut Hdma, r: &mut StateReader) -> MaybeErr<()> {
+    hdma.source = r.u16()?;
+    hdma.dest = r.u16()?;
+    hdma.remaining = r.u8()?;
+    hdma.active = r.bool()?;
+    Ok(())
+}
+
+fn save_mapper(bus: &Bus, w: &mut StateWriter) {
+    if let Some(mbc1) = &bus.mbc1 {
+        w.bool(mbc1.ram_enabled);
+        w.u8(mbc1.bank1);
+        w.u8(mbc1.bank2);
+        w.bool(mbc1.mode);
+    }
+    if let Some(mbc3) = &bus.mbc3 {
+        w.bool(mbc3.ram_enabled);
+        w.u8(mbc3.rom_bank);
+        w.u8(mbc3.select);
+        w.bytes(mbc3.ram());
+        // The .sav footer layout, stamped with the real time for catching up on load.
+        if let Some(rtc) = &mbc3.rtc {
+            w.bytes(&rtc.footer(rtc::unix_time()));
+            w.u64(rtc.last);
+            w.u64(rtc.subsecond);
+        }
+    }
+    if let Some(mbc5) = &bus.mbc5 {
+        w.bool(mbc5.ram_enabled);
+        w.u16(mbc5.rom_bank);
+        w.u8(mbc5.ram_bank);
+        w.bytes(mbc5.ram());
+        if let Some(motor) = mbc5.rumble {
+            w.bool(motor);
+        }
+    }
+}
+
+fn load_mapper(bus: &mut Bus, r: &mut StateReader, mode: RtcMode) -> MaybeErr<()> {
+    if let Some(mbc1) = &mut bus.mbc1 {
+        mbc1.ram_enabled = r.bool()?;
+        mbc1.bank1 = r.u8()?;
+        mbc1.bank2 = r.u8()?;
+        mbc1.mode = r.bool()?;
+        bus.banks.rom = mbc1.rom_bank();
+    }
+    if let Some(mbc3) = &mut bus.mbc3 {
+        mbc3.ram_enabled = r.bool()?;
+        mbc3.rom_bank = r.u8()?;
+        mbc3.select = r.u8()?;
+        r.fill(mbc3.ram_mut())?;
+        if let Some(rtc) = &mut mbc3.rtc {
+            rtc.load_footer(r.bytes(rtc::FOOTER_LEN)?, rtc::unix_time(), mode)?;
+            rtc.last = r.u64()?;
+            rtc.subsecond = r.u64()?;
+        }
+        bus.banks.rom = mbc3.rom_bank();
+    }
+    if let Some(mbc5) = &mut bus.mbc5 {
+        mbc5.ram_enabled = r.bool()?;
+        mbc5.rom_bank = r.u16()?;
+        mbc5.ram_bank = r.u8()?;
+        r.fill(mbc5.ram_mut())?;
+        if let Some(motor) = &mut mbc5.rumble {
+            *motor = r.bool()?;
+        }
+        bus.banks.rom = mbc5.rom_bank();
+        bus.banks.ram = Some(mbc5.ram_bank as usize).filter(|_| mbc5.ram_enabled);
+    }
+    Ok(())
+}
+
+// The picture isn't saved, it comes from the host.
+fn save_camera(camera: &Camera, w: &mut StateWriter) {
+    w.u8(camera.rom_bank as u8);
+    w.u8(camera.ram_bank as u8);
+    w.bool(camera.registers_mapped);
+    w.bool(camera.ram_enabled);
+    w.bytes(&camera.registers);
+    w.bool(camera.capture_done.is_some());
+    w.u64(camera.capture_done.unwrap_or(0));
+    w.bytes(camera.ram());
+}
+
+fn load_camera(bus: &mut Bus, r: &mut StateReader) -> MaybeErr<()> {
+    let camera = bus
+        .camera
+        .as_mut()
+        .ok_or("State is of a Game Boy Camera, the cartridge isn't one")?;
+    camera.rom_bank = r.u8()? as usize;
+    camera.ram_bank = r.u8()? as usize;
+    camera.registers_mapped = r.bool()?;
+    camera.ram_enabled = r.bool()?;
+    r.fill(&mut camera.registers)?;
+    let capturing = r.bool()?;
+    let done = r.u64()?;
+    camera.capture_done = Some(done).filter(|_| capturing);
+    r.fill(camera.ram_mut())?;
+    bus.banks.rom = camera.rom_bank;
+    bus.banks.ram = Some(camera.ram_bank).filter(|_| camera.ram_enabled);
+    Ok(())
+}
+
+fn chunk(tag: [u8; 4], f: impl FnOnce(&mut StateWriter)) -> Chunk {
+    let mut w = StateWriter::default();
+    f(&mut w);
+    (tag, w.buf)
+}
+
+// Shown by slot pickers without loading the whole state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    // Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub frame: u64,
+    // THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT pixels, row major.
+    pub thumbnail: Vec<u32>,
+}
+
+pub const THUMBNAIL_WIDTH: usize = SCREEN_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: usize = SCREEN_HEIGHT / 2;
+
+impl Metadata {
+    pub fn capture(emu: &Emu, timestamp: u64) -> Self {
+        let frame = emu.bus.gpu.visible_frame();
+        let thumbnail = (0..THUMBNAIL_HEIGHT)
+            .flat_map(|y| (0..THUMBNAIL_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| frame[y * 2 * SCREEN_WIDTH + x * 2])
+            .collect();
+        Self {
+            timestamp,
+            frame: emu.bus.gpu._vblank_count as u64,
+            thumbnail,
+        }
+    }
+}
+
+fn save_meta(meta: &Metadata, w: &mut StateWriter) {
+    w.u64(meta.timestamp);
+    w.u64(meta.frame);
+    w.u32(meta.thumbnail.len() as u32);
+    for pixel in &meta.thumbnail {
+        w.u32(*pixel);
+    }
+}
+
+fn load_meta(r: &mut StateReader) -> MaybeErr<Metadata> {
+    let timestamp = r.u64()?;
+    let frame = r.u64()?;
+    let len = r.u32()? as usize;
+    let thumbnail = (0..len).map(|_| r.u32()).collect::<MaybeErr<_>>()?;
+    Ok(Metadata {
+        timestamp,
+        frame,
+        thumbnail,
+    })
+}
+
+pub fn save(emu: &Emu) -> Vec<u8> {
+    save_chunks(emu, None)
+}
+
+pub fn save_with_meta(emu: &Emu, meta: &Metadata) -> Vec<u8> {
+    save_chunks(emu, Some(meta))
+}
+
+fn save_chunks(emu: &Emu, meta: Option<&Metadata>) -> Vec<u8> {
+    let mut chunks = vec![
+        chunk(CPU_TAG, |w| save_cpu(&emu.cpu, w)),
+        chunk(BUS_TAG, |w| save_bus(&emu.bus, w)),
+        chunk(GPU_TAG, |w| save_gpu(&emu.bus.gpu, &emu.bus.io_map(), w)),
+        chunk(TIMER_TAG, |w| save_timer(&emu.bus.timer, w)),
+        chunk(APU_TAG, |w| save_apu(&emu.bus.apu, w)),
+        chunk(SPEED_TAG, |w| save_speed(&emu.bus, w)),
+        chunk(HDMA_TAG, |w| save_hdma(&emu.bus.hdma, w)),
+        // Empty without a mapper.
+        chunk(MAPPER_TAG, |w| save_mapper(&emu.bus, w)),
+    ];
+    if let Some(camera) = &emu.bus.camera {
+        chunks.push(chunk(CAMERA_TAG, |w| save_camera(camera, w)));
+    }
+    if let Some(meta) = meta {
+        chunks.push(chunk(META_TAG, |w| save_meta(meta, w)));
+    }
+    assemble(CURRENT_VERSION, &chunks)
+}
+
+// The inverse of parse.
+pub fn assemble(version: u16, chunks: &[Chunk]) -> Vec<u8> {
+    let mut w = StateWriter::default();
+    w.bytes(MAGIC);
+    w.u16(version);
+    for (tag, payload) in chunks {
+        w.bytes(tag);
+        w.blob(payload);
+    }
+    w.buf
+}
+
+// Splits a savestate into its version and chunks, without interpreting them.
+pub fn parse(data: &[u8]) -> MaybeErr<(u16, Vec<Chunk>)> {
+    let mut r = StateReader::new(bess::strip_footer(data));
+    if r.bytes(4)? != MAGIC {
+        return Err("Not a savestate".into());
+    }
+    let version = r.u16()?;
+    let mut chunks = vec![];
+    while !r.is_empty() {
+        let mut tag = [0; 4];
+        r.fill(&mut tag)?;
+        chunks.push((tag, r.blob()?.to_vec()));
+    }
+    Ok((version, chunks))
+}
+
+// Applies migrations until the chunks match CURRENT_VERSION.
+pub fn migrate(version: u16, chunks: &mut Vec<Chunk>) -> MaybeErr<()> {
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(format!(
+            "Savestate version {} is not supported (current is {})",
+            version, CURRENT_VERSION
+        )
+        .into());
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(chunks)?;
+    }
+    Ok(())
+}
+
+// None for states saved without metadata.
+pub fn metadata(data: &[u8]) -> MaybeErr<Option<Metadata>> {
+    let (_, chunks) = parse(data)?;
+    match chunks.iter().find(|(tag, _)| *tag == META_TAG) {
+        Some((_, payload)) => Ok(Some(load_meta(&mut StateReader::new(payload))?)),
+        None => Ok(None),
+    }
+}
+
+// Loads a native state, or another emulator's through its BESS footer.
+pub fn load(emu: &mut Emu, data: &[u8]) -> MaybeErr<()> {
+    if !data.starts_with(MAGIC) && bess::is_bess(data) {
+        return bess::load(emu, data);
+    }
+    let (version, mut chunks) = parse(data)?;
+    migrate(version, &mut chunks)?;
+    for (tag, payload) in &chunks {
+        let r = &mut StateReader::new(payload);
+        match *tag {
+            CPU_TAG => load_cpu(&mut emu.cpu, r)?,
+            BUS_TAG => load_bus(&mut emu.bus, r)?,
+            GPU_TAG => load_gpu(&mut emu.bus.gpu, r)?,
+            TIMER_TAG => load_timer(&mut emu.bus.timer, r)?,
+            APU_TAG => load_apu(&mut emu.bus.apu, r)?,
+            SPEED_TAG => load_speed(&mut emu.bus, r)?,
+            HDMA_TAG => load_hdma(&mut emu.bus.hdma, r)?,
+            MAPPER_TAG => load_mapper(&mut emu.bus, r, emu.rtc_mode)?,
+            CAMERA_TAG => load_camera(&mut emu.bus, r)?,
+            _ => continue,
+        }
+        if !r.is_empty() {
+            return Err(format!("Trailing data in {} chunk", String::from_utf8_lossy(tag)).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Memory;
+    use crate::camera;
+    use crate::cartridge;
+    use crate::mbc1;
+    use crate::mbc3;
+    use crate::mbc5;
+    use crate::meminit::MemFill;
+    use crate::serial::SerialKind;
+
+    fn scrambled_emu(seed: u32) -> Emu {
+        let mut random = vec![0; 0x10000];
+        MemFill::Random(seed).fill(&mut random);
+        let mut emu = Emu::new(vec![], None);
+        for (b, &r) in emu.bus.memory.iter_mut().zip(&random).step_by(7) {
+            *b = r;
+        }
+        for (b, &r) in emu.bus.gpu.vram.iter_mut().zip(&random).step_by(3) {
+            *b = r;
+        }
+        // The registers come from the last few bytes.
+        let byte = |i: usize| random[0xFFF0 + i];
+        let word = |i: usize| u16::from_le_bytes([byte(i), byte(i + 1)]);
+        emu.cpu.registers.a = byte(0);
+        emu.cpu.registers.f = byte(1) & 0xF0;
+        emu.cpu.registers.sp = word(2);
+        emu.cpu.registers.pc = word(4);
+        emu.cpu.halt = byte(6) & 1 != 0;
+        emu.bus.clock = word(8).into();
+        emu.bus.int_flags = byte(10);
+        emu.bus.joypad.select = Select::Directions;
+        emu.bus.serial.output.push_str("Passed");
+        emu.bus.gpu.scanline = byte(11) % 154;
+        emu.bus.gpu.lcdc = byte(12);
+        emu.bus.timer.internal = word(13);
+        emu.bus.timer.tima = byte(15);
+        emu
+    }
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..16 {
+            let emu = scrambled_emu(0x1234_5678 + seed);
+            let saved = save(&emu);
+            let mut loaded = Emu::new(vec![], None);
+            load(&mut loaded, &saved).unwrap();
+            assert_eq!(save(&loaded), saved);
+            assert_eq!(loaded.cpu.registers.pc, emu.cpu.registers.pc);
+            assert_eq!(loaded.bus.serial.output, "Passed");
+        }
+    }
+
+    #[test]
+    fn serial_survives_a_device_change() {
+        let mut emu = Emu::new(vec![], None);
+        emu.bus.serial.sb = 0x42;
+        emu.bus.serial.sc = 0x01;
+        emu.bus.serial.output.push_str("Passed");
+        let saved = save(&emu);
+        let mut loaded = Emu::new(vec![], None);
+        load(&mut loaded, &saved).unwrap();
+        // As the frontend does after loading a state.
+        loaded.bus.serial.device = SerialKind::Mirror.device();
+        assert_eq!(loaded.bus.serial.sb, 0x42);
+        assert_eq!(loaded.bus.serial.sc, 0x01);
+        assert_eq!(loaded.bus.serial.output, "Passed");
+    }
+
+    #[test]
+    fn camera_round_trip() {
+        let mut rom = vec![0; 0x8000];
+        rom[cartridge::CARTRIDGE_TYPE] = camera::CARTRIDGE_TYPE;
+        let mut emu = Emu::new(rom.clone(), None);
+        emu.bus.write(0x0000, 0x0A);
+        emu.bus.write(0x4000, 3);
+        emu.bus.write(0xA010, 0x42);
+        let saved = save(&emu);
+        let mut loaded = Emu::new(rom, None);
+        load(&mut loaded, &saved).unwrap();
+        assert_eq!(loaded.bus.read(0xA010), 0x42);
+        assert_eq!(loaded.bus.banks.ram, Some(3));
+        assert_eq!(save(&loaded), saved);
+        assert!(load(&mut Emu::new(vec![], None), &saved).is_err());
+    }
+
+    #[test]
+    fn migrates_the_empty_mapper_chunk() {
+        for &kind in &[
+            mbc1::CARTRIDGE_TYPES[0],
+            mbc3::CARTRIDGE_TYPES[0],
+            mbc5::CARTRIDGE_TYPES[0],
+        ] {
+            let mut rom = vec![0; 4 * mbc1::ROM_BANK_SIZE];
+            rom[cartridge::CARTRIDGE_TYPE] = kind;
+            let mut emu = Emu::new(rom.clone(), None);
+            emu.bus.write(0x2000, 2);
+            let (_, mut chunks) = parse(&save(&emu)).unwrap();
+            for (tag, payload) in &mut chunks {
+                if *tag == MAPPER_TAG {
+                    payload.clear();
+                }
+            }
+            let mut loaded = Emu::new(rom, None);
+            assert!(load(&mut loaded, &assemble(CURRENT_VERSION, &chunks)).is_err());
+            drop_empty_mapper(&mut chunks).unwrap();
+            load(&mut loaded, &assemble(CURRENT_VERSION, &chunks)).unwrap();
+            assert_eq!(loaded.bus.rom_bank_at(0x4000), Some(1));
+        }
+    }
+
+    #[test]
+    fn migrates_the_apu_chunk() {
+        let mut emu = Emu::new(vec![], None);
+        emu.bus.apu.wave_timer = 100;
+        let (_, mut chunks) = parse(&save(&emu)).unwrap();
+        for (tag, payload) in &mut chunks {
+            if *tag == APU_TAG {
+                payload.truncate(payload.len() - 4);
+            }
+        }
+        let mut loaded = Emu::new(vec![], None);
+        assert!(load(&mut loaded, &assemble(CURRENT_VERSION, &chunks)).is_err());
+        loaded.bus.apu.wave_position = 5;
+        idle_wave_channel(&mut chunks).unwrap();
+        load(&mut loaded, &assemble(CURRENT_VERSION, &chunks)).unwrap();
+        assert_eq!(loaded.bus.apu.wave_timer, 0);
+        assert_eq!(loaded.bus.apu.wave_position, 0);
+    }
+
+    #[test]
+    fn rejects_truncated_and_unknown_versions() {
+        let emu = Emu::new(vec![], None);
+        let saved = save(&emu);
+        let mut loaded = Emu::new(vec![], None);
+        assert!(load(&mut loaded, &saved[..saved.len() - 1]).is_err());
+        assert!(load(&mut loaded, b"nope").is_err());
+
+        let mut future = saved.clone();
+        future[4..6].copy_from_slice(&(CURRENT_VERSION + 1).to_le_bytes());
+        assert!(load(&mut loaded, &future).is_err());
+    }
+
+    #[test]
+    fn rejects_scanline_out_of_range() {
+        let mut emu = Emu::new(vec![], None);
+        emu.bus.gpu.scanline = 100;
+        let mut saved = save(&emu);
+        // The scanline follows the mode and clock in the GPU chunk.
+        let chunk = saved.windows(4).position(|tag| tag == GPU_TAG).unwrap();
+        saved[chunk + 4 + 4 + 9] = LINES_PER_FRAME as u8;
+        let mut loaded = Emu::new(vec![], None);
+        loaded.bus.gpu.scanline = 42;
+        assert!(load(&mut loaded, &saved).is_err());
+        assert_eq!(loaded.bus.gpu.scanline, 42);
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let emu = Emu::new(vec![], None);
+        let mut saved = save(&emu);
+        saved.extend_from_slice(b"XTRA");
+        saved.extend_from_slice(&2u32.to_le_bytes());
+        saved.extend_from_slice(&[1, 2]);
+        let mut loaded = Emu::new(vec![], None);
+        load(&mut loaded, &saved).unwrap();
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let emu = Emu::new(vec![], None);
+        assert_eq!(metadata(&save(&emu)).unwrap(), None);
+        let meta = Metadata::capture(&emu, 1234);
+        assert_eq!(meta.thumbnail.len(), THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+        let saved = save_with_meta(&emu, &meta);
+        assert_eq!(metadata(&saved).unwrap(), Some(meta));
+        let mut loaded = Emu::new(vec![], None);
+        load(&mut loaded, &saved).unwrap();
+    }
+}
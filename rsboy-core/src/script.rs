@@ -0,0 +1,321 @@
+use crate::bus::Memory;
+use crate::constants::MaybeErr;
+use crate::emu::Emu;
+use crate::input::Button;
+use crate::registers::RegisterState;
+use crate::video::overlay::Shape;
+use rhai::{Engine, EvalAltResult, ImmutableString, Scope, AST, INT};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+// Name of the function a script defines to be called once per frame.
+pub const FRAME_CALLBACK: &str = "on_frame";
+
+// What a script sees while on_frame runs. Memory and registers are copied in before the
+// callback, writes and presses are queued and applied to the emulator once it returns.
+#[derive(Default)]
+struct Shared {
+    memory: Vec<u8>,
+    registers: RegisterState,
+    frame: usize,
+    writes: Vec<(u16, u8)>,
+    pressed: BTreeSet<Button>,
+    shapes: Vec<Shape>,
+}
+
+// A loaded script. Top level statements run once on load, then on_frame() is called before every
+// emulated frame, once the frontend has latched its input and cleared the overlay. Presses hold for
+// the coming frame and shapes show over it, while reads see what the previous frame left. It used
+// to run after each frame, where the next frame's latched input dropped its presses. For example
+//
+//   fn on_frame() {
+//       if read(0xC123) == 3 { press("A"); }
+//   }
+//
+// Available functions: read(addr), write(addr, value), reg(name), press(button), frame(), and
+// draw_rect(x, y, w, h, rgba), fill_rect(..), draw_line(x0, y0, x1, y1, rgba),
+// draw_text(x, y, text, rgba) for the overlay.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> MaybeErr<Self> {
+        Self::from_source(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_source(source: &str) -> MaybeErr<Self> {
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        let engine = engine(&shared);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .consume_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            shared,
+        })
+    }
+
+    // Run the frame callback against the current emulator state and apply what it queued.
+    pub fn on_frame(&mut self, emu: &mut Emu) -> MaybeErr<()> {
+        {
+            let mut shared = self.shared.borrow_mut();
+            shared.memory = (0..=0xFFFF).map(|addr| emu.bus.debug_read(addr)).collect();
+            shared.registers = emu.cpu.registers.clone();
+            shared.frame = emu.bus.gpu._vblank_count;
+            shared.writes.clear();
+            shared.pressed.clear();
+            shared.shapes.clear();
+        }
+        let result: Result<(), Box<EvalAltResult>> =
+            self.engine
+                .call_fn(&mut self.scope, &self.ast, FRAME_CALLBACK, ());
+        match result {
+            Ok(()) => {}
+            // Scripts that only run setup code don't need a frame callback.
+            Err(e) => match *e {
+                EvalAltResult::ErrorFunctionNotFound(ref name, _)
+                    if name.starts_with(FRAME_CALLBACK) => {}
+                _ => return Err(e.to_string().into()),
+            },
+        }
+        let mut shared = self.shared.borrow_mut();
+        for shape in shared.shapes.drain(..) {
+            emu.overlay.push(shape);
+        }
+        for &(addr, value) in shared.writes.iter() {
+            emu.bus.write(addr, value);
+        }
+        for button in shared.pressed.iter() {
+            button.press(&mut emu.bus);
+        }
+        Ok(())
+    }
+}
+
+fn engine(shared: &Rc<RefCell<Shared>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let state = shared.clone();
+    engine.register_fn("read", move |addr: INT| -> INT {
+        let state = state.borrow();
+        state
+            .memory
+            .get(addr as u16 as usize)
+            .copied()
+            .unwrap_or(0xFF) as INT
+    });
+
+    let state = shared.clone();
+    engine.register_fn("write", move |addr: INT, value: INT| {
+        let mut state = state.borrow_mut();
+        let (addr, value) = (addr as u16, value as u8);
+        // Later reads in the same callback see the write.
+        if let Some(byte) = state.memory.get_mut(addr as usize) {
+            *byte = value;
+        }
+        state.writes.push((addr, value));
+    });
+
+    let state = shared.clone();
+    engine.register_fn("reg", move |name: ImmutableString| -> INT {
+        let r = &state.borrow().registers;
+        (match name.to_ascii_lowercase().as_str() {
+            "a" => r.a as u16,
+            "b" => r.b as u16,
+            "c" => r.c as u16,
+            "d" => r.d as u16,
+            "e" => r.e as u16,
+            "f" => r.f as u16,
+            "h" => r.h as u16,
+            "l" => r.l as u16,
+            "af" => r.af(),
+            "bc" => r.bc(),
+            "de" => r.de(),
+            "hl" => r.hl(),
+            "sp" => r.sp,
+            "pc" => r.pc,
+            _ => return -1,
+        }) as INT
+    });
+
+    let state = shared.clone();
+    engine.register_fn("press", move |button: ImmutableString| {
+        if let Ok(button) = button.parse::<Button>() {
+            state.borrow_mut().pressed.insert(button);
+        }
+    });
+
+    let state = shared.clone();
+    engine.register_fn("frame", move || -> INT { state.borrow().frame as INT });
+
+    let state = shared.clone();
+    engine.register_fn(
+        "draw_rect",
+        move |x: INT, y: INT, w: INT, h: INT, color: INT| {
+            let (x, y, w, h) = (x as i32, y as i32, w as i32, h as i32);
+            let color = color as u32;
+            let filled = false;
+            state.borrow_mut().shapes.push(Shape::Rect {
+                x,
+                y,
+                w,
+                h,
+                color,
+                filled,
+            });
+        },
+    );
+
+    let state = shared.clone();
+    engine.register_fn(
+        "fill_rect",
+        move |x: INT, y: INT, w: INT, h: INT, color: INT| {
+            let (x, y, w, h) = (x as i32, y as i32, w as i32, h as i32);
+            let color = color as u32;
+            let filled = true;
+            state.borrow_mut().shapes.push(Shape::Rect {
+                x,
+                y,
+                w,
+                h,
+                color,
+                filled,
+            });
+        },
+    );
+
+    let state = shared.clone();
+    engine.register_fn(
+        "draw_line",
+        move |x0: INT, y0: INT, x1: INT, y1: INT, color: INT| {
+            let (x0, y0, x1, y1) = (x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+            let color = color as u32;
+            state.borrow_mut().shapes.push(Shape::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                color,
+            });
+        },
+    );
+
+    let state = shared.clone();
+    engine.register_fn(
+        "draw_text",
+        move |x: INT, y: INT, text: ImmutableString, color: INT| {
+            let (x, y) = (x as i32, y as i32);
+            let (text, color) = (text.to_string(), color as u32);
+            state
+                .borrow_mut()
+                .shapes
+                .push(Shape::Text { x, y, text, color });
+        },
+    );
+
+    engine
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::Input;
+
+    fn emu() -> Emu {
+        Emu::new(vec![0; 0x8000], None)
+    }
+
+    #[test]
+    fn reads_writes_and_presses() {
+        let mut emu = emu();
+        emu.bus.write(0xC123, 3);
+        let mut script = Script::from_source(
+            r#"
+            fn on_frame() {
+                if read(0xC123) == 3 {
+                    press("A");
+                    write(0xC124, read(0xC123) + 1);
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        script.on_frame(&mut emu).unwrap();
+        assert_eq!(emu.bus.read(0xC124), 4);
+        assert!(Button::A.is_pressed(&emu.bus));
+        assert!(!Button::B.is_pressed(&emu.bus));
+    }
+
+    #[test]
+    fn presses_hold_over_latched_input() {
+        let mut emu = emu();
+        let mut input = Input::new();
+        let mut script = Script::from_source(r#"fn on_frame() { press("A"); }"#).unwrap();
+        // The frontend's order: latch input, then run the script.
+        input.apply(&mut emu.bus);
+        script.on_frame(&mut emu).unwrap();
+        assert!(Button::A.is_pressed(&emu.bus));
+        // Latching after the script would release it before the game runs.
+        input.apply(&mut emu.bus);
+        assert!(!Button::A.is_pressed(&emu.bus));
+    }
+
+    #[test]
+    fn reads_registers() {
+        let mut emu = emu();
+        emu.cpu.registers.a = 0x42;
+        emu.cpu.registers.sp = 0xFFFE;
+        let mut script = Script::from_source(
+            r#"fn on_frame() { if reg("a") == 0x42 && reg("sp") == 0xFFFE { write(0xC000, 1); } }"#,
+        )
+        .unwrap();
+        script.on_frame(&mut emu).unwrap();
+        assert_eq!(emu.bus.read(0xC000), 1);
+    }
+
+    #[test]
+    fn draws_on_overlay() {
+        let mut emu = emu();
+        let mut script = Script::from_source(
+            r#"fn on_frame() { draw_rect(8, 16, 8, 8, 0xFF0000FF); draw_text(0, 0, "HI", 0xFFFFFFFF); }"#,
+        )
+        .unwrap();
+        script.on_frame(&mut emu).unwrap();
+        assert_eq!(emu.overlay.shapes().len(), 2);
+        assert_eq!(
+            emu.overlay.shapes()[0],
+            Shape::Rect {
+                x: 8,
+                y: 16,
+                w: 8,
+                h: 8,
+                color: 0xFF0000FF,
+                filled: false
+            }
+        );
+    }
+
+    #[test]
+    fn callback_is_optional() {
+        let mut script = Script::from_source("let x = 1;").unwrap();
+        assert!(script.on_frame(&mut emu()).is_ok());
+    }
+
+    #[test]
+    fn script_errors_surface() {
+        assert!(Script::from_source("fn on_frame( {").is_err());
+        let mut script = Script::from_source("fn on_frame() { undefined_fn(); }").unwrap();
+        assert!(script.on_frame(&mut emu()).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::cpu::SERIAL;
+use crate::io::{IoContext, IoDevice};
+use crate::printer::Printer;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const SB: usize = 0xFF01;
+pub const SC: usize = 0xFF02;
+
+// 8 bits at 8192Hz with the internal clock, in CPU cycles.
+pub const TRANSFER_CYCLES: usize = 8 * 512;
+// 8 bits at 262144Hz, with the CGB fast clock bit set.
+pub const FAST_TRANSFER_CYCLES: usize = 8 * 16;
+
+// How long a transfer started by writing `sc` to SC takes. Double speed halves it further since
+// the serial clock is divided from the CPU clock.
+pub fn transfer_cycles(sc: u8, cgb: bool) -> usize {
+    if cgb && sc & 0b10 != 0 {
+        FAST_TRANSFER_CYCLES
+    } else {
+        TRANSFER_CYCLES
+    }
+}
+
+// Whatever sits on the other end of the link cable.
+pub trait SerialDevice {
+    // Called once a byte has been fully shifted out, returns the byte shifted in.
+    fn exchange(&mut self, out: u8) -> u8;
+}
+
+// A cable with nothing plugged in, the data line floats high.
+pub struct Disconnected;
+
+impl SerialDevice for Disconnected {
+    fn exchange(&mut self, _out: u8) -> u8 {
+        0xFF
+    }
+}
+
+// Echoes back every byte, as if talking to a copy of the same game.
+pub struct Mirror;
+
+impl SerialDevice for Mirror {
+    fn exchange(&mut self, out: u8) -> u8 {
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialKind {
+    // No peer, transfers never finish.
+    None,
+    Disconnected,
+    Mirror,
+    // Game Boy Printer, saving prints to the current directory.
+    Printer,
+}
+
+impl SerialKind {
+    pub fn device(self) -> Option<Box<dyn SerialDevice>> {
+        match self {
+            SerialKind::None => None,
+            SerialKind::Disconnected => Some(Box::new(Disconnected)),
+            SerialKind::Mirror => Some(Box::new(Mirror)),
+            SerialKind::Printer => Some(Box::new(Printer::new(PathBuf::from(".")))),
+        }
+    }
+}
+
+impl FromStr for SerialKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SerialKind::None),
+            "disconnected" => Ok(SerialKind::Disconnected),
+            "mirror" | "loopback" => Ok(SerialKind::Mirror),
+            "printer" => Ok(SerialKind::Printer),
+            _ => Err(format!(
+                "Unknown serial device {}, expected none, disconnected, mirror or printer",
+                s
+            )),
+        }
+    }
+}
+
+// The link port, see https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+#[derive(Default)]
+pub struct Serial {
+    pub device: Option<Box<dyn SerialDevice>>,
+    pub sb: u8,
+    pub sc: u8,
+    // Every byte sent with the internal clock, which is how test ROMs print their results.
+    pub output: String,
+    // Byte being shifted out and cycles left, while a transfer is running.
+    transfer: Option<(u8, usize)>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_device(kind: SerialKind) -> Self {
+        Self {
+            device: kind.device(),
+            ..Default::default()
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // Transfers on the external clock finish too, the peer is assumed to drive the clock.
+    pub fn start(&mut self, out: u8, cycles: usize) {
+        if self.device.is_some() {
+            self.transfer = Some((out, cycles));
+        }
+    }
+
+    // Cycles until the running transfer completes.
+    pub fn cycles_left(&self) -> Option<usize> {
+        self.transfer.map(|(_, cycles)| cycles)
+    }
+
+    // Advances a transfer without completing it, see Bus::skip_idle.
+    pub fn skip(&mut self, n: usize) {
+        if let Some((_, cycles)) = &mut self.transfer {
+            *cycles -= n;
+        }
+    }
+
+    // Once a transfer completes SB holds the received byte, SC's start bit clears and the serial
+    // interrupt is requested.
+    pub fn tick(&mut self, int_flags: &mut u8) {
+        let (out, cycles) = match self.transfer.as_mut() {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        *cycles -= 1;
+        if *cycles > 0 {
+            return;
+        }
+        let out = *out;
+        self.transfer = None;
+        if let Some(device) = &mut self.device {
+            self.sb = device.exchange(out);
+            self.sc &= 0x7F;
+            *int_flags |= SERIAL;
+        }
+    }
+}
+
+impl IoDevice for Serial {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        match address as usize {
+            SB => Some(self.sb),
+            SC => Some(self.sc),
+            _ => None,
+        }
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, ctx: &mut IoContext) -> bool {
+        match address as usize {
+            SB => self.sb = value,
+            SC => {
+                if value == 0x81 {
+                    self.output.push(char::from(self.sb));
+                }
+                self.sc = value;
+                if value & 0x80 != 0 {
+                    self.start(self.sb, transfer_cycles(value, ctx.cgb));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Display for Serial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.transfer {
+            Some((out, cycles)) => write!(f, "OUT:{:02x} {} cycles left", out, cycles),
+            None => write!(f, "idle"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(serial: &mut Serial) -> (usize, Option<u8>) {
+        let mut flags = 0;
+        for cycle in 1..=TRANSFER_CYCLES * 2 {
+            serial.tick(&mut flags);
+            if flags & SERIAL != 0 {
+                return (cycle, Some(serial.sb));
+            }
+        }
+        (0, None)
+    }
+
+    #[test]
+    fn disconnected_reads_ff() {
+        let mut serial = Serial::with_device(SerialKind::Disconnected);
+        serial.start(0x42, TRANSFER_CYCLES);
+        assert!(serial.busy());
+        assert_eq!(run(&mut serial), (TRANSFER_CYCLES, Some(0xFF)));
+        assert!(!serial.busy());
+    }
+
+    #[test]
+    fn mirror_echoes() {
+        let mut serial = Serial::with_device(SerialKind::Mirror);
+        serial.start(0x42, TRANSFER_CYCLES);
+        assert_eq!(run(&mut serial), (TRANSFER_CYCLES, Some(0x42)));
+        serial.start(0x42, transfer_cycles(0x83, true));
+        assert_eq!(run(&mut serial), (FAST_TRANSFER_CYCLES, Some(0x42)));
+    }
+
+    #[test]
+    fn no_device_never_completes() {
+        let mut serial = Serial::new();
+        serial.start(0x42, TRANSFER_CYCLES);
+        assert!(!serial.busy());
+        assert_eq!(run(&mut serial), (0, None));
+    }
+
+    #[test]
+    fn registers_start_and_print_transfers() {
+        let mut serial = Serial::with_device(SerialKind::Mirror);
+        let mut flags = 0;
+        let mut ctx = IoContext {
+            clock: 0,
+            int_flags: &mut flags,
+            cgb: false,
+        };
+        assert!(serial.io_write(SB as u16, b'P', &mut ctx));
+        assert!(serial.io_write(SC as u16, 0x81, &mut ctx));
+        // The external clock sends too but isn't printed.
+        assert!(serial.io_write(SC as u16, 0x80, &mut ctx));
+        assert!(!serial.io_write(0xFF03, 0, &mut ctx));
+        assert_eq!(serial.output, "P");
+        assert_eq!(run(&mut serial), (TRANSFER_CYCLES, Some(b'P')));
+        assert_eq!(serial.io_read(SC as u16), Some(0x00));
+        assert_eq!(serial.io_read(0xFF03), None);
+    }
+
+    #[test]
+    fn fast_clock_is_cgb_only() {
+        assert_eq!(transfer_cycles(0x81, true), TRANSFER_CYCLES);
+        assert_eq!(transfer_cycles(0x83, false), TRANSFER_CYCLES);
+        assert_eq!(transfer_cycles(0x83, true), FAST_TRANSFER_CYCLES);
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!("loopback".parse::<SerialKind>(), Ok(SerialKind::Mirror));
+        assert!("modem".parse::<SerialKind>().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::bess;
+use crate::constants::MaybeErr;
+use crate::emu::Emu;
+use crate::savestate::{self, Metadata};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SLOT_COUNT: usize = 10;
+
+// Numbered savestates next to the ROM, `game.gb` saves to `game.ss1` .. `game.ss10`.
+pub struct Slots {
+    base: PathBuf,
+    pub meta: Vec<Option<Metadata>>,
+    // Most recently written slot, quick saves rotate through the slots after it.
+    last: Option<usize>,
+}
+
+impl Slots {
+    pub fn new(rom: &Path) -> Self {
+        let mut slots = Self {
+            base: rom.to_path_buf(),
+            meta: vec![None; SLOT_COUNT],
+            last: None,
+        };
+        slots.refresh();
+        slots
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf {
+        self.base.with_extension(format!("ss{}", slot + 1))
+    }
+
+    // Rereads the metadata of every slot from disk.
+    pub fn refresh(&mut self) {
+        for slot in 0..SLOT_COUNT {
+            self.meta[slot] = fs::read(self.path(slot))
+                .ok()
+                .and_then(|data| savestate::metadata(&data).ok().flatten());
+        }
+        self.last = (0..SLOT_COUNT)
+            .filter_map(|slot| Some((self.meta[slot].as_ref()?.timestamp, slot)))
+            .max()
+            .map(|(_, slot)| slot);
+    }
+
+    pub fn save(&mut self, slot: usize, emu: &Emu) -> MaybeErr<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let meta = Metadata::capture(emu, timestamp);
+        // The BESS footer lets other emulators open slot files too.
+        let mut data = savestate::save_with_meta(emu, &meta);
+        bess::append(emu, &mut data);
+        fs::write(self.path(slot), data)?;
+        self.meta[slot] = Some(meta);
+        self.last = Some(slot);
+        Ok(())
+    }
+
+    pub fn load(&self, slot: usize, emu: &mut Emu) -> MaybeErr<()> {
+        let data = fs::read(self.path(slot))?;
+        savestate::load(emu, &data)
+    }
+
+    // Saves to the slot after the last one written, so the last SLOT_COUNT quick saves are kept.
+    pub fn quick_save(&mut self, emu: &Emu) -> MaybeErr<usize> {
+        let slot = self.last.map_or(0, |last| (last + 1) % SLOT_COUNT);
+        self.save(slot, emu)?;
+        Ok(slot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quick_save_rotates() {
+        let dir = std::env::temp_dir().join(format!("rsboy-slots-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut slots = Slots::new(&dir.join("game.gb"));
+        assert_eq!(slots.path(9), dir.join("game.ss10"));
+        assert!(slots.meta.iter().all(Option::is_none));
+
+        let mut emu = Emu::new(vec![], None);
+        slots.save(8, &emu).unwrap();
+        assert_eq!(slots.quick_save(&emu).unwrap(), 9);
+        assert_eq!(slots.quick_save(&emu).unwrap(), 0);
+
+        emu.cpu.registers.a = 0x42;
+        slots.save(3, &emu).unwrap();
+        let mut loaded = Emu::new(vec![], None);
+        slots.load(3, &mut loaded).unwrap();
+        assert_eq!(loaded.cpu.registers.a, 0x42);
+
+        let reopened = Slots::new(&dir.join("game.gb"));
+        let used: Vec<_> = (0..SLOT_COUNT)
+            .filter(|&slot| reopened.meta[slot].is_some())
+            .collect();
+        assert_eq!(used, vec![0, 3, 8, 9]);
+        assert!(slots.load(5, &mut loaded).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
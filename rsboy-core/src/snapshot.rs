@@ -0,0 +1,303 @@
+use crate::banks::Banks;
+use crate::bugreport::TraceEntry;
+use crate::clock::{self, Cycles};
+use crate::console::ConsoleLine;
+use crate::cpu::{self, InterruptEvent};
+use crate::emu::{Emu, InstrListing};
+use crate::gpu::{PixelData, Sprite, DOTS_PER_LINE, LINES_PER_FRAME};
+use crate::iomap::{Interrupts, IoMap, Lcdc, Stat};
+use crate::registers::RegisterState;
+use crate::stats::OpcodeStats;
+use crate::timer::TimerSnapshot;
+use crate::watch::WatchRegion;
+use std::{fmt::Display, sync::Arc};
+
+// Console lines carried by each snapshot.
+pub const CONSOLE_LINES: usize = 64;
+// Latest flag transitions carried by each snapshot.
+pub const FLAG_CHANGES: usize = 8;
+
+// Copy of the IO registers the debugger displays.
+#[derive(Clone, Debug, Default)]
+pub struct IoRegs {
+    pub int_enabled: u8,
+    pub int_flags: u8,
+    pub ime: u8,
+    pub keypresses: u8,
+    pub directions: u8,
+    pub lcdc: u8,
+    pub lcdstat: u8,
+    pub scrollx: u8,
+    pub scrolly: u8,
+    pub scanline: u8,
+    pub windowx: u8,
+    pub windowy: u8,
+    pub bgrdpal: u8,
+    pub obj0pal: u8,
+    pub obj1pal: u8,
+}
+
+// One value of the debugger's register view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: u16,
+    // Hex digits to show.
+    pub digits: usize,
+}
+
+// Where the PPU is within the current frame, and where VBlank/STAT interrupts were
+// dispatched so far this frame, as (scanline, dot, kind).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PpuTiming {
+    pub lcd_on: bool,
+    pub scanline: u8,
+    pub dot: usize,
+    pub mode: &'static str,
+    pub interrupts: Vec<(usize, usize, u8)>,
+}
+
+impl PpuTiming {
+    fn new(emu: &Emu) -> Self {
+        let gpu = &emu.bus.gpu;
+        let offset = gpu.scanline as usize * DOTS_PER_LINE + gpu.dot();
+        let frame_start = clock::since(offset as Cycles, emu.bus.clock);
+        let interrupts = emu
+            .bus
+            .interrupt_log
+            .iter()
+            .filter(|e| e.kind & (cpu::VBLANK | cpu::LCDSTAT) != 0 && e.clock >= frame_start)
+            .map(|e| {
+                // Within the frame, so it fits a usize.
+                let offset = (e.clock - frame_start) as usize;
+                let line = (offset / DOTS_PER_LINE).min(LINES_PER_FRAME - 1);
+                (line, offset % DOTS_PER_LINE, e.kind)
+            })
+            .collect();
+        Self {
+            lcd_on: gpu.is_on(),
+            scanline: gpu.scanline,
+            dot: gpu.dot(),
+            mode: gpu.mode_name(),
+            interrupts,
+        }
+    }
+}
+
+impl IoRegs {
+    pub fn new(io: &IoMap) -> Self {
+        Self {
+            int_enabled: io.int_enabled().0,
+            int_flags: io.int_flags().0,
+            ime: io.ime() as u8,
+            keypresses: io.buttons(),
+            directions: io.directions(),
+            lcdc: io.lcdc().0,
+            lcdstat: io.stat().0,
+            scrollx: io.scx(),
+            scrolly: io.scy(),
+            scanline: io.ly(),
+            windowx: io.wx(),
+            windowy: io.wy(),
+            bgrdpal: io.bgp(),
+            obj0pal: io.obp0(),
+            obj1pal: io.obp1(),
+        }
+    }
+}
+
+// Owned view of the emulator state, produced once per frame.
+// Holds no references into Emu, so it can be handed to a UI running on another thread.
+#[derive(Clone)]
+pub struct EmuSnapshot {
+    pub registers: RegisterState,
+    pub clock: Cycles,
+    pub io: IoRegs,
+    pub timer: TimerSnapshot,
+    pub history: Vec<InstrListing>,
+    pub console: Vec<ConsoleLine>,
+    // Newest first, only recorded while Bus::report_log is set.
+    pub flag_changes: Vec<TraceEntry>,
+    pub watched: Vec<(WatchRegion, Vec<u8>)>,
+    pub interrupts: Vec<InterruptEvent>,
+    pub sprites: Vec<Sprite>,
+    pub banks: Banks,
+    pub cartridge_type: Option<u8>,
+    pub opcode_stats: Option<OpcodeStats>,
+    pub framebuffer: Arc<PixelData>,
+    pub ppu: PpuTiming,
+}
+
+impl Emu {
+    pub fn snapshot(&self) -> EmuSnapshot {
+        let bus = &self.bus;
+        let gpu = &bus.gpu;
+        EmuSnapshot {
+            registers: self.cpu.registers.clone(),
+            clock: bus.clock,
+            io: IoRegs::new(&bus.io_map()),
+            timer: bus.timer.snapshot(),
+            history: self.history.iter().cloned().collect(),
+            console: {
+                let mut lines: Vec<_> = bus
+                    .console
+                    .iter()
+                    .rev()
+                    .take(CONSOLE_LINES)
+                    .cloned()
+                    .collect();
+                lines.reverse();
+                lines
+            },
+            flag_changes: match &bus.report_log {
+                Some(log) => log.flag_changes(FLAG_CHANGES).cloned().collect(),
+                None => vec![],
+            },
+            watched: self.watches.read(bus),
+            interrupts: bus.interrupt_log.iter().copied().collect(),
+            sprites: gpu.sprites(),
+            banks: bus.banks.clone(),
+            cartridge_type: self.header.as_ref().map(|h| h.cartridge_type),
+            opcode_stats: bus.opcode_stats.clone(),
+            framebuffer: Arc::new(*bus.gpu.screen()),
+            ppu: PpuTiming::new(self),
+        }
+    }
+}
+
+impl EmuSnapshot {
+    // CPU registers, flags, then the IO and timer registers.
+    pub fn fields(&self) -> Vec<Field> {
+        let r = &self.registers;
+        let io = &self.io;
+        let field = |name, value: u16, digits| Field {
+            name,
+            value,
+            digits,
+        };
+        let flag = |name, bit: u8| field(name, ((r.f >> bit) & 1) as u16, 1);
+        let mut fields = vec![field("PC", r.pc, 4), field("SP", r.sp, 4)];
+        for &(name, value) in &[
+            ("A", r.a),
+            ("B", r.b),
+            ("C", r.c),
+            ("D", r.d),
+            ("E", r.e),
+            ("H", r.h),
+            ("L", r.l),
+        ] {
+            fields.push(field(name, value as u16, 2));
+        }
+        fields.extend([flag("ZF", 7), flag("NF", 6), flag("HF", 5), flag("CF", 4)]);
+        for &(name, value) in &[
+            ("IE", io.int_enabled),
+            ("IF", io.int_flags),
+            ("IME", io.ime),
+            ("BTNS", io.keypresses),
+            ("ARWS", io.directions),
+            ("LCDC", io.lcdc),
+            ("STAT", io.lcdstat),
+            ("LY", io.scanline),
+            ("SCX", io.scrollx),
+            ("SCY", io.scrolly),
+            ("WX", io.windowx),
+            ("WY", io.windowy),
+            ("BGP", io.bgrdpal),
+            ("OBP0", io.obj0pal),
+            ("OBP1", io.obj1pal),
+            ("DIV", self.timer.div),
+            ("TIMA", self.timer.tima),
+            ("TMA", self.timer.tma),
+            ("TAC", self.timer.tac),
+        ] {
+            fields.push(field(name, value as u16, 2));
+        }
+        fields
+    }
+
+    // Names of the fields that differ from `previous`, for highlighting what a step changed.
+    pub fn changed_fields(&self, previous: &EmuSnapshot) -> Vec<&'static str> {
+        self.fields()
+            .iter()
+            .zip(previous.fields())
+            .filter(|(now, before)| now.value != before.value)
+            .map(|(now, _)| now.name)
+            .collect()
+    }
+}
+
+impl Display for IoRegs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            r#"IE: {}, IF: {}, IME: {}
+[BTNS]: {:08b}
+[ARWS]: {:08b}
+LCDC: {}
+STAT: {}, LY: {}
+SCX: {}, SCY: {}, WX: {}, WY: {}
+BGP: {:08b}, OBP0: {:08b}, OBP1: {:08b}"#,
+            Interrupts(self.int_enabled),
+            Interrupts(self.int_flags),
+            self.ime,
+            self.keypresses,
+            self.directions,
+            Lcdc(self.lcdc),
+            Stat(self.lcdstat),
+            self.scanline,
+            self.scrollx,
+            self.scrolly,
+            self.windowx,
+            self.windowy,
+            self.bgrdpal,
+            self.obj0pal,
+            self.obj1pal,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn snapshot_is_thread_safe() {
+        assert_send_sync::<EmuSnapshot>();
+    }
+
+    #[test]
+    fn diffs_registers_flags_and_io() {
+        let mut emu = Emu::new(vec![], None);
+        let before = emu.snapshot();
+        assert!(emu.snapshot().changed_fields(&before).is_empty());
+        emu.cpu.registers.a ^= 0xFF;
+        emu.cpu.registers.f ^= 0x80;
+        emu.bus.gpu.scrollx ^= 0x10;
+        let changed = emu.snapshot().changed_fields(&before);
+        assert_eq!(changed, vec!["A", "ZF", "SCX"]);
+    }
+
+    #[test]
+    fn ppu_timing_places_interrupts_in_frame() {
+        let mut emu = Emu::new(vec![], None);
+        emu.bus.gpu.lcdc = 0x80;
+        let mut flags = 0;
+        while emu.bus.gpu.scanline != 145 {
+            emu.bus.clock += 1;
+            emu.bus.gpu.cycle(&mut flags);
+        }
+        let vblank_at = 144 * DOTS_PER_LINE;
+        emu.bus.log_interrupt(InterruptEvent {
+            kind: cpu::VBLANK,
+            clock: (vblank_at + 4) as Cycles,
+            pc: 0,
+            ie: 0,
+            flags: 0,
+        });
+        let timing = emu.snapshot().ppu;
+        assert_eq!(timing.scanline, 145);
+        assert_eq!(timing.mode, "VBlank");
+        assert_eq!(timing.interrupts, vec![(144, 4, cpu::VBLANK)]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::io::{IoContext, IoDevice};
+
+pub const KEY1: usize = 0xFF4D;
+
+// CGB speed switch. In double speed the CPU, timer and serial run twice as fast while the PPU
+// and Bus::clock keep counting at the normal rate, so Bus::cycles and Bus::clock drift apart.
+// The Bus only maps KEY1 in CGB mode, a DMG has nothing there.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Speed {
+    pub double: bool,
+    // KEY1 bit 0, the switch happens on the next STOP.
+    pub armed: bool,
+    // In double speed, set on the first of each pair of CPU cycles.
+    pub half: bool,
+}
+
+impl Speed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&self) -> u8 {
+        0x7E | (self.double as u8) << 7 | self.armed as u8
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.armed = value & 1 != 0;
+    }
+
+    // Called by STOP, returns true if the speed switched.
+    pub fn stop(&mut self) -> bool {
+        if !self.armed {
+            return false;
+        }
+        self.double = !self.double;
+        self.armed = false;
+        self.half = false;
+        true
+    }
+
+    // Called every CPU cycle, true if the normal speed hardware advances on it.
+    pub fn dot(&mut self) -> bool {
+        if !self.double {
+            return true;
+        }
+        self.half = !self.half;
+        !self.half
+    }
+}
+
+impl IoDevice for Speed {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        Some(self.read()).filter(|_| address as usize == KEY1)
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, _: &mut IoContext) -> bool {
+        if address as usize != KEY1 {
+            return false;
+        }
+        self.write(value);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switches_on_armed_stop() {
+        let mut speed = Speed::new();
+        assert!(!speed.stop());
+        speed.write(1);
+        assert_eq!(speed.read(), 0x7F);
+        assert!(speed.stop());
+        assert_eq!(speed.read(), 0xFE);
+        let dots = (0..8).filter(|_| speed.dot()).count();
+        assert_eq!(dots, 4);
+
+        speed.write(1);
+        assert!(speed.stop());
+        assert!(speed.dot() && speed.dot());
+    }
+}
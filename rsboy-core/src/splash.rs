@@ -0,0 +1,99 @@
+use crate::bootlogo::NINTENDO;
+use crate::cartridge::{Header, HEADER_CHECKSUM, LOGO_START, TITLE_START};
+
+// Built-in ROM shown when there is no game to run. It draws a checkerboard of framed tiles
+// through the normal CPU and GPU path, so a working splash means the whole pipeline works.
+
+const ENTRY: [u8; 4] = [
+    0x00, // nop
+    0xC3, 0x50, 0x01, // jp $0150
+];
+
+const MAIN_START: usize = 0x150;
+const MAIN: [u8; 58] = [
+    0xF3, // di
+    0xF0, 0x44, // .vblank: ldh a, [LY]
+    0xFE, 0x90, // cp 144
+    0x38, 0xFA, // jr c, .vblank
+    0xAF, // xor a
+    0xE0, 0x40, // ldh [LCDC], a
+    0x21, 0x10, 0x80, // ld hl, $8010
+    0x11, 0x00, 0x02, // ld de, TILE
+    0x06, 0x10, // ld b, 16
+    0x1A, // .tile: ld a, [de]
+    0x22, // ld [hl+], a
+    0x13, // inc de
+    0x05, // dec b
+    0x20, 0xFA, // jr nz, .tile
+    0x21, 0x00, 0x98, // ld hl, $9800
+    0x0E, 0x20, // ld c, 32
+    0x06, 0x20, // .row: ld b, 32
+    0x79, // ld a, c
+    0xE6, 0x01, // and 1
+    0x22, // .col: ld [hl+], a
+    0xEE, 0x01, // xor 1
+    0x05, // dec b
+    0x20, 0xFA, // jr nz, .col
+    0x0D, // dec c
+    0x20, 0xF2, // jr nz, .row
+    0x3E, 0xE4, // ld a, %11100100
+    0xE0, 0x47, // ldh [BGP], a
+    0xAF, // xor a
+    0xE0, 0x42, // ldh [SCY], a
+    0xE0, 0x43, // ldh [SCX], a
+    0x3E, 0x91, // ld a, %10010001
+    0xE0, 0x40, // ldh [LCDC], a
+    0x18, 0xFE, // jr @
+];
+
+// Tile 1, a square with a dark border and a light inside.
+const TILE_START: usize = 0x200;
+const TILE: [u8; 16] = [
+    0xFF, 0xFF, 0xFF, 0x81, 0xFF, 0x81, 0xFF, 0x81, 0xFF, 0x81, 0xFF, 0x81, 0xFF, 0x81, 0xFF, 0xFF,
+];
+
+pub const TITLE: &str = "RSBOY";
+
+pub fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + ENTRY.len()].copy_from_slice(&ENTRY);
+    // The bootrom refuses to start a cartridge without this logo.
+    rom[LOGO_START..LOGO_START + NINTENDO.len()].copy_from_slice(&NINTENDO);
+    rom[TITLE_START..TITLE_START + TITLE.len()].copy_from_slice(TITLE.as_bytes());
+    rom[MAIN_START..MAIN_START + MAIN.len()].copy_from_slice(&MAIN);
+    rom[TILE_START..TILE_START + TILE.len()].copy_from_slice(&TILE);
+    rom[HEADER_CHECKSUM] = Header::computed_checksum(&rom);
+    rom
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::CYCLES_PER_FRAME;
+    use crate::emu::Emu;
+
+    #[test]
+    fn header_is_valid() {
+        let rom = rom();
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title, TITLE);
+        assert_eq!(header.header_checksum, Header::computed_checksum(&rom));
+        assert!(MAIN_START + MAIN.len() <= TILE_START);
+    }
+
+    #[test]
+    fn draws_checkerboard() {
+        let mut emu = Emu::new(rom(), None);
+        while emu.bus.clock < CYCLES_PER_FRAME * 4 {
+            emu.emulate_step();
+        }
+        let front = emu.bus.gpu.screen();
+        let (empty, border, inside) = (front[0][0], front[0][8], front[1][9]);
+        assert_ne!(empty, border);
+        assert_ne!(border, inside);
+        assert_ne!(empty, inside);
+        // The next row of tiles is flipped.
+        assert_eq!(front[8][0], border);
+        assert_eq!(front[8][8], empty);
+    }
+}
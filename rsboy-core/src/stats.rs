@@ -0,0 +1,294 @@
+use crate::cpu::{interrupt_name, INTERRUPTS};
+use crate::instructions::{Instr, INSTR_TABLE};
+use std::fmt::Display;
+
+const CB_OPS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const CB_TARGETS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+
+pub fn cb_name(opcode: u8) -> String {
+    let target = CB_TARGETS[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_OPS[bit as usize], target),
+        1 => format!("BIT {},{}", bit, target),
+        2 => format!("RES {},{}", bit, target),
+        _ => format!("SET {},{}", bit, target),
+    }
+}
+
+// Which table an opcode came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Table {
+    Base,
+    Cb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodeCount {
+    pub table: Table,
+    pub opcode: u8,
+    pub count: u64,
+}
+
+impl Display for OpcodeCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.table {
+            Table::Base => write!(
+                f,
+                "   {:02x} {:<32} {:>12}",
+                self.opcode,
+                format!("{:?}", Instr::from(self.opcode)),
+                self.count
+            ),
+            Table::Cb => write!(
+                f,
+                "cb {:02x} {:<32} {:>12}",
+                self.opcode,
+                cb_name(self.opcode),
+                self.count
+            ),
+        }
+    }
+}
+
+// Number of times each opcode was dispatched, enabled with Bus::opcode_stats.
+#[derive(Clone)]
+pub struct OpcodeStats {
+    pub base: [u64; 256],
+    pub cb: [u64; 256],
+}
+
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self {
+            base: [0; 256],
+            cb: [0; 256],
+        }
+    }
+}
+
+impl OpcodeStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record(&mut self, opcode: u8) {
+        self.base[opcode as usize] += 1;
+    }
+
+    pub fn record_cb(&mut self, opcode: u8) {
+        self.cb[opcode as usize] += 1;
+    }
+
+    // Every opcode that ran at least once, most frequent first.
+    pub fn sorted(&self) -> Vec<OpcodeCount> {
+        let base = (0..=255u8).map(|op| (Table::Base, op, self.base[op as usize]));
+        let cb = (0..=255u8).map(|op| (Table::Cb, op, self.cb[op as usize]));
+        let mut counts: Vec<_> = base
+            .chain(cb)
+            .filter(|(_, _, count)| *count > 0)
+            .map(|(table, opcode, count)| OpcodeCount {
+                table,
+                opcode,
+                count,
+            })
+            .collect();
+        counts.sort_by_key(|c| std::cmp::Reverse(c.count));
+        counts
+    }
+
+    // (executed, implemented) for the base table, illegal opcodes are not counted.
+    pub fn base_coverage(&self) -> (usize, usize) {
+        let implemented = (0..256).filter(|&op| INSTR_TABLE[op] != Instr::UNIMPLEMENTED);
+        implemented.fold((0, 0), |(executed, total), op| {
+            (executed + (self.base[op] > 0) as usize, total + 1)
+        })
+    }
+
+    pub fn cb_coverage(&self) -> (usize, usize) {
+        (self.cb.iter().filter(|&&count| count > 0).count(), 256)
+    }
+
+    // Implemented opcodes that never ran, for finding gaps in test ROM coverage.
+    pub fn missing(&self) -> impl Iterator<Item = String> + '_ {
+        let base = (0..=255u8)
+            .filter(move |&op| INSTR_TABLE[op as usize] != Instr::UNIMPLEMENTED)
+            .filter(move |&op| self.base[op as usize] == 0)
+            .map(|op| format!("{:02x}", op));
+        let cb = (0..=255u8)
+            .filter(move |&op| self.cb[op as usize] == 0)
+            .map(|op| format!("cb {:02x}", op));
+        base.chain(cb)
+    }
+}
+
+impl Display for OpcodeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (base, base_total) = self.base_coverage();
+        let (cb, cb_total) = self.cb_coverage();
+        writeln!(
+            f,
+            "Coverage: base {}/{}, cb {}/{}",
+            base, base_total, cb, cb_total
+        )?;
+        for count in self.sorted() {
+            writeln!(f, "{}", count)?;
+        }
+        let missing: Vec<_> = self.missing().collect();
+        if !missing.is_empty() {
+            writeln!(f, "Never executed: {}", missing.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+// Counters the core always keeps in Bus::stats, for dashboards and long batch runs. Cycles and
+// frames come from the bus and GPU, Emu::stats fills them in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub instructions: u64,
+    // CPU machine cycles.
+    pub cycles: u64,
+    pub frames: u64,
+    // Dispatched interrupts, indexed like cpu::INTERRUPTS.
+    pub interrupts: [u64; 5],
+    pub oam_dma: u64,
+    pub hdma_blocks: u64,
+}
+
+impl Stats {
+    // Instructions per machine cycle.
+    pub fn ipc(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.instructions as f64 / self.cycles as f64
+    }
+
+    // Sums the counters of several runs, like the ROMs of a batch.
+    pub fn add(&mut self, other: &Stats) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+        self.frames += other.frames;
+        for (total, count) in self.interrupts.iter_mut().zip(other.interrupts.iter()) {
+            *total += count;
+        }
+        self.oam_dma += other.oam_dma;
+        self.hdma_blocks += other.hdma_blocks;
+    }
+
+    // Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            out += &format!(
+                "# HELP rsboy_{} {}\n# TYPE rsboy_{} {}\n",
+                name, help, name, kind
+            );
+            for (labels, value) in samples {
+                out += &format!("rsboy_{}{} {}\n", name, labels, value);
+            }
+        };
+        let total = |value: u64| vec![(String::new(), value.to_string())];
+        metric(
+            "instructions_total",
+            "counter",
+            "Instructions executed.",
+            total(self.instructions),
+        );
+        metric(
+            "cycles_total",
+            "counter",
+            "CPU machine cycles.",
+            total(self.cycles),
+        );
+        metric(
+            "frames_total",
+            "counter",
+            "Frames completed.",
+            total(self.frames),
+        );
+        let interrupts = INTERRUPTS
+            .iter()
+            .zip(self.interrupts.iter())
+            .map(|(&flag, count)| {
+                let labels = format!("{{type=\"{}\"}}", interrupt_name(flag).to_lowercase());
+                (labels, count.to_string())
+            })
+            .collect();
+        metric(
+            "interrupts_total",
+            "counter",
+            "Interrupts dispatched.",
+            interrupts,
+        );
+        metric(
+            "oam_dma_total",
+            "counter",
+            "OAM DMA transfers.",
+            total(self.oam_dma),
+        );
+        metric(
+            "hdma_blocks_total",
+            "counter",
+            "HDMA blocks copied.",
+            total(self.hdma_blocks),
+        );
+        let ipc = vec![(String::new(), format!("{:.4}", self.ipc()))];
+        metric("ipc", "gauge", "Instructions per machine cycle.", ipc);
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_cb_opcodes() {
+        assert_eq!(cb_name(0x37), "SWAP A");
+        assert_eq!(cb_name(0x7E), "BIT 7,(HL)");
+        assert_eq!(cb_name(0x80), "RES 0,B");
+        assert_eq!(cb_name(0xFF), "SET 7,A");
+    }
+
+    #[test]
+    fn counts_and_coverage() {
+        let mut stats = OpcodeStats::new();
+        stats.record(0x00);
+        stats.record(0x00);
+        stats.record(0xCB);
+        stats.record_cb(0x37);
+        let sorted = stats.sorted();
+        assert_eq!(sorted.len(), 3);
+        assert_eq!(
+            (sorted[0].table, sorted[0].opcode, sorted[0].count),
+            (Table::Base, 0, 2)
+        );
+        assert_eq!(stats.base_coverage().0, 2);
+        assert_eq!(stats.cb_coverage(), (1, 256));
+        assert!(stats.missing().any(|op| op == "01"));
+        assert!(!stats.missing().any(|op| op == "cb 37"));
+    }
+
+    #[test]
+    fn counts_a_run() {
+        // A ROM of NOPs, with the LCD on.
+        let mut emu = crate::emu::Emu::new(vec![0; 0x8000], None);
+        emu.bus.gpu.lcdc = 0x91;
+        emu.run_frame();
+        emu.run_frame();
+        let stats = emu.stats();
+        assert_eq!(stats.frames, 2);
+        assert!(stats.instructions > 0 && stats.cycles >= stats.instructions);
+        assert!(stats.ipc() > 0.0 && stats.ipc() <= 1.0);
+        let mut total = stats.clone();
+        total.add(&stats);
+        assert_eq!(total.instructions, stats.instructions * 2);
+
+        let text = stats.prometheus();
+        assert!(text.contains("# TYPE rsboy_instructions_total counter\n"));
+        assert!(text.contains(&format!("rsboy_frames_total {}\n", stats.frames)));
+        assert!(text.contains("rsboy_interrupts_total{type=\"vblank\"} 0\n"));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::gpu::ShadeMap;
+use crate::video::palette::Palette;
+use std::ops::Range;
+
+// 2bpp tile decoding shared by the GPU, the tile and sprite viewers and the printer.
+pub struct Tile {
+    pub texture: [[u32; 8]; 8],
+}
+
+impl Tile {
+    // Colors of a tile, each color index mapped through the BGP/OBP `register` and then `palette`.
+    pub fn construct(palette: Palette, register: u8, tile_data: &[u8]) -> Self {
+        Self::decode(palette, register, tile_data, false)
+    }
+
+    // Like construct, with color index 0 transparent.
+    pub fn sprite_construct(palette: Palette, register: u8, tile_data: &[u8]) -> Self {
+        Self::decode(palette, register, tile_data, true)
+    }
+
+    fn decode(palette: Palette, register: u8, tile_data: &[u8], transparent: bool) -> Self {
+        let mut texture = [[0; 8]; 8];
+        // Each row is a low byte then a high byte.
+        for (row, pixels) in tile_data.chunks_exact(2).zip(&mut texture) {
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                let index = Self::pixel_index(row[0], row[1], x);
+                let color = palette.color(Self::shade(register, index));
+                *pixel = if transparent && index == 0 {
+                    color & 0xFFFFFF00
+                } else {
+                    color
+                };
+            }
+        }
+        Self { texture }
+    }
+
+    // Writes the shades (colors after `register`) of a map tile.
+    pub fn write_shades(
+        register: u8,
+        pixels: &mut ShadeMap,
+        location: (usize, usize),
+        tile_data: &[u8],
+    ) {
+        let (mapx, mapy) = location;
+        for (row, bytes) in tile_data.chunks_exact(2).enumerate() {
+            let pixels = &mut pixels[mapy * 8 + row][mapx * 8..mapx * 8 + 8];
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = Self::shade(register, Self::pixel_index(bytes[0], bytes[1], x));
+            }
+        }
+    }
+
+    // Shade (0 lightest to 3 darkest) a BGP/OBP `register` gives color index `index`.
+    pub fn shade(register: u8, index: u8) -> u8 {
+        (register >> (index * 2)) & 0b11
+    }
+
+    // Size of a tile
+    pub fn range(i: usize) -> Range<usize> {
+        i..i + 16
+    }
+
+    pub fn texture(&self) -> &[[u32; 8]; 8] {
+        &self.texture
+    }
+
+    // Color index (0-3) of pixel `x` in a tile row given as its low and high byte.
+    pub fn pixel_index(lo: u8, hi: u8, x: usize) -> u8 {
+        let bit = 7 - x;
+        ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1)
+    }
+
+    // The row bytes with pixel `x` set to color index `index`.
+    pub fn with_pixel_index(lo: u8, hi: u8, x: usize, index: u8) -> (u8, u8) {
+        let mask = 0x80 >> x;
+        let set = |byte: u8, on: bool| if on { byte | mask } else { byte & !mask };
+        (set(lo, index & 1 != 0), set(hi, index & 2 != 0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meminit::MemFill;
+
+    // Color index straight from the bit planes, what every decode path has to agree with.
+    fn reference_index(tile: &[u8], x: usize, y: usize) -> u8 {
+        let bit = |byte: u8| (byte >> (7 - x)) & 1;
+        bit(tile[y * 2 + 1]) * 2 + bit(tile[y * 2])
+    }
+
+    #[test]
+    fn decode_paths_agree_on_random_tiles() {
+        for seed in 0..256 {
+            // The tile then the palette register.
+            let mut random = [0; 17];
+            MemFill::Random(0x1234_5678 + seed).fill(&mut random);
+            let (data, register) = (&random[..16], random[16]);
+            let mut shades = Box::new([[0; 256]; 256]);
+            Tile::write_shades(register, &mut shades, (3, 2), data);
+            let tile = Tile::construct(Palette::GREEN, register, data);
+            let sprite = Tile::sprite_construct(Palette::GREEN, register, data);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let index = reference_index(data, x, y);
+                    let shade = (register >> (index * 2)) & 0b11;
+                    let color = Palette::GREEN.color(shade);
+                    assert_eq!(Tile::pixel_index(data[y * 2], data[y * 2 + 1], x), index);
+                    assert_eq!(shades[16 + y][24 + x], shade);
+                    assert_eq!(tile.texture[y][x], color);
+                    let alpha = if index == 0 { 0 } else { color & 0xFF };
+                    assert_eq!(sprite.texture[y][x], color & 0xFFFFFF00 | alpha);
+                    let (lo, hi) =
+                        Tile::with_pixel_index(data[y * 2], data[y * 2 + 1], x, 3 - index);
+                    assert_eq!(Tile::pixel_index(lo, hi, x), 3 - index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_index_round_trip() {
+        let (lo, hi) = (0b1010_0000, 0b1100_0000);
+        assert_eq!(Tile::pixel_index(lo, hi, 0), 3);
+        assert_eq!(Tile::pixel_index(lo, hi, 1), 2);
+        assert_eq!(Tile::pixel_index(lo, hi, 2), 1);
+        assert_eq!(Tile::pixel_index(lo, hi, 3), 0);
+        for index in 0..4 {
+            let (lo2, hi2) = Tile::with_pixel_index(lo, hi, 7, index);
+            assert_eq!(Tile::pixel_index(lo2, hi2, 7), index);
+            assert_eq!(Tile::pixel_index(lo2, hi2, 0), 3);
+        }
+    }
+
+    #[test]
+    fn colors_and_shades_agree() {
+        // Rows of color indexes 0, 1, 2 and 3, twice over.
+        let data: Vec<u8> = (0..16)
+            .map(|i| {
+                if ((i / 2) % 4) & (1 + i % 2) != 0 {
+                    0xFF
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let register = 0b0001_1011;
+        let mut shades = Box::new([[0; 256]; 256]);
+        Tile::write_shades(register, &mut shades, (1, 0), &data);
+        let tile = Tile::construct(Palette::GRAY, register, &data);
+        let sprite = Tile::sprite_construct(Palette::GRAY, register, &data);
+        for row in 0..8 {
+            let shade = shades[row][8];
+            assert_eq!(shade, 3 - row as u8 % 4);
+            assert_eq!(tile.texture[row][0], Palette::GRAY.color(shade));
+            assert_eq!(sprite.texture[row][0] & 0xFF == 0, row % 4 == 0);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::bus::Bus;
+use crate::clock::{self, Cycles};
+use crate::constants::{MaybeErr, CYCLES_PER_FRAME};
+use crate::emu::Emu;
+use crate::rtc::RtcMode;
+use crate::savestate;
+use std::collections::VecDeque;
+
+// A savestate every second, a minute of them.
+pub const DEFAULT_INTERVAL: Cycles = CYCLES_PER_FRAME * 60;
+pub const DEFAULT_CAPACITY: usize = 60;
+
+// Savestates taken every `interval` cycles along with every joypad change in between, so any
+// cycle since the oldest one can be reached again by loading the one before it and replaying.
+// Input that differs from the recording while replaying rewrites the history from there on.
+#[derive(Debug)]
+pub struct Timeline {
+    interval: Cycles,
+    capacity: usize,
+    checkpoints: VecDeque<(Cycles, Vec<u8>)>,
+    // Joypad directions and buttons after each change.
+    inputs: VecDeque<(Cycles, u8, u8)>,
+    // Lines as last recorded or replayed.
+    lines: (u8, u8),
+    // Bus clock of the previous observe, inputs after it and up to now are due.
+    last: Cycles,
+    // Furthest bus clock recorded, before it input is replayed.
+    end: Cycles,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_CAPACITY)
+    }
+}
+
+impl Timeline {
+    pub fn new(interval: Cycles, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            checkpoints: VecDeque::new(),
+            inputs: VecDeque::new(),
+            lines: (0x0F, 0x0F),
+            last: 0,
+            end: 0,
+        }
+    }
+
+    // Bus clocks that can be sought to.
+    pub fn start(&self) -> Option<Cycles> {
+        self.checkpoints.front().map(|&(at, _)| at)
+    }
+
+    pub fn end(&self) -> Cycles {
+        self.end
+    }
+
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    // Called before every step: replays recorded input or records the live joypad.
+    fn observe(&mut self, bus: &mut Bus) {
+        let clock = bus.clock;
+        let live = (bus.joypad.directions, bus.joypad.buttons);
+        if clock < self.end && live != self.lines {
+            self.truncate(clock);
+        }
+        if clock < self.end {
+            let (last, now) = (self.last, clock);
+            let mut due = self
+                .inputs
+                .iter()
+                .filter(|&&(at, ..)| at > last && at <= now);
+            if let Some(&(_, directions, buttons)) = due.next_back() {
+                bus.joypad
+                    .set_lines(directions, buttons, clock, &mut bus.int_flags);
+                self.lines = (directions, buttons);
+            }
+        } else {
+            if live != self.lines {
+                self.inputs.push_back((clock, live.0, live.1));
+                self.lines = live;
+            }
+            self.end = clock;
+        }
+        self.last = clock;
+    }
+
+    fn checkpoint_due(&self, clock: Cycles) -> bool {
+        match self.checkpoints.back() {
+            Some(&(at, _)) => clock >= self.end && clock >= clock::deadline(at, self.interval),
+            None => true,
+        }
+    }
+
+    fn checkpoint(&mut self, clock: Cycles, state: Vec<u8>) {
+        self.checkpoints.push_back((clock, state));
+        if self.checkpoints.len() > self.capacity {
+            self.checkpoints.pop_front();
+            let start = self.start().unwrap_or(0);
+            while matches!(self.inputs.front(), Some(&(at, ..)) if at < start) {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    // Forgets everything recorded after `clock`.
+    fn truncate(&mut self, clock: Cycles) {
+        self.checkpoints.retain(|&(at, _)| at <= clock);
+        self.inputs.retain(|&(at, ..)| at <= clock);
+        self.end = clock;
+    }
+}
+
+impl Emu {
+    // Keeps the timeline, if there is one, up to date. Called by emulate_step.
+    pub(crate) fn record_timeline(&mut self) {
+        let timeline = match &mut self.timeline {
+            Some(timeline) => timeline,
+            None => return,
+        };
+        timeline.observe(&mut self.bus);
+        if timeline.checkpoint_due(self.bus.clock) {
+            let state = savestate::save(self);
+            if let Some(timeline) = &mut self.timeline {
+                timeline.checkpoint(self.bus.clock, state);
+            }
+        }
+    }
+
+    // Runs to the first instruction boundary at or after `target`. Going back loads the last
+    // savestate before it and replays the recorded input, so the result is the same as the first
+    // time through. Returns the bus clock reached.
+    pub fn seek_to_cycle(&mut self, target: Cycles) -> MaybeErr<Cycles> {
+        let timeline = match &mut self.timeline {
+            Some(timeline) => timeline,
+            None => return Err("No timeline is being recorded".into()),
+        };
+        if target < self.bus.clock {
+            let state = match timeline
+                .checkpoints
+                .iter()
+                .rev()
+                .find(|&&(at, _)| at <= target)
+            {
+                Some((at, state)) => {
+                    timeline.last = *at;
+                    state.clone()
+                }
+                None => {
+                    return Err(format!("Cycle {} is before the timeline starts", target).into())
+                }
+            };
+            // Replays must not depend on when they happen.
+            let rtc_mode = std::mem::replace(&mut self.rtc_mode, RtcMode::Frozen);
+            let loaded = savestate::load(self, &state);
+            self.rtc_mode = rtc_mode;
+            loaded?;
+            if let Some(timeline) = &mut self.timeline {
+                timeline.lines = (self.bus.joypad.directions, self.bus.joypad.buttons);
+            }
+        }
+        // Skipping idle time could overshoot.
+        let idle_skip = std::mem::replace(&mut self.idle_skip, false);
+        while self.bus.clock < target {
+            self.emulate_step();
+        }
+        self.idle_skip = idle_skip;
+        Ok(self.bus.clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::FRAME_CYCLES;
+    use crate::input::Button;
+
+    fn emu() -> Emu {
+        // A ROM of NOPs, with the LCD on.
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        emu.bus.gpu.lcdc = 0x91;
+        emu
+    }
+
+    fn run_to(emu: &mut Emu, clock: Cycles) {
+        while emu.bus.clock < clock {
+            emu.emulate_step();
+        }
+    }
+
+    #[test]
+    fn seeks_back_and_forth_with_the_same_input() {
+        let mut recording = emu();
+        recording.timeline = Some(Timeline::new(FRAME_CYCLES, 8));
+        run_to(&mut recording, 2 * FRAME_CYCLES);
+        let pressed = recording.bus.clock;
+        Button::Start.press(&mut recording.bus);
+        run_to(&mut recording, 4 * FRAME_CYCLES);
+        let end = recording.bus.clock;
+        let recorded = recording.timeline.as_ref().unwrap().end();
+
+        // The same run without a timeline.
+        let mut reference = emu();
+        run_to(&mut reference, pressed);
+        Button::Start.press(&mut reference.bus);
+        let target = 3 * FRAME_CYCLES + 100;
+        run_to(&mut reference, target);
+
+        assert_eq!(
+            recording.seek_to_cycle(target).unwrap(),
+            reference.bus.clock
+        );
+        assert_eq!(savestate::save(&recording), savestate::save(&reference));
+        assert!(recording.bus.joypad.is_pressed(Button::Start));
+
+        // Before the press and back to the end, where it is replayed.
+        recording.seek_to_cycle(FRAME_CYCLES).unwrap();
+        assert!(!recording.bus.joypad.is_pressed(Button::Start));
+        recording.seek_to_cycle(end).unwrap();
+        assert!(recording.bus.joypad.is_pressed(Button::Start));
+        assert_eq!(recording.timeline.as_ref().unwrap().end(), recorded);
+    }
+
+    #[test]
+    fn new_input_in_the_past_rewrites_history() {
+        let mut emu = emu();
+        emu.timeline = Some(Timeline::new(FRAME_CYCLES, 8));
+        run_to(&mut emu, 4 * FRAME_CYCLES);
+        emu.seek_to_cycle(FRAME_CYCLES + 10).unwrap();
+        Button::A.press(&mut emu.bus);
+        emu.emulate_step();
+        let timeline = emu.timeline.as_ref().unwrap();
+        assert!(timeline.end() < 2 * FRAME_CYCLES);
+        assert_eq!(timeline.checkpoints(), 2);
+        assert!(emu.bus.joypad.is_pressed(Button::A));
+    }
+
+    #[test]
+    fn keeps_a_bounded_window() {
+        let mut emu = emu();
+        emu.timeline = Some(Timeline::new(FRAME_CYCLES, 3));
+        assert!(emu.seek_to_cycle(0).is_ok());
+        run_to(&mut emu, 6 * FRAME_CYCLES);
+        let timeline = emu.timeline.as_ref().unwrap();
+        assert_eq!(timeline.checkpoints(), 3);
+        let start = timeline.start().unwrap();
+        assert!(start >= 3 * FRAME_CYCLES);
+        assert!(emu.seek_to_cycle(start - 1).is_err());
+        assert!(emu.seek_to_cycle(start).is_ok());
+
+        let mut untimed = Emu::new(vec![], None);
+        assert!(untimed.seek_to_cycle(0).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Display;
+
+use crate::clock::Cycles;
+use crate::cpu;
+use crate::io::{IoContext, IoDevice};
+
+pub const DIV: usize = 0xFF04;
+pub const TIMA: usize = 0xFF05;
+pub const TMA: usize = 0xFF06;
+pub const TAC: usize = 0xFF07;
+
+#[derive(Default, Clone, Debug)]
+pub struct Timer {
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    pub clock: Cycles,
+    pub internal: u16,
+}
+
+// Register view of the timer for the debugger and savestates.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct TimerSnapshot {
+    pub div: u8,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+    pub internal: u16,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            clock: 0,
+            internal: 0,
+        }
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.internal >> 8) as u8
+    }
+
+    pub fn snapshot(&self) -> TimerSnapshot {
+        TimerSnapshot {
+            div: self.div(),
+            tima: self.tima,
+            tma: self.tma,
+            tac: self.tac,
+            internal: self.internal,
+        }
+    }
+
+    // DIV is derived from the internal counter, so it is not restored separately.
+    pub fn restore(&mut self, snapshot: &TimerSnapshot) {
+        self.tima = snapshot.tima;
+        self.tma = snapshot.tma;
+        self.tac = snapshot.tac;
+        self.internal = snapshot.internal;
+    }
+
+    pub fn update_internal(&mut self, flags: &mut u8, new: u16) {
+        //Falling edge detector
+        let control = self.tac;
+        let clock_select = control & 0b11;
+
+        let mask = match clock_select {
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            0b11 => 1 << 7,
+            _ => unreachable!(),
+        };
+
+        let was_one = self.internal & mask != 0;
+        self.internal = new;
+        let now_zero = self.internal & mask == 0;
+        let enable = (control & 0b100) != 0;
+        if enable && was_one && now_zero {
+            let (value, overflow) = self.tima.overflowing_add(1);
+            if overflow {
+                *flags |= cpu::TIMER;
+                self.tima = self.tma;
+            } else {
+                self.tima = value;
+            }
+        }
+    }
+
+    // Cycles until TIMA next increments, None while the timer is stopped.
+    pub fn cycles_to_tick(&self) -> Option<usize> {
+        if self.tac & 0b100 == 0 {
+            return None;
+        }
+        let period: usize = match self.tac & 0b11 {
+            0b00 => 1 << 10,
+            0b01 => 1 << 4,
+            0b10 => 1 << 6,
+            _ => 1 << 8,
+        };
+        Some(period - self.internal as usize % period)
+    }
+
+    // Advances the counters without an increment of TIMA, see Bus::skip_idle.
+    pub fn skip(&mut self, n: usize) {
+        self.clock += n as Cycles;
+        self.internal = self.internal.wrapping_add(n as u16);
+    }
+
+    pub fn tick_timer_counter(&mut self, flags: &mut u8) {
+        self.clock += 1;
+        self.update_internal(flags, self.internal.wrapping_add(1));
+    }
+}
+
+impl Display for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.snapshot().fmt(f)
+    }
+}
+
+impl Display for TimerSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "DIV:{:02x}\nTIMA:{:02x}\nTMA:{:02x}\nTAC:{:08b}\n{:016b}",
+            self.div, self.tima, self.tma, self.tac, self.internal,
+        ))
+    }
+}
+
+impl IoDevice for Timer {
+    fn io_read(&self, address: u16) -> Option<u8> {
+        match address as usize {
+            DIV => Some(self.div()),
+            TIMA => Some(self.tima),
+            TMA => Some(self.tma),
+            TAC => Some(self.tac),
+            _ => None,
+        }
+    }
+
+    fn io_write(&mut self, address: u16, value: u8, ctx: &mut IoContext) -> bool {
+        match address as usize {
+            // Any write clears the whole internal counter.
+            DIV => self.update_internal(ctx.int_flags, 0),
+            TIMA => self.tima = value,
+            TMA => self.tma = value,
+            TAC => self.tac = 0b1111_1000 | value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut timer = Timer::new();
+        timer.tac = 0b101;
+        timer.tma = 0x80;
+        let mut flags = 0;
+        for _ in 0..0x1234 {
+            timer.tick_timer_counter(&mut flags);
+        }
+        let snapshot = timer.snapshot();
+        assert_eq!(snapshot.div, 0x12);
+        assert_eq!(snapshot.internal, 0x1234);
+
+        let mut restored = Timer::new();
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::clock::Cycles;
+use crate::constants::GB_CYCLE_SPEED;
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+// Stop recording past this many events, chrome://tracing and Perfetto struggle with more.
+pub const MAX_EVENTS: usize = 2_000_000;
+
+// Thread ids used to lay subsystems out on separate tracks.
+pub const CPU_TRACK: u32 = 1;
+pub const PPU_TRACK: u32 = 2;
+pub const DMA_TRACK: u32 = 3;
+
+pub struct TraceEvent {
+    pub name: String,
+    pub category: &'static str,
+    pub track: u32,
+    pub start: Cycles,
+    pub end: Cycles,
+    pub pc: Option<u16>,
+}
+
+// Records complete ("X") events in the Chrome trace-event format.
+// Timestamps are emulated cycles, converted to microseconds when written out.
+#[derive(Default)]
+pub struct Tracer {
+    pub events: Vec<TraceEvent>,
+    ppu_mode: Option<(&'static str, Cycles)>,
+}
+
+fn micros(clock: Cycles) -> f64 {
+    clock as f64 * 1_000_000.0 / GB_CYCLE_SPEED as f64
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn span(
+        &mut self,
+        track: u32,
+        category: &'static str,
+        name: String,
+        start: Cycles,
+        end: Cycles,
+        pc: Option<u16>,
+    ) {
+        if self.events.len() < MAX_EVENTS {
+            self.events.push(TraceEvent {
+                name,
+                category,
+                track,
+                start,
+                end,
+                pc,
+            });
+        }
+    }
+
+    // Called every cycle with the current PPU mode, closes the previous mode's span on a change.
+    pub fn ppu_mode(&mut self, mode: &'static str, clock: Cycles) {
+        match self.ppu_mode {
+            Some((current, _)) if current == mode => {}
+            Some((current, start)) => {
+                self.span(PPU_TRACK, "ppu", current.to_string(), start, clock, None);
+                self.ppu_mode = Some((mode, clock));
+            }
+            None => self.ppu_mode = Some((mode, clock)),
+        }
+    }
+
+    pub fn write_chrome<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(out, "{{\"traceEvents\":[")?;
+        let names = [(CPU_TRACK, "CPU"), (PPU_TRACK, "PPU"), (DMA_TRACK, "DMA")];
+        for (tid, name) in names.iter() {
+            writeln!(
+                out,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}},",
+                tid, name
+            )?;
+        }
+        for (i, e) in self.events.iter().enumerate() {
+            write!(
+                out,
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}",
+                e.name.replace('"', "'"),
+                e.category,
+                e.track,
+                micros(e.start),
+                micros(e.end.saturating_sub(e.start)),
+            )?;
+            if let Some(pc) = e.pc {
+                write!(out, ",\"args\":{{\"pc\":\"{:04x}\"}}", pc)?;
+            }
+            let sep = if i + 1 == self.events.len() { "" } else { "," };
+            writeln!(out, "}}{}", sep)?;
+        }
+        writeln!(out, "]}}")
+    }
+
+    pub fn save_chrome(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_chrome(&mut out)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ppu_modes_become_spans() {
+        let mut tracer = Tracer::new();
+        tracer.ppu_mode("OAM", 0);
+        tracer.ppu_mode("OAM", 10);
+        tracer.ppu_mode("VRAM", 80);
+        tracer.ppu_mode("HBlank", 252);
+        assert_eq!(tracer.events.len(), 2);
+        assert_eq!(tracer.events[0].name, "OAM");
+        assert_eq!(tracer.events[0].end, 80);
+        assert_eq!(tracer.events[1].start, 80);
+    }
+
+    #[test]
+    fn writes_trace_events_json() {
+        let mut tracer = Tracer::new();
+        tracer.span(CPU_TRACK, "cpu", "NOOP".into(), 0, 4, Some(0x100));
+        let mut out = vec![];
+        tracer.write_chrome(&mut out).unwrap();
+        let json = String::from_utf8(out).unwrap();
+        assert!(json.starts_with("{\"traceEvents\":["));
+        assert!(json.contains("\"name\":\"NOOP\""));
+        assert!(json.contains("\"pc\":\"0100\""));
+        assert!(json.trim_end().ends_with("]}"));
+    }
+}
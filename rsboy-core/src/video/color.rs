@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+// How the screen's colors respond, applied to the palette when a frame is presented. The CGB and
+// GBA curves take colors through RGB555 first like on a CGB, so they see what the hardware would.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    // Colors as they are, oversaturated compared to a real screen.
+    Raw,
+    // The washed out CGB LCD, channels bleed into each other.
+    Cgb,
+    // The darker GBA screen, as when playing on a GBA.
+    Gba,
+}
+
+impl FromStr for Curve {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" | "none" => Ok(Curve::Raw),
+            "cgb" | "gbc" => Ok(Curve::Cgb),
+            "gba" => Ok(Curve::Gba),
+            _ => Err(format!(
+                "Unknown color correction {}, expected raw, cgb or gba",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorCorrection {
+    pub curve: Curve,
+    // Output gamma after the curve, 1.0 leaves it as is and higher brightens the midtones.
+    pub gamma: f32,
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self {
+            curve: Curve::Raw,
+            gamma: 1.0,
+        }
+    }
+}
+
+fn to_8bit(value: f32) -> u32 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u32
+}
+
+impl ColorCorrection {
+    // Corrects an RGBA color, alpha is kept.
+    pub fn apply(self, rgba: u32) -> u32 {
+        // Raw at gamma 1.0 is the default, keep the palette exact instead of rounding to RGB555.
+        if self.curve == Curve::Raw && self.gamma == 1.0 {
+            return rgba;
+        }
+        let [r, g, b, a] = rgba.to_be_bytes();
+        let (r, g, b) = ((r >> 3) as u32, (g >> 3) as u32, (b >> 3) as u32);
+        // Channels as 0.0-1.0.
+        let (r, g, b) = match self.curve {
+            Curve::Raw => (r as f32 / 31.0, g as f32 / 31.0, b as f32 / 31.0),
+            // See https://byuu.net/video/color-emulation
+            Curve::Cgb => {
+                let channel = |c: u32| (c.min(960) >> 2) as f32 / 255.0;
+                (
+                    channel(r * 26 + g * 4 + b * 2),
+                    channel(g * 24 + b * 8),
+                    channel(r * 6 + g * 4 + b * 22),
+                )
+            }
+            Curve::Gba => {
+                let lcd = |c: u32| (c as f32 / 31.0).powf(4.0);
+                let (r, g, b) = (lcd(r), lcd(g), lcd(b));
+                let out = |c: f32| (c / 255.0).powf(1.0 / 2.2) * (255.0 / 280.0);
+                (
+                    out(50.0 * g + 255.0 * r),
+                    out(30.0 * b + 230.0 * g + 10.0 * r),
+                    out(220.0 * b + 10.0 * g + 50.0 * r),
+                )
+            }
+        };
+        let gamma = |c: f32| to_8bit(c.powf(1.0 / self.gamma));
+        (gamma(r) << 24) | (gamma(g) << 16) | (gamma(b) << 8) | a as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::video::palette::Palette;
+
+    #[test]
+    fn curves() {
+        let raw = ColorCorrection::default();
+        assert_eq!(raw.apply(0xFFFFFF80), 0xFFFFFF80);
+        assert_eq!(raw.apply(0x000000FF), 0x000000FF);
+        let cgb = ColorCorrection {
+            curve: Curve::Cgb,
+            gamma: 1.0,
+        };
+        assert_eq!(cgb.apply(0xFFFFFFFF), 0xF0F0F0FF);
+        // Pure red picks up some blue.
+        let red = cgb.apply(0xFF0000FF).to_be_bytes();
+        assert!(red[0] > 150 && red[1] == 0 && red[2] > 0);
+        let bright = ColorCorrection {
+            curve: Curve::Raw,
+            gamma: 2.2,
+        };
+        assert!(bright.apply(0x808080FF) > raw.apply(0x808080FF));
+        assert_eq!("gbc".parse::<Curve>(), Ok(Curve::Cgb));
+    }
+
+    #[test]
+    fn default_keeps_palettes_exact() {
+        let raw = ColorCorrection::default();
+        for palette in &[Palette::GREEN, Palette::GRAY] {
+            for &color in palette.0.iter() {
+                assert_eq!(raw.apply(color), color);
+            }
+        }
+    }
+}
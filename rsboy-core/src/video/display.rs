@@ -0,0 +1,61 @@
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Destination of the screen inside a window, in window pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    // Largest 10:9 area that fits in the window, centered with black bars on the other axis.
+    pub fn letterbox(window_width: u32, window_height: u32) -> Self {
+        let (sw, sh) = (SCREEN_WIDTH as u64, SCREEN_HEIGHT as u64);
+        let (ww, wh) = (window_width as u64, window_height as u64);
+        let (width, height) = if ww * sh <= wh * sw {
+            (ww, ww * sh / sw)
+        } else {
+            (wh * sw / sh, wh)
+        };
+        Self {
+            x: ((ww - width) / 2) as i32,
+            y: ((wh - height) / 2) as i32,
+            width: width.max(1) as u32,
+            height: height.max(1) as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exact_fit() {
+        assert_eq!(
+            Viewport::letterbox(480, 432),
+            Viewport {
+                x: 0,
+                y: 0,
+                width: 480,
+                height: 432
+            }
+        );
+    }
+
+    #[test]
+    fn tall_window_bars_top_and_bottom() {
+        let viewport = Viewport::letterbox(320, 600);
+        assert_eq!((viewport.width, viewport.height), (320, 288));
+        assert_eq!((viewport.x, viewport.y), (0, 156));
+    }
+
+    #[test]
+    fn wide_window_bars_left_and_right() {
+        let viewport = Viewport::letterbox(1000, 288);
+        assert_eq!((viewport.width, viewport.height), (320, 288));
+        assert_eq!((viewport.x, viewport.y), (340, 0));
+    }
+}
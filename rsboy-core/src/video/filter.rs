@@ -0,0 +1,337 @@
+use crate::gpu::{PixelData, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::str::FromStr;
+
+// Scaling filters run on the CPU before the frame is uploaded to the SDL texture.
+// `src` holds the screen (see GPU::screen),
+// `dst` receives RGBA bytes, (SCREEN_WIDTH * factor) pixels per row.
+pub trait Filter {
+    // Largest factor the filter implements, lower factors down to 1 also work.
+    fn max_factor(&self) -> usize;
+    fn scale(&self, src: &PixelData, dst: &mut [u8], factor: usize);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    Nearest,
+    Scale2x,
+    Scale3x,
+    Epx,
+}
+
+impl FilterKind {
+    pub fn filter(self) -> Box<dyn Filter> {
+        match self {
+            FilterKind::Nearest => Box::new(Nearest),
+            FilterKind::Scale2x => Box::new(Scale2x),
+            FilterKind::Scale3x => Box::new(Scale3x),
+            FilterKind::Epx => Box::new(Epx),
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" | "nearest" => Ok(FilterKind::Nearest),
+            "scale2x" => Ok(FilterKind::Scale2x),
+            "scale3x" => Ok(FilterKind::Scale3x),
+            "epx" => Ok(FilterKind::Epx),
+            _ => Err(format!(
+                "Unknown filter {}, expected none, scale2x, scale3x or epx",
+                s
+            )),
+        }
+    }
+}
+
+// Neighbourhood of a pixel, clamped at the screen edges.
+//   a b c
+//   d e f
+//   g h i
+struct Block {
+    a: u32,
+    b: u32,
+    c: u32,
+    d: u32,
+    e: u32,
+    f: u32,
+    g: u32,
+    h: u32,
+    i: u32,
+}
+
+impl Block {
+    fn at(src: &PixelData, x: usize, y: usize) -> Self {
+        let (l, r) = (x.saturating_sub(1), (x + 1).min(SCREEN_WIDTH - 1));
+        let (u, d) = (y.saturating_sub(1), (y + 1).min(SCREEN_HEIGHT - 1));
+        Block {
+            a: src[u][l],
+            b: src[u][x],
+            c: src[u][r],
+            d: src[y][l],
+            e: src[y][x],
+            f: src[y][r],
+            g: src[d][l],
+            h: src[d][x],
+            i: src[d][r],
+        }
+    }
+}
+
+fn put(dst: &mut [u8], factor: usize, x: usize, y: usize, pixel: u32) {
+    let i = (y * SCREEN_WIDTH * factor + x) * 4;
+    dst[i..i + 4].copy_from_slice(&pixel.to_be_bytes());
+}
+
+// Runs `expand` over every screen pixel, writing the factor x factor block it returns.
+fn expand_each<F>(src: &PixelData, dst: &mut [u8], factor: usize, expand: F)
+where
+    F: Fn(&Block) -> [u32; 9],
+{
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            let out = expand(&Block::at(src, x, y));
+            for dy in 0..factor {
+                for dx in 0..factor {
+                    put(
+                        dst,
+                        factor,
+                        x * factor + dx,
+                        y * factor + dy,
+                        out[dy * factor + dx],
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub struct Nearest;
+
+impl Filter for Nearest {
+    // SDL already stretches the texture with nearest neighbour, so only the fallback needs more.
+    fn max_factor(&self) -> usize {
+        1
+    }
+
+    fn scale(&self, src: &PixelData, dst: &mut [u8], factor: usize) {
+        for y in 0..SCREEN_HEIGHT * factor {
+            for x in 0..SCREEN_WIDTH * factor {
+                put(dst, factor, x, y, src[y / factor][x / factor]);
+            }
+        }
+    }
+}
+
+// AdvMAME Scale2x.
+pub struct Scale2x;
+
+impl Filter for Scale2x {
+    fn max_factor(&self) -> usize {
+        2
+    }
+
+    fn scale(&self, src: &PixelData, dst: &mut [u8], factor: usize) {
+        match factor {
+            2 => expand_each(src, dst, factor, |p| {
+                let mut out = [p.e; 9];
+                if p.b != p.h && p.d != p.f {
+                    if p.d == p.b {
+                        out[0] = p.d;
+                    }
+                    if p.b == p.f {
+                        out[1] = p.f;
+                    }
+                    if p.d == p.h {
+                        out[2] = p.d;
+                    }
+                    if p.h == p.f {
+                        out[3] = p.f;
+                    }
+                }
+                out
+            }),
+            _ => Nearest.scale(src, dst, factor),
+        }
+    }
+}
+
+// AdvMAME Scale3x, 3x only.
+pub struct Scale3x;
+
+impl Filter for Scale3x {
+    fn max_factor(&self) -> usize {
+        3
+    }
+
+    fn scale(&self, src: &PixelData, dst: &mut [u8], factor: usize) {
+        match factor {
+            3 => expand_each(src, dst, factor, |p| {
+                let mut out = [p.e; 9];
+                if p.b != p.h && p.d != p.f {
+                    let (db, bf, dh, hf) = (p.d == p.b, p.b == p.f, p.d == p.h, p.h == p.f);
+                    if db {
+                        out[0] = p.d;
+                    }
+                    if (db && p.e != p.c) || (bf && p.e != p.a) {
+                        out[1] = p.b;
+                    }
+                    if bf {
+                        out[2] = p.f;
+                    }
+                    if (db && p.e != p.g) || (dh && p.e != p.a) {
+                        out[3] = p.d;
+                    }
+                    if (bf && p.e != p.i) || (hf && p.e != p.c) {
+                        out[5] = p.f;
+                    }
+                    if dh {
+                        out[6] = p.d;
+                    }
+                    if (dh && p.e != p.i) || (hf && p.e != p.g) {
+                        out[7] = p.h;
+                    }
+                    if hf {
+                        out[8] = p.f;
+                    }
+                }
+                out
+            }),
+            _ => Nearest.scale(src, dst, factor),
+        }
+    }
+}
+
+// Eric's Pixel Expansion, 2x only.
+pub struct Epx;
+
+impl Filter for Epx {
+    fn max_factor(&self) -> usize {
+        2
+    }
+
+    fn scale(&self, src: &PixelData, dst: &mut [u8], factor: usize) {
+        if factor != 2 {
+            return Nearest.scale(src, dst, factor);
+        }
+        expand_each(src, dst, factor, |p| {
+            let (up, left, right, down) = (p.b, p.d, p.f, p.h);
+            let mut out = [p.e; 9];
+            let neighbours = [up, left, right, down];
+            // Three or more identical neighbours means a flat area or a single pixel line end.
+            if neighbours
+                .iter()
+                .any(|n| neighbours.iter().filter(|&m| m == n).count() >= 3)
+            {
+                return out;
+            }
+            if left == up {
+                out[0] = up;
+            }
+            if up == right {
+                out[1] = right;
+            }
+            if left == down {
+                out[2] = left;
+            }
+            if right == down {
+                out[3] = down;
+            }
+            out
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const W: u32 = 0xFFFFFFFF;
+    const K: u32 = 0x000000FF;
+
+    fn pixel(dst: &[u8], factor: usize, x: usize, y: usize) -> u32 {
+        let i = (y * SCREEN_WIDTH * factor + x) * 4;
+        u32::from_be_bytes([dst[i], dst[i + 1], dst[i + 2], dst[i + 3]])
+    }
+
+    fn run(filter: &dyn Filter, src: &PixelData, factor: usize) -> Vec<u8> {
+        let mut dst = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * factor * factor * 4];
+        filter.scale(src, &mut dst, factor);
+        dst
+    }
+
+    // A black diagonal step on white:
+    //   K W
+    //   K K
+    fn step() -> Box<PixelData> {
+        let mut src = Box::new([[W; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        src[10][10] = K;
+        src[11][10] = K;
+        src[11][11] = K;
+        src
+    }
+
+    #[test]
+    fn nearest_replicates() {
+        let src = step();
+        let dst = run(&Nearest, &src, 3);
+        for (dx, dy) in [(0, 0), (2, 2), (1, 2)].iter() {
+            assert_eq!(pixel(&dst, 3, 30 + dx, 30 + dy), K);
+        }
+        assert_eq!(pixel(&dst, 3, 33, 30), W);
+    }
+
+    #[test]
+    fn scale2x_rounds_corners() {
+        let src = step();
+        for filter in [&Scale2x as &dyn Filter, &Epx].iter() {
+            let dst = run(*filter, &src, 2);
+            // The white pixel at (11, 10) gets its bottom left corner filled in.
+            assert_eq!(pixel(&dst, 2, 22, 21), K);
+            assert_eq!(pixel(&dst, 2, 23, 20), W);
+            assert_eq!(pixel(&dst, 2, 22, 20), W);
+            assert_eq!(pixel(&dst, 2, 23, 21), W);
+        }
+    }
+
+    #[test]
+    fn scale3x_rounds_corners() {
+        let dst = run(&Scale3x, &step(), 3);
+        // Only the bottom left corner of the white pixel at (11, 10) is filled in.
+        assert_eq!(pixel(&dst, 3, 33, 32), K);
+        assert_eq!(pixel(&dst, 3, 34, 32), W);
+        assert_eq!(pixel(&dst, 3, 33, 31), W);
+        assert_eq!(pixel(&dst, 3, 35, 30), W);
+    }
+
+    #[test]
+    fn epx_keeps_three_identical_neighbours() {
+        // Up, left and right black with white below: only two equal pairs, but three identical.
+        let mut src = Box::new([[W; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        src[9][10] = K;
+        src[10][9] = K;
+        src[10][11] = K;
+        let dst = run(&Epx, &src, 2);
+        for &(x, y) in [(20, 20), (21, 20), (20, 21), (21, 21)].iter() {
+            assert_eq!(pixel(&dst, 2, x, y), W, "{} {}", x, y);
+        }
+    }
+
+    #[test]
+    fn flat_areas_unchanged() {
+        let src = Box::new([[W; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        for &(filter, factor) in [(&Scale2x as &dyn Filter, 2), (&Scale3x, 3), (&Epx, 2)].iter() {
+            let dst = run(filter, &src, factor);
+            assert!(dst.chunks(4).all(|p| p == &W.to_be_bytes()[..]));
+        }
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!("EPX".parse::<FilterKind>(), Ok(FilterKind::Epx));
+        assert_eq!("scale3x".parse::<FilterKind>(), Ok(FilterKind::Scale3x));
+        assert_eq!("none".parse::<FilterKind>(), Ok(FilterKind::Nearest));
+        assert!("hq9x".parse::<FilterKind>().is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod color;
+pub mod display;
+pub mod filter;
+pub mod overflow;
+pub mod overlay;
+pub mod palette;
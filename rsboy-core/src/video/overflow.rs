@@ -0,0 +1,45 @@
+use crate::gpu::{GPU, SCREEN_WIDTH, SPRITES_PER_LINE};
+use crate::video::overlay::Overlay;
+
+// Translucent red over lines that lost sprites to the per-line limit.
+pub const TINT: u32 = 0xFF000060;
+
+// Lines with more than SPRITES_PER_LINE sprites, and how many were dropped on each.
+pub fn dropped(gpu: &GPU) -> Vec<(usize, usize)> {
+    gpu.sprites_per_line()
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > SPRITES_PER_LINE)
+        .map(|(line, &count)| (line, count - SPRITES_PER_LINE))
+        .collect()
+}
+
+// Tints every line in `dropped` on the overlay, so flickering sprites can be traced to a line.
+pub fn mark(overlay: &mut Overlay, dropped: &[(usize, usize)]) {
+    for &(line, _) in dropped {
+        overlay.fill_rect(0, line as i32, SCREEN_WIDTH as i32, 1, TINT);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_and_tints_crowded_lines() {
+        let mut gpu = GPU::new();
+        // Twelve sprites on lines 4-11, a thirteenth only from line 8.
+        for i in 0..12 {
+            gpu.oam[i * 4..i * 4 + 2].copy_from_slice(&[20, 8]);
+        }
+        gpu.oam[48..50].copy_from_slice(&[24, 8]);
+        let lines = dropped(&gpu);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], (4, 2));
+        assert_eq!(lines[4], (8, 3));
+
+        let mut overlay = Overlay::new();
+        mark(&mut overlay, &lines);
+        assert_eq!(overlay.shapes().len(), 8);
+    }
+}
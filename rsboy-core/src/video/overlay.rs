@@ -0,0 +1,295 @@
+use crate::gpu::{PixelData, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Shapes drawn over the game output, in screen pixel coordinates. Colors are RGBA like
+// PixelData, an alpha below 0xFF blends with the game pixel underneath.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Rect {
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        color: u32,
+        filled: bool,
+    },
+    Line {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: u32,
+    },
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+        color: u32,
+    },
+}
+
+// Width of a character drawn by Overlay::text, including spacing.
+pub const GLYPH_ADVANCE: i32 = 4;
+pub const GLYPH_HEIGHT: i32 = 5;
+
+// Immediate mode: whoever draws does so every frame, the frontend clears it before a frame runs.
+pub struct Overlay {
+    pub enabled: bool,
+    shapes: Vec<Shape>,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay {
+            enabled: true,
+            shapes: vec![],
+        }
+    }
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    pub fn push(&mut self, shape: Shape) {
+        self.shapes.push(shape);
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u32) {
+        self.push(Shape::Rect {
+            x,
+            y,
+            w,
+            h,
+            color,
+            filled: false,
+        });
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, color: u32) {
+        self.push(Shape::Rect {
+            x,
+            y,
+            w,
+            h,
+            color,
+            filled: true,
+        });
+    }
+
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        self.push(Shape::Line {
+            x0,
+            y0,
+            x1,
+            y1,
+            color,
+        });
+    }
+
+    pub fn text(&mut self, x: i32, y: i32, text: &str, color: u32) {
+        self.push(Shape::Text {
+            x,
+            y,
+            text: text.to_string(),
+            color,
+        });
+    }
+
+    // Composites every shape onto a screen buffer (see GPU::screen).
+    pub fn draw(&self, screen: &mut PixelData) {
+        for shape in &self.shapes {
+            match *shape {
+                Shape::Rect {
+                    x,
+                    y,
+                    w,
+                    h,
+                    color,
+                    filled,
+                } => {
+                    for py in y..y + h {
+                        for px in x..x + w {
+                            let edge = py == y || py == y + h - 1 || px == x || px == x + w - 1;
+                            if filled || edge {
+                                plot(screen, px, py, color);
+                            }
+                        }
+                    }
+                }
+                Shape::Line {
+                    x0,
+                    y0,
+                    x1,
+                    y1,
+                    color,
+                } => line(screen, (x0, y0), (x1, y1), color),
+                Shape::Text {
+                    x,
+                    y,
+                    ref text,
+                    color,
+                } => {
+                    for (i, c) in text.chars().enumerate() {
+                        let gx = x + i as i32 * GLYPH_ADVANCE;
+                        for (row, bits) in glyph(c).iter().enumerate() {
+                            for col in 0..3 {
+                                if bits & (0b100 >> col) != 0 {
+                                    plot(screen, gx + col, y + row as i32, color);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn blend(under: u32, over: u32) -> u32 {
+    let alpha = over & 0xFF;
+    if alpha == 0xFF {
+        return over;
+    }
+    let channel = |shift: u32| {
+        let (a, b) = ((under >> shift) & 0xFF, (over >> shift) & 0xFF);
+        ((b * alpha + a * (0xFF - alpha)) / 0xFF) << shift
+    };
+    channel(24) | channel(16) | channel(8) | 0xFF
+}
+
+fn plot(screen: &mut PixelData, x: i32, y: i32, color: u32) {
+    if x < 0 || y < 0 || x >= SCREEN_WIDTH as i32 || y >= SCREEN_HEIGHT as i32 {
+        return;
+    }
+    let pixel = &mut screen[y as usize][x as usize];
+    *pixel = blend(*pixel, color);
+}
+
+// Bresenham
+fn line(screen: &mut PixelData, (mut x, mut y): (i32, i32), (x1, y1): (i32, i32), color: u32) {
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+    let mut err = dx + dy;
+    loop {
+        plot(screen, x, y, color);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// 3x5 font, one row of 3 bits per byte, most significant bit on the left.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010], // ?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RED: u32 = 0xFF0000FF;
+
+    fn screen() -> Box<PixelData> {
+        Box::new([[0x000000FF; SCREEN_WIDTH]; SCREEN_HEIGHT])
+    }
+
+    #[test]
+    fn rect_outline_and_clipping() {
+        let mut overlay = Overlay::new();
+        overlay.rect(2, 2, 4, 3, RED);
+        overlay.fill_rect(SCREEN_WIDTH as i32 - 1, -1, 5, 5, RED);
+        let mut screen = screen();
+        overlay.draw(&mut screen);
+        assert_eq!(screen[2][2], RED);
+        assert_eq!(screen[4][5], RED);
+        assert_eq!(screen[3][3], 0x000000FF);
+        assert_eq!(screen[0][SCREEN_WIDTH - 1], RED);
+        // Clipped at the screen edges, the rest of the rect is dropped.
+        assert_eq!(screen[0][SCREEN_WIDTH - 2], 0x000000FF);
+    }
+
+    #[test]
+    fn lines_reach_both_ends() {
+        let mut overlay = Overlay::new();
+        overlay.line(10, 10, 0, 3, RED);
+        let mut screen = screen();
+        overlay.draw(&mut screen);
+        assert_eq!(screen[10][10], RED);
+        assert_eq!(screen[3][0], RED);
+        let count: usize = screen.iter().flatten().filter(|&&p| p == RED).count();
+        assert_eq!(count, 11);
+    }
+
+    #[test]
+    fn text_and_blending() {
+        let mut overlay = Overlay::new();
+        overlay.text(0, 0, "1", RED);
+        overlay.fill_rect(20, 20, 1, 1, 0xFFFFFF80);
+        let mut screen = screen();
+        overlay.draw(&mut screen);
+        assert_eq!(screen[0][1], RED);
+        assert_eq!(screen[0][0], 0x000000FF);
+        assert_eq!(screen[4][0], RED);
+        assert_eq!(screen[20][20], 0x808080FF);
+    }
+}
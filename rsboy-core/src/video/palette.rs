@@ -0,0 +1,69 @@
+use crate::gpu::{PixelData, ShadeData};
+use crate::video::color::ColorCorrection;
+use std::str::FromStr;
+
+// Colors for the four DMG shades, lightest first, RGBA like PixelData. The GPU keeps the shades
+// of the last frame (see GPU::shades) and only applies this when the frame is presented, so a
+// new palette can be swapped in without re-running the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette(pub [u32; 4]);
+
+impl Palette {
+    // The green tint of the original screen.
+    pub const GREEN: Palette = Palette([0xE0F8D0FF, 0x88C070FF, 0x346856FF, 0x081820FF]);
+    pub const GRAY: Palette = Palette([0xFFFFFFFF, 0xAAAAAAFF, 0x555555FF, 0x000000FF]);
+
+    pub fn color(self, shade: u8) -> u32 {
+        self.0[shade as usize & 0b11]
+    }
+
+    // The palette as the screen shows it, only four colors to correct instead of every pixel.
+    pub fn corrected(self, correction: ColorCorrection) -> Palette {
+        let mut colors = self.0;
+        for color in colors.iter_mut() {
+            *color = correction.apply(*color);
+        }
+        Palette(colors)
+    }
+
+    pub fn apply(self, shades: &ShadeData, screen: &mut PixelData) {
+        for (shades, pixels) in shades.iter().zip(screen.iter_mut()) {
+            for (&shade, pixel) in shades.iter().zip(pixels.iter_mut()) {
+                *pixel = self.color(shade);
+            }
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREEN
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "green" | "dmg" => Ok(Palette::GREEN),
+            "gray" | "grey" => Ok(Palette::GRAY),
+            _ => Err(format!("Unknown palette {}, expected green or gray", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn applies_shades() {
+        let mut shades = Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        shades[1][2] = 3;
+        let mut screen = Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]);
+        Palette::GRAY.apply(&shades, &mut screen);
+        assert_eq!((screen[0][0], screen[1][2]), (0xFFFFFFFF, 0x000000FF));
+        assert_eq!("grey".parse::<Palette>(), Ok(Palette::GRAY));
+    }
+}
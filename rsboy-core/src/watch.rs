@@ -0,0 +1,114 @@
+use crate::bus::{Bus, Memory};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchRegion {
+    pub start: u16,
+    pub len: u16,
+}
+
+impl WatchRegion {
+    pub fn addresses(&self) -> impl Iterator<Item = u16> {
+        let start = self.start;
+        (0..self.len).map(move |i| start.wrapping_add(i))
+    }
+}
+
+// Memory ranges pinned in the debugger, plus frozen addresses that are rewritten every frame.
+#[derive(Default)]
+pub struct Watches {
+    pub regions: Vec<WatchRegion>,
+    pub frozen: BTreeMap<u16, u8>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, start: u16, len: u16) {
+        let region = WatchRegion { start, len };
+        if len > 0 && !self.regions.contains(&region) {
+            self.regions.push(region);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.regions.len() {
+            self.regions.remove(index);
+        }
+    }
+
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        self.frozen.insert(address, value);
+    }
+
+    pub fn unfreeze(&mut self, address: u16) {
+        self.frozen.remove(&address);
+    }
+
+    pub fn is_frozen(&self, address: u16) -> bool {
+        self.frozen.contains_key(&address)
+    }
+
+    // Rewrites every frozen address, called once per frame.
+    pub fn apply(&self, bus: &mut Bus) {
+        for (address, value) in &self.frozen {
+            bus.write(*address, *value);
+        }
+    }
+
+    // Current contents of every region.
+    pub fn read(&self, bus: &Bus) -> Vec<(WatchRegion, Vec<u8>)> {
+        self.regions
+            .iter()
+            .map(|region| {
+                (
+                    *region,
+                    region.addresses().map(|a| bus.debug_read(a)).collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn freeze_rewrites_each_apply() {
+        let mut bus = Bus::new(vec![], None);
+        let mut watches = Watches::new();
+        watches.add(0xC000, 4);
+        watches.freeze(0xC001, 0x42);
+        watches.apply(&mut bus);
+        bus.write(0xC001, 0);
+        watches.apply(&mut bus);
+        let read = watches.read(&bus);
+        assert_eq!(read[0].1, vec![0, 0x42, 0, 0]);
+
+        watches.unfreeze(0xC001);
+        bus.write(0xC001, 7);
+        watches.apply(&mut bus);
+        assert_eq!(bus.read(0xC001), 7);
+    }
+
+    #[test]
+    fn reads_write_only_registers() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xFF47, 0xE4);
+        let mut watches = Watches::new();
+        watches.add(0xFF46, 3);
+        assert_eq!(watches.read(&bus)[0].1[1], 0xE4);
+    }
+
+    #[test]
+    fn regions_wrap() {
+        let region = WatchRegion {
+            start: 0xFFFE,
+            len: 3,
+        };
+        assert_eq!(region.addresses().collect::<Vec<_>>(), [0xFFFE, 0xFFFF, 0]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::clock::{self, Cycles};
+use crate::emu::StopReason;
+
+// Cycles the CPU may spend in a tight loop before the watchdog fires.
+pub const DEFAULT_HANG_CYCLES: Cycles = 8_000_000;
+// Largest span of addresses that still counts as "the same loop".
+pub const DEFAULT_LOOP_WINDOW: u16 = 0x20;
+
+// Hang detector, fed once per instruction from Emu::emulate_step.
+// Activity is an ever increasing counter maintained by the bus.
+pub struct Watchdog {
+    pub limit: Cycles,
+    pub window: u16,
+    start_clock: Cycles,
+    lo: u16,
+    hi: u16,
+    activity: usize,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(DEFAULT_HANG_CYCLES, DEFAULT_LOOP_WINDOW)
+    }
+}
+
+impl Watchdog {
+    pub fn new(limit: Cycles, window: u16) -> Self {
+        Self {
+            limit,
+            window,
+            start_clock: 0,
+            lo: 0,
+            hi: 0,
+            activity: 0,
+        }
+    }
+
+    fn reset(&mut self, pc: u16, clock: Cycles, activity: usize) {
+        self.start_clock = clock;
+        self.lo = pc;
+        self.hi = pc;
+        self.activity = activity;
+    }
+
+    pub fn observe(&mut self, pc: u16, clock: Cycles, activity: usize) -> Option<StopReason> {
+        let lo = self.lo.min(pc);
+        let hi = self.hi.max(pc);
+        if activity != self.activity || hi - lo > self.window || clock < self.start_clock {
+            self.reset(pc, clock, activity);
+            return None;
+        }
+        self.lo = lo;
+        self.hi = hi;
+        let cycles = clock::since(self.start_clock, clock);
+        if cycles >= self.limit {
+            self.reset(pc, clock, activity);
+            return Some(StopReason::SuspectedHang {
+                start: lo,
+                end: hi,
+                cycles,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fires_on_tight_loop() {
+        let mut watchdog = Watchdog::new(100, 4);
+        let mut fired = None;
+        for clock in 0..=100 {
+            let pc = 0x200 + (clock % 3) as u16;
+            if let Some(reason) = watchdog.observe(pc, clock, 0) {
+                fired = Some(reason);
+                break;
+            }
+        }
+        assert_eq!(
+            fired,
+            Some(StopReason::SuspectedHang {
+                start: 0x200,
+                end: 0x202,
+                cycles: 100
+            })
+        );
+    }
+
+    #[test]
+    fn activity_resets() {
+        let mut watchdog = Watchdog::new(100, 4);
+        for clock in 0..1000 {
+            assert_eq!(watchdog.observe(0x200, clock, (clock / 50) as usize), None);
+        }
+    }
+
+    #[test]
+    fn wide_loop_resets() {
+        let mut watchdog = Watchdog::new(100, 4);
+        for clock in 0..1000 {
+            let pc = 0x200 + (clock % 16) as u16;
+            assert_eq!(watchdog.observe(pc, clock, 0), None);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Blargg's test ROMs as regression gates. The cpu_instrs singles are checked in under test_roms,
+// the rest are fetched into test_roms/blargg by fetch_test_roms.sh and skipped when missing.
+//
+// Each table lists whether a ROM is expected to pass. A ROM that should pass and doesn't fails
+// the test, a ROM that passes unexpectedly is only reported so its entry can be flipped.
+use rsboy_core::emu::Emu;
+use std::path::{Path, PathBuf};
+
+// Emulated time a ROM gets before it counts as hung, the slowest suites need about 20 seconds.
+const MAX_FRAMES: usize = 60 * 30;
+
+const CPU_INSTRS: [(&str, bool); 11] = [
+    ("01-special.gb", true),
+    ("02-interrupts.gb", false),
+    ("03-op sp,hl.gb", true),
+    ("04-op r,imm.gb", true),
+    ("05-op rp.gb", true),
+    ("06-ld r,r.gb", true),
+    ("07-jr,jp,call,ret,rst.gb", true),
+    ("08-misc instrs.gb", true),
+    ("09-op r,r.gb", true),
+    ("10-bit ops.gb", true),
+    ("11-op a,(hl).gb", true),
+];
+
+const MEM_TIMING: [(&str, bool); 3] = [
+    ("mem_timing/01-read_timing.gb", false),
+    ("mem_timing/02-write_timing.gb", false),
+    ("mem_timing/03-modify_timing.gb", false),
+];
+
+const HALT_BUG: [(&str, bool); 1] = [("halt_bug.gb", false)];
+
+const OAM_BUG: [(&str, bool); 8] = [
+    ("oam_bug/1-lcd_sync.gb", false),
+    ("oam_bug/2-causes.gb", false),
+    ("oam_bug/3-non_causes.gb", false),
+    ("oam_bug/4-scanline_timing.gb", false),
+    ("oam_bug/5-timing_bug.gb", false),
+    ("oam_bug/6-timing_no_bug.gb", false),
+    ("oam_bug/7-timing_effect.gb", false),
+    ("oam_bug/8-instr_effect.gb", false),
+];
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Passed,
+    Failed(String),
+    TimedOut(String),
+}
+
+fn roms() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_roms")
+}
+
+// The newer ROMs also report through cartridge RAM: A001-A003 hold DE B0 61 once the test
+// started, A000 stays 80 while it runs and then holds the result code, 0 on success.
+fn ram_result(emu: &Emu) -> Option<u8> {
+    let memory = &emu.bus.memory;
+    match (&memory[0xA001..0xA004], memory[0xA000]) {
+        ([0xDE, 0xB0, 0x61], 0x80) => None,
+        ([0xDE, 0xB0, 0x61], code) => Some(code),
+        _ => None,
+    }
+}
+
+// Text after the signature, as printed on screen.
+fn ram_text(emu: &Emu) -> String {
+    let text = &emu.bus.memory[0xA004..0xBFFF];
+    let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+    String::from_utf8_lossy(&text[..end]).into_owned()
+}
+
+fn run(path: &Path) -> Verdict {
+    let mut emu = Emu::from_path(path.to_path_buf(), None).expect("ROM exists");
+    emu.idle_skip = true;
+    for _ in 0..MAX_FRAMES {
+        emu.run_frame();
+        // Serial output is the older protocol, every ROM here prints the result with it.
+        if emu.bus.serial.output.contains("Passed") {
+            return Verdict::Passed;
+        }
+        if emu.bus.serial.output.contains("Failed") {
+            return Verdict::Failed(emu.bus.serial.output.clone());
+        }
+        match ram_result(&emu) {
+            Some(0) => return Verdict::Passed,
+            Some(_) => return Verdict::Failed(ram_text(&emu)),
+            None => {}
+        }
+    }
+    Verdict::TimedOut(emu.bus.serial.output.clone())
+}
+
+fn check(suite: &[(&str, bool)], dir: &Path) {
+    let mut regressions = vec![];
+    for &(name, expected) in suite {
+        let path = dir.join(name);
+        if !path.exists() {
+            eprintln!("Skipping {}, run fetch_test_roms.sh to get it", name);
+            continue;
+        }
+        let verdict = run(&path);
+        let passed = verdict == Verdict::Passed;
+        if expected && !passed {
+            regressions.push(format!("{}: {:?}", name, verdict));
+        } else if passed && !expected {
+            eprintln!("{} passes now, mark it as expected to pass", name);
+        }
+    }
+    assert!(regressions.is_empty(), "{}", regressions.join("\n"));
+}
+
+#[test]
+fn cpu_instrs() {
+    check(&CPU_INSTRS, &roms());
+}
+
+#[test]
+fn mem_timing() {
+    check(&MEM_TIMING, &roms().join("blargg"));
+}
+
+#[test]
+fn halt_bug() {
+    check(&HALT_BUG, &roms().join("blargg"));
+}
+
+#[test]
+fn oam_bug() {
+    check(&OAM_BUG, &roms().join("blargg"));
+}
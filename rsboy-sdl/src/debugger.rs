@@ -1,18 +1,32 @@
 extern crate imgui_opengl_renderer;
-use crate::constants::MaybeErr;
-use crate::emu::InstrListing;
+use rsboy_core::constants::MaybeErr;
+use rsboy_core::ramsearch::RamSearch;
+use rsboy_core::snapshot::EmuSnapshot;
 
 use imgui::{Context, Ui};
 use imgui_opengl_renderer::Renderer;
+use sdl2::video::GLContext;
 use sdl2::video::Window;
-use sdl2::{video::GLContext};
-use std::collections::VecDeque;
 
 #[derive(Default)]
 pub struct Info {
     pub frame_times: Vec<f32>,
     f_i: usize,
-    pub il: Vec<InstrListing>,
+    // Disassembly page shown, None follows PC.
+    pub disasm_page: Option<usize>,
+    // Watch panel inputs.
+    pub watch_start: i32,
+    pub watch_len: i32,
+    pub poke_addr: i32,
+    pub poke_value: i32,
+    // RAM search panel, None until a search is started.
+    pub ram_search: Option<RamSearch>,
+    pub search_value: i32,
+    // Sprite highlighted in the game view by the OAM panel.
+    pub selected_sprite: Option<usize>,
+    // Snapshot from before the emulator last moved, and the register fields that changed since.
+    pub previous: Option<EmuSnapshot>,
+    pub changed: Vec<&'static str>,
 }
 
 pub struct Imgui<'a> {
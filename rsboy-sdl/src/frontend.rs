@@ -0,0 +1,1243 @@
+use crate::debugger::{self, Imgui};
+use crate::logging::{self, LogControl};
+use crate::{start_frame, Hooks, Presentation};
+use imgui::im_str;
+use imgui::CollapsingHeader;
+use imgui::Slider;
+use imgui::Ui;
+use log::info;
+use rsboy_core::bugreport;
+use rsboy_core::bus::{self, Memory};
+use rsboy_core::clock::{self, Cycles};
+use rsboy_core::constants::{MaybeErr, CYCLES_PER_FRAME, FRAME_TIME, WINDOW_HEIGHT, WINDOW_WIDTH};
+use rsboy_core::cpu;
+use rsboy_core::disasm::InstrListing;
+use rsboy_core::dump;
+use rsboy_core::emu::{self, str_il, Emu};
+use rsboy_core::exec::ExecMap;
+use rsboy_core::gpu::{self, PixelData256};
+use rsboy_core::input::Input;
+use rsboy_core::instructions::Instr;
+use rsboy_core::pacing::{self, DriftCorrector, Pacing};
+use rsboy_core::ramsearch::{Comparison, RamSearch};
+use rsboy_core::savestate::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use rsboy_core::slots::{Slots, SLOT_COUNT};
+use rsboy_core::snapshot::{EmuSnapshot, PpuTiming};
+use rsboy_core::stats::{self, OpcodeStats, Table};
+use rsboy_core::texture::Tile;
+use rsboy_core::timeline::Timeline;
+use rsboy_core::video::color::Curve;
+use rsboy_core::video::{display::Viewport, overlay::Overlay, palette::Palette};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::Texture;
+use sdl2::video::Window;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Opens the game window and debugger, runs until the user quits, then shows the map and VRAM
+// viewers.
+pub fn run(
+    emu: &mut Emu,
+    mut presentation: Presentation,
+    input: &mut Input,
+    hooks: &mut Hooks,
+    slots: &mut Slots,
+    log: &LogControl,
+) -> MaybeErr<()> {
+    let context = sdl2::init()?;
+    // There is no APU output yet, so muting only skips opening the audio subsystem.
+    let _audio = if presentation.mute {
+        None
+    } else {
+        Some(context.audio()?)
+    };
+
+    let video = context.video()?;
+    if presentation.pacing == Pacing::Vsync {
+        let refresh_rate = video.current_display_mode(0)?.refresh_rate;
+        if !pacing::vsync_usable(refresh_rate) {
+            info!(
+                "Display runs at {}Hz, falling back to spin pacing",
+                refresh_rate
+            );
+            presentation.pacing = Pacing::Spin;
+        }
+    }
+    let window = video
+        .window(
+            ".rsboy",
+            WINDOW_WIDTH * presentation.scale,
+            WINDOW_HEIGHT * presentation.scale,
+        )
+        .position_centered()
+        .opengl()
+        .resizable()
+        .build()?;
+    let mut rsboy = if presentation.pacing == Pacing::Vsync {
+        window.into_canvas().present_vsync().build()?
+    } else {
+        window.into_canvas().build()?
+    };
+
+    let debugger = video
+        .window("debugger", 512, 512)
+        .position(0, 20)
+        .opengl()
+        .resizable()
+        .build()?;
+
+    // Wrapper struct for imgui to handle frame-by-frame rendering.
+    let mut debugger = Imgui::new(&debugger)?;
+
+    sdl_main(
+        &mut rsboy,
+        &mut debugger,
+        &context,
+        emu,
+        presentation,
+        input,
+        hooks,
+        slots,
+        log,
+    )?;
+    map_viewer(&context, emu)?;
+    vram_viewer(&context, emu)
+}
+
+// Written by the debugger's bug report button, attach it to issues.
+const BUG_REPORT_FILE: &str = "bugreport.zip";
+// WRAM and HRAM, see Bus::dump_ram_snapshot.
+const RAM_SNAPSHOT_FILE: &str = "ram.bin";
+
+// F1..F10 select save slots 0..9.
+fn slot_key(keycode: Keycode) -> Option<usize> {
+    let keys = [
+        Keycode::F1,
+        Keycode::F2,
+        Keycode::F3,
+        Keycode::F4,
+        Keycode::F5,
+        Keycode::F6,
+        Keycode::F7,
+        Keycode::F8,
+        Keycode::F9,
+        Keycode::F10,
+    ];
+    keys.iter().position(|&key| key == keycode)
+}
+
+// Handles pending SDL events, returns false when the user asked to quit.
+fn handle_events(
+    event_pump: &mut sdl2::EventPump,
+    input: &mut Input,
+    emu: &mut Emu,
+    debugger: &mut Imgui,
+    slots: &mut Slots,
+) -> bool {
+    for event in event_pump.poll_iter() {
+        match event {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => return false,
+            // Toggle the debug overlay.
+            Event::KeyDown {
+                keycode: Some(Keycode::F11),
+                repeat: false,
+                ..
+            } => emu.overlay.enabled = !emu.overlay.enabled,
+            Event::KeyDown {
+                keycode: Some(Keycode::F12),
+                repeat: false,
+                ..
+            } => match slots.quick_save(emu) {
+                Ok(slot) => println!("Quick saved to slot {}", slot + 1),
+                Err(e) => println!("Quick save failed: {}", e),
+            },
+            // Shift+F1..F10 saves, F1..F10 loads.
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                repeat: false,
+                ..
+            } if slot_key(keycode).is_some() => {
+                let slot = slot_key(keycode).unwrap();
+                if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    if let Err(e) = slots.save(slot, emu) {
+                        println!("Saving slot {} failed: {}", slot + 1, e);
+                    }
+                } else if let Err(e) = slots.load(slot, emu) {
+                    println!("Loading slot {} failed: {}", slot + 1, e);
+                }
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => {
+                if !input.key_down(&keycode.name()) {
+                    println!("{:?}", keycode);
+                }
+            }
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } => input.key_up(&keycode.name()),
+            Event::MouseWheel { y, .. } => {
+                debugger.imgui.io_mut().mouse_wheel = y as f32;
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+// Haptic device of the first game controller, for rumble cartridges.
+fn open_rumble(context: &sdl2::Sdl) -> Option<sdl2::haptic::Haptic> {
+    let controllers = context.game_controller().ok()?;
+    let index =
+        (0..controllers.num_joysticks().ok()?).find(|&i| controllers.is_game_controller(i))?;
+    match context.haptic().ok()?.open_from_joystick_id(index) {
+        Ok(haptic) => Some(haptic),
+        Err(e) => {
+            info!("Controller {} can't rumble: {}", index, e);
+            None
+        }
+    }
+}
+
+fn sdl_main(
+    video: &mut sdl2::render::Canvas<Window>,
+    debugger: &mut Imgui,
+    context: &sdl2::Sdl,
+    emu: &mut Emu,
+    presentation: Presentation,
+    input: &mut Input,
+    hooks: &mut Hooks,
+    slots: &mut Slots,
+    log: &LogControl,
+) -> MaybeErr<()> {
+    // Setup gl attributes, then create the texture that we will copy our framebuffer to.
+
+    let video_subsystem = context.video()?;
+    let gl_attr = video_subsystem.gl_attr();
+    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+    gl_attr.set_context_version(3, 0);
+
+    let filter = presentation.filter.filter();
+    let factor = filter.max_factor().min(presentation.scale as usize);
+    let mut screen = Box::new([[0; gpu::SCREEN_WIDTH]; gpu::SCREEN_HEIGHT]);
+    // Highlights from debugger panels, redrawn every frame.
+    let mut tools = Overlay::new();
+    let tc = video.texture_creator();
+    let mut texture = tc.create_texture_streaming(
+        PixelFormatEnum::RGBA32,
+        WINDOW_WIDTH * factor as u32,
+        WINDOW_HEIGHT * factor as u32,
+    )?;
+
+    // Some UI state
+    let mut cycle_jump = 0;
+    let mut pause = false;
+
+    // Vsync pacing runs slightly more or fewer cycles per frame to follow the wall clock.
+    let mut drift = DriftCorrector::new();
+    let mut pacing_start = Instant::now();
+
+    let mut event_pump = context.event_pump()?;
+    let mut rumble = if emu.bus.rumble().is_some() {
+        open_rumble(context)
+    } else {
+        None
+    };
+
+    emu.exec_map.get_or_insert_with(ExecMap::new);
+    emu.timeline.get_or_insert_with(Timeline::default);
+
+    loop {
+        let now = Instant::now();
+        if !handle_events(&mut event_pump, input, emu, debugger, slots) {
+            return Ok(());
+        }
+
+        pause = hooks.poll_gdb(emu, pause);
+        let mut delta_clock = 0;
+        if pause {
+            drift.reset();
+            pacing_start = Instant::now();
+        } else {
+            let frame_cycles = match presentation.pacing {
+                Pacing::Vsync => drift.cycles_for_frame(pacing_start.elapsed()),
+                Pacing::Spin => CYCLES_PER_FRAME,
+            };
+            // Input is sampled and per-frame hooks run when VBlank starts, right before games
+            // usually read the joypad. With the LCD off or the CPU in STOP there is no VBlank, so
+            // do it up front.
+            if !emu.bus.gpu.is_on() || matches!(emu.cpu.state, cpu::CPUState::Stopped) {
+                start_frame(emu, input, hooks);
+            }
+            let mut vblanks = emu.bus.gpu._vblank_count;
+            let end = clock::deadline(emu.bus.clock, frame_cycles);
+            let before = emu.bus.clock;
+            while emu.bus.clock < end {
+                if emu.bus.gpu._vblank_count != vblanks {
+                    vblanks = emu.bus.gpu._vblank_count;
+                    if !handle_events(&mut event_pump, input, emu, debugger, slots) {
+                        return Ok(());
+                    }
+                    start_frame(emu, input, hooks);
+                }
+                let reads = emu.bus.joypad_reads.get();
+                if let Some(reason) = emu.emulate_step() {
+                    println!("{}", reason);
+                    pause = true;
+                    break;
+                }
+                if emu.bus.joypad_reads.get() != reads {
+                    input.joypad_read();
+                }
+            }
+            delta_clock = emu.bus.clock - before;
+            emu.watches.apply(&mut emu.bus);
+        }
+        if let (Some(on), Some(haptic)) = (input.rumble_change(&emu.bus), &mut rumble) {
+            if on {
+                // Runs until stopped, games pulse the motor faster than frames anyway.
+                haptic.rumble_play(1.0, u32::MAX);
+            } else {
+                haptic.rumble_stop();
+            }
+        }
+        // Copy the last completed frame, the GPU swaps it in at VBlank.
+        *screen = *emu.bus.gpu.screen();
+        tools.clear();
+        if let Some(i) = debugger.info.selected_sprite {
+            let sprite = &emu.bus.gpu.sprites()[i];
+            let (x, y) = sprite.screen_pos();
+            tools.rect(x - 1, y - 1, 10, sprite.pixels.len() as i32 + 2, 0xFF0000FF);
+        }
+        if emu.overlay.enabled {
+            emu.overlay.draw(&mut screen);
+            tools.draw(&mut screen);
+        }
+        texture.with_lock(None, |buffer, _| filter.scale(&screen, buffer, factor))?;
+        let (width, height) = video.output_size()?;
+        let viewport = Viewport::letterbox(width, height);
+        video.set_draw_color(sdl2::pixels::Color::BLACK);
+        video.clear();
+        let dst = Rect::new(viewport.x, viewport.y, viewport.width, viewport.height);
+        video.copy(&texture, None, dst)?;
+        video.present();
+
+        // Delay a minimum of 16.67 milliseconds (60 fps), unless present() already waited on vsync.
+        if presentation.pacing == Pacing::Spin {
+            delay_min(now.elapsed());
+        }
+
+        // Log frame time
+        let after_delay = now.elapsed();
+        debugger.add_frame_time(after_delay.as_secs_f32());
+
+        // Panels only read from the snapshot, never from emu directly.
+        let snapshot = emu.snapshot();
+        // Kept while paused, so what the last step changed stays highlighted.
+        let info = &mut debugger.info;
+        if info
+            .previous
+            .as_ref()
+            .is_none_or(|p| p.clock != snapshot.clock)
+        {
+            if let Some(previous) = info.previous.replace(snapshot.clone()) {
+                info.changed = snapshot.changed_fields(&previous);
+            }
+        }
+
+        //ImGui display frame.
+        debugger.frame(&mut event_pump, |info, ui| {
+            ui.text(format!("Frame time: {:?}", after_delay));
+            let i = info.frame_times.as_slice();
+            ui.plot_lines(im_str!("Frame times"), i)
+                .graph_size([300.0, 100.0])
+                .build();
+            let cpu_hz = delta_clock as f64 / after_delay.as_secs_f64();
+            ui.text(format!("CPU HZ: {}", cpu_hz));
+            if let Some(latency) = input.latency {
+                ui.text(format!("Input latency (key to joypad read): {:?}", latency));
+            }
+            let turbo: Vec<String> = input.turbo_active().map(|b| format!("{:?}", b)).collect();
+            if !turbo.is_empty() {
+                ui.text_colored([1.0, 0.8, 0.0, 1.0], format!("TURBO {}", turbo.join(" ")));
+            }
+            register_panel(ui, &snapshot, &info.changed);
+            if ui.button(im_str!("Pause"), [200.0, 50.0]) {
+                println!("Pause");
+                pause = !pause;
+            }
+            ui.input_int(im_str!("Run for n cycles"), &mut cycle_jump)
+                .build();
+            Slider::new(im_str!(""))
+                .range(0..=(69905))
+                .build(ui, &mut cycle_jump);
+            if ui.button(im_str!("Step"), [200.0, 50.0]) {
+                emu.emulate_step();
+            }
+            if ui.button(im_str!("Go"), [200.0, 50.0]) {
+                let end = clock::deadline(emu.bus.clock, cycle_jump.max(0) as Cycles);
+                while emu.bus.clock < end {
+                    emu.emulate_step();
+                }
+            }
+            ui.text(format!("CLK: {}", snapshot.clock));
+            ui.text(format!("IO Registers:\n{}", snapshot.io));
+            ui.text(format!("[TIMER]:\n{}", snapshot.timer));
+            ui.text(format!("Last instructions:\n{}", str_il(&snapshot.history)));
+            if CollapsingHeader::new(im_str!("Disassembly")).build(ui) {
+                disassembly_panel(info, ui, emu, &snapshot);
+            }
+            if CollapsingHeader::new(im_str!("Watches")).build(ui) {
+                watch_panel(info, ui, emu, &snapshot);
+            }
+            if CollapsingHeader::new(im_str!("RAM search")).build(ui) {
+                ram_search_panel(info, ui, emu);
+            }
+            if CollapsingHeader::new(im_str!("Sprites (OAM)")).build(ui) {
+                sprite_panel(info, ui, &snapshot);
+            }
+            if CollapsingHeader::new(im_str!("Save slots")).build(ui) {
+                slot_panel(ui, emu, slots);
+            }
+            if CollapsingHeader::new(im_str!("Timeline")).build(ui) {
+                timeline_panel(ui, emu);
+            }
+            if CollapsingHeader::new(im_str!("Memory banks")).build(ui) {
+                bank_panel(ui, &snapshot);
+            }
+            if CollapsingHeader::new(im_str!("PPU timing")).build(ui) {
+                ppu_timing_panel(ui, &snapshot.ppu);
+            }
+            if CollapsingHeader::new(im_str!("Opcode stats")).build(ui) {
+                opcode_stats_panel(ui, emu, &snapshot);
+            }
+            if CollapsingHeader::new(im_str!("Interrupts")).build(ui) {
+                ui.text(format!("IME: {}", snapshot.io.ime));
+                for event in snapshot.interrupts.iter().rev() {
+                    ui.text(format!("{}", event));
+                }
+            }
+            if CollapsingHeader::new(im_str!("Log")).build(ui) {
+                log_panel(ui, log);
+            }
+            if CollapsingHeader::new(im_str!("Palette")).build(ui) {
+                palette_panel(ui, emu);
+            }
+            if CollapsingHeader::new(im_str!("Console")).build(ui) {
+                for line in &snapshot.console {
+                    ui.text(format!("{}", line));
+                }
+                if ui.button(im_str!("Clear"), [200.0, 20.0]) {
+                    emu.bus.console.clear();
+                }
+            }
+            if ui.button(im_str!("Bug report"), [200.0, 50.0]) {
+                let data = bugreport::bundle(emu, &crate::bug_report_config());
+                match std::fs::write(BUG_REPORT_FILE, data) {
+                    Ok(()) => println!("Wrote {}", BUG_REPORT_FILE),
+                    Err(e) => println!("Writing {} failed: {}", BUG_REPORT_FILE, e),
+                }
+            }
+            if ui.button(im_str!("Dump RAM"), [200.0, 20.0]) {
+                match std::fs::write(RAM_SNAPSHOT_FILE, emu.bus.dump_ram_snapshot()) {
+                    Ok(()) => println!("Wrote {}", RAM_SNAPSHOT_FILE),
+                    Err(e) => println!("Writing {} failed: {}", RAM_SNAPSHOT_FILE, e),
+                }
+            }
+            if ui.button(im_str!("Load RAM"), [200.0, 20.0]) {
+                let loaded = match std::fs::read(RAM_SNAPSHOT_FILE) {
+                    Ok(data) => emu.bus.load_ram_snapshot(&data),
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = loaded {
+                    println!("Loading {} failed: {}", RAM_SNAPSHOT_FILE, e);
+                }
+            }
+            if ui.button(im_str!("Hex Dump"), [200.0, 50.0]) {
+                print!("{}", dump::region_string(&emu.bus, dump::Region::Vram))
+            }
+            if ui.button(im_str!("Frame"), [200.0, 50.0]) {
+                println!("Frame");
+                let end = clock::deadline(emu.bus.clock, CYCLES_PER_FRAME);
+                while emu.bus.clock < end {
+                    emu.emulate_step();
+                }
+            }
+        });
+    }
+}
+
+// Register fields that changed in the last step.
+const CHANGED_COLOR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+const FIELDS_PER_ROW: usize = 8;
+
+// CPU, flag and IO registers, highlighting the ones the emulator changed when it last moved.
+fn register_panel(ui: &Ui, snapshot: &EmuSnapshot, changed: &[&str]) {
+    ui.text("Registers:");
+    for (i, field) in snapshot.fields().iter().enumerate() {
+        if i % FIELDS_PER_ROW != 0 {
+            ui.same_line(0.0);
+        }
+        let text = format!(
+            "{}:{:0width$x}",
+            field.name,
+            field.value,
+            width = field.digits
+        );
+        if changed.contains(&field.name) {
+            ui.text_colored(CHANGED_COLOR, text);
+        } else {
+            ui.text(text);
+        }
+    }
+    // Latest instructions that changed flags, the newest highlighted if it was this step's.
+    if snapshot.flag_changes.is_empty() {
+        return;
+    }
+    ui.text("Flag changes:");
+    let flags_changed = ["ZF", "NF", "HF", "CF"].iter().any(|f| changed.contains(f));
+    let entries = snapshot.flag_changes.iter();
+    for (i, (entry, change)) in entries
+        .filter_map(|e| Some((e, e.flag_change()?)))
+        .enumerate()
+    {
+        let text = format!("{:04x} {}", entry.registers.pc, change);
+        if i == 0 && flags_changed {
+            ui.text_colored(CHANGED_COLOR, text);
+        } else {
+            ui.text(text);
+        }
+    }
+}
+
+// Disassembly colors for lines that ran in the last frame and that never ran at all.
+const HOT_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+const NEVER_RUN_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+// A page of the disassembly, the one holding PC unless paged away from it, annotated with labels
+// and source lines when debug info is loaded.
+// Clicking the marker toggles a breakpoint on every address of that source line.
+// Lines that ran last frame are highlighted with their count, lines that never ran are dimmed.
+fn disassembly_panel(info: &mut debugger::Info, ui: &Ui, emu: &mut Emu, snapshot: &EmuSnapshot) {
+    let pc = snapshot.registers.pc;
+    let disasm = emu.disassembly();
+    let last = disasm.page_count().saturating_sub(1);
+    let page = info
+        .disasm_page
+        .unwrap_or_else(|| disasm.page_of(pc))
+        .min(last);
+    if ui.small_button(im_str!("<##disasm")) {
+        info.disasm_page = Some(page.saturating_sub(1));
+    }
+    ui.same_line(0.0);
+    if ui.small_button(im_str!(">##disasm")) {
+        info.disasm_page = Some((page + 1).min(last));
+    }
+    ui.same_line(0.0);
+    if ui.small_button(im_str!("Follow PC##disasm")) {
+        info.disasm_page = None;
+    }
+    ui.same_line(0.0);
+    ui.text(format!("Page {}/{}", page + 1, last + 1));
+    let window: Vec<(InstrListing, Option<String>)> = disasm
+        .page(page)
+        .iter()
+        .map(|listing| {
+            let label = disasm.label(listing.addr).map(str::to_string);
+            (listing.clone(), label)
+        })
+        .collect();
+    let mut toggle = None;
+    for (listing, label) in &window {
+        if let Some(label) = label {
+            ui.text(format!("{}:", label));
+        }
+        let marker = if emu.breakpoints.contains(&listing.addr) {
+            "o"
+        } else {
+            " "
+        };
+        if ui.small_button(&im_str!("{}##bp{:04x}", marker, listing.addr)) {
+            toggle = Some(listing.addr);
+        }
+        ui.same_line(0.0);
+        let cursor = if listing.addr == pc { ">" } else { " " };
+        let mut text = format!(
+            "{}{:04x}: {:?} {:?}",
+            cursor, listing.addr, listing.instr, listing.data
+        );
+        if let Some(info) = &mut emu.debug_info {
+            if let Some(loc) = info.lookup(listing.addr).cloned() {
+                let source = info.source_text(&loc).unwrap_or("").trim();
+                text += &format!("    ; {}:{} {}", loc.file, loc.line, source);
+            }
+        }
+        match &emu.exec_map {
+            Some(map) if map.hits(listing.addr) > 0 => {
+                text += &format!("    x{}", map.hits(listing.addr));
+                ui.text_colored(HOT_COLOR, text);
+            }
+            Some(map) if !map.executed(listing.addr) => ui.text_colored(NEVER_RUN_COLOR, text),
+            _ => ui.text(text),
+        }
+    }
+    if let Some(map) = &emu.exec_map {
+        let hot: Vec<String> = map
+            .hottest(5)
+            .iter()
+            .map(|(pc, hits)| format!("{:04x} x{}", pc, hits))
+            .collect();
+        ui.text(format!("Hottest: {}", hot.join(", ")));
+    }
+    if let Some(address) = toggle {
+        let addresses = match emu.debug_info.as_ref().and_then(|info| {
+            let loc = info.lookup(address)?;
+            Some(info.addresses_for(&loc.file, loc.line))
+        }) {
+            Some(addresses) => addresses,
+            None => vec![address],
+        };
+        if emu.breakpoints.contains(&address) {
+            for a in addresses {
+                emu.breakpoints.remove(&a);
+            }
+        } else {
+            emu.breakpoints.extend(addresses);
+        }
+    }
+}
+
+// Level buttons for the default and each core module, then the most recent log lines.
+fn log_panel(ui: &Ui, log: &LogControl) {
+    let modules = std::iter::once(None).chain(logging::MODULES.iter().copied().map(Some));
+    for module in modules {
+        let name = module.unwrap_or("default");
+        let current = log.level(module);
+        ui.text(format!("{:<8}", name));
+        for &level in logging::LEVELS.iter() {
+            ui.same_line(0.0);
+            let marker = if level == current { "*" } else { "" };
+            if ui.small_button(&im_str!("{}{}##{}{}", level, marker, name, level)) {
+                log.set_level(module, level);
+            }
+        }
+    }
+    if ui.button(im_str!("Clear log"), [200.0, 20.0]) {
+        log.clear();
+    }
+    for line in log.lines() {
+        ui.text(line);
+    }
+}
+
+// Palettes and color correction apply to the last frame right away, even while paused.
+fn palette_panel(ui: &Ui, emu: &mut Emu) {
+    for (i, &(name, palette)) in [("Green", Palette::GREEN), ("Gray", Palette::GRAY)]
+        .iter()
+        .enumerate()
+    {
+        if i > 0 {
+            ui.same_line(0.0);
+        }
+        let marker = if emu.bus.gpu.palette == palette {
+            "*"
+        } else {
+            ""
+        };
+        if ui.small_button(&im_str!("{}{}", name, marker)) {
+            emu.bus.gpu.palette = palette;
+            emu.bus.gpu.recolor();
+        }
+    }
+    // Clicking a color index moves it on to the next shade, written through the bus like the game
+    // would so it shows from the next line drawn.
+    let colors = emu.bus.gpu.palette;
+    for &(name, address) in PALETTE_REGISTERS.iter() {
+        let value = emu.bus.debug_read(address);
+        ui.text(format!("{:<4} {:02x}", name, value));
+        for index in 0..4 {
+            let shift = index * 2;
+            let shade = (value >> shift) & 0b11;
+            ui.same_line(0.0);
+            swatch(ui, colors.color(shade));
+            ui.same_line(0.0);
+            if ui.small_button(&im_str!("{}##{}{}", shade, name, index)) {
+                let next = (shade + 1) & 0b11;
+                emu.bus
+                    .write(address, (value & !(0b11 << shift)) | (next << shift));
+            }
+        }
+    }
+}
+
+// DMG palette registers, CGB palette RAM isn't emulated.
+const PALETTE_REGISTERS: [(&str, u16); 3] = [("BGP", 0xFF47), ("OBP0", 0xFF48), ("OBP1", 0xFF49)];
+const SWATCH_SIZE: f32 = 16.0;
+
+// A filled square of an RGBA `color`.
+fn swatch(ui: &Ui, color: u32) {
+    let [r, g, b, _] = color.to_be_bytes();
+    let [x, y] = ui.cursor_screen_pos();
+    let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+    ui.get_window_draw_list()
+        .add_rect([x, y], [x + SWATCH_SIZE, y + SWATCH_SIZE], color)
+        .filled(true)
+        .build();
+    ui.dummy([SWATCH_SIZE, SWATCH_SIZE]);
+}
+
+// Size of a sprite thumbnail pixel in the OAM panel.
+const THUMBNAIL_SCALE: f32 = 2.0;
+
+fn sprite_panel(info: &mut debugger::Info, ui: &Ui, snapshot: &EmuSnapshot) {
+    for sprite in &snapshot.sprites {
+        ui.separator();
+        {
+            let draw_list = ui.get_window_draw_list();
+            let [x0, y0] = ui.cursor_screen_pos();
+            for (row, pixels) in sprite.pixels.iter().enumerate() {
+                for (col, pixel) in pixels.iter().enumerate() {
+                    let [r, g, b, a] = pixel.to_be_bytes();
+                    if a == 0 {
+                        continue;
+                    }
+                    let min = [
+                        x0 + col as f32 * THUMBNAIL_SCALE,
+                        y0 + row as f32 * THUMBNAIL_SCALE,
+                    ];
+                    let max = [min[0] + THUMBNAIL_SCALE, min[1] + THUMBNAIL_SCALE];
+                    let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+                    draw_list.add_rect(min, max, color).filled(true).build();
+                }
+            }
+        }
+        // Always reserve room for 8x16 so rows line up.
+        ui.dummy([8.0 * THUMBNAIL_SCALE, 16.0 * THUMBNAIL_SCALE]);
+        ui.same_line(0.0);
+        ui.text(format!("{}", sprite));
+        ui.same_line(0.0);
+        let selected = info.selected_sprite == Some(sprite.index);
+        let label = if selected { "Hide" } else { "Show" };
+        if ui.small_button(&im_str!("{}##sprite{}", label, sprite.index)) {
+            info.selected_sprite = if selected { None } else { Some(sprite.index) };
+        }
+    }
+}
+
+fn age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    match now.saturating_sub(timestamp) {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
+// Draws pixels as one rect per run of equal colors, a full thumbnail as single pixels would
+// overflow the draw list.
+fn draw_thumbnail(ui: &Ui, pixels: &[u32], width: usize) {
+    let draw_list = ui.get_window_draw_list();
+    let [x0, y0] = ui.cursor_screen_pos();
+    for (y, row) in pixels.chunks(width).enumerate() {
+        let mut start = 0;
+        while start < row.len() {
+            let end = start
+                + row[start..]
+                    .iter()
+                    .take_while(|&&p| p == row[start])
+                    .count();
+            let [r, g, b, _] = row[start].to_be_bytes();
+            let min = [x0 + start as f32, y0 + y as f32];
+            let max = [x0 + end as f32, y0 + y as f32 + 1.0];
+            let color = [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+            draw_list.add_rect(min, max, color).filled(true).build();
+            start = end;
+        }
+    }
+    let height = pixels.len() / width;
+    ui.dummy([width as f32, height as f32]);
+}
+
+// Savestate slots with their thumbnails, Shift+F1..F10 and F1..F10 do the same.
+fn slot_panel(ui: &Ui, emu: &mut Emu, slots: &mut Slots) {
+    if ui.button(im_str!("Refresh"), [200.0, 20.0]) {
+        slots.refresh();
+    }
+    for slot in 0..SLOT_COUNT {
+        ui.separator();
+        match &slots.meta[slot] {
+            Some(meta) => {
+                draw_thumbnail(ui, &meta.thumbnail, THUMBNAIL_WIDTH);
+                ui.same_line(0.0);
+                ui.text(format!(
+                    "Slot {}\nFrame {}\n{}",
+                    slot + 1,
+                    meta.frame,
+                    age(meta.timestamp)
+                ));
+            }
+            None => {
+                ui.dummy([THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32]);
+                ui.same_line(0.0);
+                ui.text(format!("Slot {}\nEmpty", slot + 1));
+            }
+        }
+        ui.same_line(0.0);
+        if ui.small_button(&im_str!("Save##slot{}", slot)) {
+            if let Err(e) = slots.save(slot, emu) {
+                println!("Saving slot {} failed: {}", slot + 1, e);
+            }
+        }
+        if slots.meta[slot].is_some() {
+            ui.same_line(0.0);
+            if ui.small_button(&im_str!("Load##slot{}", slot)) {
+                if let Err(e) = slots.load(slot, emu) {
+                    println!("Loading slot {} failed: {}", slot + 1, e);
+                }
+            }
+        }
+    }
+}
+
+// Scrubs through the recorded timeline, replaying from the savestate before the chosen cycle.
+fn timeline_panel(ui: &Ui, emu: &mut Emu) {
+    let (start, end, checkpoints) = match &emu.timeline {
+        Some(timeline) => match timeline.start() {
+            Some(start) => (start, timeline.end(), timeline.checkpoints()),
+            None => return,
+        },
+        None => return,
+    };
+    let end = end.max(emu.bus.clock);
+    ui.text(format!(
+        "{} savestates, cycles {}-{}",
+        checkpoints, start, end
+    ));
+    let span = (end - start).min(i32::MAX as Cycles) as i32;
+    let mut offset = emu.bus.clock.saturating_sub(start).min(span as Cycles) as i32;
+    if Slider::new(im_str!("Cycle##timeline"))
+        .range(0..=span)
+        .build(ui, &mut offset)
+    {
+        if let Err(e) = emu.seek_to_cycle(start + offset as Cycles) {
+            println!("Seeking failed: {}", e);
+        }
+    }
+}
+
+// Mapped banks, mapper registers and recent writes to them, newest first.
+fn bank_panel(ui: &Ui, snapshot: &EmuSnapshot) {
+    match snapshot.cartridge_type {
+        Some(kind) => ui.text(format!("Cartridge type: {:02x}, mapper: none", kind)),
+        None => ui.text("Cartridge type: unknown, mapper: none"),
+    }
+    ui.text(format!("{}", snapshot.banks));
+    ui.separator();
+    for write in snapshot.banks.history().rev() {
+        ui.text(format!("{}", write));
+    }
+}
+
+// Frame strip scale: one column per 2 dots, 2 pixels per scanline.
+const TIMING_DOTS_PER_PX: f32 = 2.0;
+const TIMING_LINE_PX: f32 = 2.0;
+
+// One row per scanline of the current frame: drawn lines are filled in, the current line shows
+// the dot position, and ticks mark VBlank (red) and STAT (yellow) interrupt dispatches.
+fn ppu_timing_panel(ui: &Ui, timing: &PpuTiming) {
+    if !timing.lcd_on {
+        ui.text("LCD off");
+        return;
+    }
+    ui.text(format!(
+        "LY: {} Mode: {} Dot: {}",
+        timing.scanline, timing.mode, timing.dot
+    ));
+    let width = gpu::DOTS_PER_LINE as f32 / TIMING_DOTS_PER_PX;
+    {
+        let draw_list = ui.get_window_draw_list();
+        let [x0, y0] = ui.cursor_screen_pos();
+        for line in 0..gpu::LINES_PER_FRAME {
+            let y = y0 + line as f32 * TIMING_LINE_PX;
+            let color = if line >= timing.scanline as usize {
+                [0.15, 0.15, 0.15, 1.0]
+            } else if line < gpu::SCREEN_HEIGHT {
+                [0.5, 0.5, 0.5, 1.0]
+            } else {
+                [0.3, 0.3, 0.5, 1.0]
+            };
+            draw_list
+                .add_rect([x0, y], [x0 + width, y + TIMING_LINE_PX], color)
+                .filled(true)
+                .build();
+        }
+        let y = y0 + timing.scanline as f32 * TIMING_LINE_PX;
+        let x = x0 + timing.dot as f32 / TIMING_DOTS_PER_PX;
+        draw_list
+            .add_rect([x0, y], [x, y + TIMING_LINE_PX], [0.2, 0.8, 0.2, 1.0])
+            .filled(true)
+            .build();
+        for &(line, dot, kind) in &timing.interrupts {
+            let x = x0 + dot as f32 / TIMING_DOTS_PER_PX;
+            let y = y0 + line as f32 * TIMING_LINE_PX;
+            let color = if kind & cpu::VBLANK != 0 {
+                [1.0, 0.2, 0.2, 1.0]
+            } else {
+                [1.0, 0.9, 0.0, 1.0]
+            };
+            draw_list
+                .add_rect(
+                    [x - 1.0, y - 1.0],
+                    [x + 2.0, y + TIMING_LINE_PX + 1.0],
+                    color,
+                )
+                .filled(true)
+                .build();
+        }
+    }
+    ui.dummy([width, gpu::LINES_PER_FRAME as f32 * TIMING_LINE_PX]);
+}
+
+// Table of executed opcodes, most frequent first. Counting is off until started here or with
+// --opcode-stats.
+fn opcode_stats_panel(ui: &Ui, emu: &mut Emu, snapshot: &EmuSnapshot) {
+    let stats = match &snapshot.opcode_stats {
+        Some(stats) => stats,
+        None => {
+            if ui.button(im_str!("Start counting"), [200.0, 20.0]) {
+                emu.bus.opcode_stats = Some(OpcodeStats::new());
+            }
+            return;
+        }
+    };
+    let (base, base_total) = stats.base_coverage();
+    let (cb, cb_total) = stats.cb_coverage();
+    ui.text(format!(
+        "Coverage: base {}/{}, cb {}/{}",
+        base, base_total, cb, cb_total
+    ));
+    if ui.button(im_str!("Reset"), [200.0, 20.0]) {
+        emu.bus.opcode_stats = Some(OpcodeStats::new());
+    }
+    ui.columns(3, im_str!("opcode_stats"), true);
+    for count in stats.sorted() {
+        let (prefix, name) = match count.table {
+            Table::Base => ("", format!("{:?}", Instr::from(count.opcode))),
+            Table::Cb => ("cb ", stats::cb_name(count.opcode)),
+        };
+        ui.text(format!("{}{:02x}", prefix, count.opcode));
+        ui.next_column();
+        ui.text(name);
+        ui.next_column();
+        ui.text(format!("{}", count.count));
+        ui.next_column();
+    }
+    ui.columns(1, im_str!(""), false);
+}
+
+fn watch_panel(info: &mut debugger::Info, ui: &Ui, emu: &mut Emu, snapshot: &EmuSnapshot) {
+    ui.input_int(im_str!("Start (hex)"), &mut info.watch_start)
+        .chars_hexadecimal(true)
+        .build();
+    ui.input_int(im_str!("Length"), &mut info.watch_len).build();
+    if ui.button(im_str!("Watch"), [200.0, 20.0]) {
+        let len = info.watch_len.max(0).min(0x100) as u16;
+        emu.watches.add(info.watch_start as u16, len);
+    }
+    let mut remove = None;
+    for (i, (region, bytes)) in snapshot.watched.iter().enumerate() {
+        ui.separator();
+        ui.text(format!(
+            "{:04x}-{:04x}",
+            region.start,
+            region.start.wrapping_add(region.len - 1)
+        ));
+        ui.same_line(0.0);
+        if ui.small_button(&im_str!("Remove##{}", i)) {
+            remove = Some(i);
+        }
+        for (j, (address, value)) in region.addresses().zip(bytes).enumerate() {
+            if j % 16 == 0 {
+                ui.text(format!("{:04x}:", address));
+            }
+            ui.same_line(0.0);
+            let frozen = if emu.watches.is_frozen(address) {
+                "*"
+            } else {
+                ""
+            };
+            if ui.small_button(&im_str!("{:02x}{}##{}_{:04x}", value, frozen, i, address)) {
+                info.poke_addr = address as i32;
+                info.poke_value = *value as i32;
+            }
+        }
+    }
+    if let Some(i) = remove {
+        emu.watches.remove(i);
+    }
+    ui.separator();
+    ui.input_int(im_str!("Address (hex)"), &mut info.poke_addr)
+        .chars_hexadecimal(true)
+        .build();
+    ui.input_int(im_str!("Value (hex)"), &mut info.poke_value)
+        .chars_hexadecimal(true)
+        .build();
+    let (address, value) = (info.poke_addr as u16, info.poke_value as u8);
+    if ui.small_button(im_str!("Poke")) {
+        emu.bus.write(address, value);
+    }
+    ui.same_line(0.0);
+    if ui.small_button(im_str!("Freeze")) {
+        emu.watches.freeze(address, value);
+    }
+    ui.same_line(0.0);
+    if ui.small_button(im_str!("Unfreeze")) {
+        emu.watches.unfreeze(address);
+    }
+}
+
+// Candidates listed in the RAM search panel, the rest are only counted.
+const MAX_CANDIDATES_SHOWN: usize = 64;
+
+// Cheat search: each filter compares WRAM with the previous filter, or with when the search
+// started. Survivors can be watched or frozen at their current value.
+fn ram_search_panel(info: &mut debugger::Info, ui: &Ui, emu: &mut Emu) {
+    if ui.button(im_str!("New search"), [200.0, 20.0]) {
+        info.ram_search = Some(RamSearch::new(&emu.bus));
+    }
+    let search = match &mut info.ram_search {
+        Some(search) => search,
+        None => return,
+    };
+    let comparisons = [
+        ("Changed", Comparison::Changed),
+        ("Unchanged", Comparison::Unchanged),
+        ("Increased", Comparison::Increased),
+        ("Decreased", Comparison::Decreased),
+    ];
+    let mut filter = None;
+    for (i, &(name, comparison)) in comparisons.iter().enumerate() {
+        if i > 0 {
+            ui.same_line(0.0);
+        }
+        if ui.small_button(&im_str!("{}", name)) {
+            filter = Some(comparison);
+        }
+    }
+    ui.input_int(im_str!("Value (hex)##search"), &mut info.search_value)
+        .chars_hexadecimal(true)
+        .build();
+    ui.same_line(0.0);
+    if ui.small_button(im_str!("Equal")) {
+        filter = Some(Comparison::Equal(info.search_value as u8));
+    }
+    if let Some(comparison) = filter {
+        search.filter(&emu.bus, comparison);
+    }
+    ui.text(format!("{} candidates", search.candidates.len()));
+    for &address in search.candidates.iter().take(MAX_CANDIDATES_SHOWN) {
+        let value = emu.bus.debug_read(address);
+        ui.text(format!(
+            "{:04x}: {:02x} (was {:02x})",
+            address,
+            value,
+            search.value(address)
+        ));
+        ui.same_line(0.0);
+        if ui.small_button(&im_str!("Watch##search{:04x}", address)) {
+            emu.watches.add(address, 1);
+        }
+        ui.same_line(0.0);
+        let frozen = emu.watches.is_frozen(address);
+        let label = if frozen { "Unfreeze" } else { "Freeze" };
+        if ui.small_button(&im_str!("{}##search{:04x}", label, address)) {
+            if frozen {
+                emu.watches.unfreeze(address);
+            } else {
+                emu.watches.freeze(address, value);
+            }
+        }
+    }
+}
+
+fn delay_min(elapsed: Duration) {
+    if let Some(time) = FRAME_TIME.checked_sub(elapsed) {
+        spin_sleep::sleep(time);
+    }
+}
+
+trait GBWindow {
+    fn copy_map(&mut self, buffer: &PixelData256);
+}
+impl GBWindow for Texture<'_> {
+    fn copy_map(&mut self, buffer: &PixelData256) {
+        let mut i = 0;
+        self.with_lock(None, |tbuffer, _| {
+            for y in buffer.iter() {
+                for x in y.iter() {
+                    let bytes = x.to_be_bytes();
+                    tbuffer[i..(i + 4)].copy_from_slice(&bytes);
+                    i += 4;
+                }
+            }
+        })
+        .unwrap();
+    }
+}
+
+fn map_viewer(sdl_context: &sdl2::Sdl, emu: &emu::Emu) -> Result<(), String> {
+    let gpu = &emu.bus.gpu;
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window("Map Viewer", 256, 256)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, 256, 256)
+        .map_err(|e| e.to_string())?;
+
+    // Pitch = n_bytes(3) * map_w * tile_w
+    let mut map = Box::new([[0; 256]; 256]);
+    gpu.render_full_bg(&mut map);
+    texture.copy_map(&map);
+    canvas.copy(&texture, None, None)?;
+    let (h, v) = gpu.scroll();
+    println!("{} {}", h, v);
+    canvas
+        .draw_rect(Rect::from((
+            h as i32,
+            v as i32,
+            WINDOW_WIDTH,
+            WINDOW_HEIGHT,
+        )))
+        .unwrap();
+    canvas.present();
+    let mut event_pump = sdl_context.event_pump()?;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                _ => {}
+            }
+        }
+
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+    }
+
+    Ok(())
+}
+
+// Tile viewer and editor: clicking a pixel cycles its color index and writes it back to VRAM.
+// Return cycles the palette used for display.
+fn vram_viewer(sdl_context: &sdl2::Sdl, emu: &mut emu::Emu) -> MaybeErr<()> {
+    let video_subsystem = sdl_context.video()?;
+    let window = video_subsystem
+        .window("VRAM Viewer", 1024, 512)
+        .position_centered()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+
+    let texture_creator = canvas.texture_creator();
+
+    let mut update = |gpu: &gpu::GPU, palette: u8| -> MaybeErr<()> {
+        let tiles = gpu.tiles(palette);
+        for (i, t) in tiles.iter().enumerate() {
+            let i = i as i32;
+            let mut tex =
+                texture_creator.create_texture_streaming(PixelFormatEnum::RGBA32, 8, 8)?;
+            tex.with_lock(None, |data, _| {
+                let mut c = 0;
+                for i in t.texture.iter() {
+                    for j in i.iter() {
+                        let d = j.to_be_bytes();
+                        data[c..(c + 4)].copy_from_slice(&d);
+                        c += 4;
+                    }
+                }
+            })?;
+            let rect = ((i % 32) * TILE_VIEW, (i / 32) * TILE_VIEW, 32, 32);
+            let rect = Rect::from(rect);
+            canvas.copy(&tex, None, rect)?
+        }
+        canvas.present();
+        Ok(())
+    };
+    let gpu = &emu.bus.gpu;
+    let ps = [gpu.bgrdpal, gpu.obj0pal, gpu.obj1pal];
+    let mut i = 0;
+    update(&emu.bus.gpu, ps[i])?;
+    let mut event_pump = sdl_context.event_pump()?;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => match key {
+                    Keycode::Return => {
+                        i += 1;
+                        i %= ps.len();
+                        println!("{}", i);
+                        update(&emu.bus.gpu, ps[i])?;
+                    }
+                    _ => {}
+                },
+                Event::MouseButtonDown { x, y, .. } => {
+                    let tile = (y / TILE_VIEW * 32 + x / TILE_VIEW) as usize;
+                    if tile < gpu::TILE_DATA_RANGE.len() / gpu::TILE_SIZE {
+                        let (px, py) = ((x % TILE_VIEW) / 4, (y % TILE_VIEW) / 4);
+                        cycle_tile_pixel(&mut emu.bus, tile, px as usize, py as usize);
+                        update(&emu.bus.gpu, ps[i])?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 30));
+        // The rest of the game loop goes here...
+    }
+
+    Ok(())
+}
+
+// Size of a tile in the VRAM viewer, 8 pixels scaled by 4.
+const TILE_VIEW: i32 = 32;
+
+// Advances the color index of one pixel of a tile in VRAM, writing through the bus.
+fn cycle_tile_pixel(bus: &mut bus::Bus, tile: usize, x: usize, y: usize) {
+    let addr = (gpu::VRAM_START + tile * gpu::TILE_SIZE + y * 2) as u16;
+    let (lo, hi) = (bus.debug_read(addr), bus.debug_read(addr + 1));
+    let index = (Tile::pixel_index(lo, hi, x) + 1) % 4;
+    let (lo, hi) = Tile::with_pixel_index(lo, hi, x, index);
+    bus.write(addr, lo);
+    bus.write(addr + 1, hi);
+}
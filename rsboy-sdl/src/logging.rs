@@ -0,0 +1,124 @@
+use log::{LevelFilter, Metadata};
+use rsboy_core::constants::MaybeErr;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Core modules with their own level in the debugger, everything else uses the default level.
+pub const MODULES: [&str; 4] = ["cpu", "bus", "gpu", "timer"];
+pub const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+// Log lines kept for the debugger's log panel.
+pub const LOG_LINES: usize = 500;
+
+struct State {
+    default: LevelFilter,
+    modules: [Option<LevelFilter>; MODULES.len()],
+    lines: VecDeque<String>,
+}
+
+// Levels and recent lines, shared between the logger and the debugger so levels can be changed
+// while running.
+#[derive(Clone)]
+pub struct LogControl(Arc<Mutex<State>>);
+
+// Index into MODULES for targets like rsboy_core::cpu::value.
+fn module_index(target: &str) -> Option<usize> {
+    let module = target.strip_prefix("rsboy_core::")?.split("::").next()?;
+    MODULES.iter().position(|&m| m == module)
+}
+
+impl LogControl {
+    pub fn new(default: LevelFilter) -> Self {
+        let state = State {
+            default,
+            modules: [None; MODULES.len()],
+            lines: VecDeque::with_capacity(LOG_LINES),
+        };
+        LogControl(Arc::new(Mutex::new(state)))
+    }
+
+    // Level of one of MODULES, or the default level for None.
+    pub fn level(&self, module: Option<&str>) -> LevelFilter {
+        let state = self.0.lock().unwrap();
+        match module.and_then(|m| MODULES.iter().position(|&n| n == m)) {
+            Some(i) => state.modules[i].unwrap_or(state.default),
+            None => state.default,
+        }
+    }
+
+    pub fn set_level(&self, module: Option<&str>, level: LevelFilter) {
+        let mut state = self.0.lock().unwrap();
+        match module.and_then(|m| MODULES.iter().position(|&n| n == m)) {
+            Some(i) => state.modules[i] = Some(level),
+            None => state.default = level,
+        }
+        // Lets the log macros skip disabled records without asking the logger.
+        let max = state
+            .modules
+            .iter()
+            .flatten()
+            .fold(state.default, |a, &b| a.max(b));
+        log::set_max_level(max);
+    }
+
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let state = self.0.lock().unwrap();
+        let level = match module_index(metadata.target()) {
+            Some(i) => state.modules[i].unwrap_or(state.default),
+            None => state.default,
+        };
+        metadata.level() <= level
+    }
+
+    fn push(&self, line: String) {
+        let mut state = self.0.lock().unwrap();
+        if state.lines.len() == LOG_LINES {
+            state.lines.pop_front();
+        }
+        state.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().lines.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().lines.clear();
+    }
+}
+
+// Always feeds the debugger's log panel, the terminal and `file` only get output when a file
+// is given.
+pub fn setup_logger(control: &LogControl, file: Option<&Path>) -> MaybeErr<()> {
+    let filter = control.clone();
+    let panel = control.clone();
+    let mut dispatch = fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][{}:{}] {}",
+                record.level(),
+                record.file().unwrap_or("?"),
+                record.line().unwrap_or(0),
+                message
+            ))
+        })
+        .filter(move |metadata| filter.enabled(metadata))
+        .chain(fern::Output::call(move |record| {
+            panel.push(record.args().to_string())
+        }));
+    if let Some(path) = file {
+        dispatch = dispatch
+            .chain(std::io::stdout())
+            .chain(fern::log_file(path)?);
+    }
+    dispatch.apply()?;
+    log::set_max_level(control.level(None));
+    Ok(())
+}
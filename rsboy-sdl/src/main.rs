@@ -0,0 +1,659 @@
+#[cfg(feature = "frontend")]
+mod debugger;
+#[cfg(feature = "frontend")]
+mod frontend;
+mod logging;
+mod metrics;
+#[cfg(feature = "tui")]
+mod tui;
+
+// Without the frontend only the headless modes (batch, --headless, --compare-log) are available.
+#[cfg(not(feature = "frontend"))]
+mod frontend {
+    use super::*;
+    pub fn run(
+        _: &mut Emu,
+        _: Presentation,
+        _: &mut Input,
+        _: &mut Hooks,
+        _: &mut Slots,
+        _: &LogControl,
+    ) -> MaybeErr<()> {
+        Err("Built without the frontend feature, only batch, --headless and --compare-log are available".into())
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+mod tui {
+    use super::*;
+    pub fn run(_: &mut Emu, _: &mut Input, _: &mut Hooks) -> MaybeErr<()> {
+        Err("Built without the tui feature".into())
+    }
+}
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+//File IO
+use log::{info, warn, LevelFilter};
+use logging::LogControl;
+use metrics::Metrics;
+
+use rsboy_core::batch::Progress;
+use rsboy_core::battery::{BatterySaver, DEFAULT_SAVE_INTERVAL};
+use rsboy_core::bootlogo::BootLogo;
+use rsboy_core::bugreport::ReportLog;
+use rsboy_core::camera;
+use rsboy_core::debuginfo::DebugInfo;
+use rsboy_core::decode::DecodeCache;
+use rsboy_core::emu::Emu;
+use rsboy_core::gdb::GdbStub;
+use rsboy_core::input::{Binding, Button, Input};
+use rsboy_core::meminit::MemFill;
+use rsboy_core::model::Model;
+use rsboy_core::movie::Movie;
+use rsboy_core::pacing::Pacing;
+use rsboy_core::printer::Printer;
+use rsboy_core::serial::SerialKind;
+use rsboy_core::slots::Slots;
+use rsboy_core::stats::{OpcodeStats, Stats};
+use rsboy_core::trace::Tracer;
+use rsboy_core::video::color::{ColorCorrection, Curve};
+use rsboy_core::video::filter::FilterKind;
+use rsboy_core::video::overflow;
+use rsboy_core::video::palette::Palette;
+use rsboy_core::watchdog::{Watchdog, DEFAULT_LOOP_WINDOW};
+use structopt::StructOpt;
+
+use crate::constants::MaybeErr;
+use rsboy_core::*;
+
+#[derive(StructOpt)]
+#[structopt(name = ".rsboy", about = "Rust emulator")]
+struct Settings {
+    /// ROM to run, a built-in splash screen is shown without one.
+    #[structopt(parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// Also write the log to the terminal and this file.
+    #[structopt(parse(from_os_str))]
+    logfile: Option<PathBuf>,
+    #[structopt(short = "-b")]
+    bootrom: Option<PathBuf>,
+    /// Run the bootrom flat out before opening the window, skipping the logo scroll.
+    #[structopt(long = "fast-boot")]
+    fast_boot: bool,
+    /// Show this logo (48x8 PNG or the 48 header bytes) while the bootrom runs. The cartridge
+    /// header must hold the same logo, the DMG bootrom locks up otherwise.
+    #[structopt(long = "boot-logo", parse(from_os_str))]
+    boot_logo: Option<PathBuf>,
+    #[structopt(short = "-r")]
+    repl: bool,
+    /// Initial window scale factor.
+    #[structopt(short = "-s", long = "scale", default_value = "3")]
+    scale: u32,
+    /// Frame pacing: "spin" sleeps out each frame, "vsync" waits on the display.
+    #[structopt(long = "pacing", default_value = "spin")]
+    pacing: Pacing,
+    /// Shorthand for --pacing vsync.
+    #[structopt(long = "vsync")]
+    vsync: bool,
+    /// Scaling filter applied before upload: none, scale2x, scale3x (at --scale 3 and up) or epx.
+    #[structopt(long = "filter", default_value = "none")]
+    filter: FilterKind,
+    /// Colors for the four shades: green or gray. Defaults to the game's compat.toml entry, then
+    /// green. Can be changed in the debugger.
+    #[structopt(long = "palette")]
+    palette: Option<Palette>,
+    /// Screen color response: raw, cgb (washed out CGB LCD) or gba.
+    #[structopt(long = "color-correction", default_value = "raw")]
+    color_correction: Curve,
+    /// Output gamma after color correction, higher brightens the midtones.
+    #[structopt(long = "gamma", default_value = "1.0")]
+    gamma: f32,
+    /// Don't open an audio device.
+    #[structopt(long = "mute")]
+    mute: bool,
+    /// Don't forward rumble cartridges' motor to the first game controller.
+    #[structopt(long = "no-rumble")]
+    no_rumble: bool,
+    /// Capture writes to 0xFF7F as debug console output.
+    #[structopt(long = "debug-port")]
+    debug_port: bool,
+    /// Unmapped IO registers read 0xFF and ignore writes instead of acting as RAM.
+    #[structopt(long = "strict-io")]
+    strict_io: bool,
+    /// Log the first access to each unmapped IO register.
+    #[structopt(long = "log-unmapped-io")]
+    log_unmapped_io: bool,
+    /// Link cable peer: none (transfers hang), disconnected (always reads 0xFF), mirror or printer
+    /// (Game Boy Printer, prints are saved as PNGs next to the ROM).
+    #[structopt(long = "serial", default_value = "none")]
+    serial: SerialKind,
+    /// Record a trace of instructions, interrupts, PPU modes and DMA. Only "chrome" is supported.
+    #[structopt(long = "trace-format")]
+    trace_format: Option<String>,
+    /// Where to write the trace on exit.
+    #[structopt(long = "trace-out", parse(from_os_str), default_value = "trace.json")]
+    trace_out: PathBuf,
+    /// Register line (A:.. F:.. .. SP:.. PC:..) of a state exported by another emulator.
+    #[structopt(long = "import-regs", parse(from_os_str), requires = "import-dump")]
+    import_regs: Option<PathBuf>,
+    /// 64KiB memory dump matching --import-regs.
+    #[structopt(long = "import-dump", parse(from_os_str), requires = "import-regs")]
+    import_dump: Option<PathBuf>,
+    /// Savestate to start from, native or another emulator's with a BESS footer (SameBoy .s0 files).
+    #[structopt(long = "load-state", parse(from_os_str))]
+    load_state: Option<PathBuf>,
+    /// Address to source line mapping (BB:AAAA file.asm:LINE per line) for the disassembly panel.
+    #[structopt(long = "debug-file", parse(from_os_str))]
+    debug_file: Option<PathBuf>,
+    /// PNG, or binary PGM or PPM, image the Game Boy Camera sees instead of a gradient.
+    #[structopt(long = "camera-image", parse(from_os_str))]
+    camera_image: Option<PathBuf>,
+    /// Run in the terminal instead of an SDL window, needs the tui feature.
+    #[structopt(long = "tui")]
+    tui: bool,
+    /// Run without a window, print a JSON summary and exit with 0 on success and 1 otherwise.
+    #[structopt(long = "headless")]
+    headless: bool,
+    /// Frames to run with --headless.
+    #[structopt(long = "frames", default_value = "600")]
+    frames: usize,
+    /// With --headless, print progress to stderr every this many seconds.
+    #[structopt(long = "progress")]
+    progress: Option<f64>,
+    /// With --headless, succeed only once the serial output contains this text.
+    #[structopt(long = "expect-serial")]
+    expect_serial: Option<String>,
+    /// Same as --expect-serial Passed, for blargg's test ROMs.
+    #[structopt(long = "exit-code-from-serial")]
+    exit_code_from_serial: bool,
+    /// Run headless against a per-instruction reference log and stop at the first divergence.
+    #[structopt(long = "compare-log", parse(from_os_str))]
+    compare_log: Option<PathBuf>,
+    /// Pause and report when the CPU loops this many cycles without any I/O activity.
+    #[structopt(long = "watchdog")]
+    watchdog: Option<u64>,
+    /// RAM contents at power on: zero (default), ones, nibble, random or random:<seed>.
+    #[structopt(long = "power-on-fill", default_value = "zero")]
+    power_on_fill: MemFill,
+    /// Hardware to start up as without a bootrom: dmg, mgb or cgb.
+    #[structopt(long = "model", default_value = "dmg")]
+    model: Model,
+    /// Accept a GDB remote protocol client on this port of localhost.
+    #[structopt(long = "gdb-port")]
+    gdb_port: Option<u16>,
+    /// Serve emulation counters in Prometheus text format on this port.
+    #[structopt(long = "metrics-port")]
+    metrics_port: Option<u16>,
+    /// Address to serve metrics on, 0.0.0.0 to let other machines scrape them.
+    #[structopt(long = "metrics-bind", default_value = "127.0.0.1")]
+    metrics_bind: String,
+    /// Write a bug report bundle (savestate, trace, IO writes, config, screenshot) here on exit.
+    #[structopt(long = "bug-report", parse(from_os_str))]
+    bug_report: Option<PathBuf>,
+    /// Count executed opcodes and print them with instruction set coverage on exit.
+    #[structopt(long = "opcode-stats")]
+    opcode_stats: bool,
+    /// Jump a halted CPU straight to the next timer, PPU or serial event.
+    #[structopt(long = "idle-skip")]
+    idle_skip: bool,
+    /// Decode instructions in ROM once per bank and run them from a cache.
+    #[structopt(long = "decode-cache")]
+    decode_cache: bool,
+    /// Key that auto-fires A while held.
+    #[structopt(long = "turbo-a", default_value = "A")]
+    turbo_a: String,
+    /// Key that auto-fires B while held.
+    #[structopt(long = "turbo-b", default_value = "S")]
+    turbo_b: String,
+    /// Key that holds A+B+Start+Select for one frame, the usual soft reset combination.
+    #[structopt(long = "soft-reset", default_value = "Backspace")]
+    soft_reset: String,
+    /// Key that power cycles the emulated Game Boy.
+    #[structopt(long = "hard-reset", default_value = "Delete")]
+    hard_reset: String,
+    /// Record every frame's input, resets included, to this movie file.
+    #[structopt(long = "record-movie", parse(from_os_str))]
+    record_movie: Option<PathBuf>,
+    /// Play back a movie, its recorded JOYP reads included, before handing over to the keys.
+    #[structopt(long = "play-movie", parse(from_os_str))]
+    play_movie: Option<PathBuf>,
+    /// Frames a turbo button stays pressed, then released.
+    #[structopt(long = "turbo-rate", default_value = "2")]
+    turbo_rate: u32,
+    /// Tint lines where sprites were dropped by the 10 per line limit and log them every frame.
+    #[structopt(long = "sprite-overflow")]
+    sprite_overflow: bool,
+    /// Rhai script whose on_frame() is called before every frame.
+    #[cfg(feature = "scripting")]
+    #[structopt(long = "script", parse(from_os_str))]
+    script: Option<PathBuf>,
+}
+
+// `main batch <dir>`: run every ROM in a directory headlessly and write a compatibility report.
+#[derive(StructOpt)]
+#[structopt(
+    name = ".rsboy batch",
+    about = "Headless compatibility run over a ROM directory"
+)]
+struct BatchSettings {
+    #[structopt(parse(from_os_str))]
+    dir: PathBuf,
+    /// Frames to run each ROM for.
+    #[structopt(long = "frames", default_value = "600")]
+    frames: usize,
+    /// Report to write, HTML if the extension is .html and JSON otherwise.
+    #[structopt(long = "report", parse(from_os_str), default_value = "report.json")]
+    report: PathBuf,
+    /// Directory to save the final frame of each ROM to, as PPM.
+    #[structopt(long = "screenshots", parse(from_os_str))]
+    screenshots: Option<PathBuf>,
+    /// Serve the counters summed over finished ROMs in Prometheus text format on this port.
+    #[structopt(long = "metrics-port")]
+    metrics_port: Option<u16>,
+    /// Address to serve metrics on, 0.0.0.0 to let other machines scrape them.
+    #[structopt(long = "metrics-bind", default_value = "127.0.0.1")]
+    metrics_bind: String,
+}
+
+fn batch_main(settings: BatchSettings) -> MaybeErr<()> {
+    let metrics = settings
+        .metrics_port
+        .map(|port| Metrics::serve(&settings.metrics_bind, port))
+        .transpose()?;
+    let total = Mutex::new(Stats::default());
+    let reports = batch::run_dir_with(&settings.dir, settings.frames, |report| {
+        if let Some(metrics) = &metrics {
+            let mut total = total.lock().unwrap();
+            total.add(&report.stats);
+            metrics.update(&total);
+        }
+    })?;
+    for report in &reports {
+        println!("{:?}: {}", report.path, report.outcome);
+    }
+    if let Some(dir) = &settings.screenshots {
+        std::fs::create_dir_all(dir)?;
+        for report in reports.iter().filter(|r| r.frame_hash.is_some()) {
+            let name = report.path.file_stem().unwrap_or_default();
+            let path = dir.join(name).with_extension("ppm");
+            batch::write_screenshot(&path, &report.frame)?;
+        }
+    }
+    let html = settings.report.extension().is_some_and(|e| e == "html");
+    let report = if html {
+        batch::html_report(&reports)
+    } else {
+        batch::json_report(&reports)
+    };
+    std::fs::write(&settings.report, report)?;
+    info!("Wrote compatibility report to {:?}", settings.report);
+    Ok(())
+}
+
+// Presentation options from the command line, plumbed into the frontend.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(feature = "frontend"), allow(dead_code))]
+struct Presentation {
+    scale: u32,
+    pacing: Pacing,
+    mute: bool,
+    filter: FilterKind,
+}
+
+// `main dump <region> --rom <rom>`: run a ROM headlessly and print an annotated hex dump.
+#[derive(StructOpt)]
+#[structopt(name = ".rsboy dump", about = "Annotated hex dump of a memory region")]
+struct DumpSettings {
+    /// Region to dump: vram, oam, wram or hram.
+    region: dump::Region,
+    #[structopt(long = "rom", parse(from_os_str))]
+    rom: PathBuf,
+    #[structopt(long = "bootrom", parse(from_os_str))]
+    bootrom: Option<PathBuf>,
+    /// Frames to run before dumping.
+    #[structopt(long = "at-frame", default_value = "0")]
+    at_frame: usize,
+}
+
+fn dump_main(settings: DumpSettings) -> MaybeErr<()> {
+    let mut emu = Emu::from_path(settings.rom, settings.bootrom)?;
+    for _ in 0..settings.at_frame {
+        emu.run_frame();
+    }
+    let stdout = std::io::stdout();
+    dump::write_region(&emu.bus, settings.region, &mut stdout.lock())?;
+    Ok(())
+}
+
+// `main gallery <list>`: screenshot ROMs at set frames into a directory with an index.html.
+#[derive(StructOpt)]
+#[structopt(
+    name = ".rsboy gallery",
+    about = "Screenshots of ROMs at fixed frames, with an HTML index"
+)]
+struct GallerySettings {
+    /// File with one `<rom> <frame>` per line, ROM paths relative to it.
+    #[structopt(parse(from_os_str))]
+    list: PathBuf,
+    #[structopt(long = "out", parse(from_os_str), default_value = "gallery")]
+    out: PathBuf,
+}
+
+fn gallery_main(settings: GallerySettings) -> MaybeErr<()> {
+    let text = std::fs::read_to_string(&settings.list)?;
+    let base = settings.list.parent().unwrap_or_else(|| Path::new(""));
+    let shots = gallery::parse_list(&text, base)?;
+    std::fs::create_dir_all(&settings.out)?;
+    let entries = gallery::run(&shots, &settings.out);
+    for entry in &entries {
+        if let Err(e) = &entry.result {
+            println!("{:?} @ {}: {}", entry.shot.rom, entry.shot.frame, e);
+        }
+    }
+    let index = settings.out.join("index.html");
+    std::fs::write(&index, gallery::html(&entries))?;
+    info!("Wrote gallery to {:?}", index);
+    Ok(())
+}
+
+// Per-frame callbacks that live outside the emulator core.
+#[derive(Default)]
+struct Hooks {
+    #[cfg(feature = "scripting")]
+    script: Option<script::Script>,
+    metrics: Option<Metrics>,
+    sprite_overflow: bool,
+    gdb: Option<GdbStub>,
+}
+
+impl Hooks {
+    // Lets an attached GDB client stop and resume emulation, `paused` is whether it's stopped now.
+    #[cfg_attr(not(any(feature = "frontend", feature = "tui")), allow(dead_code))]
+    fn poll_gdb(&mut self, emu: &mut Emu, paused: bool) -> bool {
+        match &mut self.gdb {
+            Some(gdb) => gdb.poll(emu, paused),
+            None => paused,
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "frontend", feature = "tui")), allow(dead_code))]
+    fn on_frame(&mut self, emu: &mut Emu) {
+        if let Some(metrics) = &self.metrics {
+            metrics.update(&emu.stats());
+        }
+        if self.sprite_overflow {
+            let dropped = overflow::dropped(&emu.bus.gpu);
+            if !dropped.is_empty() {
+                let sprites: usize = dropped.iter().map(|&(_, n)| n).sum();
+                warn!(
+                    "Frame {}: {} sprites dropped over {} lines, first on line {}",
+                    emu.bus.gpu._vblank_count,
+                    sprites,
+                    dropped.len(),
+                    dropped[0].0
+                );
+            }
+            overflow::mark(&mut emu.overlay, &dropped);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut self.script {
+            if let Err(e) = script.on_frame(emu) {
+                println!("Script error, disabling: {}", e);
+                self.script = None;
+            }
+        }
+    }
+}
+
+// Start of an emulated frame: reset if asked to, latch input and run per-frame hooks. Hooks come
+// last so a script's presses and overlay shapes apply to the frame about to run.
+#[cfg_attr(not(any(feature = "frontend", feature = "tui")), allow(dead_code))]
+fn start_frame(emu: &mut Emu, input: &mut Input, hooks: &mut Hooks) {
+    if input.hard_reset_pending() {
+        emu.reset();
+    }
+    input.apply(&mut emu.bus);
+    emu.tick_battery();
+    if let Some(map) = &mut emu.exec_map {
+        map.end_frame();
+    }
+    emu.overlay.clear();
+    hooks.on_frame(emu);
+}
+
+// Directory of the ROM, for files saved next to it.
+fn rom_dir(settings: &Settings) -> Option<PathBuf> {
+    let dir = settings.input.as_ref()?.parent()?;
+    Some(dir.to_path_buf())
+}
+
+// Falls back to the splash ROM when no game was given or it couldn't be loaded.
+fn load_rom(settings: &Settings) -> Emu {
+    let bootrom = settings.bootrom.clone();
+    match &settings.input {
+        Some(path) => Emu::from_path(path.clone(), bootrom.clone()).unwrap_or_else(|e| {
+            eprintln!("Couldn't load {:?}: {}", path, e);
+            Emu::new(splash::rom(), bootrom)
+        }),
+        None => Emu::new(splash::rom(), bootrom),
+    }
+}
+
+// Config section of bug report bundles.
+fn bug_report_config() -> String {
+    let args: Vec<String> = std::env::args().collect();
+    format!(
+        "version: {}\nargs: {}",
+        env!("CARGO_PKG_VERSION"),
+        args.join(" ")
+    )
+}
+
+fn print_opcode_stats(emu: &Emu) {
+    if let Some(stats) = &emu.bus.opcode_stats {
+        print!("{}", stats);
+    }
+}
+
+fn main() -> MaybeErr<()> {
+    // When the program starts up, parse command line arguments and setup additional systems.
+    match std::env::args().nth(1).as_deref() {
+        Some("batch") => return batch_main(BatchSettings::from_iter(std::env::args().skip(1))),
+        Some("dump") => return dump_main(DumpSettings::from_iter(std::env::args().skip(1))),
+        Some("gallery") => {
+            return gallery_main(GallerySettings::from_iter(std::env::args().skip(1)))
+        }
+        _ => {}
+    }
+    let settings = Settings::from_args();
+    let log = LogControl::new(LevelFilter::Info);
+    logging::setup_logger(&log, settings.logfile.as_deref())?;
+    let presentation = Presentation {
+        scale: settings.scale.max(1),
+        pacing: if settings.vsync {
+            Pacing::Vsync
+        } else {
+            settings.pacing
+        },
+        mute: settings.mute,
+        filter: settings.filter,
+    };
+    info!("Running SDL Main");
+    let mut emu = load_rom(&settings);
+    emu.bus.power_on(settings.power_on_fill);
+    emu.bus.set_model(settings.model);
+    if let Some(palette) = settings.palette {
+        emu.bus.gpu.palette = palette;
+    }
+    emu.bus.gpu.color_correction = ColorCorrection {
+        curve: settings.color_correction,
+        gamma: settings.gamma,
+    };
+    if let (Some(regs), Some(dump)) = (&settings.import_regs, &settings.import_dump) {
+        info!("Importing state from {:?} and {:?}", regs, dump);
+        import::import_files(&mut emu, regs, dump)?;
+    }
+    if let Some(path) = &settings.load_state {
+        info!("Loading state from {:?}", path);
+        savestate::load(&mut emu, &std::fs::read(path)?)?;
+    }
+    if let (Some(path), Some(camera)) = (&settings.camera_image, &mut emu.bus.camera) {
+        let (width, height, gray) = camera::load_image(path)?;
+        camera.set_image(width, height, &gray);
+    }
+    if let Some(path) = &settings.debug_file {
+        emu.debug_info = Some(DebugInfo::load(path)?);
+    }
+    if let (Some(path), Some(header)) = (&settings.input, &emu.header) {
+        if header.has_battery() {
+            let save = path.with_extension("sav");
+            emu.enable_battery(BatterySaver::new(save, DEFAULT_SAVE_INTERVAL))?;
+        }
+    }
+    if settings.opcode_stats {
+        emu.bus.opcode_stats = Some(OpcodeStats::new());
+    }
+    emu.idle_skip = settings.idle_skip;
+    if settings.decode_cache {
+        emu.bus.decode_cache = Some(DecodeCache::new());
+    }
+    if let Some(path) = &settings.compare_log {
+        let reference = std::io::BufReader::new(std::fs::File::open(path)?);
+        let result = golden::compare(&mut emu, reference)?;
+        print_opcode_stats(&emu);
+        return match result {
+            Some(divergence) => Err(divergence.to_string().into()),
+            None => {
+                println!("Matched every line of {:?}", path);
+                Ok(())
+            }
+        };
+    }
+    emu.bus.report_log = Some(ReportLog::new());
+    emu.bus.debug_port = settings.debug_port;
+    emu.bus.strict_io = settings.strict_io;
+    emu.bus.log_unmapped_io = settings.log_unmapped_io;
+    // Only swap the device, SB, SC and the output may have been restored by an import or a state.
+    emu.bus.serial.device = settings.serial.device();
+    if let (SerialKind::Printer, Some(dir)) = (settings.serial, rom_dir(&settings)) {
+        emu.bus.serial.device = Some(Box::new(Printer::new(dir)));
+    }
+    match settings.trace_format.as_deref() {
+        Some("chrome") => emu.bus.tracer = Some(Tracer::new()),
+        Some(format) => return Err(format!("Unsupported trace format: {}", format).into()),
+        None => {}
+    }
+    emu.watchdog = settings
+        .watchdog
+        .map(|cycles| Watchdog::new(cycles, DEFAULT_LOOP_WINDOW));
+    if let Some(path) = &settings.boot_logo {
+        let bus = &mut emu.bus;
+        BootLogo::load(path)?.patch_bootrom(&mut bus.bootrom, &bus.memory[..0x8000])?;
+    }
+    if settings.fast_boot {
+        emu.fast_boot()?;
+    }
+    let metrics = settings
+        .metrics_port
+        .map(|port| Metrics::serve(&settings.metrics_bind, port))
+        .transpose()?;
+    if settings.headless {
+        let expect = match (&settings.expect_serial, settings.exit_code_from_serial) {
+            (Some(text), _) => Some(text.as_str()),
+            (None, true) => Some("Passed"),
+            (None, false) => None,
+        };
+        let mut progress = settings.progress.map(|seconds| {
+            let interval = Duration::from_secs_f64(seconds.max(0.0));
+            Progress::new(interval, settings.frames, emu.bus.clock, Instant::now())
+        });
+        let mut done = 0;
+        let summary = batch::run_headless_with(&mut emu, settings.frames, expect, |emu| {
+            if let Some(metrics) = &metrics {
+                metrics.update(&emu.stats());
+            }
+            done += 1;
+            if let Some(line) = progress
+                .as_mut()
+                .and_then(|p| p.update(emu.bus.clock, done, Instant::now()))
+            {
+                eprintln!("{}", line);
+            }
+        });
+        if progress.is_some() {
+            eprintln!(
+                "Ran {} frames, {:.1}s emulated in {:.1}s ({:.2}x)",
+                summary.frames,
+                summary.emulated.as_secs_f64(),
+                summary.elapsed.as_secs_f64(),
+                summary.speed()
+            );
+        }
+        println!("{}", batch::summary_json(&summary));
+        // Flushes the battery save, process::exit skips destructors.
+        drop(emu);
+        std::process::exit(if summary.passed { 0 } else { 1 });
+    }
+    let mut input = Input::new();
+    input.bind(&settings.turbo_a, Binding::Turbo(Button::A));
+    input.bind(&settings.turbo_b, Binding::Turbo(Button::B));
+    input.turbo_rate = settings.turbo_rate;
+    input.rumble = !settings.no_rumble;
+    input.bind(&settings.soft_reset, Binding::SoftReset);
+    input.bind(&settings.hard_reset, Binding::HardReset);
+    if settings.record_movie.is_some() {
+        input.movie = Some(Movie::new());
+    }
+    if let Some(path) = &settings.play_movie {
+        input.play(Movie::load(path)?);
+    }
+    #[allow(unused_mut)]
+    let mut hooks = Hooks {
+        metrics,
+        sprite_overflow: settings.sprite_overflow,
+        gdb: settings.gdb_port.map(GdbStub::bind).transpose()?,
+        ..Hooks::default()
+    };
+    #[cfg(feature = "scripting")]
+    {
+        if let Some(path) = &settings.script {
+            hooks.script = Some(script::Script::load(path)?);
+        }
+    }
+    let mut slots = Slots::new(
+        settings
+            .input
+            .as_deref()
+            .unwrap_or_else(|| Path::new("splash.gb")),
+    );
+    if settings.tui {
+        tui::run(&mut emu, &mut input, &mut hooks)?;
+    } else {
+        frontend::run(
+            &mut emu,
+            presentation,
+            &mut input,
+            &mut hooks,
+            &mut slots,
+            &log,
+        )?;
+    }
+    input.flush_reads(&emu.bus);
+    if let (Some(path), Some(movie)) = (&settings.record_movie, &input.movie) {
+        info!("Writing movie to {:?}", path);
+        movie.save(path)?;
+    }
+    print_opcode_stats(&emu);
+    if let Some(path) = &settings.bug_report {
+        info!("Writing bug report to {:?}", path);
+        std::fs::write(path, bugreport::bundle(&emu, &bug_report_config()))?;
+    }
+    if let Some(tracer) = &emu.bus.tracer {
+        info!("Writing trace to {:?}", settings.trace_out);
+        tracer.save_chrome(&settings.trace_out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use rsboy_core::constants::MaybeErr;
+use rsboy_core::stats::Stats;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How long a scraper gets to send its request and take the reply before it's dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Serves the latest Stats in the Prometheus text format on every path of
+// http://<host>:<port>/, for scraping long batch runs from a dashboard.
+#[derive(Clone)]
+pub struct Metrics(Arc<Mutex<String>>);
+
+impl Metrics {
+    // Only on localhost unless `host` says otherwise, like 0.0.0.0 for a remote dashboard.
+    pub fn serve(host: &str, port: u16) -> MaybeErr<Self> {
+        let listener = TcpListener::bind((host, port))?;
+        let text = Arc::new(Mutex::new(Stats::default().prometheus()));
+        let shared = text.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let body = shared.lock().unwrap().clone();
+                // A scraper hanging up early is its problem.
+                let _ = respond(stream, &body);
+            }
+        });
+        Ok(Metrics(text))
+    }
+
+    pub fn update(&self, stats: &Stats) {
+        *self.0.lock().unwrap() = stats.prometheus();
+    }
+}
+
+fn respond(mut stream: TcpStream, body: &str) -> std::io::Result<()> {
+    // One stalled client would hold up every other scrape.
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    // Only the request line matters, and not even that.
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,213 @@
+use crate::{start_frame, Hooks};
+use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+use ratatui::Terminal;
+use rsboy_core::constants::{MaybeErr, FRAME_TIME};
+use rsboy_core::emu::Emu;
+use rsboy_core::gpu::{PixelData, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rsboy_core::input::{Binding, Button, Input};
+use std::collections::HashMap;
+use std::io::{stdout, Stdout};
+use std::time::{Duration, Instant};
+
+// Terminals only report key presses, so each press holds its button for this many frames.
+const HOLD_FRAMES: u32 = 6;
+const SIDE_WIDTH: u16 = 32;
+
+type Term = Terminal<CrosstermBackend<Stdout>>;
+
+// Runs in the terminal until Esc or Ctrl+C: the screen drawn with half block characters, with
+// registers and disassembly next to it. P pauses, N steps one instruction while paused.
+pub fn run(emu: &mut Emu, input: &mut Input, hooks: &mut Hooks) -> MaybeErr<()> {
+    // There is no Right Shift in a terminal.
+    input.bind("Space", Binding::Button(Button::Select));
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let result = run_loop(&mut terminal, emu, input, hooks);
+    // Give the terminal back even if the loop failed.
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+// SDL style key names, which Input bindings use.
+fn key_name(code: KeyCode) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+        KeyCode::Enter => "Return".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Delete => "Delete".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn run_loop(
+    terminal: &mut Term,
+    emu: &mut Emu,
+    input: &mut Input,
+    hooks: &mut Hooks,
+) -> MaybeErr<()> {
+    let mut held: HashMap<String, u32> = HashMap::new();
+    let mut paused = false;
+    let mut status = String::new();
+    loop {
+        let now = Instant::now();
+        let mut step = false;
+        while event::poll(Duration::ZERO)? {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char('p') | KeyCode::Char('P') => paused = !paused,
+                KeyCode::Char('n') | KeyCode::Char('N') if paused => step = true,
+                code => {
+                    if let Some(name) = key_name(code) {
+                        if input.key_down(&name) {
+                            held.insert(name, HOLD_FRAMES);
+                        }
+                    }
+                }
+            }
+        }
+
+        paused = hooks.poll_gdb(emu, paused);
+        if step {
+            if let Some(reason) = emu.emulate_step() {
+                status = reason.to_string();
+            }
+        } else if !paused {
+            let frame = emu.run_frame();
+            if let Some(reason) = frame.stopped() {
+                status = reason.to_string();
+                paused = true;
+            }
+            start_frame(emu, input, hooks);
+        }
+        held.retain(|name, frames| {
+            *frames -= 1;
+            if *frames == 0 {
+                input.key_up(name);
+            }
+            *frames > 0
+        });
+
+        terminal.draw(|f| {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(20), Constraint::Length(SIDE_WIDTH)])
+                .split(f.size());
+            f.render_widget(Screen(emu.bus.gpu.screen()), columns[0]);
+            let side = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(9), Constraint::Min(4)])
+                .split(columns[1]);
+            f.render_widget(registers(emu, paused, &status), side[0]);
+            f.render_widget(disassembly(emu), side[1]);
+        })?;
+
+        if let Some(time) = FRAME_TIME.checked_sub(now.elapsed()) {
+            std::thread::sleep(time);
+        }
+    }
+}
+
+fn registers<'a>(emu: &Emu, paused: bool, status: &'a str) -> Paragraph<'a> {
+    let r = &emu.cpu.registers;
+    let io = emu.bus.io_map();
+    let lines = vec![
+        Line::from(format!(
+            "AF {:02x}{:02x}  BC {:02x}{:02x}",
+            r.a, r.f, r.b, r.c
+        )),
+        Line::from(format!(
+            "DE {:02x}{:02x}  HL {:02x}{:02x}",
+            r.d, r.e, r.h, r.l
+        )),
+        Line::from(format!("SP {:04x}  PC {:04x}", r.sp, r.pc)),
+        Line::from(format!("IE {}", io.int_enabled())),
+        Line::from(format!("IF {}", io.int_flags())),
+        Line::from(if paused { "PAUSED" } else { "" }),
+        // Why emulation last stopped.
+        Line::from(Span::styled(status, Style::default().fg(Color::Yellow))),
+    ];
+    let title = format!("CLK {}", emu.bus.clock);
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title))
+}
+
+fn disassembly(emu: &Emu) -> Paragraph<'static> {
+    let lines: Vec<Line> = emu
+        .view()
+        .iter()
+        .map(|il| {
+            let text = format!(
+                "{:04x}: {:?} {:04x}",
+                il.addr,
+                il.instr,
+                il.data.unwrap_or(0)
+            );
+            if il.addr == emu.cpu.op_addr {
+                Line::from(Span::styled(text, Style::default().bg(Color::Green)))
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect();
+    Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Code"))
+}
+
+// The screen as half block characters, the top pixel in the foreground and the bottom one in
+// the background of each cell, scaled down to fit.
+struct Screen<'a>(&'a PixelData);
+
+fn rgb(pixel: u32) -> Color {
+    let [r, g, b, _] = pixel.to_be_bytes();
+    Color::Rgb(r, g, b)
+}
+
+impl Widget for Screen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        // Pixels per cell column, the same step both ways keeps the aspect ratio.
+        let step = (SCREEN_WIDTH as f32 / area.width as f32)
+            .max(SCREEN_HEIGHT as f32 / (area.height as f32 * 2.0))
+            .max(1.0);
+        let columns = ((SCREEN_WIDTH as f32 / step) as u16).min(area.width);
+        let rows = ((SCREEN_HEIGHT as f32 / step / 2.0) as u16).min(area.height);
+        for row in 0..rows {
+            let top = (row as f32 * 2.0 * step) as usize;
+            let bottom = (((row as f32 * 2.0 + 1.0) * step) as usize).min(SCREEN_HEIGHT - 1);
+            for column in 0..columns {
+                let x = (column as f32 * step) as usize;
+                buf.get_mut(area.x + column, area.y + row)
+                    .set_char('▀')
+                    .set_fg(rgb(self.0[top][x]))
+                    .set_bg(rgb(self.0[bottom][x]));
+            }
+        }
+    }
+}
@@ -5,4 +5,4 @@ Write-Host "Running gboy"
 
 $env:RUST_LOG = $log
 $env:RUST_BACKTRACE = 1
-cargo run ./roms/Tetris.gb
\ No newline at end of file
+cargo run -p rsboy-sdl -- ./roms/Tetris.gb
\ No newline at end of file